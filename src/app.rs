@@ -1,37 +1,75 @@
-use iced::widget::{column, container, row, text_editor};
+use iced::widget::{column, container, row, scrollable, text_editor, text_input};
 use iced::{Element, Length, Subscription, Task, Theme};
 
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Instant;
 use tokio::sync::Mutex;
+use zeroize::Zeroizing;
 
 use crate::model::enums::*;
-use crate::model::profile::ConnectionProfile;
-use crate::model::rule::{Action, Condition, SieveRule};
-use crate::net::managesieve::{ManageSieveClient, ScriptInfo};
-use crate::sieve::converter;
-use crate::store::{profile_store, script_io};
+use crate::model::profile::{self, ConnectionProfile};
+use crate::model::rule::{Action, Condition, RuleBranch, SieveRule};
+use crate::model::script::RequireStatement;
+use crate::net::managesieve::{self, Capabilities, ManageSieveClient, ScriptInfo, Security};
+use crate::sieve::emitter::Indent;
+use crate::sieve::ast::Command;
+use crate::sieve::{converter, lint, parser};
+use crate::store::settings_store::{self, AppSettings, OpenOnConnect};
+use crate::store::folder_sync::{self, SyncEntry, SyncStatus};
+use crate::store::script_io::{self, ImportEntry, ManifestEntry};
+use crate::store::{profile_store, secret_store};
 use crate::ui;
-use crate::ui::action_row::ActionMessage;
-use crate::ui::condition_row::ConditionMessage;
+use crate::ui::action_row::{self, ActionMessage};
+use crate::ui::condition_row::{ConditionMessage, TransformOption};
 use crate::ui::about_modal::{AboutMessage, AboutState};
-use crate::ui::connection_modal::{ConnectionMessage, ConnectionState};
-use crate::ui::rule_card::RuleMessage;
-use crate::ui::script_list::ScriptListMessage;
+use crate::ui::find_bar::{FindMessage, FindState};
+use crate::ui::template_modal::{TemplateMessage, TemplateState};
+use crate::ui::close_modal::CloseMessage;
+use crate::ui::confirm_modal::{ConfirmMessage, ConfirmState};
+use crate::ui::connection_modal::{ConnectionMessage, ConnectionState, TestStatus};
+use crate::ui::context_menu::MenuTarget;
+use crate::ui::diff_modal::{DiffMessage, DiffState};
+use crate::ui::problems_panel::{ProblemsMessage, ProblemsState};
+use crate::ui::rule_card::{BranchId, RuleMessage};
+use crate::ui::script_list::{RenameState, ScriptListMessage};
+use crate::ui::splitter::{self, Splitter};
+use crate::ui::sync_modal::{FolderSyncState, SyncMessage};
+use crate::util::{diff, search};
 
 const RAW_SYNC_DEBOUNCE_MS: u64 = 500;
+const PARSE_ERRORS_WARNING: &str = "Script has errors — fix before uploading";
+const UNDO_LIMIT: usize = 50;
 
+/// Visual editor shortcuts that add something; what they add to depends on
+/// the editor state.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Tab {
-    Visual,
-    Raw,
+pub enum AddShortcut {
+    Rule,
+    Condition,
+    Action,
+}
+
+/// Moves of the rule selection from the keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleStep {
+    Previous,
+    Next,
+    First,
+    Last,
 }
 
 pub struct Sievers {
     // Editor state
     pub editor_content: text_editor::Content,
     pub rules: Vec<SieveRule>,
+    /// Extensions required by the loaded script, kept when regenerating it
+    pub declared_requires: Vec<String>,
+    /// Its `require` statements as written, reused while still accurate
+    pub require_statements: Vec<RequireStatement>,
+    /// Comment banner heading the loaded script
+    pub preamble: Vec<String>,
     pub active_tab: Tab,
 
     // File
@@ -44,48 +82,227 @@ pub struct Sievers {
     pub connection: ConnectionState,
     pub server_scripts: Vec<ScriptInfo>,
     pub selected_script: Option<String>,
+    pub renaming_script: Option<RenameState>,
+    pub capabilities: Option<Capabilities>,
+    /// TLS version and cipher of the connection, or plaintext
+    pub security: Option<Security>,
     client: Arc<Mutex<ManageSieveClient>>,
+    /// Credentials of the current connection, for reconnecting after a drop
+    session: Option<Session>,
 
     // Visual editor selection
     pub selected_rule: Option<usize>,
+    pub collapsed_branches: HashSet<BranchId>,
+    /// Sidebar search query; only narrows the view, never `rules`
+    pub rule_filter: String,
+    /// Notes editor for the selected rule, kept in step by `refresh_notes_editor`
+    pub notes_editor: text_editor::Content,
+    /// Editor for the selected rule's raw block, kept in step by
+    /// `refresh_raw_block_editor`
+    pub raw_block_editor: text_editor::Content,
 
-    // Theme
-    pub dark_mode: bool,
+    // Persisted preferences: theme and window geometry
+    pub settings: AppSettings,
 
     // About
     pub about: AboutState,
+    pub templates: TemplateState,
+    pub find: FindState,
+
+    // Diff against the server copy
+    pub diff: DiffState,
+    pub folder_sync: FolderSyncState,
+    pub problems: ProblemsState,
+
+    /// Export of all server scripts in progress
+    export: Option<ExportState>,
+
+    /// Import of a folder of scripts in progress
+    import: Option<ImportState>,
+
+    /// Make each uploaded script the active one; for this session only
+    pub activate_after_upload: bool,
+
+    /// A server operation is running; further ones wait for it
+    busy: bool,
+    /// Animation step of the status bar's busy indicator
+    busy_frame: usize,
+
+    /// Destructive action waiting on the confirm dialog
+    pub confirm: Option<ConfirmState<Message>>,
+    /// Entry whose right-click menu is open
+    pub context_menu: Option<MenuTarget>,
+
+    /// Editor or rules differ from the last file/server copy
+    pub dirty: bool,
+    /// Window whose close was held back by the unsaved-changes prompt
+    pub pending_close: Option<iced::window::Id>,
+
+    /// Last known scroll position of the raw editor
+    raw_viewport: Option<scrollable::Viewport>,
+
+    /// Divider being dragged, until the mouse button is released
+    resizing: Option<Splitter>,
+    /// Last move or resize of the window not yet saved to the settings
+    last_geometry_change: Option<Instant>,
+
+    /// The SIEVE reference beside the editor
+    show_help: bool,
 
     // Sync state
     syncing: bool,
     raw_dirty: bool,
     last_raw_edit: Option<Instant>,
+    /// The raw text parsed at the last sync; Upload is off while it doesn't
+    parse_ok: bool,
+    /// Rule edits not yet written to the raw text
+    visual_dirty: bool,
+    last_visual_edit: Option<Instant>,
+    /// A raw block was edited into valid SIEVE; the next sync reads the
+    /// text back so it becomes an editable rule
+    promote_raw_blocks: bool,
+
+    // Undo history, oldest first
+    undo_stack: Vec<Snapshot>,
+    redo_stack: Vec<Snapshot>,
+}
+
+/// Editor state captured before a change, for undo/redo.
+#[derive(Debug, Clone)]
+struct Snapshot {
+    rules: Vec<SieveRule>,
+    text: String,
+    /// What the script declares outside its rules
+    declared_requires: Vec<String>,
+    require_statements: Vec<RequireStatement>,
+    preamble: Vec<String>,
+}
+
+/// Scripts still to fetch in an export, one at a time.
+struct ExportState {
+    dir: PathBuf,
+    queue: std::collections::VecDeque<ManifestEntry>,
+    total: usize,
+    exported: Vec<ManifestEntry>,
+    failed: usize,
+}
+
+/// Script files still to upload in an import, one at a time.
+struct ImportState {
+    queue: std::collections::VecDeque<ImportEntry>,
+    /// Run CHECKSCRIPT before each upload
+    check: bool,
+    uploaded: usize,
+    invalid: usize,
+    failed: usize,
+    /// Uploaded script the manifest marks active
+    activate: Option<String>,
+}
+
+/// What became of one imported file.
+#[derive(Debug, Clone)]
+pub enum ImportOutcome {
+    Uploaded { name: String, active: bool },
+    /// Rejected by CHECKSCRIPT, not uploaded
+    Invalid,
+    Failed(String),
+}
+
+/// Profile and password of the current connection; kept in memory only.
+#[derive(Clone)]
+struct Session {
+    profile: ConnectionProfile,
+    password: Zeroizing<String>,
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
     // Toolbar
     Connect,
+    /// Start an empty script, after confirming unsaved changes
+    NewScript,
+    NewScriptConfirmed,
     OpenFile,
+    OpenRecent(PathBuf),
     SaveFile,
     Upload,
+    /// Upload to the active script without a server-side check
+    UploadConfirmed,
+    /// "Activate after upload" checkbox
+    SetActivateAfterUpload(bool),
+    Validate,
+    DiffAgainstServer,
+    ExportAllScripts,
+    ImportAllScripts,
     ToggleTheme,
+    SetIndent(Indent),
+    SetKeepRequireOrder(bool),
+    /// Rewrite the raw text in the generated layout
+    FormatScript,
+    /// Show or hide the SIEVE reference
+    ToggleHelp,
+    Shortcut(AddShortcut),
+    /// Move the rule selection within the sidebar
+    StepRule(RuleStep),
+    FindMsg(FindMessage),
+    Undo,
+    Redo,
     ShowAbout,
     AboutMsg(AboutMessage),
+    DiffMsg(DiffMessage),
+    ProblemsMsg(ProblemsMessage),
+
+    // Confirmation dialog
+    ConfirmMsg(ConfirmMessage),
+
+    // Window
+    CloseRequested(iced::window::Id),
+    WindowResized(iced::Size),
+    WindowMoved(iced::Point),
+    CloseMsg(CloseMessage),
+    /// A divider was pressed; the cursor's moves now resize its panel
+    StartResize(Splitter),
+    /// The cursor moved to this x while dragging a divider
+    ResizeTo(f32),
+    EndResize,
 
     // Tab
     SwitchTab(Tab),
 
     // Raw editor
     EditorAction(text_editor::Action),
+    RawEditorScrolled(scrollable::Viewport),
 
     // Visual editor
     SelectRule(usize),
     RuleMsg(usize, RuleMessage),
     AddRule,
+    /// Open the template picker
+    ShowTemplates,
+    TemplateMsg(TemplateMessage),
+    /// Add suffixes to rule names used more than once
+    RenameDuplicates,
+    CopyRule(usize),
+    /// Insert a copy of the rule right after it
+    DuplicateRule(usize),
+    CopyScript,
+    PasteRule,
+    /// Clipboard contents read for `PasteRule`
+    RulePasted(Option<String>),
+    /// Ask before removing; `RemoveRule` does the removal
+    ConfirmRemoveRule(usize),
     RemoveRule(usize),
+    SetRuleFilter(String),
+    MoveRuleUp(usize),
+    MoveRuleDown(usize),
+    /// Right-click on a rule card or script; again on the same one closes it
+    ShowContextMenu(MenuTarget),
+    CloseContextMenu,
 
     // Sync
     DebounceCheck,
+    /// Animates the busy indicator while a server operation runs
+    BusyTick,
 
     // File I/O
     FileOpened(Result<(PathBuf, String), String>),
@@ -93,26 +310,183 @@ pub enum Message {
 
     // Connection modal
     ConnectionMsg(ConnectionMessage),
+    ShowSwitchUser,
+    /// Log in as the other user, dropping the editor's unsaved changes
+    SwitchUserConfirmed,
 
     // Server operations
-    Connected(Result<Vec<ScriptInfo>, String>),
+    /// Capabilities, how the connection is protected, and the scripts
+    Connected(Result<(Capabilities, Option<Security>, Vec<ScriptInfo>), String>),
+    /// The server's certificate failed verification; carries its fingerprint
+    CertificateUntrusted(String),
+    /// Pin the fingerprint to the profile and connect again
+    TrustCertificate(String),
+    /// Result of the connection modal's "Test"
+    ConnectionTested(TestStatus),
     Disconnected,
     ScriptsLoaded(Result<Vec<ScriptInfo>, String>),
     ScriptDownloaded(Result<(String, String), String>),
-    ScriptUploaded(Result<String, String>),
+    ScriptUploaded(Result<(String, Option<PathBuf>), String>),
     ScriptDeleted(Result<String, String>),
-    ScriptActivated(Result<String, String>),
+    ScriptRenamed(Result<(String, String), String>),
+    /// SETACTIVE of `name`, or of none for an empty name, and the script
+    /// list read back after it
+    ScriptActivated {
+        name: String,
+        result: Result<Vec<ScriptInfo>, String>,
+    },
+    Validated(Result<(), String>),
+    /// CHECKSCRIPT result ahead of replacing the active script
+    ActiveUploadChecked(Result<String, String>),
+    DiffLoaded(Result<(String, String), String>),
+    ExportDirChosen(Option<PathBuf>),
+    ScriptExported(Result<ManifestEntry, String>),
+    ImportDirChosen(Option<PathBuf>),
+    /// Import `entries` though one replaces the active script unchecked
+    ImportConfirmed(Vec<ImportEntry>),
+    ScriptImported(ImportOutcome),
+    /// SETACTIVE of the script that was active before an import;
+    /// `summary` reports the import
+    ImportActivated {
+        summary: String,
+        name: String,
+        result: Result<Vec<ScriptInfo>, String>,
+    },
+    /// Compare the profile's scripts folder with the server
+    SyncFolder,
+    SyncDirChosen(Option<PathBuf>),
+    SyncCompared(Result<Vec<SyncEntry>, String>),
+    SyncMsg(SyncMessage),
+    /// A script uploaded from the folder
+    /// Push `name` though it replaces the active script unchecked
+    SyncPushConfirmed(String),
+    SyncPushed(Result<String, String>),
+    /// A script written to the folder
+    SyncPulled(Result<String, String>),
+    /// SETACTIVE after an upload with "Activate after upload" on;
+    /// `uploaded` reports the upload
+    UploadActivated {
+        uploaded: String,
+        name: String,
+        result: Result<Vec<ScriptInfo>, String>,
+    },
+    /// Connect again with the last profile after the server closed the session
+    Reconnect,
 
     // Script list
     ScriptListMsg(ScriptListMessage),
 }
 
+impl Message {
+    /// The error of a server operation's result, if it failed.
+    fn server_error(&self) -> Option<&str> {
+        let error = match self {
+            Self::Connected(Err(e))
+            | Self::ScriptsLoaded(Err(e))
+            | Self::ScriptDownloaded(Err(e))
+            | Self::ScriptUploaded(Err(e))
+            | Self::ScriptDeleted(Err(e))
+            | Self::ScriptRenamed(Err(e))
+            | Self::ScriptActivated { result: Err(e), .. }
+            | Self::Validated(Err(e))
+            | Self::ActiveUploadChecked(Err(e))
+            | Self::DiffLoaded(Err(e))
+            | Self::ScriptExported(Err(e))
+            | Self::ScriptImported(ImportOutcome::Failed(e))
+            | Self::ImportActivated { result: Err(e), .. }
+            | Self::SyncCompared(Err(e))
+            | Self::SyncPushed(Err(e))
+            | Self::SyncPulled(Err(e))
+            | Self::UploadActivated { result: Err(e), .. } => e,
+            _ => return None,
+        };
+        Some(error)
+    }
+
+    /// Whether this is the result of a server operation, ending the busy state.
+    fn is_server_result(&self) -> bool {
+        matches!(
+            self,
+            Self::Connected(_)
+                | Self::CertificateUntrusted(_)
+                | Self::ScriptsLoaded(_)
+                | Self::ScriptDownloaded(_)
+                | Self::ScriptUploaded(_)
+                | Self::ScriptDeleted(_)
+                | Self::ScriptRenamed(_)
+                | Self::ScriptActivated { .. }
+                | Self::Validated(_)
+                | Self::ActiveUploadChecked(_)
+                | Self::DiffLoaded(_)
+                | Self::ScriptExported(_)
+                | Self::ScriptImported(_)
+                | Self::ImportActivated { .. }
+                | Self::SyncCompared(_)
+                | Self::SyncPushed(_)
+                | Self::SyncPulled(_)
+                | Self::UploadActivated { .. }
+        )
+    }
+
+    /// Whether the open context menu stays open through this message: only
+    /// opening one and background ticks leave it alone.
+    fn keeps_context_menu(&self) -> bool {
+        matches!(
+            self,
+            Self::ShowContextMenu(_)
+                | Self::ScriptListMsg(ScriptListMessage::ShowMenu(_))
+                | Self::DebounceCheck
+                | Self::BusyTick
+                | Self::WindowResized(_)
+                | Self::WindowMoved(_)
+                | Self::RawEditorScrolled(_)
+        )
+    }
+
+    /// Whether this asks for a server operation, which must wait while
+    /// another one runs.
+    fn starts_server_operation(&self) -> bool {
+        matches!(
+            self,
+            Self::Connect
+                | Self::Reconnect
+                | Self::TrustCertificate(_)
+                | Self::Upload
+                | Self::UploadConfirmed
+                | Self::Validate
+                | Self::DiffAgainstServer
+                | Self::ExportAllScripts
+                | Self::ImportAllScripts
+                | Self::ImportConfirmed(_)
+                | Self::SyncFolder
+                | Self::SyncMsg(SyncMessage::Push(_) | SyncMessage::Pull(_) | SyncMessage::Refresh)
+                | Self::SyncPushConfirmed(_)
+                | Self::ConnectionMsg(ConnectionMessage::Connect | ConnectionMessage::SwitchUser)
+                | Self::SwitchUserConfirmed
+                | Self::ScriptListMsg(
+                    ScriptListMessage::SelectScript(_)
+                        | ScriptListMessage::ActivateScript(_)
+                        | ScriptListMessage::DeactivateScripts
+                        | ScriptListMessage::DeleteScript(_)
+                        | ScriptListMessage::RenameScript { .. }
+                        | ScriptListMessage::ExportAll
+                        | ScriptListMessage::ImportAll
+                        | ScriptListMessage::SyncFolder
+                )
+        )
+    }
+}
+
 impl Default for Sievers {
     fn default() -> Self {
+        let settings = settings_store::load_settings();
         Self {
             editor_content: text_editor::Content::new(),
             rules: Vec::new(),
-            active_tab: Tab::Raw,
+            declared_requires: Vec::new(),
+            require_statements: Vec::new(),
+            preamble: Vec::new(),
+            active_tab: settings.last_tab,
             current_path: None,
             current_script_name: None,
             status: "Ready".to_string(),
@@ -120,21 +494,253 @@ impl Default for Sievers {
             connection: ConnectionState::default(),
             server_scripts: Vec::new(),
             selected_script: None,
+            renaming_script: None,
+            capabilities: None,
+            security: None,
             client: Arc::new(Mutex::new(ManageSieveClient::new())),
+            session: None,
             selected_rule: None,
-            dark_mode: false,
+            collapsed_branches: HashSet::new(),
+            rule_filter: String::new(),
+            notes_editor: text_editor::Content::new(),
+            raw_block_editor: text_editor::Content::new(),
+            settings,
             about: AboutState::default(),
+            templates: TemplateState::default(),
+            find: FindState::default(),
+            diff: DiffState::default(),
+            folder_sync: FolderSyncState::default(),
+            problems: ProblemsState::default(),
+            export: None,
+            import: None,
+            activate_after_upload: false,
+            busy: false,
+            busy_frame: 0,
+            confirm: None,
+            context_menu: None,
+            dirty: false,
+            pending_close: None,
+            raw_viewport: None,
+            resizing: None,
+            last_geometry_change: None,
+            show_help: false,
             syncing: false,
             raw_dirty: false,
             last_raw_edit: None,
+            parse_ok: true,
+            visual_dirty: false,
+            last_visual_edit: None,
+            promote_raw_blocks: false,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
         }
     }
 }
 
 pub fn update(state: &mut Sievers, message: Message) -> Task<Message> {
+    if !message.keeps_context_menu() {
+        state.context_menu = None;
+    }
+    if state.busy && message.starts_server_operation() {
+        return Task::none();
+    }
+    // Handlers that chain another operation set it again
+    if message.is_server_result() {
+        state.busy = false;
+    }
+    // Whatever was running, a BYE means the session is over
+    let closed = message
+        .server_error()
+        .and_then(managesieve::server_closed_reason)
+        .map(str::to_string);
+    let task = handle_message(state, message);
+    if let Some(reason) = closed {
+        server_closed(state, &reason);
+    }
+    refresh_notes_editor(state);
+    refresh_raw_block_editor(state);
+    task
+}
+
+/// Forget the session the server ended and offer to connect again.
+fn server_closed(state: &mut Sievers, reason: &str) {
+    state.connected = false;
+    state.server_scripts.clear();
+    state.selected_script = None;
+    state.capabilities = None;
+    state.security = None;
+    state.status = format!("Disconnected by the server: {reason}");
+    state.confirm = Some(ConfirmState {
+        title: "Connection closed".to_string(),
+        body: format!("The server ended the session: {reason}"),
+        confirm_label: "Reconnect".to_string(),
+        action: Message::Reconnect,
+    });
+}
+
+/// Show `tab`, remembering it for the next start.
+fn set_tab(state: &mut Sievers, tab: Tab) {
+    state.active_tab = tab;
+    if state.settings.last_tab != tab {
+        state.settings.last_tab = tab;
+        settings_store::save_settings(&state.settings);
+    }
+}
+
+/// The visual editor when every rule of a script can be edited there,
+/// the raw text otherwise.
+fn tab_for_rules(rules: &[SieveRule]) -> Tab {
+    if !rules.is_empty() && rules.iter().all(|r| r.raw_block.is_none()) {
+        Tab::Visual
+    } else {
+        Tab::Raw
+    }
+}
+
+/// Reload the notes editor when the selected rule's comment no longer matches
+/// it (another rule was selected, or the raw text was edited).
+fn refresh_notes_editor(state: &mut Sievers) {
+    let comment = state
+        .selected_rule
+        .and_then(|i| state.rules.get(i))
+        .and_then(|r| r.comment.clone())
+        .unwrap_or_default();
+    if editor_notes(&state.notes_editor).unwrap_or_default() != comment {
+        state.notes_editor = text_editor::Content::with_text(&comment);
+    }
+}
+
+/// Reload the raw block editor when it no longer shows the selected rule's
+/// raw block. Trailing newlines don't count; the editor always ends in one.
+fn refresh_raw_block_editor(state: &mut Sievers) {
+    let raw = state
+        .selected_rule
+        .and_then(|i| state.rules.get(i))
+        .and_then(|r| r.raw_block.as_deref())
+        .unwrap_or_default();
+    if state.raw_block_editor.text().trim_end_matches('\n') != raw.trim_end_matches('\n') {
+        state.raw_block_editor = text_editor::Content::with_text(raw);
+    }
+}
+
+/// The editor's text as a rule comment; `None` when blank.
+fn editor_notes(content: &text_editor::Content) -> Option<String> {
+    let text = content.text();
+    let text = text.strip_suffix('\n').unwrap_or(&text);
+    if text.trim().is_empty() {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
+fn handle_message(state: &mut Sievers, message: Message) -> Task<Message> {
     match message {
+        Message::SetIndent(indent) => {
+            state.settings.indent = indent;
+            settings_store::save_settings(&state.settings);
+            // Regenerate the text unless it holds edits not yet in the rules
+            if !state.raw_dirty && !state.rules.is_empty() {
+                sync_visual_to_raw(state);
+            }
+            Task::none()
+        }
+
+        Message::SetKeepRequireOrder(keep) => {
+            state.settings.keep_require_order = keep;
+            settings_store::save_settings(&state.settings);
+            if !state.raw_dirty && !state.rules.is_empty() {
+                sync_visual_to_raw(state);
+            }
+            Task::none()
+        }
+
+        Message::FormatScript => {
+            flush_visual_edits(state);
+            let text = state.editor_content.text();
+            match converter::format_text(&text, &state.settings.emit_options()) {
+                Ok(formatted) if formatted.trim_end() == text.trim_end() => {
+                    state.status = "The script is already formatted".to_string();
+                }
+                Ok(formatted) => {
+                    push_undo(state);
+                    state.editor_content = text_editor::Content::with_text(&formatted);
+                    state.dirty = true;
+                    sync_raw_to_visual(state);
+                    state.status = "Formatted the script".to_string();
+                }
+                Err(e) => state.status = format!("Can't format the script: {e}"),
+            }
+            Task::none()
+        }
+
+        Message::SetActivateAfterUpload(activate) => {
+            state.activate_after_upload = activate;
+            Task::none()
+        }
+
         Message::ToggleTheme => {
-            state.dark_mode = !state.dark_mode;
+            state.settings.dark_mode = !state.settings.dark_mode;
+            settings_store::save_settings(&state.settings);
+            Task::none()
+        }
+
+        // Saved once the window has stayed put for a moment, not for
+        // every step of a drag
+        Message::WindowResized(size) => {
+            state.settings.window_size = (size.width, size.height);
+            state.last_geometry_change = Some(Instant::now());
+            Task::none()
+        }
+
+        Message::WindowMoved(point) => {
+            state.settings.window_pos = Some((point.x, point.y));
+            state.last_geometry_change = Some(Instant::now());
+            Task::none()
+        }
+
+        Message::ToggleHelp => {
+            state.show_help = !state.show_help;
+            Task::none()
+        }
+
+        Message::StartResize(splitter) => {
+            state.resizing = Some(splitter);
+            Task::none()
+        }
+
+        Message::ResizeTo(x) => {
+            resize_to(state, x);
+            Task::none()
+        }
+
+        Message::EndResize => {
+            // Saved once the drag is over rather than on every move
+            if state.resizing.take().is_some() {
+                settings_store::save_settings(&state.settings);
+            }
+            Task::none()
+        }
+
+        Message::Undo => {
+            if let Some(snapshot) = state.undo_stack.pop() {
+                let current = take_snapshot(state);
+                state.redo_stack.push(current);
+                restore_snapshot(state, snapshot);
+                state.dirty = true;
+                state.status = "Undone".to_string();
+            }
+            Task::none()
+        }
+
+        Message::Redo => {
+            if let Some(snapshot) = state.redo_stack.pop() {
+                let current = take_snapshot(state);
+                state.undo_stack.push(current);
+                restore_snapshot(state, snapshot);
+                state.dirty = true;
+                state.status = "Redone".to_string();
+            }
             Task::none()
         }
 
@@ -148,740 +754,4416 @@ pub fn update(state: &mut Sievers, message: Message) -> Task<Message> {
             Task::none()
         }
 
-        Message::Connect => {
-            if state.connected {
-                // Disconnect
-                let client = state.client.clone();
-                state.connected = false;
-                state.server_scripts.clear();
-                state.selected_script = None;
-                state.status = "Disconnected".to_string();
-                return Task::perform(
-                    async move {
-                        client.lock().await.disconnect().await;
-                    },
-                    |_| Message::Disconnected,
-                );
-            }
-            let profiles = profile_store::load_profiles();
-            state.connection.open(profiles);
+        Message::ProblemsMsg(ProblemsMessage::Toggle) => {
+            state.problems.visible = !state.problems.visible;
             Task::none()
         }
 
-        Message::OpenFile => {
-            state.status = "Opening file...".to_string();
-            Task::perform(open_file_dialog(), Message::FileOpened)
+        Message::ProblemsMsg(ProblemsMessage::Show(idx)) => {
+            state.problems.visible = false;
+            state.active_tab = Tab::Visual;
+            handle_message(state, Message::SelectRule(idx))
         }
 
-        Message::SaveFile => {
-            if state.active_tab == Tab::Visual && !state.syncing {
-                sync_visual_to_raw(state);
-            }
-            let text = state.editor_content.text();
-            let current = state.current_path.clone();
-            state.status = "Saving...".to_string();
-            Task::perform(save_file_dialog(current, text), Message::FileSaved)
+        Message::ProblemsMsg(ProblemsMessage::Close) => {
+            state.problems.visible = false;
+            Task::none()
         }
 
-        Message::Upload => {
+        Message::ConfirmMsg(ConfirmMessage::Confirm) => match state.confirm.take() {
+            Some(confirm) => handle_message(state, confirm.action),
+            None => Task::none(),
+        },
+
+        Message::ConfirmMsg(ConfirmMessage::Cancel) => {
+            state.confirm = None;
+            Task::none()
+        }
+
+        Message::DiffMsg(DiffMessage::Close) => {
+            state.diff.visible = false;
+            Task::none()
+        }
+
+        Message::DiffAgainstServer => {
             if !state.connected {
                 state.status = "Not connected. Connect first.".to_string();
                 return Task::none();
             }
+            let Some(name) = state.selected_script.clone() else {
+                state.status = "Select a server script to compare against.".to_string();
+                return Task::none();
+            };
             if state.active_tab == Tab::Visual && !state.syncing {
                 sync_visual_to_raw(state);
             }
-            let name = state
-                .current_script_name
-                .clone()
-                .unwrap_or_else(|| "default".to_string());
-            let content = state.editor_content.text();
             let client = state.client.clone();
-            state.status = format!("Uploading {name}...");
-            Task::perform(
+            let session = state.session.clone();
+            state.status = format!("Fetching {name} for comparison...");
+            server_task(
+                state,
                 async move {
-                    client
-                        .lock()
-                        .await
-                        .put_script(&name, &content)
-                        .await
-                        .map(|_| name)
-                        .map_err(|e| e.to_string())
+                    let content = reconnect_and_retry(client, session, |client| {
+                        let name = name.clone();
+                        async move { client.lock().await.get_script(&name).await }
+                    })
+                    .await
+                    .map_err(|e| e.to_string())?;
+                    Ok((name, content))
                 },
-                Message::ScriptUploaded,
+                Message::DiffLoaded,
             )
         }
 
-        Message::SwitchTab(tab) => {
-            if tab == state.active_tab {
+        Message::ExportAllScripts => {
+            if !state.connected {
+                state.status = "Not connected. Connect first.".to_string();
                 return Task::none();
             }
-            if tab == Tab::Visual && state.raw_dirty {
-                sync_raw_to_visual(state);
-            } else if tab == Tab::Raw && !state.syncing {
-                sync_visual_to_raw(state);
+            if state.export.is_some() {
+                return Task::none();
             }
-            state.active_tab = tab;
-            Task::none()
-        }
-
-        Message::EditorAction(action) => {
-            let is_edit = action.is_edit();
-            state.editor_content.perform(action);
-            if is_edit && !state.syncing {
-                state.raw_dirty = true;
-                state.last_raw_edit = Some(Instant::now());
+            if state.server_scripts.is_empty() {
+                state.status = "No server scripts to export.".to_string();
+                return Task::none();
             }
-            Task::none()
+            Task::perform(pick_folder_dialog("Export Scripts To"), Message::ExportDirChosen)
         }
 
-        Message::SelectRule(idx) => {
-            if idx < state.rules.len() {
-                state.selected_rule = Some(idx);
-            }
-            Task::none()
+        Message::ExportDirChosen(dir) => {
+            let Some(dir) = dir else {
+                return Task::none();
+            };
+            let manifest = script_io::plan_export(
+                state.server_scripts.iter().map(|s| (s.name.as_str(), s.active)),
+            );
+            state.export = Some(ExportState {
+                dir,
+                total: manifest.scripts.len(),
+                queue: manifest.scripts.into(),
+                exported: Vec::new(),
+                failed: 0,
+            });
+            export_next(state)
         }
 
-        Message::RuleMsg(idx, msg) => {
-            if idx < state.rules.len() {
-                handle_rule_message(state, idx, msg);
-                if !state.syncing {
-                    sync_visual_to_raw(state);
+        Message::ScriptExported(result) => {
+            if let Some(export) = &mut state.export {
+                match result {
+                    Ok(entry) => export.exported.push(entry),
+                    Err(_) => export.failed += 1,
                 }
             }
-            Task::none()
+            export_next(state)
         }
 
-        Message::AddRule => {
-            let name = format!("New rule {}", state.rules.len() + 1);
-            state.rules.push(SieveRule {
-                name,
-                ..Default::default()
-            });
-            state.selected_rule = Some(state.rules.len() - 1);
-            if !state.syncing {
-                sync_visual_to_raw(state);
+        Message::ImportAllScripts => {
+            if !state.connected {
+                state.status = "Not connected. Connect first.".to_string();
+                return Task::none();
             }
-            Task::none()
+            if state.import.is_some() {
+                return Task::none();
+            }
+            Task::perform(pick_folder_dialog("Import Scripts From"), Message::ImportDirChosen)
         }
 
-        Message::RemoveRule(idx) => {
-            if idx < state.rules.len() {
-                state.rules.remove(idx);
-                // Adjust selected_rule
-                if state.rules.is_empty() {
-                    state.selected_rule = None;
-                } else if let Some(sel) = state.selected_rule {
-                    if sel >= state.rules.len() {
-                        state.selected_rule = Some(state.rules.len() - 1);
-                    } else if sel > idx {
-                        state.selected_rule = Some(sel - 1);
-                    } else if sel == idx {
-                        // Keep same index if valid, otherwise go to last
-                        if sel >= state.rules.len() {
-                            state.selected_rule = Some(state.rules.len() - 1);
+        Message::ImportDirChosen(dir) => {
+            let Some(dir) = dir else {
+                return Task::none();
+            };
+            match script_io::discover_scripts(&dir) {
+                Ok(entries) if entries.is_empty() => {
+                    state.status = format!("No .siv or .sieve files in {}", dir.display());
+                    Task::none()
+                }
+                Ok(entries) => {
+                    // As with Upload, the active script is only replaced
+                    // unchecked once the user agrees
+                    let unchecked_active = entries.iter().find(|e| {
+                        upload_plan(&state.server_scripts, state.capabilities.as_ref(), &e.name)
+                            == UploadPlan::Confirm
+                    });
+                    match unchecked_active {
+                        Some(entry) => {
+                            state.confirm = Some(ConfirmState {
+                                title: "Import over active script".to_string(),
+                                body: format!(
+                                    "The import replaces \"{}\", the active script, and this server can't \
+                                     check it first. An invalid script may stop mail from being filtered. \
+                                     Import anyway?",
+                                    entry.name
+                                ),
+                                confirm_label: "Import".to_string(),
+                                action: Message::ImportConfirmed(entries),
+                            });
+                            Task::none()
                         }
+                        None => start_import(state, entries),
                     }
                 }
-                if !state.syncing {
-                    sync_visual_to_raw(state);
+                Err(e) => {
+                    state.status = format!("Error reading {}: {e}", dir.display());
+                    Task::none()
                 }
             }
-            Task::none()
         }
 
-        Message::DebounceCheck => {
-            if let Some(last) = state.last_raw_edit {
-                if last.elapsed().as_millis() >= RAW_SYNC_DEBOUNCE_MS as u128 && state.raw_dirty {
-                    sync_raw_to_visual(state);
+        Message::ImportConfirmed(entries) => start_import(state, entries),
+
+        Message::ScriptImported(outcome) => {
+            if let Some(import) = &mut state.import {
+                match outcome {
+                    ImportOutcome::Uploaded { name, active } => {
+                        import.uploaded += 1;
+                        if active {
+                            import.activate = Some(name);
+                        }
+                    }
+                    ImportOutcome::Invalid => import.invalid += 1,
+                    ImportOutcome::Failed(_) => import.failed += 1,
                 }
             }
-            Task::none()
+            import_next(state)
         }
 
-        Message::FileOpened(result) => {
+        Message::SyncFolder => {
+            if !state.connected {
+                state.status = "Not connected. Connect first.".to_string();
+                return Task::none();
+            }
+            match state.session.as_ref().and_then(|s| s.profile.local_dir.clone()) {
+                Some(dir) => compare_folder(state, dir),
+                None => Task::perform(pick_folder_dialog("Sync Scripts With"), Message::SyncDirChosen),
+            }
+        }
+
+        Message::SyncDirChosen(dir) => {
+            let Some(dir) = dir else {
+                return Task::none();
+            };
+            set_local_dir(state, &dir);
+            compare_folder(state, dir)
+        }
+
+        Message::SyncCompared(result) => {
             match result {
-                Ok((path, text)) => {
-                    state.editor_content = text_editor::Content::with_text(&text);
-                    state.status = format!("Opened: {}", path.display());
-                    state.current_path = Some(path);
-                    state.raw_dirty = false;
-                    state.last_raw_edit = None;
-                    sync_raw_to_visual(state);
+                Ok(entries) => {
+                    state.status = format!(
+                        "Compared {} script(s) with {}",
+                        entries.len(),
+                        state.folder_sync.dir.display()
+                    );
+                    state.folder_sync.entries = entries;
+                    state.folder_sync.visible = true;
                 }
-                Err(e) if e != "Cancelled" => {
-                    state.status = format!("Error: {e}");
-                }
-                _ => {}
+                Err(e) => state.status = format!("Sync failed: {e}"),
             }
             Task::none()
         }
 
-        Message::FileSaved(result) => {
+        Message::SyncMsg(msg) => handle_sync_message(state, msg),
+
+        Message::SyncPushConfirmed(name) => push_to_server(state, name),
+
+        Message::SyncPushed(result) => match result {
+            Ok(name) => {
+                state.status = format!("Pushed {name} to the server");
+                mark_synced(state, &name);
+                refresh_scripts(state)
+            }
+            Err(e) => {
+                state.status = format!("Push failed: {e}");
+                Task::none()
+            }
+        },
+
+        Message::SyncPulled(result) => {
             match result {
-                Ok(path) => {
-                    state.status = format!("Saved: {}", path.display());
-                    state.current_path = Some(path);
-                }
-                Err(e) if e != "Cancelled" => {
-                    state.status = format!("Error: {e}");
+                Ok(name) => {
+                    state.status = format!("Pulled {name} into the folder");
+                    mark_synced(state, &name);
                 }
-                _ => {}
+                Err(e) => state.status = format!("Pull failed: {e}"),
             }
             Task::none()
         }
 
-        // --- Connection modal ---
-        Message::ConnectionMsg(cmsg) => handle_connection_message(state, cmsg),
-
-        // --- Server operation results ---
-        Message::Connected(result) => {
-            match result {
-                Ok(scripts) => {
-                    state.connected = true;
-                    state.server_scripts = scripts;
-                    state.connection.close();
-                    state.status = "Connected".to_string();
+        Message::UploadActivated { uploaded, name, result } => {
+            match take_activation(state, &name, result) {
+                Ok(()) => {
+                    state.status = format!("{uploaded}; activated {name}");
+                    Task::none()
                 }
                 Err(e) => {
-                    state.status = format!("Connection failed: {e}");
+                    state.status = format!("{uploaded}; activating failed: {e}");
+                    refresh_scripts(state)
                 }
             }
-            Task::none()
         }
 
-        Message::Disconnected => Task::none(),
+        Message::ImportActivated { summary, name, result } => match take_activation(state, &name, result) {
+            Ok(()) => {
+                state.status = format!("{summary}; activated {name}");
+                Task::none()
+            }
+            Err(e) => {
+                state.status = format!("{summary}; activating failed: {e}");
+                refresh_scripts(state)
+            }
+        },
 
-        Message::ScriptsLoaded(result) => {
+        Message::DiffLoaded(result) => {
             match result {
-                Ok(scripts) => {
-                    state.server_scripts = scripts;
+                Ok((name, server_text)) => {
+                    flush_visual_edits(state);
+                    state.diff = DiffState {
+                        visible: true,
+                        changes: diff::diff_lines(&server_text, &state.editor_content.text()),
+                        script_name: name,
+                    };
+                    state.status = "Ready".to_string();
                 }
                 Err(e) => {
-                    state.status = format!("Error listing scripts: {e}");
+                    state.status = format!("Error fetching script: {e}");
                 }
             }
             Task::none()
         }
 
-        Message::ScriptDownloaded(result) => {
-            match result {
-                Ok((name, content)) => {
-                    state.current_script_name = Some(name.clone());
-                    state.editor_content = text_editor::Content::with_text(&content);
-                    state.status = format!("Downloaded: {name}");
-                    state.raw_dirty = false;
-                    state.last_raw_edit = None;
-                    sync_raw_to_visual(state);
-                }
-                Err(e) => {
-                    state.status = format!("Error downloading: {e}");
-                }
+        Message::CloseRequested(id) => {
+            save_geometry(state);
+            if state.dirty {
+                state.pending_close = Some(id);
+                Task::none()
+            } else {
+                iced::window::close(id)
             }
+        }
+
+        Message::CloseMsg(CloseMessage::Discard) => match state.pending_close.take() {
+            Some(id) => iced::window::close(id),
+            None => Task::none(),
+        },
+
+        Message::CloseMsg(CloseMessage::Cancel) => {
+            state.pending_close = None;
             Task::none()
         }
 
-        Message::ScriptUploaded(result) => {
-            match result {
-                Ok(name) => {
-                    state.status = format!("Uploaded: {name}");
-                    return refresh_scripts(state);
-                }
-                Err(e) => {
-                    state.status = format!("Upload error: {e}");
-                }
+        Message::Connect => {
+            if state.connected {
+                // Disconnect
+                let client = state.client.clone();
+                state.connected = false;
+                state.server_scripts.clear();
+                state.selected_script = None;
+                state.capabilities = None;
+                state.security = None;
+                state.session = None;
+                state.status = "Disconnected".to_string();
+                return Task::perform(
+                    async move {
+                        client.lock().await.disconnect().await;
+                    },
+                    |_| Message::Disconnected,
+                );
             }
+            let profiles = profile_store::load_profiles();
+            state.connection.open(profiles);
             Task::none()
         }
 
-        Message::ScriptDeleted(result) => {
-            match result {
-                Ok(name) => {
-                    state.status = format!("Deleted: {name}");
-                    if state.selected_script.as_deref() == Some(&name) {
-                        state.selected_script = None;
-                    }
-                    return refresh_scripts(state);
-                }
-                Err(e) => {
-                    state.status = format!("Delete error: {e}");
-                }
+        Message::ShowSwitchUser => {
+            let profiles = profile_store::load_profiles();
+            state.connection.open(profiles);
+            state.connection.switching = true;
+            Task::none()
+        }
+
+        Message::SwitchUserConfirmed => start_connect(state, true),
+
+        Message::NewScript => {
+            if state.dirty {
+                state.confirm = Some(ConfirmState {
+                    title: "New script".to_string(),
+                    body: "The editor has unsaved changes. Discard them and start a new script?".to_string(),
+                    confirm_label: "Discard".to_string(),
+                    action: Message::NewScriptConfirmed,
+                });
+            } else {
+                start_new_script(state);
             }
             Task::none()
         }
 
-        Message::ScriptActivated(result) => {
-            match result {
-                Ok(name) => {
-                    state.status = format!("Activated: {name}");
-                    return refresh_scripts(state);
+        Message::NewScriptConfirmed => {
+            start_new_script(state);
+            Task::none()
+        }
+
+        Message::OpenFile => {
+            state.status = "Opening file...".to_string();
+            Task::perform(open_file_dialog(), Message::FileOpened)
+        }
+
+        Message::OpenRecent(path) => match script_io::load_script(&path) {
+            Ok(text) => handle_message(state, Message::FileOpened(Ok((path, text)))),
+            Err(e) => {
+                if e.kind() == std::io::ErrorKind::NotFound {
+                    state.settings.remove_recent(&path);
+                    settings_store::save_settings(&state.settings);
+                    state.status = format!("Error: {} no longer exists", path.display());
+                } else {
+                    state.status = format!("Error: {e}");
                 }
-                Err(e) => {
-                    state.status = format!("Activate error: {e}");
+                Task::none()
+            }
+        },
+
+        Message::SaveFile => {
+            if state.active_tab == Tab::Visual && !state.syncing {
+                sync_visual_to_raw(state);
+            }
+            let text = state.editor_content.text();
+            let current = state.current_path.clone();
+            state.status = "Saving...".to_string();
+            Task::perform(save_file_dialog(current, text), Message::FileSaved)
+        }
+
+        Message::Upload => {
+            if !state.connected {
+                state.status = "Not connected. Connect first.".to_string();
+                return Task::none();
+            }
+            if state.active_tab == Tab::Visual && !state.syncing {
+                sync_visual_to_raw(state);
+            }
+            if state.raw_dirty {
+                sync_raw_to_visual(state);
+            }
+            if !state.parse_ok {
+                state.status = PARSE_ERRORS_WARNING.to_string();
+                return Task::none();
+            }
+            if let Some((count, limit)) = redirect_usage(state).filter(|_| redirects_over_limit(state)) {
+                state.status = format!("Not uploaded: {count} redirects, the server allows {limit}");
+                return Task::none();
+            }
+            let name = upload_name(state);
+            let content = state.editor_content.text();
+            match upload_plan(&state.server_scripts, state.capabilities.as_ref(), &name) {
+                UploadPlan::Direct => start_upload(state, name, content),
+                UploadPlan::CheckFirst => {
+                    // A broken active script stops mail delivery; have the
+                    // server check it before it replaces the live one
+                    let client = state.client.clone();
+                    let session = state.session.clone();
+                    state.status = format!("Validating before uploading to ACTIVE script {name}...");
+                    server_task(
+                        state,
+                        async move {
+                            reconnect_and_retry(client, session, |client| {
+                                let content = content.clone();
+                                async move { client.lock().await.check_script(&content).await }
+                            })
+                            .await
+                            .map(|_| content)
+                            .map_err(|e| match e {
+                                managesieve::Error::Server(msg) => managesieve::response_text(&msg),
+                                other => other.to_string(),
+                            })
+                        },
+                        Message::ActiveUploadChecked,
+                    )
+                }
+                UploadPlan::Confirm => {
+                    state.confirm = Some(ConfirmState {
+                        title: "Upload to active script".to_string(),
+                        body: format!(
+                            "\"{name}\" is the active script and this server can't check it first. \
+                             An invalid script may stop mail from being filtered. Upload anyway?"
+                        ),
+                        confirm_label: "Upload".to_string(),
+                        action: Message::UploadConfirmed,
+                    });
+                    Task::none()
                 }
             }
-            Task::none()
         }
 
-        // --- Script list ---
-        Message::ScriptListMsg(msg) => handle_script_list_message(state, msg),
-    }
-}
+        Message::UploadConfirmed => {
+            let name = upload_name(state);
+            let content = state.editor_content.text();
+            start_upload(state, name, content)
+        }
 
-fn handle_connection_message(state: &mut Sievers, msg: ConnectionMessage) -> Task<Message> {
-    match msg {
-        ConnectionMessage::SelectProfile(name) => {
-            if let Some(idx) = state.connection.profiles.iter().position(|p| p.name == name) {
-                state.connection.select(idx);
+        Message::ActiveUploadChecked(result) => match result {
+            Ok(content) => {
+                let name = upload_name(state);
+                let task = start_upload(state, name.clone(), content);
+                state.status = format!("Validated, uploading to ACTIVE script {name}...");
+                task
             }
-            Task::none()
+            Err(e) => {
+                state.status = format!("Not uploaded, the server rejected the script: {}", first_error_line(&e));
+                Task::none()
+            }
+        },
+
+        Message::Validate => {
+            if state.active_tab == Tab::Visual && !state.syncing {
+                sync_visual_to_raw(state);
+            }
+            let content = state.editor_content.text();
+            if !state.connected {
+                // No server to ask; the local parser is the best we have
+                state.status = match parser::parse(&content) {
+                    Ok(_) => "Script is valid (checked locally)".to_string(),
+                    Err(e) => format!("Invalid script: {e}"),
+                };
+                return Task::none();
+            }
+            let client = state.client.clone();
+            let session = state.session.clone();
+            state.status = "Validating on server...".to_string();
+            server_task(
+                state,
+                async move {
+                    reconnect_and_retry(client, session, |client| {
+                        let content = content.clone();
+                        async move { client.lock().await.check_script(&content).await }
+                    })
+                    .await
+                    .map_err(|e| match e {
+                        managesieve::Error::Server(msg) => managesieve::response_text(&msg),
+                        other => other.to_string(),
+                    })
+                },
+                Message::Validated,
+            )
         }
-        ConnectionMessage::NewProfile => {
-            state
-                .connection
-                .profiles
-                .push(ConnectionProfile {
-                    name: "New Server".to_string(),
-                    ..Default::default()
-                });
-            let idx = state.connection.profiles.len() - 1;
-            state.connection.select(idx);
-            Task::none()
+
+        Message::Shortcut(shortcut) => match shortcut_message(state, shortcut) {
+            Some(msg) => handle_message(state, msg),
+            None => Task::none(),
+        },
+
+        Message::StepRule(step) => {
+            if state.active_tab != Tab::Visual {
+                return Task::none();
+            }
+            // Rules hidden by the sidebar search are skipped
+            let shown: Vec<usize> = (0..state.rules.len())
+                .filter(|&i| ui::visual_editor::rule_matches(&state.rules[i], &state.rule_filter))
+                .collect();
+            let Some(idx) = step_selection(&shown, state.selected_rule, step) else {
+                return Task::none();
+            };
+            if state.selected_rule == Some(idx) {
+                return Task::none();
+            }
+            let _ = handle_message(state, Message::SelectRule(idx));
+            // Cards are about the same height, so the card's place in the
+            // list is close enough to its place in the scrollable
+            let position = shown.iter().position(|&i| i == idx).unwrap_or(0);
+            let y = position as f32 / shown.len().saturating_sub(1).max(1) as f32;
+            scrollable::snap_to(
+                ui::visual_editor::sidebar_scroll_id(),
+                scrollable::RelativeOffset { x: 0.0, y },
+            )
         }
-        ConnectionMessage::DeleteProfile => {
-            if let Some(idx) = state.connection.selected_index {
-                state.connection.profiles.remove(idx);
-                profile_store::save_profiles(&state.connection.profiles);
-                if !state.connection.profiles.is_empty() {
-                    state.connection.select(0);
-                }
+
+        Message::FindMsg(msg) => handle_find_message(state, msg),
+
+        Message::SwitchTab(tab) => {
+            if tab == state.active_tab {
+                return Task::none();
+            }
+            if tab == Tab::Visual && state.raw_dirty {
+                sync_raw_to_visual(state);
+            } else if tab == Tab::Raw && !state.syncing {
+                sync_visual_to_raw(state);
             }
+            set_tab(state, tab);
             Task::none()
         }
-        ConnectionMessage::SetName(s) => {
-            state.connection.name = s;
-            Task::none()
+
+        Message::EditorAction(action) => {
+            let is_edit = action.is_edit();
+            // One undo step per burst of typing, up to the next sync
+            if is_edit && !state.raw_dirty && !state.syncing {
+                push_undo(state);
+            }
+            state.editor_content.perform(action);
+            if is_edit && !state.syncing {
+                state.raw_dirty = true;
+                state.last_raw_edit = Some(Instant::now());
+                state.dirty = true;
+            }
+            scroll_to_cursor(state)
         }
-        ConnectionMessage::SetHost(s) => {
-            state.connection.host = s;
+
+        Message::RawEditorScrolled(viewport) => {
+            state.raw_viewport = Some(viewport);
             Task::none()
         }
-        ConnectionMessage::SetPort(s) => {
-            state.connection.port = s;
+
+        Message::SelectRule(idx) => {
+            if idx < state.rules.len() {
+                if state.selected_rule != Some(idx) {
+                    state.collapsed_branches.clear();
+                }
+                state.selected_rule = Some(idx);
+            }
             Task::none()
         }
-        ConnectionMessage::SetUsername(s) => {
-            state.connection.username = s;
+
+        Message::RuleMsg(idx, msg) => {
+            if idx < state.rules.len() {
+                let mutates = rule_message_mutates(&msg);
+                // One undo step per burst of edits, up to the next sync
+                if mutates && !state.visual_dirty {
+                    push_undo(state);
+                }
+                handle_rule_message(state, idx, msg);
+                // Re-emitting the script on every keystroke is slow for big
+                // scripts; the text catches up once typing pauses
+                if mutates && !state.syncing {
+                    state.visual_dirty = true;
+                    state.last_visual_edit = Some(Instant::now());
+                    state.dirty = true;
+                }
+            }
             Task::none()
         }
-        ConnectionMessage::SetPassword(s) => {
-            state.connection.password = s;
+
+        Message::AddRule => {
+            let name = format!("New rule {}", state.rules.len() + 1);
+            append_rule(
+                state,
+                SieveRule {
+                    name,
+                    ..Default::default()
+                },
+            );
             Task::none()
         }
-        ConnectionMessage::ToggleStartTls(b) => {
-            state.connection.use_starttls = b;
+
+        Message::RenameDuplicates => {
+            push_undo(state);
+            ui::visual_editor::rename_duplicates(&mut state.rules);
+            if !state.syncing {
+                sync_visual_to_raw(state);
+            }
+            state.status = "Renamed filters with duplicate names".to_string();
             Task::none()
         }
-        ConnectionMessage::Connect => {
-            if state.connection.host.is_empty()
-                || state.connection.username.is_empty()
-                || state.connection.password.is_empty()
-            {
-                state.status = "Host, username, and password are required.".to_string();
-                return Task::none();
-            }
 
-            let profile = state.connection.to_profile();
-            let password = state.connection.password.clone();
+        Message::CopyRule(idx) => match state.rules.get(idx) {
+            Some(rule) => {
+                let text = converter::rule_to_text(rule, &state.settings.emit_options());
+                state.status = format!("Copied \"{}\" to the clipboard", rule.name);
+                iced::clipboard::write(text)
+            }
+            None => Task::none(),
+        },
 
-            // Save profile
-            if let Some(idx) = state.connection.selected_index {
-                if idx < state.connection.profiles.len() {
-                    state.connection.profiles[idx] = profile.clone();
+        Message::DuplicateRule(idx) => {
+            if let Some(rule) = state.rules.get(idx) {
+                let mut copy = rule.clone();
+                copy.name = (2..)
+                    .map(|n| format!("{} ({n})", rule.name))
+                    .find(|name| state.rules.iter().all(|r| &r.name != name))
+                    .expect("a free suffix");
+                push_undo(state);
+                state.rules.insert(idx + 1, copy);
+                state.selected_rule = Some(idx + 1);
+                if !state.syncing {
+                    sync_visual_to_raw(state);
                 }
-            } else {
-                state.connection.profiles.push(profile.clone());
             }
-            profile_store::save_profiles(&state.connection.profiles);
+            Task::none()
+        }
 
-            state.status = format!("Connecting to {}...", profile.host);
-            let client = state.client.clone();
+        Message::CopyScript => {
+            flush_visual_edits(state);
+            state.status = "Copied the script to the clipboard".to_string();
+            iced::clipboard::write(state.editor_content.text())
+        }
 
-            Task::perform(
-                async move {
-                    let mut client = client.lock().await;
-                    client.connect(&profile, &password).await.map_err(|e| e.to_string())?;
-                    client.list_scripts().await.map_err(|e| e.to_string())
-                },
-                Message::Connected,
-            )
+        Message::PasteRule => iced::clipboard::read().map(Message::RulePasted),
+
+        Message::RulePasted(text) => {
+            match text.as_deref().map(converter::rule_from_text) {
+                Some(Ok(rule)) => {
+                    state.status = format!("Pasted \"{}\"", rule.name);
+                    append_rule(state, rule);
+                }
+                Some(Err(e)) => state.status = format!("Can't paste a rule: {e}"),
+                None => state.status = "The clipboard holds no text".to_string(),
+            }
+            Task::none()
         }
-        ConnectionMessage::Cancel => {
-            state.connection.close();
+
+        Message::ShowTemplates => {
+            state.templates = TemplateState {
+                visible: true,
+                selected: None,
+            };
             Task::none()
         }
-    }
-}
 
-fn handle_script_list_message(state: &mut Sievers, msg: ScriptListMessage) -> Task<Message> {
-    match msg {
-        ScriptListMessage::SelectScript(name) => {
-            state.selected_script = Some(name.clone());
-            state.current_script_name = Some(name.clone());
-            let client = state.client.clone();
-            state.status = format!("Downloading {name}...");
-            Task::perform(
-                async move {
-                    let mut client = client.lock().await;
-                    let content = client.get_script(&name).await.map_err(|e| e.to_string())?;
-                    Ok((name, content))
-                },
-                Message::ScriptDownloaded,
-            )
+        Message::TemplateMsg(msg) => {
+            match msg {
+                TemplateMessage::Select(template) => state.templates.selected = Some(template),
+                TemplateMessage::Add => {
+                    state.templates.visible = false;
+                    if let Some(template) = state.templates.selected.take() {
+                        append_rule(state, template.build());
+                    }
+                }
+                TemplateMessage::Cancel => state.templates.visible = false,
+            }
+            Task::none()
         }
-        ScriptListMessage::ActivateScript(name) => {
-            let client = state.client.clone();
-            state.status = format!("Activating {name}...");
-            Task::perform(
-                async move {
-                    client
-                        .lock()
-                        .await
-                        .set_active(&name)
-                        .await
-                        .map(|_| name)
-                        .map_err(|e| e.to_string())
-                },
-                Message::ScriptActivated,
-            )
+
+        Message::SetRuleFilter(query) => {
+            state.rule_filter = query;
+            Task::none()
         }
-        ScriptListMessage::DeactivateScripts => {
-            let client = state.client.clone();
-            state.status = "Deactivating all scripts...".to_string();
-            Task::perform(
-                async move {
-                    client
-                        .lock()
-                        .await
-                        .set_active("")
-                        .await
-                        .map(|_| String::new())
-                        .map_err(|e| e.to_string())
-                },
-                Message::ScriptActivated,
-            )
+
+        Message::MoveRuleUp(idx) => {
+            if idx > 0 && idx < state.rules.len() {
+                move_rule(state, idx, idx - 1);
+            }
+            Task::none()
         }
-        ScriptListMessage::DeleteScript(name) => {
-            let client = state.client.clone();
-            state.status = format!("Deleting {name}...");
-            Task::perform(
-                async move {
-                    client
-                        .lock()
-                        .await
-                        .delete_script(&name)
-                        .await
-                        .map(|_| name)
-                        .map_err(|e| e.to_string())
-                },
-                Message::ScriptDeleted,
-            )
+
+        Message::MoveRuleDown(idx) => {
+            if idx + 1 < state.rules.len() {
+                move_rule(state, idx, idx + 1);
+            }
+            Task::none()
         }
-    }
-}
 
-fn refresh_scripts(state: &mut Sievers) -> Task<Message> {
-    let client = state.client.clone();
-    Task::perform(
-        async move {
-            client
-                .lock()
-                .await
-                .list_scripts()
-                .await
-                .map_err(|e| e.to_string())
-        },
-        Message::ScriptsLoaded,
-    )
-}
+        Message::ShowContextMenu(target) => {
+            toggle_context_menu(state, target);
+            Task::none()
+        }
 
-fn handle_rule_message(state: &mut Sievers, idx: usize, msg: RuleMessage) {
-    let rule = &mut state.rules[idx];
-    match msg {
-        RuleMessage::SetName(name) => rule.name = name,
-        RuleMessage::SetEnabled(enabled) => rule.enabled = enabled,
-        RuleMessage::SetLogic(opt) => rule.logic = opt.0,
-        RuleMessage::RemoveRule => {
-            state.rules.remove(idx);
+        Message::CloseContextMenu => Task::none(),
+
+        Message::ConfirmRemoveRule(idx) => {
+            if let Some(rule) = state.rules.get(idx) {
+                let name = if rule.name.is_empty() { "this filter" } else { &rule.name };
+                state.confirm = Some(ConfirmState {
+                    title: "Remove filter".to_string(),
+                    body: format!("Remove \"{name}\" from the script?"),
+                    confirm_label: "Remove".to_string(),
+                    action: Message::RemoveRule(idx),
+                });
+            }
+            Task::none()
         }
-        RuleMessage::AddCondition => {
-            rule.conditions.push(Condition::default());
+
+        Message::RemoveRule(idx) => {
+            if idx < state.rules.len() {
+                push_undo(state);
+                state.rules.remove(idx);
+                // Adjust selected_rule
+                if state.rules.is_empty() {
+                    state.selected_rule = None;
+                } else if let Some(sel) = state.selected_rule {
+                    if sel >= state.rules.len() {
+                        state.selected_rule = Some(state.rules.len() - 1);
+                    } else if sel > idx {
+                        state.selected_rule = Some(sel - 1);
+                    } else if sel == idx {
+                        // Keep same index if valid, otherwise go to last
+                        if sel >= state.rules.len() {
+                            state.selected_rule = Some(state.rules.len() - 1);
+                        }
+                    }
+                }
+                if !state.syncing {
+                    sync_visual_to_raw(state);
+                }
+            }
+            Task::none()
         }
-        RuleMessage::AddAction => {
-            rule.actions.push(Action::default());
+
+        Message::BusyTick => {
+            state.busy_frame = state.busy_frame.wrapping_add(1);
+            Task::none()
         }
-        RuleMessage::ConditionMsg(ci, cmsg) => {
-            if ci < rule.conditions.len() {
-                handle_condition_message(&mut rule.conditions, ci, cmsg);
+
+        Message::DebounceCheck => {
+            let now = Instant::now();
+            if state.raw_dirty && debounce_elapsed(state.last_raw_edit, now) {
+                sync_raw_to_visual(state);
             }
+            if state.visual_dirty && debounce_elapsed(state.last_visual_edit, now) {
+                sync_visual_to_raw(state);
+            }
+            if debounce_elapsed(state.last_geometry_change, now) {
+                save_geometry(state);
+            }
+            Task::none()
         }
-        RuleMessage::ActionMsg(ai, amsg) => {
-            if ai < rule.actions.len() {
-                handle_action_message(&mut rule.actions, ai, amsg);
+
+        Message::FileOpened(result) => {
+            match result {
+                Ok((path, text)) => {
+                    push_undo(state);
+                    state.editor_content = text_editor::Content::with_text(&text);
+                    state.status = format!("Opened: {}", path.display());
+                    state.settings.add_recent(path.clone());
+                    settings_store::save_settings(&state.settings);
+                    state.current_path = Some(path);
+                    state.raw_dirty = false;
+                    state.last_raw_edit = None;
+                    sync_raw_to_visual(state);
+                    state.dirty = false;
+                }
+                Err(e) if e != "Cancelled" => {
+                    state.status = format!("Error: {e}");
+                }
+                _ => {}
+            }
+            Task::none()
+        }
+
+        Message::FileSaved(result) => {
+            match result {
+                Ok(path) => {
+                    state.status = format!("Saved: {}", path.display());
+                    state.dirty = false;
+                    state.settings.add_recent(path.clone());
+                    settings_store::save_settings(&state.settings);
+                    state.current_path = Some(path);
+                }
+                Err(e) if e != "Cancelled" => {
+                    state.status = format!("Error: {e}");
+                }
+                _ => {}
+            }
+            Task::none()
+        }
+
+        // --- Connection modal ---
+        Message::ConnectionMsg(cmsg) => handle_connection_message(state, cmsg),
+
+        // --- Server operation results ---
+        Message::Connected(result) => {
+            match result {
+                Ok((capabilities, security, scripts)) => {
+                    state.connected = true;
+                    state.capabilities = Some(capabilities);
+                    state.status = connected_status(security.as_ref());
+                    state.security = security;
+                    state.connection.close();
+                    let default = state
+                        .session
+                        .as_ref()
+                        .and_then(|s| s.profile.default_script.as_deref());
+                    let open = script_to_open(&scripts, default);
+                    state.server_scripts = scripts;
+                    if let Some(name) = open {
+                        return open_script_after_connect(state, name);
+                    }
+                }
+                Err(e) => {
+                    // A failed user switch leaves the client disconnected too
+                    state.connected = false;
+                    state.capabilities = None;
+                    state.security = None;
+                    state.session = None;
+                    state.status = format!("Connection failed: {e}");
+                }
+            }
+            Task::none()
+        }
+
+        Message::ConnectionTested(status) => {
+            state.connection.test_status = Some(status);
+            Task::none()
+        }
+        Message::CertificateUntrusted(fingerprint) => {
+            state.session = None;
+            let host = state.connection.to_profile().display_host();
+            // A pinned certificate that no longer matches may mean someone
+            // is in the middle, not just a first visit
+            let (status, title, body) = match state.connection.tls_fingerprint.as_deref() {
+                Some(pinned) if pinned != fingerprint => (
+                    "Connection failed: the server's certificate has changed",
+                    "Certificate changed",
+                    format!(
+                        "WARNING: {host} presented a different certificate from the one you \
+                         trusted. It may have been renewed, or someone may be intercepting the \
+                         connection. Only trust the new one if you know why it changed.\n\n\
+                         Trusted: {pinned}\nPresented: {fingerprint}"
+                    ),
+                ),
+                _ => (
+                    "Connection failed: the server's certificate is not trusted",
+                    "Untrusted certificate",
+                    format!(
+                        "{host} presented a certificate that isn't signed by a trusted authority. \
+                         If it is your server's self-signed certificate, compare its SHA-256 \
+                         fingerprint before trusting it:\n\n{fingerprint}"
+                    ),
+                ),
+            };
+            state.status = status.to_string();
+            state.confirm = Some(ConfirmState {
+                title: title.to_string(),
+                body,
+                confirm_label: "Trust this certificate".to_string(),
+                action: Message::TrustCertificate(fingerprint),
+            });
+            Task::none()
+        }
+
+        Message::TrustCertificate(fingerprint) => {
+            state.connection.tls_fingerprint = Some(fingerprint);
+            // Connecting saves the profile, pin included
+            handle_connection_message(state, ConnectionMessage::Connect)
+        }
+
+        Message::Disconnected => Task::none(),
+
+        // The modal still holds the profile last used; its password was
+        // wiped when it closed, so it comes back from the session
+        Message::Reconnect => {
+            if let Some(session) = &state.session {
+                state.connection.password = session.password.to_string();
+            }
+            handle_connection_message(state, ConnectionMessage::Connect)
+        }
+
+        Message::ScriptsLoaded(result) => {
+            match result {
+                Ok(scripts) => {
+                    state.server_scripts = scripts;
+                }
+                Err(e) => {
+                    state.status = format!("Error listing scripts: {e}");
+                }
+            }
+            Task::none()
+        }
+
+        Message::ScriptDownloaded(result) => {
+            match result {
+                Ok((name, content)) => {
+                    state.current_script_name = Some(name.clone());
+                    push_undo(state);
+                    state.editor_content = text_editor::Content::with_text(&content);
+                    state.status = format!("Downloaded: {name}");
+                    state.dirty = false;
+                    state.raw_dirty = false;
+                    state.last_raw_edit = None;
+                    sync_raw_to_visual(state);
+                    // Pick up where a script can be edited
+                    set_tab(state, tab_for_rules(&state.rules));
+                    state.selected_rule = (!state.rules.is_empty()).then_some(0);
+                    state.collapsed_branches.clear();
+                    if let Some(warning) = missing_extensions_warning(state) {
+                        state.status = format!("{} {warning}", state.status);
+                    }
+                }
+                Err(e) => {
+                    state.status = format!("Error downloading: {e}");
+                }
+            }
+            Task::none()
+        }
+
+        Message::ScriptUploaded(result) => {
+            match result {
+                Ok((name, backup)) => {
+                    state.status = match backup {
+                        Some(path) => format!(
+                            "Uploaded: {name} (previous version saved to {})",
+                            path.display()
+                        ),
+                        None => format!("Uploaded: {name}"),
+                    };
+                    state.dirty = false;
+                    let active = state.server_scripts.iter().any(|s| s.active && s.name == name);
+                    if state.activate_after_upload && !active {
+                        let uploaded = std::mem::take(&mut state.status);
+                        state.status = format!("{uploaded}; activating...");
+                        return activate_uploaded(state, uploaded, name);
+                    }
+                    return refresh_scripts(state);
+                }
+                Err(e) => {
+                    state.status = format!("Upload error: {e}");
+                }
+            }
+            Task::none()
+        }
+
+        Message::ScriptDeleted(result) => {
+            match result {
+                Ok(name) => {
+                    state.status = format!("Deleted: {name}");
+                    if state.selected_script.as_deref() == Some(&name) {
+                        state.selected_script = None;
+                    }
+                    return refresh_scripts(state);
+                }
+                Err(e) => {
+                    state.status = format!("Delete error: {e}");
+                }
             }
+            Task::none()
         }
+
+        Message::ScriptRenamed(result) => {
+            match result {
+                Ok((old, new)) => {
+                    state.status = format!("Renamed: {old} → {new}");
+                    if state.selected_script.as_deref() == Some(&old) {
+                        state.selected_script = Some(new.clone());
+                    }
+                    if state.current_script_name.as_deref() == Some(&old) {
+                        state.current_script_name = Some(new);
+                    }
+                    return refresh_scripts(state);
+                }
+                Err(e) => {
+                    state.status = format!("Rename error: {e}");
+                }
+            }
+            Task::none()
+        }
+
+        Message::ScriptActivated { name, result } => {
+            state.status = match (take_activation(state, &name, result), name.is_empty()) {
+                (Ok(()), true) => "Deactivated all scripts".to_string(),
+                (Ok(()), false) => format!("Activated: {name}"),
+                (Err(e), true) => format!("Deactivate error: {e}"),
+                (Err(e), false) => format!("Activate error: {e}"),
+            };
+            Task::none()
+        }
+
+        Message::Validated(result) => {
+            state.status = match result {
+                Ok(()) => "Script is valid".to_string(),
+                Err(e) => format!("Invalid script: {}", first_error_line(&e)),
+            };
+            Task::none()
+        }
+
+        // --- Script list ---
+        Message::ScriptListMsg(msg) => handle_script_list_message(state, msg),
+    }
+}
+
+fn handle_connection_message(state: &mut Sievers, msg: ConnectionMessage) -> Task<Message> {
+    // A test result no longer describes the form once it changes
+    if !matches!(msg, ConnectionMessage::Test) {
+        state.connection.test_status = None;
+    }
+    match msg {
+        ConnectionMessage::SelectProfile(name) => {
+            if let Some(idx) = state.connection.profiles.iter().position(|p| p.name == name) {
+                state.connection.select(idx);
+            }
+            Task::none()
+        }
+        ConnectionMessage::NewProfile => {
+            let name = profile::unique_name(&state.connection.profiles, "New Server", None);
+            state
+                .connection
+                .profiles
+                .push(ConnectionProfile {
+                    name,
+                    ..Default::default()
+                });
+            let idx = state.connection.profiles.len() - 1;
+            state.connection.select(idx);
+            Task::none()
+        }
+        ConnectionMessage::DeleteProfile => {
+            if let Some(idx) = state.connection.selected_index {
+                let removed = state.connection.profiles.remove(idx);
+                secret_store::forget_password(&removed);
+                profile_store::save_profiles(&state.connection.profiles);
+                if state.connection.profiles.is_empty() {
+                    // Connecting again saves the form as a new profile
+                    state.connection.selected_index = None;
+                } else {
+                    state.connection.select(0);
+                }
+            }
+            Task::none()
+        }
+        ConnectionMessage::SetName(s) => {
+            state.connection.set_name(s);
+            Task::none()
+        }
+        ConnectionMessage::SetHost(s) => {
+            state.connection.host = s;
+            Task::none()
+        }
+        ConnectionMessage::SetPort(s) => {
+            state.connection.port = s;
+            Task::none()
+        }
+        ConnectionMessage::SetUsername(s) => {
+            state.connection.username = s;
+            Task::none()
+        }
+        ConnectionMessage::SetPassword(s) => {
+            state.connection.password = s;
+            Task::none()
+        }
+        ConnectionMessage::SetTlsMode(mode) => {
+            state.connection.tls_mode = mode;
+            Task::none()
+        }
+        ConnectionMessage::ToggleRememberPassword(remember) => {
+            state.connection.remember_password = remember;
+            Task::none()
+        }
+        ConnectionMessage::SetDefaultScript(name) => {
+            state.connection.default_script = name;
+            Task::none()
+        }
+        ConnectionMessage::SetLocalDir(dir) => {
+            state.connection.local_dir = dir;
+            Task::none()
+        }
+        ConnectionMessage::SetOpenOnConnect(open) => {
+            state.settings.open_on_connect = open;
+            settings_store::save_settings(&state.settings);
+            Task::none()
+        }
+        ConnectionMessage::ForgetCertificate => {
+            state.connection.tls_fingerprint = None;
+            Task::none()
+        }
+        ConnectionMessage::Test => test_connection(state),
+        ConnectionMessage::Connect => start_connect(state, false),
+        ConnectionMessage::SwitchUser => {
+            // The editor's script belongs to the old account and is closed
+            if state.dirty {
+                state.confirm = Some(ConfirmState {
+                    title: "Switch user".to_string(),
+                    body: "The editor has unsaved changes. Discard them and log in as another user?"
+                        .to_string(),
+                    confirm_label: "Discard".to_string(),
+                    action: Message::SwitchUserConfirmed,
+                });
+                Task::none()
+            } else {
+                start_connect(state, true)
+            }
+        }
+        ConnectionMessage::Cancel => {
+            state.connection.close();
+            Task::none()
+        }
+    }
+}
+
+/// Save the profile from the connection form and log in with it: on a new
+/// connection, or as a different user on the current one.
+fn start_connect(state: &mut Sievers, switch_user: bool) -> Task<Message> {
+    if state.connection.host.trim().is_empty()
+        || state.connection.username.trim().is_empty()
+        || state.connection.password.is_empty()
+    {
+        state.status = "Host, username, and password are required.".to_string();
+        return Task::none();
+    }
+    if let Some(error) = &state.connection.name_error {
+        state.status = error.clone();
+        return Task::none();
+    }
+
+    let mut profile = state.connection.to_profile();
+    // Profiles are picked by name, so no two may share one
+    profile.name = profile::unique_name(
+        &state.connection.profiles,
+        &profile.name,
+        state.connection.selected_index,
+    );
+    state.connection.name = profile.name.clone();
+    let password = Zeroizing::new(state.connection.password.clone());
+
+    // Save profile
+    if let Some(idx) = state.connection.selected_index {
+        if idx < state.connection.profiles.len() {
+            state.connection.profiles[idx] = profile.clone();
+        }
+    } else {
+        state.connection.profiles.push(profile.clone());
+    }
+    profile_store::save_profiles(&state.connection.profiles);
+    if profile.remember_password {
+        secret_store::save_password(&profile, &password);
+    } else {
+        secret_store::forget_password(&profile);
+    }
+
+    if switch_user {
+        // Nothing of the old account's may be uploaded to the new one
+        state.server_scripts.clear();
+        start_new_script(state);
+    }
+    state.status = if switch_user {
+        format!("Logging in as {}...", profile.username)
+    } else {
+        format!("Connecting to {}...", profile.display_host())
+    };
+    state.session = Some(Session {
+        profile: profile.clone(),
+        password: password.clone(),
+    });
+    let client = state.client.clone();
+
+    let host = profile.display_host();
+    server_task(
+        state,
+        async move {
+            let mut client = client.lock().await;
+            let caps = if switch_user {
+                client.reauthenticate(&profile, &password).await?
+            } else {
+                client.connect(&profile, &password).await?
+            };
+            let security = client.security();
+            let scripts = client.list_scripts().await?;
+            Ok((caps, security, scripts))
+        },
+        move |result| match result {
+            Err(managesieve::Error::UntrustedCertificate(fingerprint)) => {
+                Message::CertificateUntrusted(fingerprint)
+            }
+            result => Message::Connected(result.map_err(|e| connect_error(&host, e))),
+        },
+    )
+}
+
+/// Run a server operation, keeping the app busy until its result message
+/// arrives.
+fn server_task<T: Send + 'static>(
+    state: &mut Sievers,
+    operation: impl std::future::Future<Output = T> + Send + 'static,
+    on_done: impl Fn(T) -> Message + Send + 'static,
+) -> Task<Message> {
+    state.busy = true;
+    Task::perform(operation, on_done)
+}
+
+/// Status once connected, saying how the connection is protected.
+fn connected_status(security: Option<&Security>) -> String {
+    match security {
+        Some(security) => format!("Connected ({security})"),
+        None => "Connected".to_string(),
+    }
+}
+
+fn connect_error(host: &str, error: managesieve::Error) -> String {
+    match error {
+        managesieve::Error::Timeout => format!("{host} did not respond in time"),
+        other => other.to_string(),
+    }
+}
+
+/// Log in with the form's settings on a separate client and log out again,
+/// leaving the current connection and the saved profiles alone.
+fn test_connection(state: &mut Sievers) -> Task<Message> {
+    let form = &state.connection;
+    if form.host.trim().is_empty() || form.username.trim().is_empty() || form.password.is_empty() {
+        state.connection.test_status = Some(TestStatus::Failed(
+            "Host, username, and password are required.".to_string(),
+        ));
+        return Task::none();
+    }
+
+    let profile = form.to_profile();
+    let password = Zeroizing::new(form.password.clone());
+    let host = profile.display_host();
+    state.connection.test_status = Some(TestStatus::Running);
+    Task::perform(
+        async move {
+            let mut client = ManageSieveClient::new();
+            let result = client.connect(&profile, &password).await;
+            client.disconnect().await;
+            result
+        },
+        move |result| Message::ConnectionTested(test_status(&host, result)),
+    )
+}
+
+fn test_status(host: &str, result: Result<Capabilities, managesieve::Error>) -> TestStatus {
+    match result {
+        Ok(caps) => TestStatus::Passed(match caps.implementation {
+            Some(implementation) => format!("Logged in to {host} ({implementation})"),
+            None => format!("Logged in to {host}"),
+        }),
+        Err(managesieve::Error::UntrustedCertificate(_)) => TestStatus::Failed(
+            "The server's certificate is not trusted; connect to review it".to_string(),
+        ),
+        Err(e) => TestStatus::Failed(connect_error(host, e)),
+    }
+}
+
+fn handle_script_list_message(state: &mut Sievers, msg: ScriptListMessage) -> Task<Message> {
+    match msg {
+        ScriptListMessage::ShowMenu(name) => {
+            toggle_context_menu(state, MenuTarget::Script(name));
+            Task::none()
+        }
+        ScriptListMessage::SelectScript(name) => {
+            state.selected_script = Some(name.clone());
+            state.current_script_name = Some(name.clone());
+            let client = state.client.clone();
+            let session = state.session.clone();
+            state.status = format!("Downloading {name}...");
+            server_task(
+                state,
+                async move {
+                    let content = reconnect_and_retry(client, session, |client| {
+                        let name = name.clone();
+                        async move { client.lock().await.get_script(&name).await }
+                    })
+                    .await
+                    .map_err(|e| e.to_string())?;
+                    Ok((name, content))
+                },
+                Message::ScriptDownloaded,
+            )
+        }
+        ScriptListMessage::ActivateScript(name) => {
+            let client = state.client.clone();
+            let session = state.session.clone();
+            state.status = format!("Activating {name}...");
+            server_task(state, set_active_and_list(client, session, name.clone()), move |result| {
+                Message::ScriptActivated {
+                    name: name.clone(),
+                    result,
+                }
+            })
+        }
+        ScriptListMessage::DeactivateScripts => {
+            let client = state.client.clone();
+            let session = state.session.clone();
+            state.status = "Deactivating all scripts...".to_string();
+            server_task(state, set_active_and_list(client, session, String::new()), |result| {
+                Message::ScriptActivated {
+                    name: String::new(),
+                    result,
+                }
+            })
+        }
+        ScriptListMessage::ConfirmDelete(name) => {
+            state.confirm = Some(ConfirmState {
+                title: "Delete script".to_string(),
+                body: format!("Delete \"{name}\" from the server? This cannot be undone."),
+                confirm_label: "Delete".to_string(),
+                action: Message::ScriptListMsg(ScriptListMessage::DeleteScript(name)),
+            });
+            Task::none()
+        }
+        ScriptListMessage::DeleteScript(name) => {
+            let client = state.client.clone();
+            let session = state.session.clone();
+            state.status = format!("Deleting {name}...");
+            server_task(
+                state,
+                async move {
+                    reconnect_and_retry(client, session, |client| {
+                        let name = name.clone();
+                        async move { client.lock().await.delete_script(&name).await }
+                    })
+                    .await
+                    .map(|_| name)
+                        .map_err(|e| e.to_string())
+                },
+                Message::ScriptDeleted,
+            )
+        }
+        ScriptListMessage::StartRename(name) => {
+            state.renaming_script = Some(RenameState {
+                new: name.clone(),
+                old: name,
+            });
+            Task::none()
+        }
+        ScriptListMessage::SetRenameText(text) => {
+            if let Some(rename) = &mut state.renaming_script {
+                rename.new = text;
+            }
+            Task::none()
+        }
+        ScriptListMessage::ExportAll => handle_message(state, Message::ExportAllScripts),
+        ScriptListMessage::ImportAll => handle_message(state, Message::ImportAllScripts),
+        ScriptListMessage::SyncFolder => handle_message(state, Message::SyncFolder),
+        ScriptListMessage::CancelRename => {
+            state.renaming_script = None;
+            Task::none()
+        }
+        ScriptListMessage::RenameScript { old, new } => {
+            let new = new.trim().to_string();
+            if new.is_empty() || new == old {
+                state.renaming_script = None;
+                return Task::none();
+            }
+            state.renaming_script = None;
+            let client = state.client.clone();
+            let session = state.session.clone();
+            state.status = format!("Renaming {old}...");
+            server_task(
+                state,
+                async move {
+                    reconnect_and_retry(client, session, |client| {
+                        let (old, new) = (old.clone(), new.clone());
+                        async move { client.lock().await.rename_script(&old, &new).await }
+                    })
+                    .await
+                    .map(|_| (old, new))
+                        .map_err(|e| e.to_string())
+                },
+                Message::ScriptRenamed,
+            )
+        }
+    }
+}
+
+/// Fetch the next queued script of an export, or finish it off with the
+/// manifest and a summary once the queue is empty.
+fn export_next(state: &mut Sievers) -> Task<Message> {
+    let Some(export) = &mut state.export else {
+        return Task::none();
+    };
+    let Some(entry) = export.queue.pop_front() else {
+        let export = state.export.take().expect("export in progress");
+        let manifest = script_io::Manifest {
+            scripts: export.exported,
+        };
+        let done = manifest.scripts.len();
+        state.status = match script_io::write_manifest(&export.dir, &manifest) {
+            Ok(()) if export.failed == 0 => {
+                format!("Exported {done} script(s) to {}", export.dir.display())
+            }
+            Ok(()) => format!(
+                "Exported {done} of {} script(s) to {}; {} failed",
+                export.total,
+                export.dir.display(),
+                export.failed
+            ),
+            Err(e) => format!("Exported {done} script(s), but writing the manifest failed: {e}"),
+        };
+        return Task::none();
+    };
+
+    state.status = format!(
+        "Exporting {} of {}: {}...",
+        export.total - export.queue.len(),
+        export.total,
+        entry.name
+    );
+    let path = export.dir.join(&entry.file);
+    let client = state.client.clone();
+    let session = state.session.clone();
+    server_task(
+        state,
+        async move {
+            let content = reconnect_and_retry(client, session, |client| {
+                let name = entry.name.clone();
+                async move { client.lock().await.get_script(&name).await }
+            })
+            .await
+            .map_err(|e| e.to_string())?;
+            script_io::save_script(&path, &content).map_err(|e| e.to_string())?;
+            Ok(entry)
+        },
+        Message::ScriptExported,
+    )
+}
+
+/// Upload the next queued file of an import. Once the queue is empty,
+/// report the summary, restore the active script and refresh the list.
+/// Upload `entries` one after the other, checking each first where the
+/// server can.
+fn start_import(state: &mut Sievers, entries: Vec<ImportEntry>) -> Task<Message> {
+    state.import = Some(ImportState {
+        queue: entries.into(),
+        check: state
+            .capabilities
+            .as_ref()
+            .is_some_and(Capabilities::supports_checkscript),
+        uploaded: 0,
+        invalid: 0,
+        failed: 0,
+        activate: None,
+    });
+    import_next(state)
+}
+
+fn import_next(state: &mut Sievers) -> Task<Message> {
+    let Some(import) = &mut state.import else {
+        return Task::none();
+    };
+    let client = state.client.clone();
+    let session = state.session.clone();
+
+    let Some(entry) = import.queue.pop_front() else {
+        let import = state.import.take().expect("import in progress");
+        let summary = import_summary(import.uploaded, import.invalid, import.failed);
+        state.status = summary.clone();
+        return match import.activate {
+            Some(name) => {
+                let activate = set_active_and_list(client, session, name.clone());
+                server_task(state, activate, move |result| Message::ImportActivated {
+                    summary: summary.clone(),
+                    name: name.clone(),
+                    result,
+                })
+            }
+            None => refresh_scripts(state),
+        };
+    };
+
+    let done = import.uploaded + import.invalid + import.failed;
+    state.status = format!(
+        "Importing {} of {}: {}...",
+        done + 1,
+        done + 1 + import.queue.len(),
+        entry.name
+    );
+    let check = import.check;
+    let exists = state.server_scripts.iter().any(|s| s.name == entry.name);
+    server_task(
+        state,
+        async move {
+            let content = match script_io::load_script(&entry.path) {
+                Ok(content) => content,
+                Err(e) => return ImportOutcome::Failed(e.to_string()),
+            };
+            if check {
+                let checked = reconnect_and_retry(client.clone(), session.clone(), |client| {
+                    let content = content.clone();
+                    async move { client.lock().await.check_script(&content).await }
+                })
+                .await;
+                match checked {
+                    Ok(()) => {}
+                    Err(managesieve::Error::Server(_)) => return ImportOutcome::Invalid,
+                    Err(e) => return ImportOutcome::Failed(e.to_string()),
+                }
+            }
+            match backup_and_put(client, session, &entry.name, &content, exists).await {
+                Ok(_) => ImportOutcome::Uploaded {
+                    name: entry.name,
+                    active: entry.active,
+                },
+                Err(e) => ImportOutcome::Failed(e),
+            }
+        },
+        Message::ScriptImported,
+    )
+}
+
+fn handle_sync_message(state: &mut Sievers, msg: SyncMessage) -> Task<Message> {
+    match msg {
+        SyncMessage::Push(name) => {
+            if upload_plan(&state.server_scripts, state.capabilities.as_ref(), &name) == UploadPlan::Confirm {
+                state.confirm = Some(ConfirmState {
+                    title: "Push to active script".to_string(),
+                    body: format!(
+                        "\"{name}\" is the active script and this server can't check it first. \
+                         An invalid script may stop mail from being filtered. Push anyway?"
+                    ),
+                    confirm_label: "Push".to_string(),
+                    action: Message::SyncPushConfirmed(name),
+                });
+                return Task::none();
+            }
+            push_to_server(state, name)
+        }
+        SyncMessage::Pull(name) => {
+            let Some(entry) = state.folder_sync.entries.iter().find(|e| e.name == name) else {
+                return Task::none();
+            };
+            let path = state.folder_sync.dir.join(&entry.file);
+            let client = state.client.clone();
+            let session = state.session.clone();
+            state.status = format!("Pulling {name}...");
+            server_task(
+                state,
+                async move {
+                    let content = reconnect_and_retry(client, session, |client| {
+                        let name = name.clone();
+                        async move { client.lock().await.get_script(&name).await }
+                    })
+                    .await
+                    .map_err(|e| e.to_string())?;
+                    script_io::save_script(&path, &content).map_err(|e| e.to_string())?;
+                    Ok(name)
+                },
+                Message::SyncPulled,
+            )
+        }
+        SyncMessage::Refresh => compare_folder(state, state.folder_sync.dir.clone()),
+        SyncMessage::ChangeFolder => {
+            Task::perform(pick_folder_dialog("Sync Scripts With"), Message::SyncDirChosen)
+        }
+        SyncMessage::Close => {
+            state.folder_sync.visible = false;
+            Task::none()
+        }
+    }
+}
+
+/// Upload the folder's copy of `name` the way Upload does: checked first
+/// where the server can, with the server copy it replaces backed up.
+fn push_to_server(state: &mut Sievers, name: String) -> Task<Message> {
+    let Some(entry) = state.folder_sync.entries.iter().find(|e| e.name == name) else {
+        return Task::none();
+    };
+    let path = state.folder_sync.dir.join(&entry.file);
+    let client = state.client.clone();
+    let session = state.session.clone();
+    let exists = state.server_scripts.iter().any(|s| s.name == name);
+    let check = state
+        .capabilities
+        .as_ref()
+        .is_some_and(Capabilities::supports_checkscript);
+    state.status = format!("Pushing {name}...");
+    server_task(
+        state,
+        async move {
+            let content = script_io::load_script(&path).map_err(|e| e.to_string())?;
+            if check {
+                reconnect_and_retry(client.clone(), session.clone(), |client| {
+                    let content = content.clone();
+                    async move { client.lock().await.check_script(&content).await }
+                })
+                .await
+                .map_err(|e| match e {
+                    managesieve::Error::Server(msg) => {
+                        format!("the server rejected it: {}", first_error_line(&managesieve::response_text(&msg)))
+                    }
+                    other => other.to_string(),
+                })?;
+            }
+            backup_and_put(client, session, &name, &content, exists).await?;
+            Ok(name)
+        },
+        Message::SyncPushed,
+    )
+}
+
+/// Read the scripts in `dir` and every script on the server, and compare
+/// them.
+fn compare_folder(state: &mut Sievers, dir: PathBuf) -> Task<Message> {
+    state.status = format!("Comparing with {}...", dir.display());
+    state.folder_sync.dir = dir.clone();
+    let client = state.client.clone();
+    let session = state.session.clone();
+    server_task(
+        state,
+        async move {
+            let local = folder_sync::load_folder(&dir)
+                .map_err(|e| format!("Error reading {}: {e}", dir.display()))?;
+            let scripts = reconnect_and_retry(client.clone(), session.clone(), |client| async move {
+                client.lock().await.list_scripts().await
+            })
+            .await
+            .map_err(|e| e.to_string())?;
+            let mut server = Vec::new();
+            for script in scripts {
+                let content = reconnect_and_retry(client.clone(), session.clone(), |client| {
+                    let name = script.name.clone();
+                    async move { client.lock().await.get_script(&name).await }
+                })
+                .await
+                .map_err(|e| e.to_string())?;
+                server.push((script.name, content));
+            }
+            let recorded = script_io::read_manifest(&dir).unwrap_or_default();
+            Ok(folder_sync::compare(&local, &server, &recorded))
+        },
+        Message::SyncCompared,
+    )
+}
+
+/// Remember `dir` as the scripts folder of the connected profile.
+fn set_local_dir(state: &mut Sievers, dir: &Path) {
+    let Some(session) = &mut state.session else {
+        return;
+    };
+    session.profile.local_dir = Some(dir.to_path_buf());
+    let name = session.profile.name.clone();
+    if let Some(profile) = state.connection.profiles.iter_mut().find(|p| p.name == name) {
+        profile.local_dir = Some(dir.to_path_buf());
+    }
+    // The form's list is only loaded while the dialog is open
+    let mut profiles = profile_store::load_profiles();
+    if let Some(profile) = profiles.iter_mut().find(|p| p.name == name) {
+        profile.local_dir = Some(dir.to_path_buf());
+        profile_store::save_profiles(&profiles);
+    }
+}
+
+/// Record a pushed or pulled script as in sync, in the dialog and in the
+/// folder's manifest.
+fn mark_synced(state: &mut Sievers, name: &str) {
+    let sync = &mut state.folder_sync;
+    if let Some(entry) = sync.entries.iter_mut().find(|e| e.name == name) {
+        entry.status = SyncStatus::Same;
+    }
+    let scripts = &state.server_scripts;
+    let manifest = folder_sync::synced_manifest(&sync.entries, |name| {
+        scripts.iter().any(|s| s.name == name && s.active)
+    });
+    if let Err(e) = script_io::write_manifest(&sync.dir, &manifest) {
+        state.status = format!("{}, but writing the manifest failed: {e}", state.status);
+    }
+}
+
+/// E.g. "7 uploaded, 1 skipped (invalid)".
+fn import_summary(uploaded: usize, invalid: usize, failed: usize) -> String {
+    let mut summary = format!("{uploaded} uploaded");
+    if invalid > 0 {
+        summary.push_str(&format!(", {invalid} skipped (invalid)"));
+    }
+    if failed > 0 {
+        summary.push_str(&format!(", {failed} failed"));
+    }
+    summary
+}
+
+/// Whether an error means the connection itself is gone (idle timeout,
+/// network blip) rather than the server refusing the command.
+fn is_connection_lost(error: &managesieve::Error) -> bool {
+    match error {
+        managesieve::Error::Io(_) => true,
+        managesieve::Error::Protocol(msg) => msg == "Connection closed",
+        _ => false,
+    }
+}
+
+/// Run a server operation; if the connection turns out to have dropped,
+/// reconnect with the session's credentials and run it once more. Any
+/// failure after that is returned as is.
+async fn reconnect_and_retry<T, F, Fut>(
+    client: Arc<Mutex<ManageSieveClient>>,
+    session: Option<Session>,
+    op: F,
+) -> Result<T, managesieve::Error>
+where
+    F: Fn(Arc<Mutex<ManageSieveClient>>) -> Fut,
+    Fut: std::future::Future<Output = Result<T, managesieve::Error>>,
+{
+    match op(client.clone()).await {
+        Err(e) if is_connection_lost(&e) => {
+            let Some(session) = session else {
+                return Err(e);
+            };
+            client.lock().await.connect(&session.profile, &session.password).await?;
+            op(client).await
+        }
+        result => result,
+    }
+}
+
+/// The script to open once connected: the profile's default when the server
+/// has it, otherwise the active script.
+fn script_to_open(scripts: &[ScriptInfo], default: Option<&str>) -> Option<String> {
+    default
+        .and_then(|name| scripts.iter().find(|s| s.name == name))
+        .or_else(|| scripts.iter().find(|s| s.active))
+        .map(|s| s.name.clone())
+}
+
+/// What connecting does about the script it would open.
+#[derive(Debug, PartialEq)]
+enum ConnectOpen {
+    Open,
+    Ask,
+    Keep,
+}
+
+/// Whether to open the server script after connecting, given the user's
+/// preference and whether the editor holds unsaved changes.
+fn connect_open(setting: OpenOnConnect, dirty: bool) -> ConnectOpen {
+    match setting {
+        OpenOnConnect::Never => ConnectOpen::Keep,
+        OpenOnConnect::Ask => ConnectOpen::Ask,
+        OpenOnConnect::Always if dirty => ConnectOpen::Ask,
+        OpenOnConnect::Always => ConnectOpen::Open,
+    }
+}
+
+/// Download `name` into the editor as the "After connecting" setting says,
+/// asking first if that would throw away unsaved edits.
+fn open_script_after_connect(state: &mut Sievers, name: String) -> Task<Message> {
+    match connect_open(state.settings.open_on_connect, state.dirty) {
+        ConnectOpen::Keep => Task::none(),
+        ConnectOpen::Ask => {
+            let body = if state.dirty {
+                format!("The editor has unsaved changes. Replace them with \"{name}\" from the server?")
+            } else {
+                format!("Open \"{name}\" from the server in the editor?")
+            };
+            state.confirm = Some(ConfirmState {
+                title: "Open server script".to_string(),
+                body,
+                confirm_label: "Open".to_string(),
+                action: Message::ScriptListMsg(ScriptListMessage::SelectScript(name)),
+            });
+            Task::none()
+        }
+        ConnectOpen::Open => handle_script_list_message(state, ScriptListMessage::SelectScript(name)),
+    }
+}
+
+/// Make a just-uploaded script the active one; `uploaded` is the upload's
+/// status line.
+fn activate_uploaded(state: &mut Sievers, uploaded: String, name: String) -> Task<Message> {
+    let client = state.client.clone();
+    let session = state.session.clone();
+    server_task(state, set_active_and_list(client, session, name.clone()), move |result| {
+        Message::UploadActivated {
+            uploaded: uploaded.clone(),
+            name: name.clone(),
+            result,
+        }
+    })
+}
+
+/// Make `name` the active script, or deactivate them all for an empty
+/// name, then read the list back: some servers answer OK to SETACTIVE
+/// and change nothing. A refusal reports the server's reason.
+async fn set_active_and_list(
+    client: Arc<Mutex<ManageSieveClient>>,
+    session: Option<Session>,
+    name: String,
+) -> Result<Vec<ScriptInfo>, String> {
+    reconnect_and_retry(client, session, |client| {
+        let name = name.clone();
+        async move {
+            let mut client = client.lock().await;
+            client.set_active(&name).await?;
+            client.list_scripts().await
+        }
+    })
+    .await
+    .map_err(|e| match e {
+        managesieve::Error::Server(msg) => match managesieve::response_text(&msg) {
+            reason if reason.is_empty() => "the server refused".to_string(),
+            reason => format!("the server refused: {reason}"),
+        },
+        other => other.to_string(),
+    })
+}
+
+/// Take in the list read back after a SETACTIVE of `name` and check that
+/// the server did what was asked.
+fn take_activation(state: &mut Sievers, name: &str, result: Result<Vec<ScriptInfo>, String>) -> Result<(), String> {
+    let scripts = result?;
+    let checked = check_active(&scripts, name);
+    state.server_scripts = scripts;
+    checked
+}
+
+/// Whether the scripts the server lists as active agree with a SETACTIVE
+/// of `expected`. Servers that allow several active scripts only need to
+/// list `expected` among them; an empty name expects none.
+fn check_active(scripts: &[ScriptInfo], expected: &str) -> Result<(), String> {
+    let active: Vec<&str> = scripts.iter().filter(|s| s.active).map(|s| s.name.as_str()).collect();
+    if expected.is_empty() {
+        return match active.as_slice() {
+            [] => Ok(()),
+            still => Err(format!("the server accepted it but still lists {} as active", still.join(", "))),
+        };
+    }
+    match active.as_slice() {
+        names if names.contains(&expected) => Ok(()),
+        [] => Err(format!("the server accepted it but lists no active script, not {expected}")),
+        others => Err(format!(
+            "the server accepted it but lists {} as active, not {expected}",
+            others.join(", ")
+        )),
+    }
+}
+
+fn refresh_scripts(state: &mut Sievers) -> Task<Message> {
+    let client = state.client.clone();
+    let session = state.session.clone();
+    server_task(
+        state,
+        async move {
+            reconnect_and_retry(client, session, |client| async move {
+                client.lock().await.list_scripts().await
+            })
+            .await
+            .map_err(|e| e.to_string())
+        },
+        Message::ScriptsLoaded,
+    )
+}
+
+/// Empty the editor for a script that isn't a file or a server script yet.
+fn start_new_script(state: &mut Sievers) {
+    push_undo(state);
+    state.editor_content = text_editor::Content::new();
+    state.current_path = None;
+    state.current_script_name = None;
+    state.selected_script = None;
+    state.selected_rule = None;
+    state.raw_dirty = false;
+    state.last_raw_edit = None;
+    sync_raw_to_visual(state);
+    state.dirty = false;
+    state.status = "New script".to_string();
+}
+
+/// Add `rule` at the end of the list and select it.
+fn append_rule(state: &mut Sievers, rule: SieveRule) {
+    push_undo(state);
+    state.rules.push(rule);
+    state.selected_rule = Some(state.rules.len() - 1);
+    if !state.syncing {
+        sync_visual_to_raw(state);
+    }
+}
+
+/// Open the context menu on `target`, or close it if it is already open there.
+fn toggle_context_menu(state: &mut Sievers, target: MenuTarget) {
+    state.context_menu = if state.context_menu.as_ref() == Some(&target) {
+        None
+    } else {
+        Some(target)
+    };
+}
+
+/// Swap the rule at `from` with its neighbour at `to`, keeping the selection
+/// on the same rule.
+fn move_rule(state: &mut Sievers, from: usize, to: usize) {
+    push_undo(state);
+    state.rules.swap(from, to);
+    state.selected_rule = match state.selected_rule {
+        Some(sel) if sel == from => Some(to),
+        Some(sel) if sel == to => Some(from),
+        other => other,
+    };
+    if !state.syncing {
+        sync_visual_to_raw(state);
+    }
+}
+
+fn handle_rule_message(state: &mut Sievers, idx: usize, msg: RuleMessage) {
+    let rule = &mut state.rules[idx];
+    match msg {
+        RuleMessage::SetName(name) => rule.name = name,
+        RuleMessage::SetEnabled(enabled) => rule.enabled = enabled,
+        RuleMessage::SetLogic(opt) => rule.logic = opt.0,
+        RuleMessage::SetNegateGroup(negate) => rule.negate_group = negate,
+        RuleMessage::SetCommentedOut(commented_out) => rule.commented_out = commented_out,
+        RuleMessage::NotesAction(action) => {
+            state.notes_editor.perform(action);
+            rule.comment = editor_notes(&state.notes_editor);
+        }
+        RuleMessage::RawBlockAction(action) => {
+            if rule.raw_block.is_some() {
+                state.raw_block_editor.perform(action);
+                let text = state.raw_block_editor.text();
+                // Top-level actions have no place in the rule list, so a
+                // block holding one stays raw rather than lose it
+                state.promote_raw_blocks = parser::parse(&text)
+                    .is_ok_and(|s| !s.commands.iter().any(|c| matches!(c, Command::Action(_))));
+                rule.raw_block = Some(text);
+            }
+        }
+        RuleMessage::RemoveRule => {
+            state.rules.remove(idx);
+        }
+        RuleMessage::AddCondition => {
+            rule.conditions.push(Condition::default());
+        }
+        RuleMessage::AddAction => {
+            rule.actions.push(Action::default());
+        }
+        RuleMessage::ConditionMsg(ci, cmsg) => {
+            if ci < rule.conditions.len() {
+                handle_condition_message(&mut rule.conditions, ci, cmsg);
+            }
+        }
+        RuleMessage::ActionMsg(ai, amsg) => {
+            if ai < rule.actions.len() {
+                handle_action_message(&mut rule.actions, ai, amsg);
+            }
+        }
+        RuleMessage::AddElsIf => {
+            rule.alternatives.push(RuleBranch::default());
+        }
+        RuleMessage::RemoveElsIf(bi) => {
+            if bi < rule.alternatives.len() {
+                rule.alternatives.remove(bi);
+                state.collapsed_branches.clear();
+            }
+        }
+        RuleMessage::SetBranchLogic(bi, opt) => {
+            if let Some(branch) = rule.alternatives.get_mut(bi) {
+                branch.logic = opt.0;
+            }
+        }
+        RuleMessage::AddBranchCondition(bi) => {
+            if let Some(branch) = rule.alternatives.get_mut(bi) {
+                branch.conditions.push(Condition::default());
+            }
+        }
+        RuleMessage::AddBranchAction(bi) => {
+            if let Some(branch) = rule.alternatives.get_mut(bi) {
+                branch.actions.push(Action::default());
+            }
+        }
+        RuleMessage::BranchConditionMsg(bi, ci, cmsg) => {
+            if let Some(branch) = rule.alternatives.get_mut(bi) {
+                if ci < branch.conditions.len() {
+                    handle_condition_message(&mut branch.conditions, ci, cmsg);
+                }
+            }
+        }
+        RuleMessage::BranchActionMsg(bi, ai, amsg) => {
+            if let Some(branch) = rule.alternatives.get_mut(bi) {
+                if ai < branch.actions.len() {
+                    handle_action_message(&mut branch.actions, ai, amsg);
+                }
+            }
+        }
+        RuleMessage::AddElse => {
+            if rule.else_actions.is_none() {
+                rule.else_actions = Some(vec![Action::default()]);
+            }
+        }
+        RuleMessage::RemoveElse => {
+            rule.else_actions = None;
+        }
+        RuleMessage::AddElseAction => {
+            if let Some(actions) = &mut rule.else_actions {
+                actions.push(Action::default());
+            }
+        }
+        RuleMessage::ElseActionMsg(ai, amsg) => {
+            if let Some(actions) = &mut rule.else_actions {
+                if ai < actions.len() {
+                    handle_action_message(actions, ai, amsg);
+                }
+            }
+        }
+        RuleMessage::MoveStopLast(branch) => {
+            let actions = match branch {
+                None => Some(&mut rule.actions),
+                Some(BranchId::ElsIf(bi)) => rule.alternatives.get_mut(bi).map(|b| &mut b.actions),
+                Some(BranchId::Else) => rule.else_actions.as_mut(),
+            };
+            if let Some(actions) = actions {
+                lint::move_stop_last(actions);
+            }
+        }
+        RuleMessage::ToggleBranch(id) => {
+            if !state.collapsed_branches.remove(&id) {
+                state.collapsed_branches.insert(id);
+            }
+        }
+    }
+}
+
+fn handle_condition_message(conditions: &mut Vec<Condition>, idx: usize, msg: ConditionMessage) {
+    match msg {
+        ConditionMessage::SetTestType(opt) => conditions[idx].test_type = opt.0,
+        ConditionMessage::SetMatchType(opt) => conditions[idx].match_type = opt.0,
+        ConditionMessage::SetAddressPart(opt) => conditions[idx].address_part = opt.0,
+        ConditionMessage::SetSizeComparator(opt) => conditions[idx].size_comparator = opt.0,
+        ConditionMessage::SetComparator(opt) => conditions[idx].comparator = opt.0,
+        ConditionMessage::SetTransform(opt) => {
+            let cond = &mut conditions[idx];
+            cond.transform = match (opt, &cond.transform) {
+                (TransformOption::Text, _) => BodyTransform::Text,
+                (TransformOption::Raw, _) => BodyTransform::Raw,
+                (TransformOption::Content, BodyTransform::Content(media)) => BodyTransform::Content(media.clone()),
+                (TransformOption::Content, _) => BodyTransform::Content("text/plain".to_string()),
+            };
+        }
+        ConditionMessage::SetContentType(media) => {
+            if let BodyTransform::Content(current) = &mut conditions[idx].transform {
+                *current = media.trim().to_string();
+            }
+        }
+        ConditionMessage::SetHeaders(s) => {
+            conditions[idx].header_names = s.split(',').map(|h| h.trim().to_string()).collect();
+        }
+        ConditionMessage::SetValue(s) => {
+            // Keystrokes that can't be part of a size limit are dropped;
+            // partial input like "10" or "" is flagged inline instead
+            if s.chars().all(|c| c.is_ascii_digit() || "KMGkmg".contains(c)) {
+                conditions[idx].size_value = s;
+            }
+        }
+        ConditionMessage::SetKey(i, s) => {
+            if let Some(key) = conditions[idx].keys.get_mut(i) {
+                *key = s;
+            }
+        }
+        ConditionMessage::AddKey => conditions[idx].keys.push(String::new()),
+        ConditionMessage::RemoveKey(i) => {
+            if conditions[idx].keys.len() > 1 && i < conditions[idx].keys.len() {
+                conditions[idx].keys.remove(i);
+            }
+        }
+        ConditionMessage::ToggleNegate(negate) => conditions[idx].negate = negate,
+        ConditionMessage::ToggleEnabled(enabled) => conditions[idx].enabled = enabled,
+        ConditionMessage::Remove => {
+            conditions.remove(idx);
+        }
+    }
+    // A test that spans lines can't be kept as a note, so it stays on
+    if let Some(cond) = conditions.get_mut(idx) {
+        cond.enabled |= !converter::can_disable(cond);
+    }
+}
+
+fn handle_action_message(actions: &mut Vec<Action>, idx: usize, msg: ActionMessage) {
+    match msg {
+        ActionMessage::SetActionType(opt) => actions[idx].action_type = opt.0,
+        ActionMessage::SetArgument(s) => actions[idx].argument = s,
+        ActionMessage::SetDays(s) => {
+            let s = s.trim();
+            if s.is_empty() {
+                actions[idx].days = None;
+            } else if let Ok(days) = s.parse() {
+                actions[idx].days = Some(days);
+            }
+        }
+        ActionMessage::ToggleCopy(copy) => actions[idx].copy = copy,
+        ActionMessage::ToggleCreate(create) => actions[idx].create = create,
+        ActionMessage::SetSubject(s) => {
+            actions[idx].subject = if s.is_empty() { None } else { Some(s) };
+        }
+        ActionMessage::SetAddresses(s) => {
+            actions[idx].addresses = if s.trim().is_empty() {
+                Vec::new()
+            } else {
+                s.split(',').map(|a| a.trim().to_string()).collect()
+            };
+        }
+        ActionMessage::SetValue(s) => actions[idx].value = s,
+        ActionMessage::SetMessage(s) => {
+            actions[idx].message = if s.is_empty() { None } else { Some(s) };
+        }
+        ActionMessage::ToggleFlag(flag, on) => {
+            let flags = &mut actions[idx].flags;
+            flags.retain(|f| !f.eq_ignore_ascii_case(flag));
+            if on {
+                flags.push(flag.to_string());
+            }
+        }
+        ActionMessage::SetCustomFlags(s) => {
+            let flags = &mut actions[idx].flags;
+            flags.retain(|f| action_row::is_system_flag(f));
+            // Split on single spaces so a trailing space survives while typing;
+            // the emitter drops the blanks
+            if !s.is_empty() {
+                flags.extend(s.split(' ').map(str::to_string));
+            }
+        }
+        ActionMessage::SetFlagVariable(s) => {
+            actions[idx].flag_variable = if s.is_empty() { None } else { Some(s) };
+        }
+        ActionMessage::Remove => {
+            actions.remove(idx);
+        }
+    }
+}
+
+/// The first line of a server diagnostic that points at a script line
+/// (`line N: ...`), or the first line overall.
+fn first_error_line(message: &str) -> &str {
+    let mut lines = message.lines().map(str::trim).filter(|l| !l.is_empty());
+    let first = lines.clone().next().unwrap_or(message);
+    lines.find(|l| l.to_lowercase().contains("line ")).unwrap_or(first)
+}
+
+/// How an upload reaches the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UploadPlan {
+    /// Not the active script: upload as is
+    Direct,
+    /// Active script: CHECKSCRIPT first, upload only if it passes
+    CheckFirst,
+    /// Active script on a server without CHECKSCRIPT: ask the user
+    Confirm,
+}
+
+fn upload_plan(scripts: &[ScriptInfo], capabilities: Option<&Capabilities>, name: &str) -> UploadPlan {
+    let active = scripts.iter().any(|s| s.active && s.name == name);
+    if !active {
+        UploadPlan::Direct
+    } else if capabilities.is_some_and(Capabilities::supports_checkscript) {
+        UploadPlan::CheckFirst
+    } else {
+        UploadPlan::Confirm
+    }
+}
+
+fn upload_name(state: &Sievers) -> String {
+    state
+        .current_script_name
+        .clone()
+        .unwrap_or_else(|| "default".to_string())
+}
+
+/// PUTSCRIPT `content` as `name`, backing up the server copy it replaces.
+fn start_upload(state: &mut Sievers, name: String, content: String) -> Task<Message> {
+    let client = state.client.clone();
+    let session = state.session.clone();
+    let exists = state.server_scripts.iter().any(|s| s.name == name);
+    let check_space = state
+        .capabilities
+        .as_ref()
+        .is_some_and(|caps| caps.supports_havespace());
+    state.status = format!("Uploading {name}...");
+    if let Some(warning) = missing_extensions_warning(state) {
+        state.status = format!("{} {warning}", state.status);
+    }
+    server_task(
+        state,
+        async move {
+            // Ask first rather than have the server cut off the transfer
+            if check_space {
+                let fits = reconnect_and_retry(client.clone(), session.clone(), |client| {
+                    let (name, size) = (name.clone(), content.len());
+                    async move { client.lock().await.have_space(&name, size).await }
+                })
+                .await
+                .map_err(|e| e.to_string())?;
+                if !fits {
+                    return Err(format!("Not enough quota for {name}"));
+                }
+            }
+            let backup = backup_and_put(client, session, &name, &content, exists).await?;
+            Ok((name, backup))
+        },
+        Message::ScriptUploaded,
+    )
+}
+
+/// PUTSCRIPT `content` as `name`, first backing up the server copy it
+/// replaces if there is one (`exists`). Gives the backup's path.
+async fn backup_and_put(
+    client: Arc<Mutex<ManageSieveClient>>,
+    session: Option<Session>,
+    name: &str,
+    content: &str,
+    exists: bool,
+) -> Result<Option<PathBuf>, String> {
+    // Keep the copy about to be replaced; new scripts have none
+    let backup = if exists {
+        let current = reconnect_and_retry(client.clone(), session.clone(), |client| {
+            let name = name.to_string();
+            async move { client.lock().await.get_script(&name).await }
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+        let path = script_io::backup_script(name, &current)
+            .map_err(|e| format!("Backup failed, not uploading: {e}"))?;
+        Some(path)
+    } else {
+        None
+    };
+    reconnect_and_retry(client, session, |client| {
+        let (name, content) = (name.to_string(), content.to_string());
+        async move { client.lock().await.put_script(&name, &content).await }
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(backup)
+}
+
+/// Redirects in the rules and the server's `MAXREDIRECTS`, when it has one.
+fn redirect_usage(state: &Sievers) -> Option<(usize, u32)> {
+    let limit = state.capabilities.as_ref()?.max_redirects?;
+    Some((converter::count_redirects(&state.rules), limit))
+}
+
+fn redirects_over_limit(state: &Sievers) -> bool {
+    redirect_usage(state).is_some_and(|(count, limit)| count > limit as usize)
+}
+
+/// Status-bar warning listing extensions the rules need but the server lacks.
+fn missing_extensions_warning(state: &Sievers) -> Option<String> {
+    let caps = state.capabilities.as_ref()?;
+    let missing = converter::missing_extensions(&state.rules, &caps.sieve_extensions);
+    if missing.is_empty() {
+        None
+    } else {
+        Some(format!("(server does not support: {})", missing.join(", ")))
+    }
+}
+
+// --- Bidirectional sync ---
+
+fn sync_visual_to_raw(state: &mut Sievers) {
+    state.syncing = true;
+    let script = crate::model::script::SieveScript {
+        rules: state.rules.clone(),
+        requires: state.declared_requires.clone(),
+        preamble: state.preamble.clone(),
+        require_statements: state.require_statements.clone(),
+        ..Default::default()
+    };
+    let text = converter::script_to_text(&script, &state.settings.emit_options());
+    // Raw blocks are written back verbatim, broken ones included
+    let ast = parser::parse(&text);
+    state.parse_ok = ast.is_ok();
+    // Lint against the requires just written
+    let requires = match &ast {
+        Ok(ast) => ast
+            .commands
+            .iter()
+            .filter_map(|cmd| match cmd {
+                Command::Require { extensions, .. } => Some(extensions.clone()),
+                _ => None,
+            })
+            .flatten()
+            .collect(),
+        Err(_) => script.requires,
+    };
+    refresh_problems(state, requires);
+    state.editor_content = text_editor::Content::with_text(&text);
+    state.raw_dirty = false;
+    state.last_raw_edit = None;
+    state.visual_dirty = false;
+    state.last_visual_edit = None;
+    state.syncing = false;
+    // A fixed raw block reads back as conditions and actions
+    if std::mem::take(&mut state.promote_raw_blocks) && state.parse_ok {
+        sync_raw_to_visual(state);
+    }
+}
+
+/// Lint the rules against the script's `requires` for the Problems panel.
+fn refresh_problems(state: &mut Sievers, requires: Vec<String>) {
+    let script = crate::model::script::SieveScript {
+        rules: state.rules.clone(),
+        requires,
+        ..Default::default()
+    };
+    state.problems.lints = lint::lint_script(&script);
+}
+
+/// Write debounced rule edits to the raw text now, for code that reads it.
+fn flush_visual_edits(state: &mut Sievers) {
+    if state.visual_dirty && !state.syncing {
+        sync_visual_to_raw(state);
+    }
+}
+
+/// Write the window's size and position if they changed since last time.
+fn save_geometry(state: &mut Sievers) {
+    if state.last_geometry_change.take().is_some() {
+        settings_store::save_settings(&state.settings);
+    }
+}
+
+/// Whether edits made at `last` have been idle long enough to sync.
+fn debounce_elapsed(last: Option<Instant>, now: Instant) -> bool {
+    last.is_some_and(|last| now.duration_since(last).as_millis() >= RAW_SYNC_DEBOUNCE_MS as u128)
+}
+
+fn sync_raw_to_visual(state: &mut Sievers) {
+    state.syncing = true;
+    let text = state.editor_content.text();
+    state.parse_ok = parser::parse(&text).is_ok();
+    let script = converter::text_to_script(&text, "");
+    state.rules = script.rules;
+    state.declared_requires = script.requires;
+    state.require_statements = script.require_statements;
+    state.preamble = script.preamble;
+    refresh_problems(state, state.declared_requires.clone());
+    state.raw_dirty = false;
+    state.last_raw_edit = None;
+    state.visual_dirty = false;
+    state.last_visual_edit = None;
+    clamp_selected_rule(state);
+    state.syncing = false;
+}
+
+fn clamp_selected_rule(state: &mut Sievers) {
+    // Auto-select first rule if none selected
+    if !state.rules.is_empty() && state.selected_rule.is_none() {
+        state.selected_rule = Some(0);
+    }
+    // Clamp selected_rule if rules shrunk
+    if let Some(sel) = state.selected_rule {
+        if sel >= state.rules.len() {
+            state.selected_rule = if state.rules.is_empty() {
+                None
+            } else {
+                Some(state.rules.len() - 1)
+            };
+        }
+    }
+}
+
+/// Record the current state as an undo step and drop the redo history.
+/// Every undoable change also marks the buffer dirty.
+fn push_undo(state: &mut Sievers) {
+    state.dirty = true;
+    let snapshot = take_snapshot(state);
+    state.undo_stack.push(snapshot);
+    if state.undo_stack.len() > UNDO_LIMIT {
+        state.undo_stack.remove(0);
+    }
+    state.redo_stack.clear();
+}
+
+fn take_snapshot(state: &mut Sievers) -> Snapshot {
+    // Pending edits on either side are synced first so the pair matches
+    if state.raw_dirty {
+        sync_raw_to_visual(state);
+    }
+    flush_visual_edits(state);
+    Snapshot {
+        rules: state.rules.clone(),
+        text: state.editor_content.text(),
+        declared_requires: state.declared_requires.clone(),
+        require_statements: state.require_statements.clone(),
+        preamble: state.preamble.clone(),
+    }
+}
+
+fn handle_find_message(state: &mut Sievers, msg: FindMessage) -> Task<Message> {
+    match msg {
+        FindMessage::Toggle if state.find.visible => state.find.visible = false,
+        FindMessage::Toggle => {
+            state.find.visible = true;
+            return text_input::focus(ui::find_bar::query_id());
+        }
+        FindMessage::SetQuery(query) => state.find.query = query,
+        FindMessage::SetReplacement(replacement) => state.find.replacement = replacement,
+        FindMessage::ToggleCaseSensitive(on) => state.find.case_sensitive = on,
+        FindMessage::Close => state.find.visible = false,
+        FindMessage::FindNext => return find_next(state),
+        FindMessage::ReplaceAll => {
+            flush_visual_edits(state);
+            let text = state.editor_content.text();
+            let find = &state.find;
+            let count = search::find_all(&text, &find.query, find.case_sensitive).len();
+            if count == 0 {
+                state.status = format!("No matches for \"{}\"", find.query);
+                return Task::none();
+            }
+            let replaced = search::replace_all(&text, &find.query, &find.replacement, find.case_sensitive);
+            push_undo(state);
+            state.editor_content = text_editor::Content::with_text(&replaced);
+            state.dirty = true;
+            sync_raw_to_visual(state);
+            state.status = format!("Replaced {count} occurrence{}", if count == 1 { "" } else { "s" });
+        }
+    }
+    Task::none()
+}
+
+/// Select the next match after the cursor in the raw editor, wrapping
+/// around to the top, and scroll it into view.
+fn find_next(state: &mut Sievers) -> Task<Message> {
+    if state.active_tab != Tab::Raw {
+        let _ = handle_message(state, Message::SwitchTab(Tab::Raw));
+    }
+    let text = state.editor_content.text();
+    let find = &state.find;
+    let cursor = cursor_offset(&text, state.editor_content.cursor_position());
+    let Some(found) = search::find_from(&text, &find.query, cursor, find.case_sensitive)
+        .or_else(|| search::find_from(&text, &find.query, 0, find.case_sensitive))
+    else {
+        state.status = format!("No matches for \"{}\"", find.query);
+        return Task::none();
+    };
+
+    // The editor moves by characters: get there from the top, then select
+    let content = &mut state.editor_content;
+    content.perform(text_editor::Action::Move(text_editor::Motion::DocumentStart));
+    for _ in text[..found.start].chars() {
+        content.perform(text_editor::Action::Move(text_editor::Motion::Right));
+    }
+    for _ in text[found].chars() {
+        content.perform(text_editor::Action::Select(text_editor::Motion::Right));
+    }
+    scroll_to_cursor(state)
+}
+
+/// Byte offset in `text` of a (line, column) cursor position.
+fn cursor_offset(text: &str, (line, column): (usize, usize)) -> usize {
+    let line_start: usize = text.split_inclusive('\n').take(line).map(str::len).sum();
+    let rest = &text[line_start.min(text.len())..];
+    line_start + rest.char_indices().nth(column).map_or(rest.len(), |(i, _)| i)
+}
+
+/// Scroll the raw editor just enough to keep the cursor line in view; the
+/// editor itself never scrolls since it grows to fit its text.
+fn scroll_to_cursor(state: &Sievers) -> Task<Message> {
+    let Some(viewport) = state.raw_viewport else {
+        return Task::none();
+    };
+    let (line, _) = state.editor_content.cursor_position();
+    let top = ui::raw_editor::line_top(line);
+    let bottom = top + ui::raw_editor::line_height_px();
+    let offset = viewport.absolute_offset().y;
+    let height = viewport.bounds().height;
+
+    let y = if top < offset {
+        top
+    } else if bottom > offset + height {
+        bottom - height
+    } else {
+        return Task::none();
+    };
+    scrollable::scroll_to(
+        ui::raw_editor::scroll_id(),
+        scrollable::AbsoluteOffset { x: 0.0, y },
+    )
+}
+
+fn restore_snapshot(state: &mut Sievers, snapshot: Snapshot) {
+    state.rules = snapshot.rules;
+    state.declared_requires = snapshot.declared_requires;
+    state.require_statements = snapshot.require_statements;
+    state.preamble = snapshot.preamble;
+    refresh_problems(state, state.declared_requires.clone());
+    state.parse_ok = parser::parse(&snapshot.text).is_ok();
+    state.editor_content = text_editor::Content::with_text(&snapshot.text);
+    state.raw_dirty = false;
+    state.last_raw_edit = None;
+    state.visual_dirty = false;
+    state.last_visual_edit = None;
+    clamp_selected_rule(state);
+}
+
+/// Whether a rule message changes the rule (and so deserves an undo step).
+fn rule_message_mutates(msg: &RuleMessage) -> bool {
+    match msg {
+        RuleMessage::ToggleBranch(_) => false,
+        RuleMessage::NotesAction(action) | RuleMessage::RawBlockAction(action) => action.is_edit(),
+        _ => true,
+    }
+}
+
+// --- View ---
+
+pub fn title(state: &Sievers) -> String {
+    let marker = if state.dirty { "* " } else { "" };
+    match state.current_script_name.as_deref().or(
+        state
+            .current_path
+            .as_ref()
+            .and_then(|p| p.file_name())
+            .and_then(|n| n.to_str()),
+    ) {
+        Some(name) => format!("{marker}{name} — Sievers"),
+        None => format!("{marker}Sievers — SIEVE Filter Manager"),
+    }
+}
+
+pub fn view(state: &Sievers) -> Element<'_, Message> {
+    let toolbar = ui::toolbar::view(
+        state.connected,
+        state.busy,
+        state.parse_ok && !redirects_over_limit(state),
+        state.activate_after_upload,
+        state.settings.dark_mode,
+        &state.settings.recent_files,
+        state.security.as_ref(),
+    );
+    let tab_bar = view_tab_bar(state.active_tab, &state.settings, state.show_help);
+
+    let editor_area = match state.active_tab {
+        Tab::Visual => ui::visual_editor::view(
+            &state.rules,
+            state.selected_rule,
+            &state.collapsed_branches,
+            &state.notes_editor,
+            &state.raw_block_editor,
+            &state.rule_filter,
+            state.capabilities.as_ref(),
+            match &state.context_menu {
+                Some(MenuTarget::Rule(idx)) => Some(*idx),
+                _ => None,
+            },
+            Splitter::RuleSidebar.clamp(state.settings.rule_sidebar_width),
+        ),
+        Tab::Raw => ui::raw_editor::view(&state.editor_content),
+    };
+    let editor_area: Element<'_, Message> = if state.find.visible {
+        column![ui::find_bar::view(&state.find).map(Message::FindMsg), editor_area].into()
+    } else {
+        editor_area
+    };
+    let editor_area: Element<'_, Message> = if state.show_help {
+        let rule = state.selected_rule.and_then(|i| state.rules.get(i));
+        row![
+            container(editor_area).width(Length::Fill),
+            ui::help_panel::view(rule)
+        ]
+        .into()
+    } else {
+        editor_area
+    };
+
+    // 1-based, like the positions in parser errors
+    let cursor = (state.active_tab == Tab::Raw).then(|| {
+        let (line, col) = state.editor_content.cursor_position();
+        (line + 1, col + 1)
+    });
+    let warning = (!state.parse_ok).then_some(PARSE_ERRORS_WARNING);
+    let status_bar = ui::status_bar::view(&state.status, state.busy.then_some(state.busy_frame), state.dirty, warning, state.problems.lints.len(), redirect_usage(state), cursor);
+
+    // Main layout: optional sidebar + editor
+    let main_content: Element<'_, Message> = if state.connected {
+        let sidebar = ui::script_list::view(
+            &state.server_scripts,
+            state.selected_script.as_deref(),
+            state.renaming_script.as_ref(),
+            match &state.context_menu {
+                Some(MenuTarget::Script(name)) => Some(name.as_str()),
+                _ => None,
+            },
+            state.busy,
+            Splitter::ScriptList.clamp(state.settings.script_list_width),
+        )
+        .map(Message::ScriptListMsg);
+
+        row![
+            sidebar,
+            splitter::view(Splitter::ScriptList),
+            column![tab_bar, editor_area].width(Length::Fill)
+        ]
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .into()
+    } else {
+        column![tab_bar, editor_area]
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .into()
+    };
+
+    let mut content: Element<'_, Message> =
+        column![toolbar, main_content, status_bar].into();
+
+    // Connection modal overlay
+    if state.connection.visible {
+        content = iced::widget::stack![
+            content,
+            ui::connection_modal::view(&state.connection, state.settings.open_on_connect, state.busy).map(Message::ConnectionMsg),
+        ]
+        .into();
+    }
+
+    // About modal overlay
+    if state.about.visible {
+        content = iced::widget::stack![
+            content,
+            ui::about_modal::view(&state.about).map(Message::AboutMsg),
+        ]
+        .into();
+    }
+
+    if state.templates.visible {
+        content = iced::widget::stack![
+            content,
+            ui::template_modal::view(&state.templates).map(Message::TemplateMsg),
+        ]
+        .into();
+    }
+
+    if state.diff.visible {
+        content = iced::widget::stack![content, ui::diff_modal::view(&state.diff).map(Message::DiffMsg)]
+            .into();
+    }
+
+    if state.folder_sync.visible {
+        content = iced::widget::stack![
+            content,
+            ui::sync_modal::view(&state.folder_sync, state.busy).map(Message::SyncMsg),
+        ]
+        .into();
+    }
+
+    if state.problems.visible {
+        content = iced::widget::stack![
+            content,
+            ui::problems_panel::view(&state.problems, &state.rules).map(Message::ProblemsMsg),
+        ]
+        .into();
+    }
+
+    if let Some(confirm) = &state.confirm {
+        content = iced::widget::stack![content, ui::confirm_modal::view(confirm).map(Message::ConfirmMsg)]
+            .into();
+    }
+
+    if state.pending_close.is_some() {
+        content = iced::widget::stack![content, ui::close_modal::view().map(Message::CloseMsg)].into();
+    }
+
+    content
+}
+
+fn view_tab_bar(active: Tab, settings: &AppSettings, show_help: bool) -> Element<'static, Message> {
+    let visual_style = if active == Tab::Visual {
+        iced::widget::button::primary
+    } else {
+        iced::widget::button::secondary
+    };
+    let raw_style = if active == Tab::Raw {
+        iced::widget::button::primary
+    } else {
+        iced::widget::button::secondary
+    };
+
+    container(
+        row![
+            iced::widget::button("Visual")
+                .on_press(Message::SwitchTab(Tab::Visual))
+                .style(visual_style),
+            iced::widget::button("Raw")
+                .on_press(Message::SwitchTab(Tab::Raw))
+                .style(raw_style),
+            iced::widget::horizontal_space(),
+            iced::widget::button(iced::widget::text("Format").size(13))
+                .on_press(Message::FormatScript)
+                .style(iced::widget::button::secondary)
+                .padding([2, 8]),
+            iced::widget::Space::with_width(4),
+            iced::widget::button(iced::widget::text("Help").size(13))
+                .on_press(Message::ToggleHelp)
+                .style(if show_help {
+                    iced::widget::button::primary
+                } else {
+                    iced::widget::button::secondary
+                })
+                .padding([2, 8]),
+            iced::widget::Space::with_width(12),
+            iced::widget::checkbox("Keep require order", settings.keep_require_order)
+                .on_toggle(Message::SetKeepRequireOrder)
+                .size(14)
+                .text_size(13),
+            iced::widget::Space::with_width(12),
+            iced::widget::text("Indent:").size(13),
+            iced::widget::pick_list(Indent::ALL, Some(settings.indent), Message::SetIndent)
+                .text_size(13),
+        ]
+        .spacing(2)
+        .align_y(iced::Alignment::Center),
+    )
+    .padding([4, 8])
+    .width(Length::Fill)
+    .style(|theme: &Theme| {
+        let palette = theme.palette();
+        container::Style {
+            background: Some(iced::Background::Color(iced::Color::from_rgba(
+                palette.text.r,
+                palette.text.g,
+                palette.text.b,
+                0.03,
+            ))),
+            ..container::Style::default()
+        }
+    })
+    .into()
+}
+
+pub fn theme(state: &Sievers) -> Theme {
+    if state.settings.dark_mode {
+        Theme::Dark
+    } else {
+        Theme::Light
+    }
+}
+
+pub fn subscription(state: &Sievers) -> Subscription<Message> {
+    let mut subs = vec![
+        iced::keyboard::on_key_press(handle_key_press),
+        iced::window::close_requests().map(Message::CloseRequested),
+        iced::event::listen_with(window_geometry_event),
+    ];
+
+    let raw_pending = state.raw_dirty && state.last_raw_edit.is_some();
+    let visual_pending = state.visual_dirty && state.last_visual_edit.is_some();
+    if raw_pending || visual_pending || state.last_geometry_change.is_some() {
+        subs.push(
+            iced::time::every(std::time::Duration::from_millis(100))
+                .map(|_| Message::DebounceCheck),
+        );
+    }
+    if state.busy {
+        subs.push(
+            iced::time::every(std::time::Duration::from_millis(150)).map(|_| Message::BusyTick),
+        );
+    }
+    if state.resizing.is_some() {
+        subs.push(iced::event::listen_with(resize_event));
+    }
+
+    Subscription::batch(subs)
+}
+
+/// Set the width of the panel whose divider is dragged, the cursor being
+/// at `x` in the window.
+fn resize_to(state: &mut Sievers, x: f32) {
+    match state.resizing {
+        Some(Splitter::ScriptList) => {
+            state.settings.script_list_width = Splitter::ScriptList.clamp(x);
+        }
+        Some(Splitter::RuleSidebar) => {
+            // Right of the script list, when that is shown
+            let left = if state.connected {
+                Splitter::ScriptList.clamp(state.settings.script_list_width) + splitter::WIDTH
+            } else {
+                0.0
+            };
+            state.settings.rule_sidebar_width = Splitter::RuleSidebar.clamp(x - left);
+        }
+        None => {}
+    }
+}
+
+/// Cursor moves while a divider is dragged, and the release ending it.
+fn resize_event(
+    event: iced::Event,
+    _status: iced::event::Status,
+    _id: iced::window::Id,
+) -> Option<Message> {
+    use iced::mouse;
+
+    match event {
+        iced::Event::Mouse(mouse::Event::CursorMoved { position }) => Some(Message::ResizeTo(position.x)),
+        iced::Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => Some(Message::EndResize),
+        _ => None,
+    }
+}
+
+/// Window resizes and moves, remembered for the next start.
+fn window_geometry_event(
+    event: iced::Event,
+    _status: iced::event::Status,
+    _id: iced::window::Id,
+) -> Option<Message> {
+    match event {
+        iced::Event::Window(iced::window::Event::Resized(size)) => Some(Message::WindowResized(size)),
+        iced::Event::Window(iced::window::Event::Moved(point)) => Some(Message::WindowMoved(point)),
+        _ => None,
+    }
+}
+
+fn handle_key_press(
+    key: iced::keyboard::Key,
+    modifiers: iced::keyboard::Modifiers,
+) -> Option<Message> {
+    use iced::keyboard::key::Named;
+    use iced::keyboard::Key;
+
+    if key == Key::Named(Named::Escape) {
+        return Some(Message::CloseContextMenu);
+    }
+    if key == Key::Named(Named::F1) {
+        return Some(Message::ToggleHelp);
+    }
+    if modifiers.control() {
+        match &key {
+            Key::Character(c) if c.as_str() == "o" => Some(Message::OpenFile),
+            Key::Character(c) if c.as_str() == "s" => Some(Message::SaveFile),
+            Key::Character(c) if c.as_str() == "u" => Some(Message::Upload),
+            Key::Character(c) if c.eq_ignore_ascii_case("z") && modifiers.shift() => {
+                Some(Message::Redo)
+            }
+            Key::Character(c) if c.as_str() == "z" => Some(Message::Undo),
+            Key::Character(c) if c.as_str() == "C" && modifiers.shift() => Some(Message::Connect),
+            Key::Named(Named::Tab) => Some(Message::SwitchTab(Tab::Visual)), // Ctrl+Tab toggles
+            Key::Named(Named::Enter) if modifiers.shift() => {
+                Some(Message::Shortcut(AddShortcut::Action))
+            }
+            Key::Named(Named::Enter) => Some(Message::Shortcut(AddShortcut::Condition)),
+            Key::Character(c) if c.eq_ignore_ascii_case("n") && modifiers.shift() => {
+                Some(Message::Shortcut(AddShortcut::Rule))
+            }
+            Key::Character(c) if c.as_str() == "n" => Some(Message::NewScript),
+            Key::Character(c) if c.eq_ignore_ascii_case("f") && modifiers.shift() => {
+                Some(Message::FormatScript)
+            }
+            Key::Character(c) if c.as_str() == "f" => Some(Message::FindMsg(FindMessage::Toggle)),
+            _ => None,
+        }
+    } else if modifiers.is_empty() {
+        match key {
+            Key::Named(Named::ArrowUp) => Some(Message::StepRule(RuleStep::Previous)),
+            Key::Named(Named::ArrowDown) => Some(Message::StepRule(RuleStep::Next)),
+            Key::Named(Named::Home) => Some(Message::StepRule(RuleStep::First)),
+            Key::Named(Named::End) => Some(Message::StepRule(RuleStep::Last)),
+            _ => None,
+        }
+    } else {
+        None
+    }
+}
+
+/// The rule selected after `step` from `current`, among the `shown` rule
+/// indices (ascending). Steps stop at either end. A selection hidden by the
+/// search steps to the nearest shown rule that way; with nothing selected,
+/// Down picks the first rule and Up the last.
+fn step_selection(shown: &[usize], current: Option<usize>, step: RuleStep) -> Option<usize> {
+    let (&first, &last) = (shown.first()?, shown.last()?);
+    let next = match (step, current) {
+        (RuleStep::First, _) | (RuleStep::Next, None) => first,
+        (RuleStep::Last, _) | (RuleStep::Previous, None) => last,
+        (RuleStep::Previous, Some(c)) => shown.iter().rev().find(|&&i| i < c).copied().unwrap_or(first),
+        (RuleStep::Next, Some(c)) => shown.iter().find(|&&i| i > c).copied().unwrap_or(last),
+    };
+    Some(next)
+}
+
+/// What a visual editor shortcut does right now. Only the Visual tab takes
+/// them; a focused text input swallows the keys before they get here.
+fn shortcut_message(state: &Sievers, shortcut: AddShortcut) -> Option<Message> {
+    if state.active_tab != Tab::Visual {
+        return None;
+    }
+    let rule = state
+        .selected_rule
+        .filter(|&i| state.rules.get(i).is_some_and(|r| r.raw_block.is_none()));
+    match shortcut {
+        AddShortcut::Rule => Some(Message::AddRule),
+        AddShortcut::Condition => rule.map(|i| Message::RuleMsg(i, RuleMessage::AddCondition)),
+        AddShortcut::Action => rule.map(|i| Message::RuleMsg(i, RuleMessage::AddAction)),
+    }
+}
+
+// --- Async file operations ---
+
+async fn open_file_dialog() -> Result<(PathBuf, String), String> {
+    let handle = rfd::AsyncFileDialog::new()
+        .set_title("Open SIEVE Script")
+        .add_filter("SIEVE Scripts", &["siv", "sieve"])
+        .add_filter("All Files", &["*"])
+        .pick_file()
+        .await;
+
+    match handle {
+        Some(handle) => {
+            let path = handle.path().to_path_buf();
+            let text = script_io::load_script(&path).map_err(|e| e.to_string())?;
+            Ok((path, text))
+        }
+        None => Err("Cancelled".to_string()),
+    }
+}
+
+async fn pick_folder_dialog(title: &'static str) -> Option<PathBuf> {
+    rfd::AsyncFileDialog::new()
+        .set_title(title)
+        .pick_folder()
+        .await
+        .map(|handle| handle.path().to_path_buf())
+}
+
+async fn save_file_dialog(
+    current_path: Option<PathBuf>,
+    text: String,
+) -> Result<PathBuf, String> {
+    let path = if let Some(path) = current_path {
+        path
+    } else {
+        let handle = rfd::AsyncFileDialog::new()
+            .set_title("Save SIEVE Script")
+            .add_filter("SIEVE Scripts", &["siv", "sieve"])
+            .add_filter("All Files", &["*"])
+            .save_file()
+            .await;
+
+        match handle {
+            Some(handle) => handle.path().to_path_buf(),
+            None => return Err("Cancelled".to_string()),
+        }
+    };
+
+    script_io::save_script(&path, &text).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sieve::emitter::EmitOptions;
+    use crate::sieve::templates::Template;
+    use crate::ui::condition_row::SizeComparatorOption;
+
+    #[test]
+    fn test_condition_keys_edited_as_list() {
+        let mut conditions = vec![Condition::default()];
+        let set_key = |i, s: &str| ConditionMessage::SetKey(i, s.to_string());
+        handle_condition_message(&mut conditions, 0, set_key(0, "Hello, world"));
+        handle_condition_message(&mut conditions, 0, ConditionMessage::AddKey);
+        handle_condition_message(&mut conditions, 0, set_key(1, "b@y.com"));
+        assert_eq!(conditions[0].keys, vec!["Hello, world", "b@y.com"]);
+
+        handle_condition_message(&mut conditions, 0, ConditionMessage::RemoveKey(1));
+        assert_eq!(conditions[0].keys, vec!["Hello, world"]);
+        // The last key stays
+        handle_condition_message(&mut conditions, 0, ConditionMessage::RemoveKey(0));
+        assert_eq!(conditions[0].keys, vec!["Hello, world"]);
+
+        let rule = SieveRule {
+            name: "Greeting".to_string(),
+            conditions: conditions.clone(),
+            actions: vec![Action::default()],
+            ..Default::default()
+        };
+        let text = converter::rule_to_text(&rule, &EmitOptions::default());
+        assert!(text.contains(r#"header :contains "From" "Hello, world""#), "{text}");
+        let script = converter::text_to_script(&text, "");
+        assert_eq!(script.rules[0].conditions[0].keys, vec!["Hello, world"]);
+    }
+
+    #[test]
+    fn test_size_value_stays_scalar() {
+        let mut conditions = vec![Condition {
+            test_type: ConditionTest::Size,
+            ..Default::default()
+        }];
+        handle_condition_message(&mut conditions, 0, ConditionMessage::SetValue("15M".to_string()));
+        assert_eq!(conditions[0].size_value, "15M");
+        handle_condition_message(&mut conditions, 0, ConditionMessage::SetValue("big".to_string()));
+        assert_eq!(conditions[0].size_value, "15M");
+        assert_eq!(conditions[0].keys, vec![String::new()]);
+    }
+
+    #[test]
+    fn test_size_limit_kept_as_written() {
+        for limit in ["1M", "1024", "100k"] {
+            let text = format!("if size :over {limit} {{\n    discard;\n}}\n");
+            let mut script = converter::text_to_script(&text, "");
+            let conditions = &mut script.rules[0].conditions;
+            handle_condition_message(
+                conditions,
+                0,
+                ConditionMessage::SetSizeComparator(SizeComparatorOption(SizeComparator::Under)),
+            );
+            handle_condition_message(conditions, 0, ConditionMessage::ToggleNegate(true));
+            let emitted = converter::script_to_text(&script, &EmitOptions::default());
+            assert!(emitted.contains(&format!("if not size :under {limit} {{")), "{emitted}");
+        }
+    }
+
+    #[test]
+    fn test_context_menu_duplicate_rule() {
+        let mut state = Sievers::default();
+        for name in ["First", "First (2)", "Second"] {
+            state.rules.push(SieveRule {
+                name: name.to_string(),
+                ..Default::default()
+            });
+        }
+
+        let _ = update(&mut state, Message::ShowContextMenu(MenuTarget::Rule(0)));
+        assert_eq!(state.context_menu, Some(MenuTarget::Rule(0)));
+        // Right-clicking the same entry again closes it
+        let _ = update(&mut state, Message::ShowContextMenu(MenuTarget::Rule(0)));
+        assert_eq!(state.context_menu, None);
+
+        let _ = update(&mut state, Message::ShowContextMenu(MenuTarget::Rule(0)));
+        let _ = update(&mut state, Message::DuplicateRule(0));
+        assert_eq!(state.context_menu, None);
+        let names: Vec<&str> = state.rules.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["First", "First (3)", "First (2)", "Second"]);
+        assert_eq!(state.selected_rule, Some(1));
+
+        let _ = update(&mut state, Message::Undo);
+        assert_eq!(state.rules.len(), 3);
+    }
+
+    #[test]
+    fn test_move_rule_up_keeps_selection() {
+        let mut state = Sievers::default();
+        for name in ["First", "Second", "Third"] {
+            state.rules.push(SieveRule {
+                name: name.to_string(),
+                ..Default::default()
+            });
+        }
+        state.selected_rule = Some(1);
+
+        let _ = update(&mut state, Message::MoveRuleUp(1));
+        let names: Vec<&str> = state.rules.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["Second", "First", "Third"]);
+        assert_eq!(state.selected_rule, Some(0));
+
+        // Already at the top
+        let _ = update(&mut state, Message::MoveRuleUp(0));
+        assert_eq!(state.rules[0].name, "Second");
+        assert_eq!(state.selected_rule, Some(0));
+    }
+
+    #[test]
+    fn test_debounce_elapsed() {
+        let now = Instant::now();
+        let wait = std::time::Duration::from_millis(RAW_SYNC_DEBOUNCE_MS);
+        assert!(!debounce_elapsed(None, now));
+        assert!(!debounce_elapsed(Some(now), now));
+        assert!(!debounce_elapsed(Some(now - wait / 2), now));
+        assert!(debounce_elapsed(Some(now - wait), now));
+    }
+
+    #[test]
+    fn test_fixed_raw_block_becomes_structured() {
+        let mut state = Sievers {
+            active_tab: Tab::Visual,
+            editor_content: text_editor::Content::with_text(
+                "# Filter: Odd\nif header :is \"X-Odd\" \"yes\" {\n    frobnicate;\n}\n",
+            ),
+            ..Default::default()
+        };
+        sync_raw_to_visual(&mut state);
+        assert!(state.rules[0].raw_block.is_some());
+        let _ = update(&mut state, Message::SelectRule(0));
+        assert!(state.raw_block_editor.text().contains("frobnicate;"));
+
+        let edit = |action| Message::RuleMsg(0, RuleMessage::RawBlockAction(action));
+        let fixed = "# Filter: Odd\nif header :is \"X-Odd\" \"yes\" {\n    discard;\n}\n";
+        let _ = update(&mut state, edit(text_editor::Action::SelectAll));
+        let _ = update(
+            &mut state,
+            edit(text_editor::Action::Edit(text_editor::Edit::Paste(Arc::new(fixed.to_string())))),
+        );
+        assert_eq!(state.rules[0].raw_block.as_deref(), Some(fixed));
+
+        sync_visual_to_raw(&mut state);
+        let rule = &state.rules[0];
+        assert!(rule.raw_block.is_none());
+        assert_eq!(rule.name, "Odd");
+        assert_eq!(rule.conditions[0].header_names, vec!["X-Odd"]);
+        assert_eq!(rule.actions[0].action_type, ActionType::Discard);
+    }
+
+    #[test]
+    fn test_raw_block_with_two_rules_survives_sync() {
+        let mut state = Sievers {
+            active_tab: Tab::Visual,
+            editor_content: text_editor::Content::with_text(
+                "# Filter: Odd\nif header :is \"X-Odd\" \"yes\" {\n    frobnicate;\n}\n",
+            ),
+            ..Default::default()
+        };
+        sync_raw_to_visual(&mut state);
+        let _ = update(&mut state, Message::SelectRule(0));
+
+        let edit = |action| Message::RuleMsg(0, RuleMessage::RawBlockAction(action));
+        let replace = |state: &mut Sievers, text: &str| {
+            let _ = update(state, edit(text_editor::Action::SelectAll));
+            let _ = update(
+                state,
+                edit(text_editor::Action::Edit(text_editor::Edit::Paste(Arc::new(text.to_string())))),
+            );
+            sync_visual_to_raw(state);
+        };
+        let two_rules = "# Filter: Odd\nif header :is \"X-Odd\" \"yes\" {\n    discard;\n}\n\n\
+                         # Filter: Even\nif header :is \"X-Even\" \"yes\" {\n    keep;\n}\n";
+
+        // A trailing action keeps the block raw, with all of it intact
+        replace(&mut state, &format!("{two_rules}\nstop;\n"));
+        let text = state.editor_content.text();
+        assert!(text.contains("# Filter: Odd"));
+        assert!(text.contains("# Filter: Even"));
+        assert!(text.contains("stop;"));
+        assert_eq!(state.rules.len(), 1);
+        assert!(state.rules[0].raw_block.as_deref().is_some_and(|b| b.contains("stop;")));
+
+        // Two rules and nothing else become two structured rules
+        replace(&mut state, two_rules);
+        let names: Vec<&str> = state.rules.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["Odd", "Even"]);
+        assert!(state.rules.iter().all(|r| r.raw_block.is_none()));
+        assert_eq!(state.rules[1].actions[0].action_type, ActionType::Keep);
+    }
+
+    #[test]
+    fn test_rule_edits_sync_after_a_pause() {
+        let mut state = Sievers {
+            active_tab: Tab::Visual,
+            ..Default::default()
+        };
+        let _ = update(&mut state, Message::AddRule);
+        let rename = |name: &str| Message::RuleMsg(0, RuleMessage::SetName(name.to_string()));
+
+        // Typing leaves the text alone until the edits go idle
+        let _ = update(&mut state, rename("Li"));
+        let _ = update(&mut state, rename("Lists"));
+        assert!(state.visual_dirty);
+        assert!(!state.editor_content.text().contains("# Filter: Lists"));
+        let _ = update(&mut state, Message::DebounceCheck);
+        assert!(state.visual_dirty);
+
+        state.last_visual_edit = state
+            .last_visual_edit
+            .map(|t| t - std::time::Duration::from_millis(RAW_SYNC_DEBOUNCE_MS));
+        let _ = update(&mut state, Message::DebounceCheck);
+        assert!(!state.visual_dirty);
+        assert!(state.editor_content.text().contains("# Filter: Lists"));
+
+        // The burst was one undo step
+        let _ = update(&mut state, Message::Undo);
+        assert_eq!(state.rules[0].name, "New rule 1");
+
+        // Switching tabs writes pending edits straight away
+        let _ = update(&mut state, rename("Spam"));
+        let _ = update(&mut state, Message::SwitchTab(Tab::Raw));
+        assert!(!state.visual_dirty);
+        assert!(state.editor_content.text().contains("# Filter: Spam"));
+    }
+
+    #[test]
+    fn test_redirect_limit_blocks_upload() {
+        let redirect = |to: &str| SieveRule {
+            actions: vec![Action {
+                action_type: ActionType::Redirect,
+                argument: to.to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut state = Sievers {
+            connected: true,
+            rules: vec![redirect("a@example.org"), redirect("b@example.org")],
+            capabilities: Some(Capabilities {
+                max_redirects: Some(1),
+                ..Default::default()
+            }),
+            active_tab: Tab::Visual,
+            ..Default::default()
+        };
+        assert_eq!(redirect_usage(&state), Some((2, 1)));
+        assert!(redirects_over_limit(&state));
+        let _ = update(&mut state, Message::Upload);
+        assert_eq!(state.status, "Not uploaded: 2 redirects, the server allows 1");
+
+        // A disabled rule's redirect never runs
+        state.rules[1].enabled = false;
+        assert_eq!(redirect_usage(&state), Some((1, 1)));
+        assert!(!redirects_over_limit(&state));
+
+        // No advertised limit, nothing to check
+        state.capabilities = Some(Capabilities::default());
+        assert_eq!(redirect_usage(&state), None);
+        assert!(!redirects_over_limit(&state));
+    }
+
+    #[test]
+    fn test_parse_errors_block_upload() {
+        let mut state = Sievers {
+            connected: true,
+            ..Default::default()
+        };
+        assert!(state.parse_ok);
+
+        let open = |text: &str| Message::FileOpened(Ok((PathBuf::from("a.sieve"), text.to_string())));
+        let _ = update(&mut state, open("if header :contains \"Subject\" {\n"));
+        assert!(!state.parse_ok);
+        let _ = update(&mut state, Message::Upload);
+        assert_eq!(state.status, PARSE_ERRORS_WARNING);
+
+        let _ = update(&mut state, open("require \"fileinto\";\nfileinto \"Lists\";\n"));
+        assert!(state.parse_ok);
+        let _ = update(&mut state, open(""));
+        assert!(state.parse_ok);
+    }
+
+    #[test]
+    fn test_paste_rule() {
+        let mut state = Sievers::default();
+        let pasted = "# Filter: Spam\nif header :contains \"Subject\" \"SPAM\" { discard; }\n";
+        let _ = update(&mut state, Message::RulePasted(Some(pasted.to_string())));
+        assert_eq!(state.rules.len(), 1);
+        assert_eq!(state.rules[0].name, "Spam");
+        assert_eq!(state.selected_rule, Some(0));
+
+        let _ = update(&mut state, Message::RulePasted(Some("not sieve {".to_string())));
+        assert_eq!(state.rules.len(), 1);
+        assert!(state.status.starts_with("Can't paste a rule"));
+    }
+
+    #[test]
+    fn test_tab_for_downloaded_script() {
+        let editable = converter::text_to_script("# Filter: Spam\nif header :contains \"Subject\" \"SPAM\" { discard; }\n", "");
+        assert_eq!(tab_for_rules(&editable.rules), Tab::Visual);
+        let broken = converter::text_to_script("if header {", "");
+        assert_eq!(tab_for_rules(&broken.rules), Tab::Raw);
+        assert_eq!(tab_for_rules(&[]), Tab::Raw);
+
+        let mut state = Sievers::default();
+        let script = "# Filter: Spam\nif header :contains \"Subject\" \"SPAM\" { discard; }\n";
+        let _ = update(&mut state, Message::ScriptDownloaded(Ok(("main".to_string(), script.to_string()))));
+        assert_eq!(state.active_tab, Tab::Visual);
+        assert_eq!(state.settings.last_tab, Tab::Visual);
+        assert_eq!(state.selected_rule, Some(0));
+
+        let _ = update(&mut state, Message::ScriptDownloaded(Ok(("raw".to_string(), "if header {".to_string()))));
+        assert_eq!(state.active_tab, Tab::Raw);
+    }
+
+    #[test]
+    fn test_switch_user_confirms_unsaved_changes() {
+        let mut state = Sievers {
+            current_script_name: Some("main".to_string()),
+            ..Default::default()
+        };
+        let _ = update(&mut state, Message::AddRule);
+        assert!(state.dirty);
+
+        let _ = update(&mut state, Message::ConnectionMsg(ConnectionMessage::SwitchUser));
+        assert_eq!(state.current_script_name.as_deref(), Some("main"));
+        assert_eq!(state.rules.len(), 1);
+        assert!(matches!(state.confirm.as_ref().map(|c| &c.action), Some(Message::SwitchUserConfirmed)));
+    }
+
+    #[test]
+    fn test_new_script_resets_editor() {
+        let mut state = Sievers::default();
+        let opened = "require \"fileinto\";\n# Filter: Lists\nif header :contains \"List-Id\" \"x\" { fileinto \"Lists\"; }\n";
+        let _ = update(
+            &mut state,
+            Message::FileOpened(Ok((PathBuf::from("/tmp/filters.siv"), opened.to_string()))),
+        );
+        state.current_script_name = Some("main".to_string());
+        assert_eq!(state.rules.len(), 1);
+
+        let _ = update(&mut state, Message::NewScript);
+        assert_eq!(state.current_path, None);
+        assert_eq!(state.current_script_name, None);
+        assert!(state.rules.is_empty());
+        assert!(state.declared_requires.is_empty());
+        assert_eq!(state.editor_content.text().trim(), "");
+        assert_eq!(state.status, "New script");
+        assert!(!state.dirty);
+
+        // Unsaved edits need confirming first
+        let _ = update(&mut state, Message::AddRule);
+        let _ = update(&mut state, Message::NewScript);
+        assert_eq!(state.rules.len(), 1);
+        assert!(matches!(state.confirm.as_ref().map(|c| &c.action), Some(Message::NewScriptConfirmed)));
+    }
+
+    #[test]
+    fn test_undo_add_rule() {
+        let mut state = Sievers::default();
+        let _ = update(&mut state, Message::AddRule);
+        assert_eq!(state.rules.len(), 1);
+
+        let _ = update(&mut state, Message::Undo);
+        assert!(state.rules.is_empty());
+        assert_eq!(state.selected_rule, None);
+
+        let _ = update(&mut state, Message::Redo);
+        assert_eq!(state.rules.len(), 1);
+        assert!(state.editor_content.text().contains("# Filter: New rule 1"));
+    }
+
+    #[test]
+    fn test_undo_across_open() {
+        let mut state = Sievers::default();
+        let open = |path: &str, text: &str| Message::FileOpened(Ok((PathBuf::from(path), text.to_string())));
+        let a = "# Script A\nrequire \"fileinto\";\n\n# Filter: Lists\nif true {\n    fileinto \"Lists\";\n}\n";
+        let b = "# Script B\nrequire \"vacation\";\n\n# Filter: Away\nif true {\n    vacation \"Away\";\n}\n";
+        let _ = update(&mut state, open("a.sieve", a));
+        let _ = update(&mut state, open("b.sieve", b));
+
+        // Back to A, header and requires included
+        let _ = update(&mut state, Message::Undo);
+        assert_eq!(state.rules[0].name, "Lists");
+        let _ = update(&mut state, Message::RuleMsg(0, RuleMessage::SetName("Mailing lists".to_string())));
+        flush_visual_edits(&mut state);
+        let text = state.editor_content.text();
+        assert!(text.starts_with("# Script A\n"), "{text}");
+        assert!(text.contains("\nrequire \"fileinto\";\n"), "{text}");
+        assert!(!text.contains("vacation") && !text.contains("Script B"), "{text}");
+        assert!(text.contains("# Filter: Mailing lists"), "{text}");
+    }
+
+    #[test]
+    fn test_undo_stack_is_capped() {
+        let mut state = Sievers::default();
+        for _ in 0..UNDO_LIMIT + 10 {
+            let _ = update(&mut state, Message::AddRule);
+        }
+        assert_eq!(state.undo_stack.len(), UNDO_LIMIT);
+    }
+
+    #[test]
+    fn test_dirty_set_by_edits_and_cleared_on_save() {
+        let mut state = Sievers::default();
+        assert!(!state.dirty);
+
+        let _ = update(&mut state, Message::AddRule);
+        assert!(state.dirty);
+        let _ = update(&mut state, Message::FileSaved(Ok(PathBuf::from("/tmp/test.sieve"))));
+        assert!(!state.dirty);
+        assert_eq!(title(&state), "test.sieve — Sievers");
+
+        let _ = update(&mut state, Message::RuleMsg(0, RuleMessage::SetName("Renamed".to_string())));
+        assert!(state.dirty);
+        assert_eq!(title(&state), "* test.sieve — Sievers");
+        let _ = update(&mut state, Message::ScriptUploaded(Ok(("test".to_string(), None))));
+        assert!(!state.dirty);
+    }
+
+    #[test]
+    fn test_opening_a_file_clears_dirty() {
+        let mut state = Sievers::default();
+        let _ = update(&mut state, Message::AddRule);
+        let _ = update(
+            &mut state,
+            Message::FileOpened(Ok((PathBuf::from("a.sieve"), "keep;\n".to_string()))),
+        );
+        assert!(!state.dirty);
+
+        // Selecting or collapsing does not count as a change
+        let _ = update(&mut state, Message::SelectRule(0));
+        assert!(!state.dirty);
+    }
+
+    #[test]
+    fn test_window_geometry_saved_when_settled() {
+        let mut state = Sievers::default();
+        let _ = update(&mut state, Message::WindowResized(iced::Size::new(900.0, 700.0)));
+        let _ = update(&mut state, Message::WindowMoved(iced::Point::new(10.0, 20.0)));
+        assert_eq!(state.settings.window_size, (900.0, 700.0));
+        assert_eq!(state.settings.window_pos, Some((10.0, 20.0)));
+
+        // Still moving
+        let _ = update(&mut state, Message::DebounceCheck);
+        assert!(state.last_geometry_change.is_some());
+        state.last_geometry_change = Instant::now()
+            .checked_sub(std::time::Duration::from_millis(RAW_SYNC_DEBOUNCE_MS));
+        let _ = update(&mut state, Message::DebounceCheck);
+        assert!(state.last_geometry_change.is_none());
+
+        // Or when the window closes
+        let _ = update(&mut state, Message::WindowMoved(iced::Point::new(0.0, 0.0)));
+        let _ = update(&mut state, Message::CloseRequested(iced::window::Id::unique()));
+        assert!(state.last_geometry_change.is_none());
+    }
+
+    #[test]
+    fn test_close_request_held_back_when_dirty() {
+        let mut state = Sievers::default();
+        let _ = update(&mut state, Message::AddRule);
+        let id = iced::window::Id::unique();
+        let _ = update(&mut state, Message::CloseRequested(id));
+        assert_eq!(state.pending_close, Some(id));
+        let _ = update(&mut state, Message::CloseMsg(CloseMessage::Cancel));
+        assert_eq!(state.pending_close, None);
+    }
+
+    #[test]
+    fn test_confirm_delete_script() {
+        let mut state = Sievers::default();
+        let _ = update(
+            &mut state,
+            Message::ScriptListMsg(ScriptListMessage::ConfirmDelete("old".to_string())),
+        );
+        assert!(state.confirm.is_some());
+        assert_eq!(state.status, "Ready");
+
+        let _ = update(&mut state, Message::ConfirmMsg(ConfirmMessage::Confirm));
+        assert!(state.confirm.is_none());
+        assert_eq!(state.status, "Deleting old...");
+    }
+
+    #[test]
+    fn test_cancel_leaves_rule_in_place() {
+        let mut state = Sievers::default();
+        let _ = update(&mut state, Message::AddRule);
+        let _ = update(&mut state, Message::ConfirmRemoveRule(0));
+        assert!(state.confirm.is_some());
+        assert_eq!(state.rules.len(), 1);
+
+        let _ = update(&mut state, Message::ConfirmMsg(ConfirmMessage::Cancel));
+        assert!(state.confirm.is_none());
+        assert_eq!(state.rules.len(), 1);
+
+        let _ = update(&mut state, Message::ConfirmRemoveRule(0));
+        let _ = update(&mut state, Message::ConfirmMsg(ConfirmMessage::Confirm));
+        assert!(state.rules.is_empty());
+    }
+
+    #[test]
+    fn test_upload_plan() {
+        let scripts = vec![
+            ScriptInfo {
+                name: "live".to_string(),
+                active: true,
+            },
+            ScriptInfo {
+                name: "draft".to_string(),
+                active: false,
+            },
+        ];
+        let with_check = Capabilities {
+            version: Some("1.0".to_string()),
+            ..Default::default()
+        };
+        let without_check = Capabilities::default();
+
+        assert_eq!(upload_plan(&scripts, Some(&with_check), "draft"), UploadPlan::Direct);
+        assert_eq!(upload_plan(&scripts, Some(&without_check), "new"), UploadPlan::Direct);
+        assert_eq!(upload_plan(&scripts, Some(&with_check), "live"), UploadPlan::CheckFirst);
+        assert_eq!(upload_plan(&scripts, Some(&without_check), "live"), UploadPlan::Confirm);
+        assert_eq!(upload_plan(&scripts, None, "live"), UploadPlan::Confirm);
+    }
+
+    #[test]
+    fn test_rejected_check_does_not_upload() {
+        let mut state = Sievers::default();
+        let _ = update(
+            &mut state,
+            Message::ActiveUploadChecked(Err("line 3: unknown command".to_string())),
+        );
+        assert!(state.status.starts_with("Not uploaded"));
+        assert!(state.status.contains("line 3"));
+    }
+
+    #[test]
+    fn test_untrusted_certificate_offers_to_pin() {
+        let mut state = Sievers::default();
+        let _ = update(&mut state, Message::CertificateUntrusted("AB:CD".to_string()));
+        let confirm = state.confirm.as_ref().expect("trust prompt");
+        assert!(confirm.body.contains("AB:CD"));
+        assert!(matches!(&confirm.action, Message::TrustCertificate(f) if f == "AB:CD"));
+
+        let _ = update(&mut state, Message::ConfirmMsg(ConfirmMessage::Confirm));
+        assert_eq!(state.connection.tls_fingerprint.as_deref(), Some("AB:CD"));
+        assert_eq!(state.connection.to_profile().tls_fingerprint.as_deref(), Some("AB:CD"));
+    }
+
+    #[test]
+    fn test_changed_certificate_warns() {
+        let mut state = Sievers::default();
+        state.connection.tls_fingerprint = Some("AB:CD".to_string());
+        let _ = update(&mut state, Message::CertificateUntrusted("EF:01".to_string()));
+        let confirm = state.confirm.as_ref().expect("trust prompt");
+        assert_eq!(confirm.title, "Certificate changed");
+        assert!(confirm.body.contains("Trusted: AB:CD"));
+        assert!(confirm.body.contains("Presented: EF:01"));
+        assert!(state.status.contains("has changed"));
+        assert!(matches!(&confirm.action, Message::TrustCertificate(f) if f == "EF:01"));
+    }
+
+    #[test]
+    fn test_add_rule_from_template() {
+        let mut state = Sievers::default();
+        let _ = update(&mut state, Message::AddRule);
+        let _ = update(&mut state, Message::ShowTemplates);
+        assert!(state.templates.visible);
+        let _ = update(
+            &mut state,
+            Message::TemplateMsg(TemplateMessage::Select(Template::MoveMailingList)),
+        );
+        let _ = update(&mut state, Message::TemplateMsg(TemplateMessage::Add));
+        assert!(!state.templates.visible);
+        assert_eq!(state.rules.len(), 2);
+        assert_eq!(state.rules[1].name, "Move mailing list");
+        assert_eq!(state.selected_rule, Some(1));
+        assert!(state.editor_content.text().contains("\"List-Id\""));
+    }
+
+    #[test]
+    fn test_editor_shortcut_keys() {
+        use iced::keyboard::key::Named;
+        use iced::keyboard::{Key, Modifiers};
+
+        let press = |key: Key, modifiers| match handle_key_press(key, modifiers) {
+            Some(Message::Shortcut(shortcut)) => Some(shortcut),
+            _ => None,
+        };
+        let enter = || Key::Named(Named::Enter);
+        assert_eq!(press(enter(), Modifiers::CTRL), Some(AddShortcut::Condition));
+        assert_eq!(
+            press(enter(), Modifiers::CTRL | Modifiers::SHIFT),
+            Some(AddShortcut::Action)
+        );
+        assert_eq!(
+            press(Key::Character("N".into()), Modifiers::CTRL | Modifiers::SHIFT),
+            Some(AddShortcut::Rule)
+        );
+        assert!(matches!(
+            handle_key_press(Key::Character("n".into()), Modifiers::CTRL),
+            Some(Message::NewScript)
+        ));
+        assert_eq!(press(enter(), Modifiers::empty()), None);
+        assert_eq!(press(Key::Character("n".into()), Modifiers::empty()), None);
+    }
+
+    #[test]
+    fn test_step_selection() {
+        use RuleStep::*;
+
+        let all = [0, 1, 2, 3];
+        assert_eq!(step_selection(&all, Some(1), Next), Some(2));
+        assert_eq!(step_selection(&all, Some(1), Previous), Some(0));
+        // Steps stop at either end
+        assert_eq!(step_selection(&all, Some(3), Next), Some(3));
+        assert_eq!(step_selection(&all, Some(0), Previous), Some(0));
+        assert_eq!(step_selection(&all, Some(2), First), Some(0));
+        assert_eq!(step_selection(&all, Some(1), Last), Some(3));
+        // Nothing selected yet
+        assert_eq!(step_selection(&all, None, Next), Some(0));
+        assert_eq!(step_selection(&all, None, Previous), Some(3));
+        assert_eq!(step_selection(&[0], Some(0), Next), Some(0));
+        assert_eq!(step_selection(&[], None, Next), None);
+        assert_eq!(step_selection(&[], Some(2), First), None);
+
+        // Rules 1 and 3 hidden by the search
+        let shown = [0, 2, 4];
+        assert_eq!(step_selection(&shown, Some(2), Next), Some(4));
+        assert_eq!(step_selection(&shown, Some(3), Previous), Some(2));
+        assert_eq!(step_selection(&shown, Some(3), Next), Some(4));
+        assert_eq!(step_selection(&shown, Some(5), Next), Some(4));
+        assert_eq!(step_selection(&shown, Some(1), Last), Some(4));
+    }
+
+    #[test]
+    fn test_arrow_keys_move_selection() {
+        use iced::keyboard::key::Named;
+        use iced::keyboard::{Key, Modifiers};
+
+        let mut state = Sievers {
+            active_tab: Tab::Visual,
+            ..Default::default()
+        };
+        for _ in 0..3 {
+            let _ = update(&mut state, Message::AddRule);
+        }
+        let press = |state: &mut Sievers, key| {
+            if let Some(msg) = handle_key_press(Key::Named(key), Modifiers::empty()) {
+                let _ = update(state, msg);
+            }
+        };
+        press(&mut state, Named::Home);
+        assert_eq!(state.selected_rule, Some(0));
+        press(&mut state, Named::ArrowDown);
+        assert_eq!(state.selected_rule, Some(1));
+        press(&mut state, Named::End);
+        press(&mut state, Named::ArrowDown);
+        assert_eq!(state.selected_rule, Some(2));
+        press(&mut state, Named::ArrowUp);
+        assert_eq!(state.selected_rule, Some(1));
+
+        // Only the Visual tab moves the selection
+        let _ = update(&mut state, Message::SwitchTab(Tab::Raw));
+        press(&mut state, Named::Home);
+        assert_eq!(state.selected_rule, Some(1));
+    }
+
+    #[test]
+    fn test_shortcut_message_follows_editor_state() {
+        let mut state = Sievers {
+            rules: vec![
+                SieveRule::default(),
+                SieveRule {
+                    raw_block: Some("if true { stop; }".to_string()),
+                    ..Default::default()
+                },
+            ],
+            active_tab: Tab::Visual,
+            ..Default::default()
+        };
+        // Nothing selected: only new rules
+        assert!(matches!(shortcut_message(&state, AddShortcut::Rule), Some(Message::AddRule)));
+        assert!(shortcut_message(&state, AddShortcut::Condition).is_none());
+
+        state.selected_rule = Some(0);
+        assert!(matches!(
+            shortcut_message(&state, AddShortcut::Condition),
+            Some(Message::RuleMsg(0, RuleMessage::AddCondition))
+        ));
+        assert!(matches!(
+            shortcut_message(&state, AddShortcut::Action),
+            Some(Message::RuleMsg(0, RuleMessage::AddAction))
+        ));
+
+        // Raw rules have no conditions to add to
+        state.selected_rule = Some(1);
+        assert!(shortcut_message(&state, AddShortcut::Action).is_none());
+
+        // The raw editor keeps its keys
+        state.selected_rule = Some(0);
+        state.active_tab = Tab::Raw;
+        assert!(shortcut_message(&state, AddShortcut::Rule).is_none());
+        assert!(shortcut_message(&state, AddShortcut::Condition).is_none());
+
+        state.active_tab = Tab::Visual;
+        let _ = update(&mut state, Message::Shortcut(AddShortcut::Action));
+        assert_eq!(state.rules[0].actions.len(), 1);
+    }
+
+    fn find(state: &mut Sievers, query: &str) {
+        let _ = update(state, Message::FindMsg(FindMessage::SetQuery(query.to_string())));
+    }
+
+    #[test]
+    fn test_resize_clamps() {
+        let mut state = Sievers::default();
+        // Moves without a drag change nothing
+        let _ = update(&mut state, Message::ResizeTo(320.0));
+        assert_eq!(state.settings.script_list_width, 200.0);
+
+        let _ = update(&mut state, Message::StartResize(Splitter::ScriptList));
+        let _ = update(&mut state, Message::ResizeTo(320.0));
+        assert_eq!(state.settings.script_list_width, 320.0);
+        let _ = update(&mut state, Message::ResizeTo(20.0));
+        assert_eq!(state.settings.script_list_width, 140.0);
+        let _ = update(&mut state, Message::ResizeTo(2000.0));
+        assert_eq!(state.settings.script_list_width, 400.0);
+        let _ = update(&mut state, Message::EndResize);
+        let _ = update(&mut state, Message::ResizeTo(300.0));
+        assert_eq!(state.settings.script_list_width, 400.0);
+
+        // The rule sidebar is measured from the script list when connected
+        let _ = update(&mut state, Message::StartResize(Splitter::RuleSidebar));
+        let _ = update(&mut state, Message::ResizeTo(300.0));
+        assert_eq!(state.settings.rule_sidebar_width, 300.0);
+        state.connected = true;
+        let _ = update(&mut state, Message::ResizeTo(705.0));
+        assert_eq!(state.settings.rule_sidebar_width, 300.0);
+        let _ = update(&mut state, Message::ResizeTo(450.0));
+        assert_eq!(state.settings.rule_sidebar_width, 180.0);
+        let _ = update(&mut state, Message::ResizeTo(1500.0));
+        assert_eq!(state.settings.rule_sidebar_width, 500.0);
+    }
+
+    #[test]
+    fn test_problems_follow_the_script() {
+        let text = "# Filter: Lists\nif exists \"List-Id\" { fileinto \"Lists\"; }\n";
+        let mut state = Sievers {
+            editor_content: text_editor::Content::with_text(text),
+            ..Default::default()
+        };
+        sync_raw_to_visual(&mut state);
+        assert_eq!(
+            state.problems.lints.iter().map(|l| l.kind.to_string()).collect::<Vec<_>>(),
+            vec!["uses \"fileinto\" but the script doesn't require it"]
+        );
+
+        let _ = update(&mut state, Message::ProblemsMsg(ProblemsMessage::Toggle));
+        assert!(state.problems.visible);
+        let _ = update(&mut state, Message::ProblemsMsg(ProblemsMessage::Show(0)));
+        assert!(!state.problems.visible);
+        assert_eq!(state.active_tab, Tab::Visual);
+        assert_eq!(state.selected_rule, Some(0));
+
+        // Regenerated from the rules, the require is written
+        sync_visual_to_raw(&mut state);
+        assert!(state.problems.lints.is_empty());
+    }
+
+    #[test]
+    fn test_format_script() {
+        let ugly = "require \"fileinto\";\n# Filter: Lists\nif exists \"List-Id\"{fileinto \"Lists\";}\n";
+        let mut state = Sievers {
+            editor_content: text_editor::Content::with_text(ugly),
+            ..Default::default()
+        };
+        let _ = update(&mut state, Message::FormatScript);
+        let expected = converter::format_text(ugly, &EmitOptions::default()).unwrap();
+        assert_eq!(
+            expected,
+            "require \"fileinto\";\n\n# Filter: Lists\nif exists \"List-Id\" {\n    fileinto \"Lists\";\n}\n"
+        );
+        assert_eq!(state.editor_content.text().trim_end(), expected.trim_end());
+        assert!(state.dirty);
+        assert_eq!(state.rules[0].name, "Lists");
+
+        let _ = update(&mut state, Message::Undo);
+        assert_eq!(state.editor_content.text().trim_end(), ugly.trim_end());
+
+        let broken = "if exists \"List-Id\" { fileinto \"Lists\";\n";
+        let mut state = Sievers {
+            editor_content: text_editor::Content::with_text(broken),
+            ..Default::default()
+        };
+        let _ = update(&mut state, Message::FormatScript);
+        assert_eq!(state.editor_content.text().trim_end(), broken.trim_end());
+        assert!(!state.dirty);
+        assert!(state.status.starts_with("Can't format the script"));
+    }
+
+    #[test]
+    fn test_replace_all_updates_buffer() {
+        let mut state = Sievers {
+            editor_content: text_editor::Content::with_text(
+                "# Filter: A\nif address :domain \"from\" \"old.org\" {\n    fileinto \"old.org\";\n}\n",
+            ),
+            ..Default::default()
+        };
+        sync_raw_to_visual(&mut state);
+        find(&mut state, "OLD.org");
+        let _ = update(&mut state, Message::FindMsg(FindMessage::SetReplacement("new.org".to_string())));
+        let _ = update(&mut state, Message::FindMsg(FindMessage::ReplaceAll));
+
+        assert!(state.dirty);
+        assert!(!state.editor_content.text().contains("old.org"));
+        assert_eq!(state.rules[0].conditions[0].keys, vec!["new.org"]);
+        assert_eq!(state.rules[0].actions[0].argument, "new.org");
+        assert_eq!(state.status, "Replaced 2 occurrences");
+
+        // Undo brings the old text back
+        let _ = update(&mut state, Message::Undo);
+        assert!(state.editor_content.text().contains("old.org"));
     }
-}
 
-fn handle_condition_message(conditions: &mut Vec<Condition>, idx: usize, msg: ConditionMessage) {
-    match msg {
-        ConditionMessage::SetTestType(opt) => conditions[idx].test_type = opt.0,
-        ConditionMessage::SetMatchType(opt) => conditions[idx].match_type = opt.0,
-        ConditionMessage::SetAddressPart(opt) => conditions[idx].address_part = opt.0,
-        ConditionMessage::SetSizeComparator(opt) => conditions[idx].size_comparator = opt.0,
-        ConditionMessage::SetHeaders(s) => {
-            conditions[idx].header_names = s.split(',').map(|h| h.trim().to_string()).collect();
-        }
-        ConditionMessage::SetValue(s) => {
-            if conditions[idx].test_type == ConditionTest::Size {
-                conditions[idx].size_value = s;
-            } else {
-                conditions[idx].keys = vec![s];
-            }
-        }
-        ConditionMessage::Remove => {
-            conditions.remove(idx);
-        }
+    #[test]
+    fn test_find_next_selects_matches() {
+        let mut state = Sievers {
+            editor_content: text_editor::Content::with_text("keep;\nfileinto \"Junk\";\nfileinto \"junk\";\n"),
+            ..Default::default()
+        };
+        find(&mut state, "junk");
+        let _ = update(&mut state, Message::FindMsg(FindMessage::FindNext));
+        assert_eq!(state.editor_content.selection().as_deref(), Some("Junk"));
+        assert_eq!(state.editor_content.cursor_position().0, 1);
+
+        let _ = update(&mut state, Message::FindMsg(FindMessage::FindNext));
+        assert_eq!(state.editor_content.cursor_position().0, 2);
+        // Wraps around to the first match
+        let _ = update(&mut state, Message::FindMsg(FindMessage::FindNext));
+        assert_eq!(state.editor_content.cursor_position().0, 1);
+
+        find(&mut state, "missing");
+        let _ = update(&mut state, Message::FindMsg(FindMessage::FindNext));
+        assert_eq!(state.status, "No matches for \"missing\"");
     }
-}
 
-fn handle_action_message(actions: &mut Vec<Action>, idx: usize, msg: ActionMessage) {
-    match msg {
-        ActionMessage::SetActionType(opt) => actions[idx].action_type = opt.0,
-        ActionMessage::SetArgument(s) => actions[idx].argument = s,
-        ActionMessage::Remove => {
-            actions.remove(idx);
-        }
+    #[test]
+    fn test_script_to_open() {
+        let scripts = |list: &[(&str, bool)]| -> Vec<ScriptInfo> {
+            list.iter()
+                .map(|(name, active)| ScriptInfo {
+                    name: name.to_string(),
+                    active: *active,
+                })
+                .collect()
+        };
+        let list = scripts(&[("vacation", false), ("main", true), ("test", false)]);
+        assert_eq!(script_to_open(&list, None).as_deref(), Some("main"));
+        assert_eq!(script_to_open(&list, Some("test")).as_deref(), Some("test"));
+        // A default the server doesn't have falls back to the active script
+        assert_eq!(script_to_open(&list, Some("gone")).as_deref(), Some("main"));
+        assert_eq!(script_to_open(&scripts(&[("a", false), ("b", false)]), None), None);
+        assert_eq!(script_to_open(&[], Some("a")), None);
     }
-}
 
-// --- Bidirectional sync ---
+    #[test]
+    fn test_connect_wipes_form_password() {
+        let mut state = Sievers::default();
+        state.connection.visible = true;
+        state.connection.password = "secret".to_string();
+        state.session = Some(Session {
+            profile: ConnectionProfile::default(),
+            password: Zeroizing::new("secret".to_string()),
+        });
+        let _ = update(&mut state, Message::Connected(Ok((Capabilities::default(), None, Vec::new()))));
+        assert!(!state.connection.visible);
+        assert!(state.connection.password.is_empty());
+        assert_eq!(state.session.as_ref().unwrap().password.as_str(), "secret");
+    }
 
-fn sync_visual_to_raw(state: &mut Sievers) {
-    state.syncing = true;
-    let script = crate::model::script::SieveScript {
-        rules: state.rules.clone(),
-        ..Default::default()
-    };
-    let text = converter::script_to_text(&script);
-    state.editor_content = text_editor::Content::with_text(&text);
-    state.raw_dirty = false;
-    state.last_raw_edit = None;
-    state.syncing = false;
-}
+    #[test]
+    fn test_connected_status() {
+        let tls = Security::Tls {
+            version: "TLS1.3".to_string(),
+            cipher: "TLS13_AES_256_GCM_SHA384".to_string(),
+        };
+        assert_eq!(
+            connected_status(Some(&tls)),
+            "Connected (TLS1.3, TLS13_AES_256_GCM_SHA384)"
+        );
+        assert_eq!(
+            connected_status(Some(&Security::Plaintext)),
+            "Connected (INSECURE, not encrypted)"
+        );
 
-fn sync_raw_to_visual(state: &mut Sievers) {
-    state.syncing = true;
-    let text = state.editor_content.text();
-    let script = converter::text_to_script(&text, "");
-    state.rules = script.rules;
-    state.raw_dirty = false;
-    state.last_raw_edit = None;
-    // Auto-select first rule if none selected
-    if !state.rules.is_empty() && state.selected_rule.is_none() {
-        state.selected_rule = Some(0);
+        let mut state = Sievers::default();
+        let _ = update(&mut state, Message::Connected(Ok((Capabilities::default(), Some(tls.clone()), Vec::new()))));
+        assert_eq!(state.status, "Connected (TLS1.3, TLS13_AES_256_GCM_SHA384)");
+        assert_eq!(state.security, Some(tls));
+        let _ = update(&mut state, Message::Connect);
+        assert_eq!(state.security, None);
     }
-    // Clamp selected_rule if rules shrunk
-    if let Some(sel) = state.selected_rule {
-        if sel >= state.rules.len() {
-            state.selected_rule = if state.rules.is_empty() {
-                None
-            } else {
-                Some(state.rules.len() - 1)
-            };
-        }
+
+    #[test]
+    fn test_connect_open() {
+        assert_eq!(connect_open(OpenOnConnect::Always, false), ConnectOpen::Open);
+        assert_eq!(connect_open(OpenOnConnect::Always, true), ConnectOpen::Ask);
+        assert_eq!(connect_open(OpenOnConnect::Ask, false), ConnectOpen::Ask);
+        assert_eq!(connect_open(OpenOnConnect::Ask, true), ConnectOpen::Ask);
+        assert_eq!(connect_open(OpenOnConnect::Never, false), ConnectOpen::Keep);
+        assert_eq!(connect_open(OpenOnConnect::Never, true), ConnectOpen::Keep);
+
+        // Left alone, unsaved edits get no prompt either
+        let mut state = Sievers {
+            dirty: true,
+            settings: AppSettings {
+                open_on_connect: OpenOnConnect::Never,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let scripts = vec![ScriptInfo {
+            name: "main".to_string(),
+            active: true,
+        }];
+        let _ = update(&mut state, Message::Connected(Ok((Capabilities::default(), None, scripts))));
+        assert!(state.connected);
+        assert!(state.confirm.is_none());
+        assert!(state.dirty);
     }
-    state.syncing = false;
-}
 
-// --- View ---
+    #[test]
+    fn test_connect_asks_before_replacing_unsaved_edits() {
+        let mut state = Sievers {
+            dirty: true,
+            ..Default::default()
+        };
+        let scripts = vec![ScriptInfo {
+            name: "main".to_string(),
+            active: true,
+        }];
+        let _ = update(&mut state, Message::Connected(Ok((Capabilities::default(), None, scripts))));
+        assert!(state.connected);
+        let confirm = state.confirm.as_ref().expect("dirty guard");
+        assert!(matches!(
+            &confirm.action,
+            Message::ScriptListMsg(ScriptListMessage::SelectScript(name)) if name == "main"
+        ));
+        assert_eq!(state.current_script_name, None);
+    }
 
-pub fn view(state: &Sievers) -> Element<'_, Message> {
-    let toolbar = ui::toolbar::view(state.connected, state.dark_mode);
-    let tab_bar = view_tab_bar(state.active_tab);
+    #[test]
+    fn test_connection_test_status() {
+        let caps = Capabilities {
+            implementation: Some("Dovecot Pigeonhole".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            test_status("mail.example.com", Ok(caps)),
+            TestStatus::Passed("Logged in to mail.example.com (Dovecot Pigeonhole)".to_string())
+        );
+        assert_eq!(
+            test_status("mail.example.com", Ok(Capabilities::default())),
+            TestStatus::Passed("Logged in to mail.example.com".to_string())
+        );
+        assert_eq!(
+            test_status("mail.example.com", Err(managesieve::Error::AuthFailed)),
+            TestStatus::Failed("Authentication failed".to_string())
+        );
+        assert_eq!(
+            test_status("mail.example.com", Err(managesieve::Error::Timeout)),
+            TestStatus::Failed("mail.example.com did not respond in time".to_string())
+        );
+        assert!(matches!(
+            test_status("mail.example.com", Err(managesieve::Error::UntrustedCertificate("ab:cd".to_string()))),
+            TestStatus::Failed(m) if m.contains("not trusted")
+        ));
+    }
 
-    let editor_area = match state.active_tab {
-        Tab::Visual => ui::visual_editor::view(&state.rules, state.selected_rule),
-        Tab::Raw => ui::raw_editor::view(&state.editor_content),
-    };
+    #[test]
+    fn test_connection_test_requires_credentials() {
+        let mut state = Sievers::default();
+        let _ = handle_connection_message(&mut state, ConnectionMessage::Test);
+        assert!(matches!(state.connection.test_status, Some(TestStatus::Failed(_))));
+        assert!(!state.connected);
+        assert!(state.session.is_none());
 
-    let status_bar = ui::status_bar::view(&state.status);
+        // Editing the form clears the result
+        let _ = handle_connection_message(&mut state, ConnectionMessage::SetHost("x".to_string()));
+        assert!(state.connection.test_status.is_none());
+    }
 
-    // Main layout: optional sidebar + editor
-    let main_content: Element<'_, Message> = if state.connected {
-        let sidebar = ui::script_list::view(
-            &state.server_scripts,
-            state.selected_script.as_deref(),
-        )
-        .map(Message::ScriptListMsg);
+    #[test]
+    fn test_server_bye_disconnects() {
+        let mut state = Sievers {
+            connected: true,
+            server_scripts: vec![ScriptInfo {
+                name: "main".to_string(),
+                active: true,
+            }],
+            ..Default::default()
+        };
+        let error = managesieve::Error::ServerClosed("Session timeout".to_string());
+        let _ = update(&mut state, Message::ScriptDownloaded(Err(error.to_string())));
+        assert!(!state.connected);
+        assert!(state.server_scripts.is_empty());
+        assert_eq!(state.status, "Disconnected by the server: Session timeout");
+        let confirm = state.confirm.as_ref().expect("reconnect prompt");
+        assert_eq!(confirm.confirm_label, "Reconnect");
+        assert!(matches!(confirm.action, Message::Reconnect));
 
-        row![sidebar, column![tab_bar, editor_area].width(Length::Fill)]
-            .width(Length::Fill)
-            .height(Length::Fill)
-            .into()
-    } else {
-        column![tab_bar, editor_area]
-            .width(Length::Fill)
-            .height(Length::Fill)
-            .into()
-    };
+        // Other failures leave the connection alone
+        let mut state = Sievers {
+            connected: true,
+            ..Default::default()
+        };
+        let _ = update(&mut state, Message::ScriptDeleted(Err("Server error: NO".to_string())));
+        assert!(state.connected);
+        assert!(state.confirm.is_none());
+    }
 
-    let mut content: Element<'_, Message> =
-        column![toolbar, main_content, status_bar].into();
+    #[test]
+    fn test_connection_lost_errors() {
+        let io = std::io::Error::new(std::io::ErrorKind::BrokenPipe, "broken pipe");
+        assert!(is_connection_lost(&managesieve::Error::Io(io)));
+        assert!(is_connection_lost(&managesieve::Error::Protocol("Connection closed".to_string())));
+        assert!(!is_connection_lost(&managesieve::Error::Protocol("Bad literal".to_string())));
+        assert!(!is_connection_lost(&managesieve::Error::Server("NO".to_string())));
+        assert!(!is_connection_lost(&managesieve::Error::AuthFailed));
+        assert!(!is_connection_lost(&managesieve::Error::Timeout));
+    }
 
-    // Connection modal overlay
-    if state.connection.visible {
-        content = iced::widget::stack![
-            content,
-            ui::connection_modal::view(&state.connection).map(Message::ConnectionMsg),
-        ]
-        .into();
+    #[tokio::test]
+    async fn test_retry_only_after_connection_loss() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let client = Arc::new(Mutex::new(ManageSieveClient::new()));
+        let calls = AtomicUsize::new(0);
+        let failing_op = |error: fn() -> managesieve::Error| {
+            let calls = &calls;
+            move |_client| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                async move { Err::<(), _>(error()) }
+            }
+        };
+
+        // A refused command is reported straight away
+        let result = reconnect_and_retry(
+            client.clone(),
+            None,
+            failing_op(|| managesieve::Error::Server("NO".to_string())),
+        )
+        .await;
+        assert!(matches!(result, Err(managesieve::Error::Server(_))));
+        assert_eq!(calls.swap(0, Ordering::SeqCst), 1);
+
+        // A dropped connection without credentials can't be restored
+        let result = reconnect_and_retry(
+            client.clone(),
+            None,
+            failing_op(|| managesieve::Error::Protocol("Connection closed".to_string())),
+        )
+        .await;
+        assert!(matches!(result, Err(managesieve::Error::Protocol(_))));
+        assert_eq!(calls.swap(0, Ordering::SeqCst), 1);
+
+        // Reconnecting fails too (nothing listens there): no second attempt
+        let session = Session {
+            profile: ConnectionProfile {
+                host: "127.0.0.1".to_string(),
+                port: 1,
+                ..Default::default()
+            },
+            password: Zeroizing::new("secret".to_string()),
+        };
+        let result = reconnect_and_retry(
+            client,
+            Some(session),
+            failing_op(|| managesieve::Error::Protocol("Connection closed".to_string())),
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
     }
 
-    // About modal overlay
-    if state.about.visible {
-        content = iced::widget::stack![
-            content,
-            ui::about_modal::view(&state.about).map(Message::AboutMsg),
-        ]
-        .into();
+    #[test]
+    fn test_export_summary_counts_failures() {
+        let dir = std::env::temp_dir().join(format!("sievers-export-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let manifest = script_io::plan_export([("main", true), ("broken", false)]);
+        let mut state = Sievers {
+            export: Some(ExportState {
+                dir: dir.clone(),
+                queue: manifest.scripts[1..].to_vec().into(),
+                total: 2,
+                exported: Vec::new(),
+                failed: 0,
+            }),
+            ..Default::default()
+        };
+
+        // "main" arrives and "broken" is fetched next, which then fails
+        let _ = update(&mut state, Message::ScriptExported(Ok(manifest.scripts[0].clone())));
+        assert_eq!(state.status, "Exporting 2 of 2: broken...");
+        let _ = update(&mut state, Message::ScriptExported(Err("NO".to_string())));
+        assert!(state.export.is_none());
+        assert!(state.status.starts_with("Exported 1 of 2 script(s)"));
+        assert!(state.status.ends_with("1 failed"));
+
+        let written: script_io::Manifest =
+            serde_json::from_str(&std::fs::read_to_string(dir.join(script_io::MANIFEST_FILE)).unwrap())
+                .unwrap();
+        assert_eq!(written.scripts, vec![manifest.scripts[0].clone()]);
+        let _ = std::fs::remove_dir_all(&dir);
     }
 
-    content
-}
+    #[test]
+    fn test_folder_sync_records_pulls() {
+        let dir = std::env::temp_dir().join(format!("sievers-sync-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let entry = |name: &str, status| SyncEntry {
+            name: name.to_string(),
+            file: format!("{name}.siv"),
+            status,
+        };
+        let mut state = Sievers {
+            folder_sync: FolderSyncState {
+                dir: dir.clone(),
+                ..Default::default()
+            },
+            server_scripts: vec![ScriptInfo {
+                name: "main".to_string(),
+                active: true,
+            }],
+            ..Default::default()
+        };
+        let entries = vec![
+            entry("draft", SyncStatus::LocalOnly { synced: false }),
+            entry("main", SyncStatus::ServerOnly { synced: false }),
+            entry("spam", SyncStatus::Same),
+        ];
+        let _ = update(&mut state, Message::SyncCompared(Ok(entries)));
+        assert!(state.folder_sync.visible);
 
-fn view_tab_bar(active: Tab) -> Element<'static, Message> {
-    let visual_style = if active == Tab::Visual {
-        iced::widget::button::primary
-    } else {
-        iced::widget::button::secondary
-    };
-    let raw_style = if active == Tab::Raw {
-        iced::widget::button::primary
-    } else {
-        iced::widget::button::secondary
-    };
+        let _ = update(&mut state, Message::SyncPulled(Ok("main".to_string())));
+        assert_eq!(state.status, "Pulled main into the folder");
+        assert_eq!(state.folder_sync.entries[1].status, SyncStatus::Same);
+        // The folder's manifest now lists the scripts on both sides
+        let written = script_io::read_manifest(&dir).unwrap();
+        let names: Vec<_> = written.scripts.iter().map(|e| (e.name.as_str(), e.active)).collect();
+        assert_eq!(names, vec![("main", true), ("spam", false)]);
 
-    container(
-        row![
-            iced::widget::button("Visual")
-                .on_press(Message::SwitchTab(Tab::Visual))
-                .style(visual_style),
-            iced::widget::button("Raw")
-                .on_press(Message::SwitchTab(Tab::Raw))
-                .style(raw_style),
-        ]
-        .spacing(2),
-    )
-    .padding([4, 8])
-    .width(Length::Fill)
-    .style(|theme: &Theme| {
-        let palette = theme.palette();
-        container::Style {
-            background: Some(iced::Background::Color(iced::Color::from_rgba(
-                palette.text.r,
-                palette.text.g,
-                palette.text.b,
-                0.03,
-            ))),
-            ..container::Style::default()
-        }
-    })
-    .into()
-}
+        let _ = update(&mut state, Message::SyncPulled(Err("NO".to_string())));
+        assert_eq!(state.status, "Pull failed: NO");
 
-pub fn theme(state: &Sievers) -> Theme {
-    if state.dark_mode {
-        Theme::Dark
-    } else {
-        Theme::Light
+        // Pushing over the active script unchecked needs confirming
+        let _ = update(&mut state, Message::SyncMsg(SyncMessage::Push("main".to_string())));
+        assert!(matches!(
+            state.confirm.as_ref().map(|c| &c.action),
+            Some(Message::SyncPushConfirmed(name)) if name == "main"
+        ));
+        state.confirm = None;
+        let _ = update(&mut state, Message::SyncMsg(SyncMessage::Push("draft".to_string())));
+        assert!(state.confirm.is_none());
+        assert_eq!(state.status, "Pushing draft...");
+        let _ = std::fs::remove_dir_all(&dir);
     }
-}
 
-pub fn subscription(state: &Sievers) -> Subscription<Message> {
-    let mut subs = vec![iced::keyboard::on_key_press(handle_key_press)];
+    #[test]
+    fn test_import_confirms_unchecked_active_script() {
+        let dir = std::env::temp_dir().join(format!("sievers-import-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("main.siv"), "keep;\n").unwrap();
+        let mut state = Sievers {
+            server_scripts: vec![ScriptInfo {
+                name: "main".to_string(),
+                active: true,
+            }],
+            ..Default::default()
+        };
 
-    if state.raw_dirty && state.last_raw_edit.is_some() {
-        subs.push(
-            iced::time::every(std::time::Duration::from_millis(100))
-                .map(|_| Message::DebounceCheck),
+        // No CHECKSCRIPT to catch a broken script: ask before replacing it
+        let _ = update(&mut state, Message::ImportDirChosen(Some(dir.clone())));
+        assert!(state.import.is_none());
+        let Some(Message::ImportConfirmed(entries)) = state.confirm.as_ref().map(|c| &c.action) else {
+            panic!("Expected a confirmation");
+        };
+        assert_eq!(entries[0].name, "main");
+
+        let _ = update(&mut state, Message::ConfirmMsg(ConfirmMessage::Confirm));
+        assert!(state.import.is_some());
+        assert_eq!(state.status, "Importing 1 of 1: main...");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_import_skips_invalid_scripts() {
+        let entry = |name: &str| ImportEntry {
+            name: name.to_string(),
+            path: PathBuf::from(format!("/tmp/{name}.siv")),
+            active: false,
+        };
+        let mut state = Sievers {
+            import: Some(ImportState {
+                queue: vec![entry("broken"), entry("extra")].into(),
+                check: true,
+                uploaded: 0,
+                invalid: 0,
+                failed: 0,
+                activate: None,
+            }),
+            ..Default::default()
+        };
+
+        let _ = update(
+            &mut state,
+            Message::ScriptImported(ImportOutcome::Uploaded {
+                name: "main".to_string(),
+                active: true,
+            }),
+        );
+        assert_eq!(state.status, "Importing 2 of 3: broken...");
+        let _ = update(&mut state, Message::ScriptImported(ImportOutcome::Invalid));
+        let _ = update(
+            &mut state,
+            Message::ScriptImported(ImportOutcome::Failed("I/O error".to_string())),
         );
+        assert!(state.import.is_none());
+        assert_eq!(state.status, "1 uploaded, 1 skipped (invalid), 1 failed");
+
+        assert_eq!(import_summary(7, 1, 0), "7 uploaded, 1 skipped (invalid)");
+        assert_eq!(import_summary(2, 0, 0), "2 uploaded");
     }
 
-    Subscription::batch(subs)
-}
+    #[test]
+    fn test_edit_flags() {
+        let mut actions = vec![Action {
+            action_type: ActionType::Addflag,
+            flags: vec!["\\Seen".to_string(), "$Work".to_string()],
+            ..Default::default()
+        }];
+        handle_action_message(&mut actions, 0, ActionMessage::ToggleFlag("\\Flagged", true));
+        handle_action_message(&mut actions, 0, ActionMessage::ToggleFlag("\\Seen", false));
+        assert_eq!(actions[0].flags, vec!["$Work", "\\Flagged"]);
 
-fn handle_key_press(
-    key: iced::keyboard::Key,
-    modifiers: iced::keyboard::Modifiers,
-) -> Option<Message> {
-    use iced::keyboard::key::Named;
-    use iced::keyboard::Key;
+        // The trailing space is kept while typing the next flag
+        handle_action_message(&mut actions, 0, ActionMessage::SetCustomFlags("$Work ".to_string()));
+        assert_eq!(action_row::custom_flags(&actions[0]), "$Work ");
+        handle_action_message(&mut actions, 0, ActionMessage::SetCustomFlags("$Work Junk".to_string()));
+        assert_eq!(actions[0].flags, vec!["\\Flagged", "$Work", "Junk"]);
+        handle_action_message(&mut actions, 0, ActionMessage::SetCustomFlags(String::new()));
+        assert_eq!(actions[0].flags, vec!["\\Flagged"]);
+    }
 
-    if modifiers.control() {
-        match &key {
-            Key::Character(c) if c.as_str() == "o" => Some(Message::OpenFile),
-            Key::Character(c) if c.as_str() == "s" => Some(Message::SaveFile),
-            Key::Character(c) if c.as_str() == "u" => Some(Message::Upload),
-            Key::Character(c) if c.as_str() == "C" && modifiers.shift() => Some(Message::Connect),
-            Key::Named(Named::Tab) => Some(Message::SwitchTab(Tab::Visual)), // Ctrl+Tab toggles
-            _ => None,
+    #[test]
+    fn test_busy_during_server_operations() {
+        let mut state = Sievers {
+            connected: true,
+            ..Default::default()
+        };
+        let _ = update(
+            &mut state,
+            Message::ScriptListMsg(ScriptListMessage::SelectScript("main".to_string())),
+        );
+        assert!(state.busy);
+        assert_eq!(state.status, "Downloading main...");
+
+        // A second operation waits for the first
+        let _ = update(
+            &mut state,
+            Message::ScriptListMsg(ScriptListMessage::ActivateScript("main".to_string())),
+        );
+        assert_eq!(state.status, "Downloading main...");
+        let _ = update(&mut state, Message::ScriptDownloaded(Ok(("main".to_string(), String::new()))));
+        assert!(!state.busy);
+
+        let error = || "I/O error".to_string();
+        let results = vec![
+            Message::Connected(Err(error())),
+            Message::CertificateUntrusted("AB:CD".to_string()),
+            Message::ScriptsLoaded(Err(error())),
+            Message::ScriptDownloaded(Err(error())),
+            Message::ScriptUploaded(Err(error())),
+            Message::ScriptDeleted(Err(error())),
+            Message::ScriptRenamed(Err(error())),
+            Message::ScriptActivated {
+                name: "main".to_string(),
+                result: Err(error()),
+            },
+            Message::Validated(Err(error())),
+            Message::ActiveUploadChecked(Err(error())),
+            Message::DiffLoaded(Err(error())),
+            Message::ScriptExported(Err(error())),
+            Message::ScriptImported(ImportOutcome::Failed(error())),
+        ];
+        for result in results {
+            state.busy = true;
+            let name = format!("{result:?}");
+            let _ = update(&mut state, result);
+            assert!(!state.busy, "{name}");
         }
-    } else {
-        None
+
+        // Results that chain another operation stay busy
+        state.busy = true;
+        let _ = update(&mut state, Message::ScriptUploaded(Ok(("main".to_string(), None))));
+        assert!(state.busy);
+        let _ = update(&mut state, Message::ScriptsLoaded(Ok(Vec::new())));
+        assert!(!state.busy);
+        let _ = update(
+            &mut state,
+            Message::ImportActivated {
+                summary: "Imported 1 script(s)".to_string(),
+                name: "main".to_string(),
+                result: Err(error()),
+            },
+        );
+        assert!(state.busy);
     }
-}
 
-// --- Async file operations ---
+    #[test]
+    fn test_check_active() {
+        let list = |active: &[&str]| {
+            ["main", "spam", "vacation"]
+                .iter()
+                .map(|name| ScriptInfo {
+                    name: name.to_string(),
+                    active: active.contains(name),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(check_active(&list(&["main"]), "main"), Ok(()));
+        assert_eq!(check_active(&list(&[]), ""), Ok(()));
+        // Servers with several active scripts
+        assert_eq!(check_active(&list(&["main", "spam"]), "spam"), Ok(()));
 
-async fn open_file_dialog() -> Result<(PathBuf, String), String> {
-    let handle = rfd::AsyncFileDialog::new()
-        .set_title("Open SIEVE Script")
-        .add_filter("SIEVE Scripts", &["siv", "sieve"])
-        .add_filter("All Files", &["*"])
-        .pick_file()
-        .await;
+        // SETACTIVE answered OK but changed nothing
+        assert_eq!(
+            check_active(&list(&["main"]), "spam"),
+            Err("the server accepted it but lists main as active, not spam".to_string())
+        );
+        assert_eq!(
+            check_active(&list(&[]), "spam"),
+            Err("the server accepted it but lists no active script, not spam".to_string())
+        );
+        assert_eq!(
+            check_active(&list(&["main", "vacation"]), ""),
+            Err("the server accepted it but still lists main, vacation as active".to_string())
+        );
+    }
 
-    match handle {
-        Some(handle) => {
-            let path = handle.path().to_path_buf();
-            let text = script_io::load_script(&path).map_err(|e| e.to_string())?;
-            Ok((path, text))
-        }
-        None => Err("Cancelled".to_string()),
+    #[test]
+    fn test_activation_result() {
+        let mut state = Sievers {
+            connected: true,
+            ..Default::default()
+        };
+        let list = |active: Option<&str>| {
+            ["main", "spam"]
+                .iter()
+                .map(|name| ScriptInfo {
+                    name: name.to_string(),
+                    active: active == Some(*name),
+                })
+                .collect::<Vec<_>>()
+        };
+        let deactivated = |result| Message::ScriptActivated {
+            name: String::new(),
+            result,
+        };
+
+        // The message, not the status bar, says what was asked
+        let _ = update(&mut state, Message::ScriptListMsg(ScriptListMessage::DeactivateScripts));
+        state.status = "Connected".to_string();
+        let refused = "the server refused: Script is included by another".to_string();
+        let _ = update(&mut state, deactivated(Err(refused)));
+        assert_eq!(state.status, "Deactivate error: the server refused: Script is included by another");
+        let _ = update(&mut state, deactivated(Ok(list(None))));
+        assert_eq!(state.status, "Deactivated all scripts");
+
+        // A SETACTIVE the server ignored: the list shows what it did
+        let _ = update(
+            &mut state,
+            Message::ScriptActivated {
+                name: "spam".to_string(),
+                result: Ok(list(Some("main"))),
+            },
+        );
+        assert_eq!(
+            state.status,
+            "Activate error: the server accepted it but lists main as active, not spam"
+        );
+        assert_eq!(state.server_scripts, list(Some("main")));
+        assert!(!state.busy);
     }
-}
 
-async fn save_file_dialog(
-    current_path: Option<PathBuf>,
-    text: String,
-) -> Result<PathBuf, String> {
-    let path = if let Some(path) = current_path {
-        path
-    } else {
-        let handle = rfd::AsyncFileDialog::new()
-            .set_title("Save SIEVE Script")
-            .add_filter("SIEVE Scripts", &["siv", "sieve"])
-            .add_filter("All Files", &["*"])
-            .save_file()
-            .await;
+    #[test]
+    fn test_activate_after_upload() {
+        let mut state = Sievers {
+            connected: true,
+            activate_after_upload: true,
+            ..Default::default()
+        };
+        let uploaded = || Message::ScriptUploaded(Ok(("main".to_string(), None)));
 
-        match handle {
-            Some(handle) => handle.path().to_path_buf(),
-            None => return Err("Cancelled".to_string()),
-        }
-    };
+        // A failed upload activates nothing
+        let _ = update(&mut state, Message::ScriptUploaded(Err("I/O error".to_string())));
+        assert_eq!(state.status, "Upload error: I/O error");
+        assert!(!state.busy);
 
-    script_io::save_script(&path, &text).map_err(|e| e.to_string())?;
-    Ok(path)
+        let _ = update(&mut state, uploaded());
+        assert_eq!(state.status, "Uploaded: main; activating...");
+        assert!(state.busy);
+        let active_main = vec![ScriptInfo {
+            name: "main".to_string(),
+            active: true,
+        }];
+        // Whatever the status bar shows meanwhile
+        state.status = "Connected".to_string();
+        let _ = update(
+            &mut state,
+            Message::UploadActivated {
+                uploaded: "Uploaded: main".to_string(),
+                name: "main".to_string(),
+                result: Ok(active_main.clone()),
+            },
+        );
+        assert_eq!(state.status, "Uploaded: main; activated main");
+        // The list came back with the activation
+        assert_eq!(state.server_scripts, active_main);
+        assert!(!state.busy);
+
+        state.server_scripts.clear();
+        let _ = update(&mut state, uploaded());
+        let _ = update(
+            &mut state,
+            Message::UploadActivated {
+                uploaded: "Uploaded: main".to_string(),
+                name: "main".to_string(),
+                result: Err("NO".to_string()),
+            },
+        );
+        assert_eq!(state.status, "Uploaded: main; activating failed: NO");
+        // Refreshing the list
+        assert!(state.busy);
+
+        // Already active, or the option off: just refresh
+        state.server_scripts = vec![ScriptInfo {
+            name: "main".to_string(),
+            active: true,
+        }];
+        let _ = update(&mut state, Message::ScriptsLoaded(Err("I/O error".to_string())));
+        let _ = update(&mut state, uploaded());
+        assert_eq!(state.status, "Uploaded: main");
+        let _ = update(&mut state, Message::ScriptsLoaded(Ok(Vec::new())));
+        let _ = update(&mut state, Message::SetActivateAfterUpload(false));
+        let _ = update(&mut state, uploaded());
+        assert_eq!(state.status, "Uploaded: main");
+    }
 }
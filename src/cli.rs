@@ -0,0 +1,192 @@
+/// Headless mode: `sievers --upload <file> --profile <name> [--activate]
+/// [--password-file <path>]`.
+///
+/// Uploads a local script with a saved profile and exits, for use from CI.
+/// The password comes from `--password-file`, then `SIEVERS_PASSWORD`, then
+/// the OS keyring when the profile remembers it.
+use std::path::{Path, PathBuf};
+
+use crate::net::managesieve::{self, ManageSieveClient};
+use crate::store::{password_source, profile_store, script_io, secret_store};
+
+pub const USAGE: &str =
+    "Usage: sievers --upload <file> --profile <name> [--activate] [--password-file <path>]";
+
+/// Environment variable holding the password for headless uploads.
+const PASSWORD_VAR: &str = "SIEVERS_PASSWORD";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UploadArgs {
+    pub file: PathBuf,
+    pub profile: String,
+    pub activate: bool,
+    /// Read the password from this file instead of the environment
+    pub password_file: Option<PathBuf>,
+}
+
+/// `Ok(None)` when no headless option was given and the GUI should start.
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<UploadArgs>, String> {
+    let mut file = None;
+    let mut profile = None;
+    let mut activate = false;
+    let mut password_file = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--upload" => file = Some(args.next().ok_or("--upload needs a file")?),
+            "--profile" => profile = Some(args.next().ok_or("--profile needs a profile name")?),
+            "--activate" => activate = true,
+            "--password-file" => {
+                password_file = Some(args.next().ok_or("--password-file needs a path")?)
+            }
+            other => return Err(format!("Unknown argument '{other}'")),
+        }
+    }
+
+    match (file, profile) {
+        (None, None) if !activate && password_file.is_none() => Ok(None),
+        (Some(file), Some(profile)) => Ok(Some(UploadArgs {
+            file: PathBuf::from(file),
+            profile,
+            activate,
+            password_file: password_file.map(PathBuf::from),
+        })),
+        (None, _) => Err("--upload <file> is required".to_string()),
+        (_, None) => Err("--profile <name> is required".to_string()),
+    }
+}
+
+/// Run the upload on a fresh runtime, reporting to stdout/stderr. Returns
+/// the process exit code: 0 on success, 1 on any failure.
+pub fn run(args: UploadArgs) -> i32 {
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return 1;
+        }
+    };
+    match runtime.block_on(upload(&args)) {
+        Ok(message) => {
+            println!("{message}");
+            0
+        }
+        Err(e) => {
+            eprintln!("error: {e}");
+            1
+        }
+    }
+}
+
+async fn upload(args: &UploadArgs) -> Result<String, String> {
+    let profile = profile_store::load_profiles()
+        .into_iter()
+        .find(|p| p.name == args.profile)
+        .ok_or_else(|| format!("No profile named '{}'", args.profile))?;
+    let password = password_source::resolve_password(
+        args.password_file.as_deref(),
+        std::env::var(PASSWORD_VAR).ok(),
+        || {
+            if profile.remember_password {
+                secret_store::load_password(&profile)
+            } else {
+                String::new()
+            }
+        },
+    )?;
+    if password.is_empty() {
+        return Err(format!("No password: use --password-file, set {PASSWORD_VAR} or remember it in the app"));
+    }
+
+    let content = script_io::load_script(&args.file)
+        .map_err(|e| format!("{}: {e}", args.file.display()))?;
+    let name = script_name(&args.file)?;
+
+    let mut client = ManageSieveClient::new();
+    let caps = client
+        .connect(&profile, &password)
+        .await
+        .map_err(|e| format!("Connecting to {}: {e}", profile.display_host()))?;
+
+    let result = async {
+        if caps.supports_checkscript() {
+            client.check_script(&content).await.map_err(|e| match e {
+                managesieve::Error::Server(msg) => {
+                    format!("Script is invalid: {}", managesieve::response_text(&msg))
+                }
+                other => other.to_string(),
+            })?;
+        }
+        if caps.supports_havespace()
+            && !client.have_space(&name, content.len()).await.map_err(|e| e.to_string())?
+        {
+            return Err(format!("Not enough quota for {name}"));
+        }
+        client
+            .put_script(&name, &content)
+            .await
+            .map_err(|e| format!("Upload failed: {e}"))?;
+        if args.activate {
+            client
+                .set_active(&name)
+                .await
+                .map_err(|e| format!("Activating failed: {e}"))?;
+            Ok(format!("Uploaded and activated {name}"))
+        } else {
+            Ok(format!("Uploaded {name}"))
+        }
+    }
+    .await;
+
+    client.disconnect().await;
+    result
+}
+
+/// Scripts go up under their file stem, as in the GUI's import.
+fn script_name(file: &Path) -> Result<String, String> {
+    file.file_stem()
+        .and_then(|s| s.to_str())
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .ok_or_else(|| format!("Can't name a script after {}", file.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Result<Option<UploadArgs>, String> {
+        parse_args(list.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(args(&[]), Ok(None));
+        assert_eq!(
+            args(&["--upload", "rules.siv", "--profile", "work", "--activate"]),
+            Ok(Some(UploadArgs {
+                file: PathBuf::from("rules.siv"),
+                profile: "work".to_string(),
+                activate: true,
+                password_file: None,
+            }))
+        );
+        assert_eq!(
+            args(&["--password-file", "/run/secrets/sieve", "--upload", "rules.siv", "--profile", "work"]),
+            Ok(Some(UploadArgs {
+                file: PathBuf::from("rules.siv"),
+                profile: "work".to_string(),
+                activate: false,
+                password_file: Some(PathBuf::from("/run/secrets/sieve")),
+            }))
+        );
+        assert!(args(&["--password-file", "/run/secrets/sieve"]).is_err());
+        assert!(args(&["--upload", "rules.siv", "--profile", "work", "--password-file"]).is_err());
+        assert!(args(&["--upload", "rules.siv"]).is_err());
+        assert!(args(&["--profile", "work"]).is_err());
+        assert!(args(&["--activate"]).is_err());
+        assert!(args(&["--upload"]).is_err());
+        assert!(args(&["--verbose"]).is_err());
+    }
+}
@@ -1,19 +1,37 @@
 #![allow(dead_code)]
 
 mod app;
+mod cli;
 mod config;
 mod model;
 mod net;
 mod sieve;
 mod store;
 mod ui;
+mod util;
 
 pub fn main() -> iced::Result {
-    iced::application("Sievers — SIEVE Filter Manager", app::update, app::view)
+    match cli::parse_args(std::env::args().skip(1)) {
+        Ok(Some(upload)) => std::process::exit(cli::run(upload)),
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("error: {e}\n{}", cli::USAGE);
+            std::process::exit(2);
+        }
+    }
+
+    let settings = store::settings_store::load_settings();
+    let position = match settings.window_pos {
+        Some((x, y)) => iced::window::Position::Specific(iced::Point::new(x, y)),
+        None => iced::window::Position::Centered,
+    };
+
+    iced::application(app::title, app::update, app::view)
         .subscription(app::subscription)
+        .exit_on_close_request(false)
         .theme(app::theme)
         .font(ui::icons::ICON_FONT_BYTES)
-        .window_size((1000.0, 650.0))
-        .centered()
+        .window_size(settings.window_size)
+        .position(position)
         .run()
 }
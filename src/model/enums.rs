@@ -97,6 +97,28 @@ impl fmt::Display for SizeComparator {
     }
 }
 
+/// What a `body` test looks at (RFC 5173 §5).
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum BodyTransform {
+    /// The body as sent, MIME structure and encodings included
+    Raw,
+    /// The decoded text parts; a test without a transform means this
+    #[default]
+    Text,
+    /// The decoded parts of a media type, e.g. `text/html` or `image`
+    Content(String),
+}
+
+impl BodyTransform {
+    pub fn as_sieve(&self) -> &'static str {
+        match self {
+            Self::Raw => ":raw",
+            Self::Text => ":text",
+            Self::Content(_) => ":content",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LogicOperator {
     AllOf,
@@ -137,6 +159,10 @@ pub enum ActionType {
     Setflag,
     Addflag,
     Removeflag,
+    Vacation,
+    Notify,
+    Addheader,
+    Deleteheader,
 }
 
 impl ActionType {
@@ -151,6 +177,10 @@ impl ActionType {
             Self::Setflag => "setflag",
             Self::Addflag => "addflag",
             Self::Removeflag => "removeflag",
+            Self::Vacation => "vacation",
+            Self::Notify => "notify",
+            Self::Addheader => "addheader",
+            Self::Deleteheader => "deleteheader",
         }
     }
 
@@ -165,6 +195,10 @@ impl ActionType {
             "setflag" => Some(Self::Setflag),
             "addflag" => Some(Self::Addflag),
             "removeflag" => Some(Self::Removeflag),
+            "vacation" => Some(Self::Vacation),
+            "notify" => Some(Self::Notify),
+            "addheader" => Some(Self::Addheader),
+            "deleteheader" => Some(Self::Deleteheader),
             _ => None,
         }
     }
@@ -172,6 +206,16 @@ impl ActionType {
     pub fn takes_argument(&self) -> bool {
         !matches!(self, Self::Discard | Self::Keep | Self::Stop)
     }
+
+    /// Whether the action accepts the `:copy` tag (RFC 3894).
+    pub fn supports_copy(&self) -> bool {
+        matches!(self, Self::Fileinto | Self::Redirect)
+    }
+
+    /// `setflag`, `addflag` and `removeflag` (RFC 5232), which take a flag list.
+    pub fn is_flag_action(&self) -> bool {
+        matches!(self, Self::Setflag | Self::Addflag | Self::Removeflag)
+    }
 }
 
 impl fmt::Display for ActionType {
@@ -191,6 +235,8 @@ pub enum ConditionTest {
     False,
     Not,
     Body,
+    /// `hasflag` (RFC 5232): the message's IMAP flags
+    Hasflag,
 }
 
 impl ConditionTest {
@@ -205,6 +251,7 @@ impl ConditionTest {
             Self::False => "false",
             Self::Not => "not",
             Self::Body => "body",
+            Self::Hasflag => "hasflag",
         }
     }
 
@@ -219,6 +266,7 @@ impl ConditionTest {
             "false" => Some(Self::False),
             "not" => Some(Self::Not),
             "body" => Some(Self::Body),
+            "hasflag" => Some(Self::Hasflag),
             _ => None,
         }
     }
@@ -229,3 +277,11 @@ impl fmt::Display for ConditionTest {
         f.write_str(self.as_sieve())
     }
 }
+
+/// The editor views of a script; remembered in the settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Tab {
+    Visual,
+    #[default]
+    Raw,
+}
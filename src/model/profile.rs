@@ -1,12 +1,54 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::Ipv6Addr;
+use std::path::PathBuf;
+
+/// How the connection to the server is secured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TlsMode {
+    /// Plaintext only
+    None,
+    /// Plaintext greeting, then upgrade with STARTTLS
+    #[default]
+    StartTls,
+    /// TLS from the first byte (direct TLS)
+    Implicit,
+}
+
+impl TlsMode {
+    pub const ALL: &'static [TlsMode] = &[Self::None, Self::StartTls, Self::Implicit];
+}
+
+impl fmt::Display for TlsMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => f.write_str("None (plaintext)"),
+            Self::StartTls => f.write_str("STARTTLS"),
+            Self::Implicit => f.write_str("Implicit TLS"),
+        }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredProfile")]
 pub struct ConnectionProfile {
     pub name: String,
     pub host: String,
     pub port: u16,
     pub username: String,
-    pub use_starttls: bool,
+    pub tls_mode: TlsMode,
+    /// Keep the password in the OS keyring
+    #[serde(default)]
+    pub remember_password: bool,
+    /// SHA-256 fingerprint of a certificate trusted even without a CA chain
+    #[serde(default)]
+    pub tls_fingerprint: Option<String>,
+    /// Script opened after connecting instead of the active one
+    #[serde(default)]
+    pub default_script: Option<String>,
+    /// Folder the profile's scripts are synced with
+    #[serde(default)]
+    pub local_dir: Option<PathBuf>,
 }
 
 impl Default for ConnectionProfile {
@@ -16,7 +58,147 @@ impl Default for ConnectionProfile {
             host: String::new(),
             port: 4190,
             username: String::new(),
-            use_starttls: true,
+            tls_mode: TlsMode::StartTls,
+            remember_password: false,
+            tls_fingerprint: None,
+            default_script: None,
+            local_dir: None,
         }
     }
 }
+
+impl ConnectionProfile {
+    /// Host as shown to the user, with IPv6 literals in brackets.
+    pub fn display_host(&self) -> String {
+        display_host(&self.host)
+    }
+}
+
+/// Host as typed by the user, ready to connect to: trimmed, with the
+/// brackets around an IPv6 literal removed.
+pub fn normalize_host(input: &str) -> String {
+    let host = input.trim();
+    match host.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+        Some(inner) if inner.parse::<Ipv6Addr>().is_ok() => inner.to_string(),
+        _ => host.to_string(),
+    }
+}
+
+/// Inverse of [`normalize_host`]: bracket bare IPv6 literals.
+pub fn display_host(host: &str) -> String {
+    if host.parse::<Ipv6Addr>().is_ok() {
+        format!("[{host}]")
+    } else {
+        host.to_string()
+    }
+}
+
+/// `name` trimmed, with a ` (2)`, ` (3)`, ... suffix when another profile
+/// already uses it. The profile at `own` (the one being saved) doesn't count.
+pub fn unique_name(profiles: &[ConnectionProfile], name: &str, own: Option<usize>) -> String {
+    let name = name.trim();
+    let taken = |candidate: &str| {
+        profiles
+            .iter()
+            .enumerate()
+            .any(|(i, p)| Some(i) != own && p.name == candidate)
+    };
+    if !taken(name) {
+        return name.to_string();
+    }
+    (2..)
+        .map(|n| format!("{name} ({n})"))
+        .find(|candidate| !taken(candidate))
+        .expect("some suffix is free")
+}
+
+/// On-disk profile format, accepting the `use_starttls` flag written by
+/// older versions in place of `tls_mode`.
+#[derive(Deserialize)]
+struct StoredProfile {
+    name: String,
+    host: String,
+    port: u16,
+    username: String,
+    #[serde(default)]
+    tls_mode: Option<TlsMode>,
+    #[serde(default)]
+    use_starttls: Option<bool>,
+    #[serde(default)]
+    remember_password: bool,
+    #[serde(default)]
+    tls_fingerprint: Option<String>,
+    #[serde(default)]
+    default_script: Option<String>,
+    #[serde(default)]
+    local_dir: Option<PathBuf>,
+}
+
+impl From<StoredProfile> for ConnectionProfile {
+    fn from(p: StoredProfile) -> Self {
+        let tls_mode = p.tls_mode.unwrap_or(match p.use_starttls {
+            Some(false) => TlsMode::None,
+            _ => TlsMode::StartTls,
+        });
+        Self {
+            name: p.name,
+            host: p.host,
+            port: p.port,
+            username: p.username,
+            tls_mode,
+            remember_password: p.remember_password,
+            tls_fingerprint: p.tls_fingerprint,
+            default_script: p.default_script,
+            local_dir: p.local_dir,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrates_use_starttls() {
+        let old = r#"{"name":"a","host":"h","port":4190,"username":"u","use_starttls":false}"#;
+        let profile: ConnectionProfile = serde_json::from_str(old).unwrap();
+        assert_eq!(profile.tls_mode, TlsMode::None);
+
+        let json = serde_json::to_string(&ConnectionProfile {
+            tls_mode: TlsMode::Implicit,
+            ..Default::default()
+        })
+        .unwrap();
+        let profile: ConnectionProfile = serde_json::from_str(&json).unwrap();
+        assert_eq!(profile.tls_mode, TlsMode::Implicit);
+    }
+
+    #[test]
+    fn test_normalize_host() {
+        assert_eq!(normalize_host("[2001:db8::1]"), "2001:db8::1");
+        // Brackets around anything but an IPv6 literal are left for the
+        // connect error to report
+        assert_eq!(normalize_host("[mail.example.com]"), "[mail.example.com]");
+
+        assert_eq!(display_host("::1"), "[::1]");
+        assert_eq!(display_host("192.0.2.1"), "192.0.2.1");
+        assert_eq!(display_host("mail.example.com"), "mail.example.com");
+    }
+
+    #[test]
+    fn test_unique_name() {
+        let profiles: Vec<ConnectionProfile> = ["Work", "Home", "Home (2)"]
+            .iter()
+            .map(|name| ConnectionProfile {
+                name: name.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        assert_eq!(unique_name(&profiles, "  Office ", None), "Office");
+        assert_eq!(unique_name(&profiles, "Work ", None), "Work (2)");
+        assert_eq!(unique_name(&profiles, "Home", None), "Home (3)");
+        // Saving a profile under its own name keeps it
+        assert_eq!(unique_name(&profiles, "Work", Some(0)), "Work");
+        assert_eq!(unique_name(&profiles, "Home", Some(0)), "Home (3)");
+    }
+}
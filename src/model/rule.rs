@@ -1,7 +1,8 @@
 use serde::{Deserialize, Serialize};
 
 use crate::model::enums::{
-    ActionType, AddressPartType, ConditionTest, LogicOperator, MatchType, SizeComparator,
+    ActionType, AddressPartType, BodyTransform, ConditionTest, LogicOperator, MatchType,
+    SizeComparator,
 };
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -10,10 +11,26 @@ pub struct Condition {
     pub header_names: Vec<String>,
     pub keys: Vec<String>,
     pub match_type: MatchType,
+    /// `:comparator` name, e.g. `"i;octet"`; `None` leaves the server default
+    #[serde(default)]
+    pub comparator: Option<String>,
     pub address_part: AddressPartType,
+    /// Part of the message a `body` test looks at
+    #[serde(default)]
+    pub transform: BodyTransform,
     pub size_comparator: SizeComparator,
+    /// Size limit as written (`1M`, `1024`), never converted to bytes so
+    /// unrelated edits leave it alone
     pub size_value: String,
     pub negate: bool,
+    /// Switched off: left out of the rule's test but kept in the script
+    /// as a `# Disabled condition N:` note (see the converter)
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+}
+
+fn enabled_by_default() -> bool {
+    true
 }
 
 impl Default for Condition {
@@ -23,18 +40,68 @@ impl Default for Condition {
             header_names: vec!["From".to_string()],
             keys: vec![String::new()],
             match_type: MatchType::Contains,
+            comparator: None,
             address_part: AddressPartType::All,
+            transform: BodyTransform::Text,
             size_comparator: SizeComparator::Over,
             size_value: "0".to_string(),
             negate: false,
+            enabled: true,
         }
     }
 }
 
+impl Condition {
+    /// The size limit in bytes, or `None` if `size_value` isn't a valid limit.
+    pub fn size_bytes(&self) -> Option<u64> {
+        size_to_bytes(&self.size_value)
+    }
+}
+
+/// Parse a SIEVE size limit: digits with an optional `K`, `M` or `G`
+/// suffix (powers of 1024, RFC 5228 §2.4.1).
+pub fn size_to_bytes(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let (digits, multiplier) = match s.char_indices().last()? {
+        (i, 'K' | 'k') => (&s[..i], 1u64 << 10),
+        (i, 'M' | 'm') => (&s[..i], 1 << 20),
+        (i, 'G' | 'g') => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Action {
     pub action_type: ActionType,
     pub argument: String,
+    /// `:copy` (RFC 3894) on fileinto/redirect
+    pub copy: bool,
+    /// `:create` (RFC 5490) on fileinto — create the mailbox if missing
+    pub create: bool,
+    /// `vacation :days` — minimum days between replies to the same sender
+    pub days: Option<u32>,
+    /// `vacation :subject` — subject line of the auto-reply
+    pub subject: Option<String>,
+    /// `vacation :addresses` — the user's other addresses, which also
+    /// count as being written to
+    #[serde(default)]
+    pub addresses: Vec<String>,
+    /// Second positional argument: the field value of `addheader`, or the
+    /// value pattern of `deleteheader` (empty deletes every occurrence)
+    pub value: String,
+    /// `notify :message` — text of the notification
+    pub message: Option<String>,
+    /// IMAP flags of `setflag`/`addflag`/`removeflag`, one per entry
+    #[serde(default)]
+    pub flags: Vec<String>,
+    /// Optional variable the flag action works on instead of the
+    /// internal flags (needs `variables`)
+    #[serde(default)]
+    pub flag_variable: Option<String>,
 }
 
 impl Default for Action {
@@ -42,6 +109,53 @@ impl Default for Action {
         Self {
             action_type: ActionType::Keep,
             argument: String::new(),
+            copy: false,
+            create: false,
+            days: None,
+            subject: None,
+            addresses: Vec::new(),
+            value: String::new(),
+            message: None,
+            flags: Vec::new(),
+            flag_variable: None,
+        }
+    }
+}
+
+/// A condition tree for tests that nest `allof`/`anyof` groups.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ConditionNode {
+    Leaf(Condition),
+    Group {
+        logic: LogicOperator,
+        children: Vec<ConditionNode>,
+    },
+}
+
+impl ConditionNode {
+    /// All conditions in the tree, depth first.
+    pub fn leaves(&self) -> Vec<&Condition> {
+        match self {
+            Self::Leaf(c) => vec![c],
+            Self::Group { children, .. } => children.iter().flat_map(Self::leaves).collect(),
+        }
+    }
+}
+
+/// An `elsif` branch of a rule.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuleBranch {
+    pub logic: LogicOperator,
+    pub conditions: Vec<Condition>,
+    pub actions: Vec<Action>,
+}
+
+impl Default for RuleBranch {
+    fn default() -> Self {
+        Self {
+            logic: LogicOperator::AllOf,
+            conditions: vec![Condition::default()],
+            actions: Vec::new(),
         }
     }
 }
@@ -50,9 +164,26 @@ impl Default for Action {
 pub struct SieveRule {
     pub name: String,
     pub enabled: bool,
+    /// A disabled rule is written inside `/* ... */` instead of being marked
+    /// `[DISABLED]`
+    #[serde(default)]
+    pub commented_out: bool,
+    /// Free-text notes on why the rule exists
+    pub comment: Option<String>,
     pub logic: LogicOperator,
     pub conditions: Vec<Condition>,
+    /// Match when the conditions as a whole don't (`if not anyof (...)`)
+    #[serde(default)]
+    pub negate_group: bool,
+    /// Nested test that the flat `logic`/`conditions` pair can't express;
+    /// when set it replaces them
+    #[serde(default)]
+    pub condition_tree: Option<ConditionNode>,
     pub actions: Vec<Action>,
+    /// `elsif` branches, in order
+    pub alternatives: Vec<RuleBranch>,
+    /// Actions of the trailing `else` branch, if any
+    pub else_actions: Option<Vec<Action>>,
     /// Opaque text for unrecognized constructs
     pub raw_block: Option<String>,
 }
@@ -62,10 +193,42 @@ impl Default for SieveRule {
         Self {
             name: String::new(),
             enabled: true,
+            commented_out: false,
+            comment: None,
             logic: LogicOperator::AllOf,
             conditions: Vec::new(),
+            negate_group: false,
+            condition_tree: None,
             actions: Vec::new(),
+            alternatives: Vec::new(),
+            else_actions: None,
             raw_block: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_size_to_bytes() {
+        assert_eq!(size_to_bytes("1M"), Some(1_048_576));
+        assert_eq!(size_to_bytes("500K"), Some(512_000));
+        assert_eq!(size_to_bytes("2g"), Some(2_147_483_648));
+        assert_eq!(size_to_bytes("100"), Some(100));
+        assert_eq!(size_to_bytes("big"), None);
+        assert_eq!(size_to_bytes("K"), None);
+        assert_eq!(size_to_bytes(""), None);
+    }
+
+    #[test]
+    fn test_condition_size_bytes() {
+        let cond = Condition {
+            test_type: ConditionTest::Size,
+            size_value: "10K".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(cond.size_bytes(), Some(10_240));
+    }
+}
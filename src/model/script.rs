@@ -7,5 +7,19 @@ pub struct SieveScript {
     pub name: String,
     pub rules: Vec<SieveRule>,
     pub requires: Vec<String>,
+    /// Comment lines heading the script, ahead of any code
+    #[serde(default)]
+    pub preamble: Vec<String>,
+    /// `require` statements as the loaded script wrote them, reused while
+    /// they still declare exactly the extensions needed
+    #[serde(default)]
+    pub require_statements: Vec<RequireStatement>,
     pub active: bool,
 }
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequireStatement {
+    pub extensions: Vec<String>,
+    /// Written as a bracketed list, even of a single extension
+    pub list: bool,
+}
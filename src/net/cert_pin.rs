@@ -0,0 +1,161 @@
+/// Certificate pinning for servers with self-signed certificates.
+///
+/// A profile can pin the SHA-256 fingerprint of its server's certificate.
+/// A certificate matching the pin is accepted as is; anything else still has
+/// to pass normal webpki verification. When that fails, the fingerprint of
+/// the rejected certificate is kept so the user can be offered to pin it.
+use std::sync::{Arc, Mutex};
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, RootCertStore, SignatureScheme};
+use sha2::{Digest, Sha256};
+
+/// SHA-256 of a DER certificate as colon-separated uppercase hex, the way
+/// `openssl x509 -fingerprint -sha256` prints it.
+pub fn fingerprint(cert_der: &[u8]) -> String {
+    Sha256::digest(cert_der)
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Compare fingerprints ignoring case, colons and whitespace, so pins pasted
+/// from other tools match.
+pub fn fingerprints_match(a: &str, b: &str) -> bool {
+    fn hex(s: &str) -> impl Iterator<Item = char> + '_ {
+        s.chars()
+            .filter(|c| *c != ':' && !c.is_whitespace())
+            .map(|c| c.to_ascii_uppercase())
+    }
+    !a.trim().is_empty() && hex(a).eq(hex(b))
+}
+
+#[derive(Debug)]
+pub struct PinnedCertVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    pinned: Option<String>,
+    /// Fingerprint of the last certificate that failed verification
+    rejected: Mutex<Option<String>>,
+}
+
+impl PinnedCertVerifier {
+    pub fn new(roots: RootCertStore, pinned: Option<String>) -> Result<Self, rustls::Error> {
+        let inner = WebPkiServerVerifier::builder(Arc::new(roots))
+            .build()
+            .map_err(|e| rustls::Error::General(e.to_string()))?;
+        Ok(Self {
+            inner,
+            pinned,
+            rejected: Mutex::new(None),
+        })
+    }
+
+    /// Fingerprint of the certificate rejected during the last handshake.
+    pub fn take_rejected(&self) -> Option<String> {
+        self.rejected.lock().ok()?.take()
+    }
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let fingerprint = fingerprint(end_entity);
+        if self
+            .pinned
+            .as_deref()
+            .is_some_and(|pin| fingerprints_match(pin, &fingerprint))
+        {
+            return Ok(ServerCertVerified::assertion());
+        }
+        self.inner
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+            .inspect_err(|e| {
+                if matches!(e, rustls::Error::InvalidCertificate(_)) {
+                    if let Ok(mut rejected) = self.rejected.lock() {
+                        *rejected = Some(fingerprint);
+                    }
+                }
+            })
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn webpki_roots() -> RootCertStore {
+        let mut store = RootCertStore::empty();
+        store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        store
+    }
+
+    fn verify(verifier: &PinnedCertVerifier, cert: &CertificateDer<'_>) -> bool {
+        let name = ServerName::try_from("mail.example.com").unwrap();
+        verifier
+            .verify_server_cert(cert, &[], &name, &[], UnixTime::now())
+            .is_ok()
+    }
+
+    #[test]
+    fn test_fingerprints_match() {
+        assert!(fingerprints_match("AB:CD:01", "abcd01"));
+        assert!(fingerprints_match(" ab cd 01 ", "AB:CD:01"));
+        assert!(!fingerprints_match("AB:CD:01", "AB:CD:02"));
+        assert!(!fingerprints_match("", ""));
+    }
+
+    #[test]
+    fn test_pinned_certificate_is_accepted() {
+        let cert = rcgen::generate_simple_self_signed(vec!["mail.example.com".to_string()])
+            .unwrap()
+            .cert;
+        let other = rcgen::generate_simple_self_signed(vec!["mail.example.com".to_string()])
+            .unwrap()
+            .cert;
+        let pin = fingerprint(cert.der());
+        assert_eq!(pin.len(), 32 * 3 - 1);
+
+        // Unpinned: rejected, with the fingerprint kept for the trust prompt
+        let verifier = PinnedCertVerifier::new(webpki_roots(), None).unwrap();
+        assert!(!verify(&verifier, cert.der()));
+        assert_eq!(verifier.take_rejected(), Some(pin.clone()));
+        assert_eq!(verifier.take_rejected(), None);
+
+        let verifier =
+            PinnedCertVerifier::new(webpki_roots(), Some(pin.to_lowercase())).unwrap();
+        assert!(verify(&verifier, cert.der()));
+        assert!(!verify(&verifier, other.der()));
+        assert_eq!(verifier.take_rejected(), Some(fingerprint(other.der())));
+    }
+}
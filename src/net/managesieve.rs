@@ -1,22 +1,142 @@
 /// Async ManageSieve client (RFC 5804).
 ///
-/// Supports STARTTLS, SASL PLAIN authentication, and all standard commands:
-/// LISTSCRIPTS, GETSCRIPT, PUTSCRIPT, SETACTIVE, DELETESCRIPT, CHECKSCRIPT, LOGOUT.
+/// Supports STARTTLS and implicit TLS, SASL SCRAM-SHA-256 and PLAIN authentication,
+/// and all standard commands:
+/// LISTSCRIPTS, GETSCRIPT, PUTSCRIPT, SETACTIVE, DELETESCRIPT, RENAMESCRIPT, CHECKSCRIPT, LOGOUT.
 use base64::Engine;
 use rustls::ClientConfig;
+use rustls::pki_types::ServerName;
+use std::net::IpAddr;
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 use tokio::net::TcpStream;
 use tokio_rustls::TlsConnector;
+use zeroize::Zeroizing;
 
-use crate::model::profile::ConnectionProfile;
+use crate::model::profile::{self, ConnectionProfile, TlsMode};
+use crate::net::cert_pin::PinnedCertVerifier;
+use crate::net::scram::{self, ScramClient};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ScriptInfo {
     pub name: String,
     pub active: bool,
 }
 
+/// Capabilities advertised by the server in its greeting (RFC 5804, section 1.7).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Capabilities {
+    pub implementation: Option<String>,
+    /// Extensions from the `SIEVE` capability, e.g. `fileinto`, `vacation`
+    pub sieve_extensions: Vec<String>,
+    pub sasl_mechanisms: Vec<String>,
+    pub starttls: bool,
+    pub max_redirects: Option<u32>,
+    /// Methods from the `NOTIFY` capability, e.g. `mailto`
+    pub notify_methods: Vec<String>,
+    pub version: Option<String>,
+    /// Server accepts UNAUTHENTICATE to switch users on the same connection
+    pub unauthenticate: bool,
+}
+
+impl Capabilities {
+    /// Parse capability lines such as `"SIEVE" "fileinto vacation"` or `"STARTTLS"`.
+    pub fn parse(lines: &[String]) -> Self {
+        let mut caps = Self::default();
+        for line in lines {
+            let Some(name) = extract_quoted_string(line) else {
+                continue;
+            };
+            let value = line
+                .trim()
+                .get(quoted_len(&name)..)
+                .and_then(extract_quoted_string);
+            let words = || -> Vec<String> {
+                value
+                    .as_deref()
+                    .unwrap_or("")
+                    .split_whitespace()
+                    .map(str::to_string)
+                    .collect()
+            };
+            match name.to_uppercase().as_str() {
+                "IMPLEMENTATION" => caps.implementation = value.clone(),
+                "SIEVE" => caps.sieve_extensions = words(),
+                "SASL" => caps.sasl_mechanisms = words(),
+                "STARTTLS" => caps.starttls = true,
+                "MAXREDIRECTS" => caps.max_redirects = value.as_deref().and_then(|v| v.parse().ok()),
+                "NOTIFY" => caps.notify_methods = words(),
+                "VERSION" => caps.version = value.clone(),
+                "UNAUTHENTICATE" => caps.unauthenticate = true,
+                _ => {}
+            }
+        }
+        caps
+    }
+
+    pub fn supports_extension(&self, ext: &str) -> bool {
+        self.sieve_extensions.iter().any(|e| e.eq_ignore_ascii_case(ext))
+    }
+
+    pub fn supports_sasl(&self, mechanism: &str) -> bool {
+        self.sasl_mechanisms.iter().any(|m| m.eq_ignore_ascii_case(mechanism))
+    }
+
+    /// RENAMESCRIPT is only guaranteed by servers advertising `VERSION` (RFC 5804, 2.11.1).
+    pub fn supports_rename(&self) -> bool {
+        self.version.is_some()
+    }
+
+    /// CHECKSCRIPT comes with `VERSION` as well (RFC 5804, 2.12).
+    pub fn supports_checkscript(&self) -> bool {
+        self.version.is_some()
+    }
+
+    /// HAVESPACE is part of the same RFC 5804 baseline as CHECKSCRIPT.
+    pub fn supports_havespace(&self) -> bool {
+        self.version.is_some()
+    }
+}
+
+/// How the connection to the server is protected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Security {
+    /// No TLS: the password and scripts cross the network readable
+    Plaintext,
+    /// Negotiated protocol version and cipher suite, e.g. `TLS1.3` and
+    /// `TLS13_AES_256_GCM_SHA384`
+    Tls { version: String, cipher: String },
+}
+
+impl Security {
+    fn of(connection: &rustls::ClientConnection) -> Self {
+        let version = match connection.protocol_version() {
+            Some(rustls::ProtocolVersion::TLSv1_3) => "TLS1.3".to_string(),
+            Some(rustls::ProtocolVersion::TLSv1_2) => "TLS1.2".to_string(),
+            Some(other) => other.as_str().unwrap_or("TLS").to_string(),
+            None => "TLS".to_string(),
+        };
+        let cipher = connection
+            .negotiated_cipher_suite()
+            .map(|suite| match suite.suite().as_str() {
+                Some(name) => name.to_string(),
+                None => format!("{:?}", suite.suite()),
+            })
+            .unwrap_or_else(|| "unknown cipher".to_string());
+        Self::Tls { version, cipher }
+    }
+}
+
+impl std::fmt::Display for Security {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Plaintext => write!(f, "INSECURE, not encrypted"),
+            Self::Tls { version, cipher } => write!(f, "{version}, {cipher}"),
+        }
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("I/O error: {0}")]
@@ -31,108 +151,292 @@ pub enum Error {
     AuthFailed,
     #[error("Not connected")]
     NotConnected,
+    /// The certificate failed verification; carries its SHA-256 fingerprint
+    #[error("Untrusted certificate (SHA-256 {0})")]
+    UntrustedCertificate(String),
+    #[error("Timed out waiting for the server")]
+    Timeout,
+    /// The server ended the session with `BYE`; carries its reason
+    #[error("{SERVER_CLOSED}: {0}")]
+    ServerClosed(String),
+}
+
+const SERVER_CLOSED: &str = "Server closed the connection";
+
+/// The reason of a `ServerClosed` error once turned into a string, as the
+/// app's task results carry it.
+pub fn server_closed_reason(error: &str) -> Option<&str> {
+    error.strip_prefix(SERVER_CLOSED)?.strip_prefix(": ")
 }
 
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long to wait for the capabilities a server should re-send after
+/// STARTTLS before going on with the ones from before the handshake
+const DEFAULT_CAPABILITY_GRACE: Duration = Duration::from_secs(2);
+/// Script bodies are written in pieces of this size rather than copied
+/// into the command
+const LITERAL_CHUNK: usize = 16 * 1024;
+
 // We use a dynamic stream type to handle both plain and TLS connections
-enum Stream {
+enum Transport {
     Plain(BufReader<TcpStream>),
     Tls(Box<BufReader<tokio_rustls::client::TlsStream<TcpStream>>>),
+    #[cfg(test)]
+    Mock(BufReader<tokio::io::DuplexStream>),
+}
+
+struct Stream {
+    transport: Transport,
+    /// Limit for each read; a server that stalls longer yields `Error::Timeout`
+    read_timeout: Duration,
 }
 
 impl Stream {
-    async fn read_line(&mut self, buf: &mut String) -> Result<usize, std::io::Error> {
-        match self {
-            Self::Plain(r) => r.read_line(buf).await,
-            Self::Tls(r) => r.read_line(buf).await,
+    fn new(transport: Transport, read_timeout: Duration) -> Self {
+        Self {
+            transport,
+            read_timeout,
+        }
+    }
+
+    async fn read_line(&mut self, buf: &mut String) -> Result<usize, Error> {
+        let read = async {
+            match &mut self.transport {
+                Transport::Plain(r) => r.read_line(buf).await,
+                Transport::Tls(r) => r.read_line(buf).await,
+                #[cfg(test)]
+                Transport::Mock(r) => r.read_line(buf).await,
+            }
+        };
+        match tokio::time::timeout(self.read_timeout, read).await {
+            Ok(result) => Ok(result?),
+            Err(_) => Err(Error::Timeout),
+        }
+    }
+
+    /// Whether the server sends something (or hangs up) within `wait`.
+    /// Nothing is consumed.
+    async fn sends_within(&mut self, wait: Duration) -> Result<bool, Error> {
+        let fill = async {
+            match &mut self.transport {
+                Transport::Plain(r) => r.fill_buf().await.map(|_| ()),
+                Transport::Tls(r) => r.fill_buf().await.map(|_| ()),
+                #[cfg(test)]
+                Transport::Mock(r) => r.fill_buf().await.map(|_| ()),
+            }
+        };
+        match tokio::time::timeout(wait, fill).await {
+            Ok(result) => Ok(result.map(|_| true)?),
+            Err(_) => Ok(false),
+        }
+    }
+
+    async fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        let read = async {
+            match &mut self.transport {
+                Transport::Plain(r) => r.read_exact(buf).await,
+                Transport::Tls(r) => r.read_exact(buf).await,
+                #[cfg(test)]
+                Transport::Mock(r) => r.read_exact(buf).await,
+            }
+        };
+        match tokio::time::timeout(self.read_timeout, read).await {
+            Ok(result) => Ok(result.map(|_| ())?),
+            Err(_) => Err(Error::Timeout),
         }
     }
 
     async fn write_all(&mut self, data: &[u8]) -> Result<(), std::io::Error> {
-        match self {
-            Self::Plain(r) => r.get_mut().write_all(data).await,
-            Self::Tls(r) => r.get_mut().write_all(data).await,
+        match &mut self.transport {
+            Transport::Plain(r) => r.get_mut().write_all(data).await,
+            Transport::Tls(r) => r.get_mut().write_all(data).await,
+            #[cfg(test)]
+            Transport::Mock(r) => r.get_mut().write_all(data).await,
         }
     }
 
     async fn flush(&mut self) -> Result<(), std::io::Error> {
-        match self {
-            Self::Plain(r) => r.get_mut().flush().await,
-            Self::Tls(r) => r.get_mut().flush().await,
+        match &mut self.transport {
+            Transport::Plain(r) => r.get_mut().flush().await,
+            Transport::Tls(r) => r.get_mut().flush().await,
+            #[cfg(test)]
+            Transport::Mock(r) => r.get_mut().flush().await,
         }
     }
 }
 
 pub struct ManageSieveClient {
     stream: Option<Stream>,
+    capabilities: Capabilities,
+    /// Host, port and TLS mode of the current connection
+    endpoint: Option<(String, u16, TlsMode)>,
+    /// Trust anchors for server certificates
+    tls_roots: rustls::RootCertStore,
+    /// Limit for establishing the TCP connection and TLS handshake
+    connect_timeout: Duration,
+    /// Limit for each read from the server
+    read_timeout: Duration,
+    /// Wait for capabilities after STARTTLS
+    capability_grace: Duration,
 }
 
 impl ManageSieveClient {
     pub fn new() -> Self {
-        Self { stream: None }
+        Self {
+            stream: None,
+            capabilities: Capabilities::default(),
+            endpoint: None,
+            tls_roots: root_store(),
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            read_timeout: DEFAULT_READ_TIMEOUT,
+            capability_grace: DEFAULT_CAPABILITY_GRACE,
+        }
+    }
+
+    /// Change the connect and per-read timeouts. Takes effect on the next `connect`.
+    pub fn set_timeouts(&mut self, connect: Duration, read: Duration) {
+        self.connect_timeout = connect;
+        self.read_timeout = read;
     }
 
     pub fn is_connected(&self) -> bool {
         self.stream.is_some()
     }
 
-    /// Connect to a ManageSieve server, optionally upgrading to TLS via STARTTLS,
-    /// then authenticate using SASL PLAIN.
+    /// How the current connection is protected, or `None` when not connected.
+    pub fn security(&self) -> Option<Security> {
+        let stream = self.stream.as_ref()?;
+        Some(match &stream.transport {
+            Transport::Tls(r) => Security::of(r.get_ref().get_ref().1),
+            Transport::Plain(_) => Security::Plaintext,
+            #[cfg(test)]
+            Transport::Mock(_) => Security::Plaintext,
+        })
+    }
+
+    /// Connect to a ManageSieve server over plaintext, STARTTLS or implicit TLS
+    /// (per `profile.tls_mode`), then authenticate using SCRAM-SHA-256 if the
+    /// server offers it, else PLAIN. Returns the capabilities the server advertised.
     pub async fn connect(
         &mut self,
         profile: &ConnectionProfile,
         password: &str,
-    ) -> Result<(), Error> {
-        let tcp = TcpStream::connect((&*profile.host, profile.port)).await?;
-        let mut stream = Stream::Plain(BufReader::new(tcp));
+    ) -> Result<Capabilities, Error> {
+        // Profiles saved before hosts were normalized may still carry brackets
+        let host = profile::normalize_host(&profile.host);
+        let tcp = tokio::time::timeout(
+            self.connect_timeout,
+            TcpStream::connect((&*host, profile.port)),
+        )
+        .await
+        .map_err(|_| Error::Timeout)??;
+        let transport = if profile.tls_mode == TlsMode::Implicit {
+            let tls_stream = self.tls_connect(&host, profile.tls_fingerprint.clone(), tcp).await?;
+            Transport::Tls(Box::new(BufReader::new(tls_stream)))
+        } else {
+            Transport::Plain(BufReader::new(tcp))
+        };
+        let mut stream = Stream::new(transport, self.read_timeout);
 
         // Read server greeting/capabilities
-        read_response(&mut stream).await?;
+        let mut greeting = read_response(&mut stream).await?;
 
-        // STARTTLS if requested
-        if profile.use_starttls {
+        // STARTTLS if requested. Without it the password would go out in
+        // the clear, so a server that doesn't offer it is refused outright
+        if profile.tls_mode == TlsMode::StartTls {
+            if !Capabilities::parse(&greeting.lines).starttls {
+                return Err(Error::Protocol("Server does not support STARTTLS".to_string()));
+            }
             send_command(&mut stream, "STARTTLS").await?;
             let resp = read_response(&mut stream).await?;
             if !resp.ok {
                 return Err(Error::Server("STARTTLS rejected".to_string()));
             }
 
-            // Upgrade to TLS
-            let mut tls_config = ClientConfig::builder()
-                .with_root_certificates(root_store())
-                .with_no_client_auth();
-            tls_config.alpn_protocols = vec![];
-
-            let connector = TlsConnector::from(Arc::new(tls_config));
-            let server_name = rustls::pki_types::ServerName::try_from(profile.host.clone())
-                .map_err(|e| Error::Protocol(format!("Invalid server name: {e}")))?;
-
             // Extract the TcpStream from the BufReader
-            let tcp = match stream {
-                Stream::Plain(r) => r.into_inner(),
+            let tcp = match stream.transport {
+                Transport::Plain(r) => r.into_inner(),
                 _ => unreachable!(),
             };
 
-            let tls_stream = connector.connect(server_name, tcp).await?;
-            stream = Stream::Tls(Box::new(BufReader::new(tls_stream)));
+            let tls_stream = self.tls_connect(&host, profile.tls_fingerprint.clone(), tcp).await?;
+            stream = Stream::new(
+                Transport::Tls(Box::new(BufReader::new(tls_stream))),
+                self.read_timeout,
+            );
 
-            // Re-read capabilities after TLS
-            read_response(&mut stream).await?;
+            // Re-read capabilities after TLS. Some servers never send them;
+            // those keep the capabilities from the greeting
+            if stream.sends_within(self.capability_grace).await? {
+                greeting = read_response(&mut stream).await?;
+            }
         }
 
-        // Authenticate with SASL PLAIN
-        // SASL PLAIN: \0username\0password
-        let auth_data = format!("\0{}\0{}", profile.username, password);
-        let b64 = base64::engine::general_purpose::STANDARD.encode(auth_data.as_bytes());
-        let auth_cmd = format!("AUTHENTICATE \"PLAIN\" \"{}\"", b64);
+        let capabilities = Capabilities::parse(&greeting.lines);
+        authenticate(&mut stream, &capabilities, &profile.username, password).await?;
 
-        send_command(&mut stream, &auth_cmd).await?;
-        let resp = read_response(&mut stream).await?;
-        if !resp.ok {
-            return Err(Error::AuthFailed);
-        }
+        self.stream = Some(stream);
+        self.capabilities = capabilities.clone();
+        self.endpoint = Some(endpoint(profile));
+        Ok(capabilities)
+    }
 
+    /// Log in as `profile`'s user. On the same server, with UNAUTHENTICATE
+    /// advertised, this reuses the connection and skips the TLS handshake;
+    /// otherwise it disconnects and connects afresh.
+    pub async fn reauthenticate(
+        &mut self,
+        profile: &ConnectionProfile,
+        password: &str,
+    ) -> Result<Capabilities, Error> {
+        if !self.can_unauthenticate(profile) {
+            self.disconnect().await;
+            return self.connect(profile, password).await;
+        }
+        let mut stream = self.stream.take().ok_or(Error::NotConnected)?;
+        send_command(&mut stream, "UNAUTHENTICATE").await?;
+        if !read_response(&mut stream).await?.ok {
+            self.stream = Some(stream);
+            self.disconnect().await;
+            return self.connect(profile, password).await;
+        }
+        // A failed login leaves the stream unauthenticated; it stays dropped
+        authenticate(&mut stream, &self.capabilities, &profile.username, password).await?;
         self.stream = Some(stream);
-        Ok(())
+        Ok(self.capabilities.clone())
+    }
+
+    fn can_unauthenticate(&self, profile: &ConnectionProfile) -> bool {
+        self.stream.is_some()
+            && self.capabilities.unauthenticate
+            && self.endpoint.as_ref() == Some(&endpoint(profile))
+    }
+
+    async fn tls_connect(
+        &self,
+        host: &str,
+        pinned: Option<String>,
+        tcp: TcpStream,
+    ) -> Result<tokio_rustls::client::TlsStream<TcpStream>, Error> {
+        let verifier = Arc::new(PinnedCertVerifier::new(self.tls_roots.clone(), pinned)?);
+        let mut tls_config = ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(verifier.clone())
+            .with_no_client_auth();
+        tls_config.alpn_protocols = vec![];
+
+        let connector = TlsConnector::from(Arc::new(tls_config));
+        let server_name = server_name(host)?;
+
+        match tokio::time::timeout(self.connect_timeout, connector.connect(server_name, tcp)).await {
+            Err(_) => Err(Error::Timeout),
+            Ok(Ok(stream)) => Ok(stream),
+            Ok(Err(e)) => Err(match verifier.take_rejected() {
+                Some(fingerprint) => Error::UntrustedCertificate(fingerprint),
+                None => Error::from(e),
+            }),
+        }
     }
 
     pub async fn disconnect(&mut self) {
@@ -141,6 +445,7 @@ impl ManageSieveClient {
             let _ = read_response(stream).await;
         }
         self.stream = None;
+        self.endpoint = None;
     }
 
     pub async fn list_scripts(&mut self) -> Result<Vec<ScriptInfo>, Error> {
@@ -156,43 +461,47 @@ impl ManageSieveClient {
             if trimmed.starts_with("OK") {
                 break;
             }
-            if trimmed.starts_with("NO") || trimmed.starts_with("BYE") {
+            if let Some(reason) = bye_reason(trimmed) {
+                return Err(Error::ServerClosed(reason));
+            }
+            if trimmed.starts_with("NO") {
                 return Err(Error::Server(trimmed.to_string()));
             }
 
-            // Parse script line: "scriptname" [ACTIVE]
-            if let Some(name) = extract_quoted_string(trimmed) {
-                let active = trimmed.contains("ACTIVE");
-                scripts.push(ScriptInfo { name, active });
-            }
+            // Parse script line: "scriptname" [ACTIVE], where the name may
+            // also be a {size} literal with the flag after its bytes
+            let (name, rest) = if let Some(size) = extract_literal_size(trimmed) {
+                let name = read_literal_exact(stream, size).await?;
+                let mut rest = String::new();
+                stream.read_line(&mut rest).await?;
+                (name, rest)
+            } else if let Some(name) = extract_quoted_string(trimmed) {
+                let rest = trimmed.get(quoted_len(&name)..).unwrap_or_default().to_string();
+                (name, rest)
+            } else {
+                continue;
+            };
+            let active = rest.trim().eq_ignore_ascii_case("ACTIVE");
+            scripts.push(ScriptInfo { name, active });
         }
 
         Ok(scripts)
     }
 
+    /// Fetch a script's content exactly as stored. Compliant servers send
+    /// the body as a `{size}` literal or a quoted string; servers that do
+    /// neither get their reply lines taken verbatim.
     pub async fn get_script(&mut self, name: &str) -> Result<String, Error> {
         let stream = self.stream.as_mut().ok_or(Error::NotConnected)?;
-        let cmd = format!("GETSCRIPT \"{}\"", escape_sieve(name));
+        let cmd = format!("GETSCRIPT {}", string_arg(name));
         send_command(stream, &cmd).await?;
 
-        let mut content = String::new();
-        let mut in_literal = false;
-        let mut remaining = 0usize;
-
+        let mut literal = None;
+        let mut lines = String::new();
         loop {
             let mut line = String::new();
-            stream.read_line(&mut line).await?;
-
-            if in_literal {
-                if remaining > 0 {
-                    let take = line.len().min(remaining);
-                    content.push_str(&line[..take]);
-                    remaining -= take;
-                }
-                if remaining == 0 {
-                    in_literal = false;
-                }
-                continue;
+            if stream.read_line(&mut line).await? == 0 {
+                return Err(Error::Protocol("Connection closed".to_string()));
             }
 
             let trimmed = line.trim();
@@ -200,36 +509,41 @@ impl ManageSieveClient {
                 break;
             }
             if trimmed.starts_with("NO") || trimmed.starts_with("BYE") {
-                return Err(Error::Server(trimmed.to_string()));
+                let message = read_status_text(stream, trimmed).await?;
+                if let Some(reason) = bye_reason(&message) {
+                    return Err(Error::ServerClosed(reason));
+                }
+                return Err(Error::Server(message));
             }
 
-            // Check for literal: {size+}
-            if let Some(size) = extract_literal_size(trimmed) {
-                remaining = size;
-                in_literal = true;
-            } else if let Some(s) = extract_quoted_string(trimmed) {
-                content.push_str(&s);
-            } else {
-                content.push_str(&line);
+            if literal.is_some() {
+                // Only the CRLF ending the response line is left
+                continue;
             }
+            if lines.is_empty() {
+                if let Some(size) = extract_literal_size(trimmed) {
+                    literal = Some(read_literal_exact(stream, size).await?);
+                    continue;
+                }
+            }
+            lines.push_str(&line);
         }
 
-        // Remove trailing \r\n from content
-        while content.ends_with('\n') || content.ends_with('\r') {
-            content.pop();
-        }
-
-        Ok(content)
+        Ok(literal.unwrap_or_else(|| {
+            // Drop the line ending that terminated the response line
+            let body = lines.strip_suffix('\n').unwrap_or(&lines);
+            let body = body.strip_suffix('\r').unwrap_or(body);
+            if body.contains('\n') {
+                return body.to_string();
+            }
+            whole_quoted_string(body).unwrap_or_else(|| body.to_string())
+        }))
     }
 
     pub async fn put_script(&mut self, name: &str, content: &str) -> Result<(), Error> {
         let stream = self.stream.as_mut().ok_or(Error::NotConnected)?;
-        let size = content.len();
-        let cmd = format!(
-            "PUTSCRIPT \"{}\" {{{size}+}}\r\n{content}",
-            escape_sieve(name)
-        );
-        send_command(stream, &cmd).await?;
+        let cmd = format!("PUTSCRIPT {}", string_arg(name));
+        send_literal_command(stream, &cmd, content).await?;
         let resp = read_response(stream).await?;
         if !resp.ok {
             return Err(Error::Server(resp.message));
@@ -237,9 +551,26 @@ impl ManageSieveClient {
         Ok(())
     }
 
+    /// Ask whether a script of `size` bytes would fit the user's quota
+    /// (RFC 5804, 2.5). A NO with a `QUOTA` or `QUOTA/...` response code
+    /// gives `Ok(false)`; any other NO is an error carrying the server's text.
+    pub async fn have_space(&mut self, name: &str, size: usize) -> Result<bool, Error> {
+        let stream = self.stream.as_mut().ok_or(Error::NotConnected)?;
+        let cmd = format!("HAVESPACE {} {size}", string_arg(name));
+        send_command(stream, &cmd).await?;
+        let resp = read_response(stream).await?;
+        if resp.ok {
+            return Ok(true);
+        }
+        match response_code(&resp.message) {
+            Some(code) if code == "QUOTA" || code.starts_with("QUOTA/") => Ok(false),
+            _ => Err(Error::Server(resp.message)),
+        }
+    }
+
     pub async fn set_active(&mut self, name: &str) -> Result<(), Error> {
         let stream = self.stream.as_mut().ok_or(Error::NotConnected)?;
-        let cmd = format!("SETACTIVE \"{}\"", escape_sieve(name));
+        let cmd = format!("SETACTIVE {}", string_arg(name));
         send_command(stream, &cmd).await?;
         let resp = read_response(stream).await?;
         if !resp.ok {
@@ -250,7 +581,7 @@ impl ManageSieveClient {
 
     pub async fn delete_script(&mut self, name: &str) -> Result<(), Error> {
         let stream = self.stream.as_mut().ok_or(Error::NotConnected)?;
-        let cmd = format!("DELETESCRIPT \"{}\"", escape_sieve(name));
+        let cmd = format!("DELETESCRIPT {}", string_arg(name));
         send_command(stream, &cmd).await?;
         let resp = read_response(stream).await?;
         if !resp.ok {
@@ -259,13 +590,52 @@ impl ManageSieveClient {
         Ok(())
     }
 
-    pub async fn check_script(&mut self, content: &str) -> Result<bool, Error> {
+    /// Rename a script. Servers without RENAMESCRIPT get the equivalent
+    /// GETSCRIPT/PUTSCRIPT/DELETESCRIPT sequence, re-activating the new name
+    /// if the old one was active.
+    pub async fn rename_script(&mut self, old: &str, new: &str) -> Result<(), Error> {
+        if !self.capabilities.supports_rename() {
+            return self.rename_by_copy(old, new).await;
+        }
         let stream = self.stream.as_mut().ok_or(Error::NotConnected)?;
-        let size = content.len();
-        let cmd = format!("CHECKSCRIPT {{{size}+}}\r\n{content}");
+        let cmd = format!("RENAMESCRIPT {} {}", string_arg(old), string_arg(new));
         send_command(stream, &cmd).await?;
         let resp = read_response(stream).await?;
-        Ok(resp.ok)
+        if !resp.ok {
+            return Err(Error::Server(resp.message));
+        }
+        Ok(())
+    }
+
+    /// RENAMESCRIPT by hand: copy, move the active mark, delete. Like the
+    /// real command it refuses to replace a script of the new name.
+    async fn rename_by_copy(&mut self, old: &str, new: &str) -> Result<(), Error> {
+        let scripts = self.list_scripts().await?;
+        if scripts.iter().any(|s| s.name == new) {
+            return Err(Error::Server(format!(
+                "NO (ALREADYEXISTS) \"A script named {} already exists\"",
+                escape_sieve(new)
+            )));
+        }
+        let was_active = scripts.iter().any(|s| s.name == old && s.active);
+        let content = self.get_script(old).await?;
+        self.put_script(new, &content).await?;
+        if was_active {
+            self.set_active(new).await?;
+        }
+        self.delete_script(old).await
+    }
+
+    /// Ask the server to validate a script. A NO response is returned as
+    /// `Error::Server` carrying the server's message verbatim.
+    pub async fn check_script(&mut self, content: &str) -> Result<(), Error> {
+        let stream = self.stream.as_mut().ok_or(Error::NotConnected)?;
+        send_literal_command(stream, "CHECKSCRIPT", content).await?;
+        let resp = read_response(stream).await?;
+        if !resp.ok {
+            return Err(Error::Server(resp.message));
+        }
+        Ok(())
     }
 }
 
@@ -274,18 +644,34 @@ impl ManageSieveClient {
 struct Response {
     ok: bool,
     message: String,
+    /// Untagged lines received before the OK/NO/BYE, e.g. capabilities
+    lines: Vec<String>,
 }
 
 async fn send_command(stream: &mut Stream, cmd: &str) -> Result<(), Error> {
-    let data = format!("{cmd}\r\n");
+    // Commands can carry credentials, so the copy is wiped once sent
+    let data = Zeroizing::new(format!("{cmd}\r\n"));
     stream.write_all(data.as_bytes()).await?;
     stream.flush().await?;
     Ok(())
 }
 
+/// Send `cmd` with `content` as its final, non-synchronizing literal
+/// argument. The body goes out straight from `content`.
+async fn send_literal_command(stream: &mut Stream, cmd: &str, content: &str) -> Result<(), Error> {
+    let header = format!("{cmd} {{{}+}}\r\n", literal_len(content));
+    stream.write_all(header.as_bytes()).await?;
+    for chunk in content.as_bytes().chunks(LITERAL_CHUNK) {
+        stream.write_all(chunk).await?;
+    }
+    stream.write_all(b"\r\n").await?;
+    stream.flush().await?;
+    Ok(())
+}
+
 async fn read_response(stream: &mut Stream) -> Result<Response, Error> {
     // Read lines until we get OK, NO, or BYE
-    let mut full_response = String::new();
+    let mut lines = Vec::new();
     loop {
         let mut line = String::new();
         let n = stream.read_line(&mut line).await?;
@@ -294,29 +680,239 @@ async fn read_response(stream: &mut Stream) -> Result<Response, Error> {
         }
 
         let trimmed = line.trim();
-        if trimmed.starts_with("OK") {
+        let ok = trimmed.starts_with("OK");
+        if ok || trimmed.starts_with("NO") || trimmed.starts_with("BYE") {
+            let message = read_status_text(stream, trimmed).await?;
+            if let Some(reason) = bye_reason(&message) {
+                return Err(Error::ServerClosed(reason));
+            }
             return Ok(Response {
-                ok: true,
-                message: trimmed.to_string(),
+                ok,
+                message,
+                lines,
             });
         }
-        if trimmed.starts_with("NO") {
-            return Ok(Response {
-                ok: false,
-                message: trimmed.to_string(),
-            });
+
+        lines.push(trimmed.to_string());
+    }
+}
+
+/// Complete an OK/NO/BYE line whose human-readable text may follow as a
+/// literal (`NO {42}`), inlining that text as a quoted string.
+async fn read_status_text(stream: &mut Stream, line: &str) -> Result<String, Error> {
+    let mut message = line.trim().to_string();
+    if let Some(size) = trailing_literal_size(&message) {
+        let mut text = read_literal(stream, size).await?;
+        if text.len() == size {
+            // The literal ended on a line boundary; consume the response's CRLF
+            let mut rest = String::new();
+            stream.read_line(&mut rest).await?;
         }
-        if trimmed.starts_with("BYE") {
-            return Ok(Response {
-                ok: false,
-                message: trimmed.to_string(),
-            });
+        if text.is_char_boundary(size) {
+            text.truncate(size);
+        }
+        message.truncate(message.rfind('{').unwrap_or(message.len()));
+        message.push_str(&format!("\"{}\"", escape_sieve(text.trim_end())));
+    }
+    Ok(message)
+}
+
+/// Read a `{size}` literal's payload, which may span several lines.
+async fn read_literal(stream: &mut Stream, size: usize) -> Result<String, Error> {
+    let mut data = String::new();
+    while data.len() < size {
+        let mut chunk = String::new();
+        if stream.read_line(&mut chunk).await? == 0 {
+            return Err(Error::Protocol("Connection closed".to_string()));
         }
+        data.push_str(&chunk);
+    }
+    Ok(data)
+}
+
+/// Read exactly `size` bytes of literal payload, CRLFs included.
+async fn read_literal_exact(stream: &mut Stream, size: usize) -> Result<String, Error> {
+    let mut data = vec![0; size];
+    stream.read_exact(&mut data).await?;
+    String::from_utf8(data).map_err(|_| Error::Protocol("Script is not valid UTF-8".to_string()))
+}
 
-        full_response.push_str(&line);
+/// The human-readable part of an OK/NO/BYE line: the quoted text after the
+/// optional `(CODE)`, e.g. `NO (QUOTA) "Too big"` -> `Too big`.
+pub fn response_text(message: &str) -> String {
+    let rest = message
+        .trim()
+        .trim_start_matches(|c: char| c.is_ascii_alphabetic())
+        .trim_start();
+    let rest = if rest.starts_with('(') {
+        rest.find(')').map(|i| rest[i + 1..].trim_start()).unwrap_or(rest)
+    } else {
+        rest
+    };
+    extract_quoted_string(rest).unwrap_or_else(|| rest.to_string())
+}
+
+/// The response code of an OK/NO/BYE line, upper-cased and without its
+/// arguments, e.g. `NO (QUOTA/MAXSIZE) "Too big"` -> `QUOTA/MAXSIZE`.
+fn response_code(message: &str) -> Option<String> {
+    let rest = message
+        .trim()
+        .trim_start_matches(|c: char| c.is_ascii_alphabetic())
+        .trim_start()
+        .strip_prefix('(')?;
+    let code = rest.split(|c: char| c == ')' || c.is_whitespace()).next()?;
+    (!code.is_empty()).then(|| code.to_ascii_uppercase())
+}
+
+/// The reason a `BYE` line gives, e.g. `BYE "Too many connections"` ->
+/// `Too many connections`. `None` for any other line.
+fn bye_reason(line: &str) -> Option<String> {
+    if !line.trim_start().starts_with("BYE") {
+        return None;
+    }
+    let reason = response_text(line);
+    Some(if reason.is_empty() {
+        "no reason given".to_string()
+    } else {
+        reason
+    })
+}
+
+// --- SASL ---
+
+/// SCRAM-SHA-256 if the server offers it, else PLAIN.
+async fn authenticate(
+    stream: &mut Stream,
+    capabilities: &Capabilities,
+    username: &str,
+    password: &str,
+) -> Result<(), Error> {
+    if capabilities.supports_sasl(scram::MECHANISM) {
+        let nonce = scram::generate_nonce()?;
+        authenticate_scram(stream, username, password, &nonce).await
+    } else {
+        authenticate_plain(stream, username, password).await
     }
 }
 
+/// SASL PLAIN: `\0username\0password`. The credentials go out as an
+/// initial response; servers that prompt for them with an empty `+`
+/// challenge instead get them on the following line.
+async fn authenticate_plain(stream: &mut Stream, username: &str, password: &str) -> Result<(), Error> {
+    let auth_data = Zeroizing::new(format!("\0{username}\0{password}"));
+    let b64 = Zeroizing::new(base64::engine::general_purpose::STANDARD.encode(auth_data.as_bytes()));
+    let auth_cmd = Zeroizing::new(format!("AUTHENTICATE \"PLAIN\" \"{}\"", b64.as_str()));
+
+    send_command(stream, &auth_cmd).await?;
+    let reply = match read_sasl_response(stream).await? {
+        SaslResponse::Challenge(_) => {
+            send_command(stream, &Zeroizing::new(format!("\"{}\"", b64.as_str()))).await?;
+            read_sasl_response(stream).await?
+        }
+        done => done,
+    };
+    match reply {
+        SaslResponse::Ok(_) => Ok(()),
+        SaslResponse::Challenge(_) => {
+            Err(Error::Protocol("Unexpected SASL challenge after PLAIN response".to_string()))
+        }
+        SaslResponse::No(_) | SaslResponse::Bye(_) => Err(Error::AuthFailed),
+    }
+}
+
+/// SCRAM-SHA-256: client-first as initial response, then one challenge
+/// (server-first) answered with client-final. The server-final message comes
+/// either as a further challenge (acknowledged with an empty string) or in
+/// the `OK (SASL "...")` response code.
+async fn authenticate_scram(
+    stream: &mut Stream,
+    username: &str,
+    password: &str,
+    nonce: &str,
+) -> Result<(), Error> {
+    let mut client = ScramClient::new(username, password, nonce);
+    let first = base64::engine::general_purpose::STANDARD.encode(client.client_first());
+    send_command(stream, &format!("AUTHENTICATE \"{}\" \"{first}\"", scram::MECHANISM)).await?;
+
+    let server_first = match read_sasl_response(stream).await? {
+        SaslResponse::Challenge(c) => c,
+        _ => return Err(Error::AuthFailed),
+    };
+    let client_final = client.client_final(&server_first)?;
+    let b64 = base64::engine::general_purpose::STANDARD.encode(client_final);
+    send_command(stream, &format!("\"{b64}\"")).await?;
+
+    let resp = match read_sasl_response(stream).await? {
+        SaslResponse::Challenge(server_final) => {
+            client.verify_server_final(&server_final)?;
+            send_command(stream, "\"\"").await?;
+            return match read_sasl_response(stream).await? {
+                SaslResponse::Ok(_) => Ok(()),
+                _ => Err(Error::AuthFailed),
+            };
+        }
+        SaslResponse::Ok(resp) => resp,
+        SaslResponse::No(_) | SaslResponse::Bye(_) => return Err(Error::AuthFailed),
+    };
+    let server_final = extract_sasl_code(&resp.message)
+        .ok_or_else(|| Error::Protocol("Missing SCRAM server-final message".to_string()))?;
+    client.verify_server_final(&server_final)
+}
+
+enum SaslResponse {
+    Ok(Response),
+    No(Response),
+    Bye(Response),
+    /// A decoded server challenge
+    Challenge(String),
+}
+
+/// Read one server reply during an AUTHENTICATE exchange. Challenges are
+/// base64 strings, quoted or as a literal, optionally prefixed with `+`.
+async fn read_sasl_response(stream: &mut Stream) -> Result<SaslResponse, Error> {
+    let mut line = String::new();
+    if stream.read_line(&mut line).await? == 0 {
+        return Err(Error::Protocol("Connection closed".to_string()));
+    }
+    let trimmed = line.trim();
+    let status = |ok| Response {
+        ok,
+        message: trimmed.to_string(),
+        lines: Vec::new(),
+    };
+    if trimmed.starts_with("OK") {
+        return Ok(SaslResponse::Ok(status(true)));
+    }
+    if trimmed.starts_with("NO") {
+        return Ok(SaslResponse::No(status(false)));
+    }
+    if trimmed.starts_with("BYE") {
+        return Ok(SaslResponse::Bye(status(false)));
+    }
+
+    let challenge = trimmed.strip_prefix('+').unwrap_or(trimmed).trim();
+    let encoded = if let Some(size) = extract_literal_size(challenge) {
+        read_literal(stream, size).await?.trim().to_string()
+    } else {
+        extract_quoted_string(challenge).unwrap_or_else(|| challenge.to_string())
+    };
+
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(encoded.as_bytes())
+        .map_err(|e| Error::Protocol(format!("Invalid SASL challenge: {e}")))?;
+    String::from_utf8(decoded)
+        .map(SaslResponse::Challenge)
+        .map_err(|e| Error::Protocol(format!("Invalid SASL challenge: {e}")))
+}
+
+/// Decode the `(SASL "...")` response code of an OK line, if present.
+fn extract_sasl_code(message: &str) -> Option<String> {
+    let start = message.find("(SASL ")? + "(SASL ".len();
+    let encoded = extract_quoted_string(&message[start..])?;
+    let decoded = base64::engine::general_purpose::STANDARD.decode(encoded).ok()?;
+    String::from_utf8(decoded).ok()
+}
+
 fn extract_quoted_string(s: &str) -> Option<String> {
     let s = s.trim().strip_prefix('"')?;
     let mut result = String::new();
@@ -335,24 +931,817 @@ fn extract_quoted_string(s: &str) -> Option<String> {
     }
 }
 
+/// `s` unquoted if it is a single quoted string with nothing around it.
+fn whole_quoted_string(s: &str) -> Option<String> {
+    let inner = s.trim().strip_prefix('"')?.strip_suffix('"')?;
+    let mut result = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => result.push(chars.next()?),
+            '"' => return None,
+            c => result.push(c),
+        }
+    }
+    Some(result)
+}
+
+/// Length of `s` once quoted and escaped, for skipping past it in a line.
+fn quoted_len(s: &str) -> usize {
+    escape_sieve(s).len() + 2
+}
+
+/// The size of a `{n}` or `{n+}` literal announcement making up all of `s`.
 fn extract_literal_size(s: &str) -> Option<usize> {
-    let s = s.trim();
-    if s.starts_with('{') {
-        let end = s.find('}')?;
-        let num_str = &s[1..end];
-        let num_str = num_str.trim_end_matches('+');
-        num_str.parse().ok()
+    let s = s.trim().strip_prefix('{')?.strip_suffix('}')?;
+    let num_str = s.strip_suffix('+').unwrap_or(s);
+    if num_str.is_empty() || !num_str.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    num_str.parse().ok()
+}
+
+/// The size of a literal announced at the very end of a response line, as
+/// in `NO {42}`. Braces anywhere else, e.g. `${1}` quoted in an error
+/// message, are just text.
+fn trailing_literal_size(line: &str) -> Option<usize> {
+    let line = line.trim_end();
+    line.rfind('{').and_then(|i| extract_literal_size(&line[i..]))
+}
+
+/// Encode a command argument: a quoted string for plain printable ASCII,
+/// otherwise a non-synchronizing literal, which carries any UTF-8 unescaped
+/// and is accepted by servers that are picky about quoted strings.
+fn string_arg(s: &str) -> String {
+    let quotable = s
+        .chars()
+        .all(|c| (c.is_ascii_graphic() || c == ' ') && c != '"' && c != '\\');
+    if quotable {
+        format!("\"{s}\"")
     } else {
-        None
+        format!("{{{}+}}\r\n{s}", literal_len(s))
     }
 }
 
+/// The size announced in a `{n+}` literal: octets, so the UTF-8 byte
+/// length. A character count would cut multibyte text short.
+fn literal_len(s: &str) -> usize {
+    s.len()
+}
+
 fn escape_sieve(s: &str) -> String {
     s.replace('\\', "\\\\").replace('"', "\\\"")
 }
 
+// --- Connection setup ---
+
+/// Host, port and TLS mode to connect with.
+fn endpoint(profile: &ConnectionProfile) -> (String, u16, TlsMode) {
+    (profile::normalize_host(&profile.host), profile.port, profile.tls_mode)
+}
+
+/// TLS name to verify the certificate against. IP literals get the
+/// `IpAddress` variant, for which rustls sends no SNI.
+fn server_name(host: &str) -> Result<ServerName<'static>, Error> {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(ServerName::IpAddress(ip.into()));
+    }
+    ServerName::try_from(host.to_string())
+        .map_err(|e| Error::Protocol(format!("Invalid server name: {e}")))
+}
+
 fn root_store() -> rustls::RootCertStore {
     let mut store = rustls::RootCertStore::empty();
     store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
     store
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    fn b64(s: &str) -> String {
+        base64::engine::general_purpose::STANDARD.encode(s)
+    }
+
+    /// A client-side `Stream` wired to an in-memory server end.
+    fn mock_pair() -> (Stream, BufReader<tokio::io::DuplexStream>) {
+        let (client, server) = tokio::io::duplex(4096);
+        let stream = Stream::new(Transport::Mock(BufReader::new(client)), DEFAULT_READ_TIMEOUT);
+        (stream, BufReader::new(server))
+    }
+
+    async fn expect_line(server: &mut BufReader<tokio::io::DuplexStream>, expected: &str) {
+        let mut line = String::new();
+        server.read_line(&mut line).await.unwrap();
+        assert_eq!(line, format!("{expected}\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_parse_capability_greeting() {
+        let (mut stream, mut server) = mock_pair();
+        server
+            .get_mut()
+            .write_all(
+                b"\"IMPLEMENTATION\" \"Dovecot Pigeonhole\"\r\n\
+                  \"SIEVE\" \"fileinto reject envelope vacation imap4flags regex\"\r\n\
+                  \"NOTIFY\" \"mailto\"\r\n\
+                  \"SASL\" \"PLAIN SCRAM-SHA-256\"\r\n\
+                  \"STARTTLS\"\r\n\
+                  \"MAXREDIRECTS\" \"4\"\r\n\
+                  \"VERSION\" \"1.0\"\r\n\
+                  OK \"Dovecot ready.\"\r\n",
+            )
+            .await
+            .unwrap();
+
+        let greeting = read_response(&mut stream).await.unwrap();
+        assert!(greeting.ok);
+        let caps = Capabilities::parse(&greeting.lines);
+        assert_eq!(caps.implementation.as_deref(), Some("Dovecot Pigeonhole"));
+        assert_eq!(
+            caps.sieve_extensions,
+            vec!["fileinto", "reject", "envelope", "vacation", "imap4flags", "regex"]
+        );
+        assert!(caps.supports_extension("Vacation"));
+        assert!(!caps.supports_extension("body"));
+        assert_eq!(caps.sasl_mechanisms, vec!["PLAIN", "SCRAM-SHA-256"]);
+        assert!(caps.starttls);
+        assert_eq!(caps.max_redirects, Some(4));
+        assert_eq!(caps.notify_methods, vec!["mailto"]);
+        assert_eq!(caps.version.as_deref(), Some("1.0"));
+    }
+
+    #[tokio::test]
+    async fn test_scram_exchange() {
+        // RFC 7677 test vector, framed as a ManageSieve transcript
+        let (mut stream, mut server) = mock_pair();
+        let server_task = tokio::spawn(async move {
+            expect_line(
+                &mut server,
+                &format!("AUTHENTICATE \"SCRAM-SHA-256\" \"{}\"", b64("n,,n=user,r=rOprNGfwEbeRWgbNEkqO")),
+            )
+            .await;
+            let server_first = "r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096";
+            server
+                .get_mut()
+                .write_all(format!("\"{}\"\r\n", b64(server_first)).as_bytes())
+                .await
+                .unwrap();
+            expect_line(
+                &mut server,
+                &format!(
+                    "\"{}\"",
+                    b64("c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=")
+                ),
+            )
+            .await;
+            let server_final = b64("v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=");
+            server
+                .get_mut()
+                .write_all(format!("OK (SASL \"{server_final}\") \"Logged in\"\r\n").as_bytes())
+                .await
+                .unwrap();
+            let mut rest = Vec::new();
+            server.read_to_end(&mut rest).await.unwrap();
+        });
+
+        authenticate_scram(&mut stream, "user", "pencil", "rOprNGfwEbeRWgbNEkqO")
+            .await
+            .unwrap();
+        drop(stream);
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scram_rejected() {
+        let (mut stream, mut server) = mock_pair();
+        tokio::spawn(async move {
+            let mut line = String::new();
+            server.read_line(&mut line).await.unwrap();
+            server
+                .get_mut()
+                .write_all(b"NO \"Authentication failed\"\r\n")
+                .await
+                .unwrap();
+        });
+
+        let result = authenticate_scram(&mut stream, "user", "wrong", "abc").await;
+        assert!(matches!(result, Err(Error::AuthFailed)));
+    }
+
+    #[tokio::test]
+    async fn test_plain_single_step() {
+        let (mut stream, mut server) = mock_pair();
+        let credentials = b64("\0user\0secret");
+        let server_task = tokio::spawn(async move {
+            expect_line(&mut server, &format!("AUTHENTICATE \"PLAIN\" \"{credentials}\"")).await;
+            server.get_mut().write_all(b"OK \"Logged in\"\r\n").await.unwrap();
+        });
+
+        authenticate_plain(&mut stream, "user", "secret").await.unwrap();
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_plain_challenge_then_response() {
+        let (mut stream, mut server) = mock_pair();
+        let credentials = b64("\0user\0secret");
+        let server_task = tokio::spawn(async move {
+            expect_line(&mut server, &format!("AUTHENTICATE \"PLAIN\" \"{credentials}\"")).await;
+            server.get_mut().write_all(b"+ \"\"\r\n").await.unwrap();
+            expect_line(&mut server, &format!("\"{credentials}\"")).await;
+            server.get_mut().write_all(b"OK \"Logged in\"\r\n").await.unwrap();
+        });
+
+        authenticate_plain(&mut stream, "user", "secret").await.unwrap();
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_plain_bye_fails() {
+        let (mut stream, mut server) = mock_pair();
+        tokio::spawn(async move {
+            let mut line = String::new();
+            server.read_line(&mut line).await.unwrap();
+            server.get_mut().write_all(b"BYE \"Too many failures\"\r\n").await.unwrap();
+        });
+
+        let result = authenticate_plain(&mut stream, "user", "wrong").await;
+        assert!(matches!(result, Err(Error::AuthFailed)));
+    }
+
+    /// A connected client whose server side is scripted by the test.
+    fn mock_client(capabilities: Capabilities) -> (ManageSieveClient, BufReader<tokio::io::DuplexStream>) {
+        let (stream, server) = mock_pair();
+        let client = ManageSieveClient {
+            stream: Some(stream),
+            capabilities,
+            ..ManageSieveClient::new()
+        };
+        (client, server)
+    }
+
+    async fn reply(server: &mut BufReader<tokio::io::DuplexStream>, data: &str) {
+        server.get_mut().write_all(data.as_bytes()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rename_script_native() {
+        let caps = Capabilities {
+            version: Some("1.0".to_string()),
+            ..Default::default()
+        };
+        let (mut client, mut server) = mock_client(caps);
+        tokio::spawn(async move {
+            expect_line(&mut server, "RENAMESCRIPT \"old\" \"new\"").await;
+            reply(&mut server, "NO (ALREADYEXISTS) \"Script exists\"\r\n").await;
+            expect_line(&mut server, "RENAMESCRIPT \"old\" \"other\"").await;
+            reply(&mut server, "OK\r\n").await;
+        });
+
+        let err = client.rename_script("old", "new").await.unwrap_err();
+        assert!(matches!(err, Error::Server(m) if m.contains("ALREADYEXISTS")));
+        client.rename_script("old", "other").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rename_script_fallback_keeps_active() {
+        let (mut client, mut server) = mock_client(Capabilities::default());
+        let server_task = tokio::spawn(async move {
+            expect_line(&mut server, "LISTSCRIPTS").await;
+            reply(&mut server, "\"old\" ACTIVE\r\n\"misc\"\r\nOK\r\n").await;
+            expect_line(&mut server, "GETSCRIPT \"old\"").await;
+            reply(&mut server, "{7}\r\nkeep;\r\n\r\nOK\r\n").await;
+            expect_line(&mut server, "PUTSCRIPT \"new\" {7+}").await;
+            expect_line(&mut server, "keep;").await;
+            expect_line(&mut server, "").await;
+            reply(&mut server, "OK\r\n").await;
+            expect_line(&mut server, "SETACTIVE \"new\"").await;
+            reply(&mut server, "OK\r\n").await;
+            expect_line(&mut server, "DELETESCRIPT \"old\"").await;
+            reply(&mut server, "OK\r\n").await;
+        });
+
+        client.rename_script("old", "new").await.unwrap();
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rename_script_fallback_keeps_existing() {
+        let (mut client, mut server) = mock_client(Capabilities::default());
+        let server_task = tokio::spawn(async move {
+            expect_line(&mut server, "LISTSCRIPTS").await;
+            reply(&mut server, "\"old\" ACTIVE\r\n\"misc\"\r\nOK\r\n").await;
+        });
+
+        // Nothing is written or deleted
+        let err = client.rename_script("old", "misc").await.unwrap_err();
+        let Error::Server(message) = err else {
+            panic!("Expected server error");
+        };
+        assert!(message.contains("ALREADYEXISTS"));
+        assert_eq!(response_text(&message), "A script named misc already exists");
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_script_is_byte_exact() {
+        let body = "# \"quoted\" comment\r\n\r\nif header :is \"Subject\" \"\\\"x\\\"\" {\r\n  discard;\r\n}\r\n\r\n";
+        let (mut client, mut server) = mock_client(Capabilities::default());
+        tokio::spawn(async move {
+            expect_line(&mut server, "GETSCRIPT \"quotes\"").await;
+            reply(&mut server, &format!("{{{}}}\r\n{body}\r\nOK\r\n", body.len())).await;
+            expect_line(&mut server, "GETSCRIPT \"plain\"").await;
+            reply(&mut server, "keep;\r\nOK\r\n").await;
+        });
+
+        assert_eq!(client.get_script("quotes").await.unwrap(), body);
+        // No literal announced: the reply lines are the script
+        assert_eq!(client.get_script("plain").await.unwrap(), "keep;");
+    }
+
+    #[tokio::test]
+    async fn test_get_script_quoted_body() {
+        let (mut client, mut server) = mock_client(Capabilities::default());
+        tokio::spawn(async move {
+            expect_line(&mut server, "GETSCRIPT \"short\"").await;
+            reply(&mut server, "\"fileinto \\\"Junk\\\";\"\r\nOK\r\n").await;
+        });
+
+        assert_eq!(client.get_script("short").await.unwrap(), "fileinto \"Junk\";");
+    }
+
+    #[tokio::test]
+    async fn test_get_script_no_with_literal_reason() {
+        let (mut client, mut server) = mock_client(Capabilities::default());
+        tokio::spawn(async move {
+            expect_line(&mut server, "GETSCRIPT \"missing\"").await;
+            reply(&mut server, "NO (NONEXISTENT) {16}\r\nNo such script\r\n\r\n").await;
+            expect_line(&mut server, "GETSCRIPT \"plain\"").await;
+            reply(&mut server, "{5}\r\nkeep;\r\nOK\r\n").await;
+        });
+
+        let err = client.get_script("missing").await.unwrap_err();
+        let Error::Server(message) = err else {
+            panic!("Expected server error");
+        };
+        assert_eq!(message, "NO (NONEXISTENT) \"No such script\"");
+        // The reason was read in full, so the next reply is intact
+        assert_eq!(client.get_script("plain").await.unwrap(), "keep;");
+    }
+
+    #[tokio::test]
+    async fn test_put_script_streams_large_body() {
+        // About 256 KiB of 20-byte lines
+        let body = "# allow list entry\r\n".repeat(256 * 1024 / 20);
+        let expected = body.clone();
+        let (mut client, mut server) = mock_client(Capabilities::default());
+        let server_task = tokio::spawn(async move {
+            expect_line(&mut server, &format!("PUTSCRIPT \"big\" {{{}+}}", expected.len())).await;
+            let mut literal = vec![0; expected.len() + 2];
+            server.read_exact(&mut literal).await.unwrap();
+            assert!(literal == format!("{expected}\r\n").into_bytes());
+            reply(&mut server, "OK\r\n").await;
+        });
+
+        client.put_script("big", &body).await.unwrap();
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_put_script_frames_multibyte_text() {
+        let body = "if header :contains \"Subject\" \"über\" {\r\n    fileinto \"Grüße\";\r\n}\r\n";
+        assert_eq!(literal_len(body), body.chars().count() + 3);
+        let expected = body.to_string();
+        let (mut client, mut server) = mock_client(Capabilities::default());
+        let server_task = tokio::spawn(async move {
+            expect_line(&mut server, &format!("PUTSCRIPT \"utf8\" {{{}+}}", expected.len())).await;
+            let mut literal = vec![0; expected.len() + 2];
+            server.read_exact(&mut literal).await.unwrap();
+            assert_eq!(String::from_utf8(literal).unwrap(), format!("{expected}\r\n"));
+            reply(&mut server, "OK\r\n").await;
+        });
+
+        client.put_script("utf8", body).await.unwrap();
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_list_scripts_with_literal_names() {
+        let (mut client, mut server) = mock_client(Capabilities::default());
+        tokio::spawn(async move {
+            expect_line(&mut server, "LISTSCRIPTS").await;
+            reply(
+                &mut server,
+                "\"INACTIVE-old\"\r\n{12}\r\nsay \"hi\" now ACTIVE\r\n{4}\r\nmisc\r\nOK\r\n",
+            )
+            .await;
+        });
+
+        let scripts = client.list_scripts().await.unwrap();
+        let listed: Vec<(&str, bool)> = scripts.iter().map(|s| (s.name.as_str(), s.active)).collect();
+        assert_eq!(
+            listed,
+            vec![("INACTIVE-old", false), ("say \"hi\" now", true), ("misc", false)]
+        );
+    }
+
+    #[test]
+    fn test_string_arg_encoding() {
+        assert_eq!(string_arg("vacation-2024"), "\"vacation-2024\"");
+        assert_eq!(string_arg("my rules"), "\"my rules\"");
+        assert_eq!(string_arg("say \"hi\""), "{8+}\r\nsay \"hi\"");
+        assert_eq!(string_arg("back\\slash"), "{10+}\r\nback\\slash");
+        // Length is in bytes, not chars
+        assert_eq!(string_arg("Büro"), "{5+}\r\nBüro");
+        assert_eq!(string_arg("tab\there"), "{8+}\r\ntab\there");
+    }
+
+    #[tokio::test]
+    async fn test_literal_names_in_commands() {
+        let (mut client, mut server) = mock_client(Capabilities::default());
+        let server_task = tokio::spawn(async move {
+            expect_line(&mut server, "PUTSCRIPT {5+}").await;
+            expect_line(&mut server, "Büro {5+}").await;
+            expect_line(&mut server, "keep;").await;
+            reply(&mut server, "OK\r\n").await;
+            expect_line(&mut server, "SETACTIVE {5+}").await;
+            expect_line(&mut server, "Büro").await;
+            reply(&mut server, "OK\r\n").await;
+        });
+
+        client.put_script("Büro", "keep;").await.unwrap();
+        client.set_active("Büro").await.unwrap();
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reauthenticate_on_same_connection() {
+        let caps = Capabilities {
+            sasl_mechanisms: vec!["PLAIN".to_string()],
+            unauthenticate: true,
+            ..Default::default()
+        };
+        let (mut client, mut server) = mock_client(caps);
+        let alice = ConnectionProfile {
+            host: "mail.example.com".to_string(),
+            username: "alice".to_string(),
+            ..Default::default()
+        };
+        let bob = ConnectionProfile {
+            username: "bob".to_string(),
+            ..alice.clone()
+        };
+        let other_server = ConnectionProfile {
+            host: "other.example.com".to_string(),
+            ..bob.clone()
+        };
+        client.endpoint = Some(endpoint(&alice));
+        assert!(client.can_unauthenticate(&bob));
+        assert!(!client.can_unauthenticate(&other_server));
+
+        let server_task = tokio::spawn(async move {
+            expect_line(&mut server, "UNAUTHENTICATE").await;
+            reply(&mut server, "OK\r\n").await;
+            let credentials = b64("\0bob\0hunter2");
+            expect_line(&mut server, &format!("AUTHENTICATE \"PLAIN\" \"{credentials}\"")).await;
+            reply(&mut server, "OK\r\n").await;
+        });
+
+        client.reauthenticate(&bob, "hunter2").await.unwrap();
+        assert!(client.is_connected());
+        server_task.await.unwrap();
+    }
+
+    #[test]
+    fn test_unauthenticate_needs_capability() {
+        let (mut client, _server) = mock_client(Capabilities::default());
+        let profile = ConnectionProfile::default();
+        client.endpoint = Some(endpoint(&profile));
+        assert!(!client.can_unauthenticate(&profile));
+
+        client.capabilities = Capabilities::parse(&["\"UNAUTHENTICATE\"".to_string()]);
+        assert!(client.capabilities.unauthenticate);
+        assert!(client.can_unauthenticate(&profile));
+
+        client.stream = None;
+        assert!(!client.can_unauthenticate(&profile));
+    }
+
+    #[tokio::test]
+    async fn test_bye_ends_the_session() {
+        assert_eq!(bye_reason("BYE \"Session timeout\""), Some("Session timeout".to_string()));
+        assert_eq!(bye_reason("BYE (TRYLATER) \"Busy\""), Some("Busy".to_string()));
+        assert_eq!(bye_reason("BYE"), Some("no reason given".to_string()));
+        assert_eq!(bye_reason("NO \"Nope\""), None);
+
+        let (mut client, mut server) = mock_client(Capabilities::default());
+        tokio::spawn(async move {
+            expect_line(&mut server, "SETACTIVE \"main\"").await;
+            reply(&mut server, "BYE \"Session timeout\"\r\n").await;
+        });
+        let err = client.set_active("main").await.unwrap_err();
+        assert!(matches!(&err, Error::ServerClosed(reason) if reason == "Session timeout"));
+        assert_eq!(server_closed_reason(&err.to_string()), Some("Session timeout"));
+        assert_eq!(server_closed_reason("Server error: NO"), None);
+    }
+
+    #[tokio::test]
+    async fn test_have_space() {
+        let (mut client, mut server) = mock_client(Capabilities::default());
+        tokio::spawn(async move {
+            expect_line(&mut server, "HAVESPACE \"small\" 120").await;
+            reply(&mut server, "OK\r\n").await;
+            expect_line(&mut server, "HAVESPACE \"huge\" 9000000").await;
+            reply(&mut server, "NO (QUOTA/MAXSIZE) \"Script too large\"\r\n").await;
+        });
+
+        assert!(client.have_space("small", 120).await.unwrap());
+        assert!(!client.have_space("huge", 9_000_000).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_have_space_other_failure() {
+        let (mut client, mut server) = mock_client(Capabilities::default());
+        tokio::spawn(async move {
+            expect_line(&mut server, "HAVESPACE \"bad/name\" 10").await;
+            reply(&mut server, "NO \"Invalid script name\"\r\n").await;
+            expect_line(&mut server, "HAVESPACE \"many\" 10").await;
+            reply(&mut server, "NO (quota/maxscripts) \"Too many scripts\"\r\n").await;
+        });
+
+        let err = client.have_space("bad/name", 10).await.unwrap_err();
+        assert!(matches!(&err, Error::Server(m) if response_text(m) == "Invalid script name"));
+        assert!(!client.have_space("many", 10).await.unwrap());
+        assert_eq!(response_code("NO (TAG \"x\") \"y\""), Some("TAG".to_string()));
+        assert_eq!(response_code("NO \"y\""), None);
+    }
+
+    #[tokio::test]
+    async fn test_check_script_preserves_no_message() {
+        let (mut client, mut server) = mock_client(Capabilities::default());
+        tokio::spawn(async move {
+            expect_line(&mut server, "CHECKSCRIPT {10+}").await;
+            expect_line(&mut server, "bogus cmd;").await;
+            reply(
+                &mut server,
+                "NO \"line 1: unknown command 'bogus' (only reported the first error)\"\r\n",
+            )
+            .await;
+            expect_line(&mut server, "CHECKSCRIPT {5+}").await;
+            expect_line(&mut server, "keep;").await;
+            reply(&mut server, "NO {34}\r\nline 1: error 1\r\nline 2: error 2\r\n\r\n").await;
+        });
+
+        let err = client.check_script("bogus cmd;").await.unwrap_err();
+        let Error::Server(message) = err else {
+            panic!("Expected server error");
+        };
+        assert_eq!(
+            message,
+            "NO \"line 1: unknown command 'bogus' (only reported the first error)\""
+        );
+        assert_eq!(
+            response_text(&message),
+            "line 1: unknown command 'bogus' (only reported the first error)"
+        );
+
+        let err = client.check_script("keep;").await.unwrap_err();
+        let Error::Server(message) = err else {
+            panic!("Expected server error");
+        };
+        assert_eq!(response_text(&message), "line 1: error 1\r\nline 2: error 2");
+    }
+
+    #[tokio::test]
+    async fn test_check_script_braces_in_quoted_text() {
+        let (mut client, mut server) = mock_client(Capabilities::default());
+        tokio::spawn(async move {
+            expect_line(&mut server, "CHECKSCRIPT {5+}").await;
+            expect_line(&mut server, "keep;").await;
+            reply(&mut server, "NO \"line 5: unknown variable ${1}\"\r\n").await;
+            // Left open so a client waiting for literal bytes would hang
+            std::future::pending::<()>().await;
+        });
+
+        let err = tokio::time::timeout(Duration::from_secs(5), client.check_script("keep;"))
+            .await
+            .expect("reply should not be read as a literal")
+            .unwrap_err();
+        let Error::Server(message) = err else {
+            panic!("Expected server error");
+        };
+        assert_eq!(message, "NO \"line 5: unknown variable ${1}\"");
+        assert_eq!(response_text(&message), "line 5: unknown variable ${1}");
+    }
+
+    #[test]
+    fn test_extract_literal_size() {
+        assert_eq!(extract_literal_size("{42}"), Some(42));
+        assert_eq!(extract_literal_size(" {7+} "), Some(7));
+        assert_eq!(extract_literal_size("{1}\""), None);
+        assert_eq!(extract_literal_size("{+5}"), None);
+        assert_eq!(trailing_literal_size("NO {12}\r\n"), Some(12));
+        assert_eq!(trailing_literal_size("NO \"unknown variable ${1}\""), None);
+    }
+
+    /// Implicit-TLS server for one session: greeting, then OK to AUTHENTICATE.
+    async fn spawn_tls_server(cert: &rcgen::CertifiedKey) -> u16 {
+        let key_der = rustls::pki_types::PrivateKeyDer::Pkcs8(
+            cert.key_pair.serialize_der().into(),
+        );
+        let server_config = rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.cert.der().clone()], key_der)
+            .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(server_config));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            // Handshake first: nothing may be sent in the clear
+            let Ok(tls) = acceptor.accept(tcp).await else {
+                return;
+            };
+            let mut server = BufReader::new(tls);
+            server
+                .get_mut()
+                .write_all(b"\"IMPLEMENTATION\" \"Test TLS\"\r\n\"SASL\" \"PLAIN\"\r\nOK\r\n")
+                .await
+                .unwrap();
+            let mut line = String::new();
+            server.read_line(&mut line).await.unwrap();
+            assert!(line.starts_with("AUTHENTICATE \"PLAIN\""));
+            server.get_mut().write_all(b"OK\r\n").await.unwrap();
+        });
+        port
+    }
+
+    fn tls_profile(port: u16) -> ConnectionProfile {
+        ConnectionProfile {
+            host: "127.0.0.1".to_string(),
+            port,
+            username: "user".to_string(),
+            tls_mode: TlsMode::Implicit,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_implicit_tls_reads_greeting_over_tls() {
+        let cert = rcgen::generate_simple_self_signed(vec!["127.0.0.1".to_string()]).unwrap();
+        let port = spawn_tls_server(&cert).await;
+
+        let mut client = ManageSieveClient::new();
+        client.tls_roots = rustls::RootCertStore::empty();
+        client.tls_roots.add(cert.cert.der().clone()).unwrap();
+        let caps = client.connect(&tls_profile(port), "secret").await.unwrap();
+        assert_eq!(caps.implementation.as_deref(), Some("Test TLS"));
+        assert!(client.is_connected());
+        match client.security() {
+            Some(Security::Tls { version, cipher }) => {
+                assert_eq!(version, "TLS1.3");
+                assert!(cipher.starts_with("TLS13_"), "{cipher}");
+            }
+            other => panic!("Expected TLS, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_self_signed_certificate_needs_pin() {
+        let cert = rcgen::generate_simple_self_signed(vec!["127.0.0.1".to_string()]).unwrap();
+        let fingerprint = crate::net::cert_pin::fingerprint(cert.cert.der());
+
+        let port = spawn_tls_server(&cert).await;
+        let mut client = ManageSieveClient::new();
+        let err = client.connect(&tls_profile(port), "secret").await.unwrap_err();
+        assert!(matches!(&err, Error::UntrustedCertificate(f) if *f == fingerprint));
+
+        let port = spawn_tls_server(&cert).await;
+        let profile = ConnectionProfile {
+            tls_fingerprint: Some(fingerprint),
+            ..tls_profile(port)
+        };
+        client.connect(&profile, "secret").await.unwrap();
+        assert!(client.is_connected());
+    }
+
+    #[tokio::test]
+    async fn test_starttls_required_but_not_offered() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut server = BufReader::new(tcp);
+            server
+                .get_mut()
+                .write_all(b"\"IMPLEMENTATION\" \"Plain only\"\r\n\"SASL\" \"PLAIN\"\r\nOK\r\n")
+                .await
+                .unwrap();
+            // Everything the client sends until it hangs up
+            let mut received = String::new();
+            server.read_to_string(&mut received).await.unwrap();
+            received
+        });
+
+        let profile = ConnectionProfile {
+            tls_mode: TlsMode::StartTls,
+            ..tls_profile(port)
+        };
+        let mut client = ManageSieveClient::new();
+        let err = client.connect(&profile, "secret").await.unwrap_err();
+        assert!(matches!(&err, Error::Protocol(m) if m == "Server does not support STARTTLS"));
+        assert!(!client.is_connected());
+        drop(client);
+        assert_eq!(server.await.unwrap(), "");
+    }
+
+    /// STARTTLS server for one session. After the handshake it re-sends its
+    /// capabilities only when `resend_capabilities` is set.
+    async fn spawn_starttls_server(cert: &rcgen::CertifiedKey, resend_capabilities: bool) -> u16 {
+        let key_der = rustls::pki_types::PrivateKeyDer::Pkcs8(
+            cert.key_pair.serialize_der().into(),
+        );
+        let server_config = rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.cert.der().clone()], key_der)
+            .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(server_config));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut plain = BufReader::new(tcp);
+            plain
+                .get_mut()
+                .write_all(b"\"IMPLEMENTATION\" \"Before TLS\"\r\n\"STARTTLS\"\r\nOK\r\n")
+                .await
+                .unwrap();
+            let mut line = String::new();
+            plain.read_line(&mut line).await.unwrap();
+            assert_eq!(line, "STARTTLS\r\n");
+            plain.get_mut().write_all(b"OK\r\n").await.unwrap();
+
+            let tls = acceptor.accept(plain.into_inner()).await.unwrap();
+            let mut server = BufReader::new(tls);
+            if resend_capabilities {
+                server
+                    .get_mut()
+                    .write_all(b"\"IMPLEMENTATION\" \"After TLS\"\r\n\"SASL\" \"PLAIN\"\r\nOK\r\n")
+                    .await
+                    .unwrap();
+            }
+            let mut line = String::new();
+            server.read_line(&mut line).await.unwrap();
+            assert!(line.starts_with("AUTHENTICATE \"PLAIN\""));
+            server.get_mut().write_all(b"OK\r\n").await.unwrap();
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_starttls_without_capabilities_after_handshake() {
+        let cert = rcgen::generate_simple_self_signed(vec!["127.0.0.1".to_string()]).unwrap();
+        let profile = |port| ConnectionProfile {
+            tls_mode: TlsMode::StartTls,
+            ..tls_profile(port)
+        };
+        let mut client = ManageSieveClient::new();
+        client.tls_roots = rustls::RootCertStore::empty();
+        client.tls_roots.add(cert.cert.der().clone()).unwrap();
+        client.capability_grace = Duration::from_millis(100);
+
+        let port = spawn_starttls_server(&cert, true).await;
+        let caps = client.connect(&profile(port), "secret").await.unwrap();
+        assert_eq!(caps.implementation.as_deref(), Some("After TLS"));
+        client.disconnect().await;
+
+        // Silent after the handshake: log in with what the greeting said
+        let port = spawn_starttls_server(&cert, false).await;
+        let caps = client.connect(&profile(port), "secret").await.unwrap();
+        assert_eq!(caps.implementation.as_deref(), Some("Before TLS"));
+        assert!(client.is_connected());
+    }
+
+    #[tokio::test]
+    async fn test_read_timeout_on_stalled_server() {
+        let (mut stream, _server) = mock_pair();
+        stream.read_timeout = Duration::from_millis(50);
+        // The server end stays open but never sends a greeting
+        let result = read_response(&mut stream).await;
+        assert!(matches!(result, Err(Error::Timeout)));
+    }
+
+    #[test]
+    fn test_server_name_for_ip_literals() {
+        for ip in ["::1", "192.0.2.1"] {
+            assert!(matches!(server_name(ip), Ok(ServerName::IpAddress(_))), "{ip}");
+        }
+        assert!(matches!(server_name("mail.example.com"), Ok(ServerName::DnsName(_))));
+        assert!(server_name("[::1]").is_err());
+    }
+}
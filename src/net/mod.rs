@@ -1 +1,3 @@
+pub mod cert_pin;
 pub mod managesieve;
+pub mod scram;
@@ -0,0 +1,171 @@
+/// Client side of SASL SCRAM-SHA-256 (RFC 5802 / RFC 7677).
+///
+/// Only the message computation lives here; the ManageSieve exchange that
+/// carries these messages is driven by `managesieve::authenticate_scram`.
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::net::managesieve::Error;
+
+pub const MECHANISM: &str = "SCRAM-SHA-256";
+
+/// GS2 header for a client without channel binding.
+const GS2_HEADER: &str = "n,,";
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub struct ScramClient {
+    /// Wiped when the client is dropped
+    password: Zeroizing<String>,
+    client_first_bare: String,
+    nonce: String,
+    /// Server signature expected in server-final, known once client-final is built
+    server_signature: Option<Vec<u8>>,
+}
+
+impl ScramClient {
+    pub fn new(username: &str, password: &str, nonce: &str) -> Self {
+        Self {
+            password: Zeroizing::new(password.to_string()),
+            client_first_bare: format!("n={},r={nonce}", sasl_name(username)),
+            nonce: nonce.to_string(),
+            server_signature: None,
+        }
+    }
+
+    /// The client-first message, e.g. `n,,n=user,r=<nonce>`.
+    pub fn client_first(&self) -> String {
+        format!("{GS2_HEADER}{}", self.client_first_bare)
+    }
+
+    /// Consume the server-first message and build the client-final message.
+    pub fn client_final(&mut self, server_first: &str) -> Result<String, Error> {
+        let mut nonce = None;
+        let mut salt = None;
+        let mut iterations = None;
+        for attr in server_first.split(',') {
+            match attr.split_once('=') {
+                Some(("r", v)) => nonce = Some(v),
+                Some(("s", v)) => salt = Some(v),
+                Some(("i", v)) => iterations = v.parse::<u32>().ok(),
+                Some(("e", v)) => return Err(Error::Server(format!("SCRAM error: {v}"))),
+                _ => {}
+            }
+        }
+        let (Some(nonce), Some(salt), Some(iterations)) = (nonce, salt, iterations) else {
+            return Err(Error::Protocol(format!(
+                "Malformed SCRAM server-first message: {server_first}"
+            )));
+        };
+        if !nonce.starts_with(&self.nonce) || iterations == 0 {
+            return Err(Error::Protocol("Invalid SCRAM server nonce".to_string()));
+        }
+        let salt = base64::engine::general_purpose::STANDARD
+            .decode(salt)
+            .map_err(|e| Error::Protocol(format!("Invalid SCRAM salt: {e}")))?;
+
+        let mut salted_password = Zeroizing::new([0u8; 32]);
+        pbkdf2::pbkdf2_hmac::<Sha256>(
+            self.password.as_bytes(),
+            &salt,
+            iterations,
+            &mut *salted_password,
+        );
+
+        let mut client_key = hmac(&*salted_password, b"Client Key");
+        let stored_key = Sha256::digest(&client_key);
+        let channel_binding = base64::engine::general_purpose::STANDARD.encode(GS2_HEADER);
+        let without_proof = format!("c={channel_binding},r={nonce}");
+        let auth_message = format!("{},{server_first},{without_proof}", self.client_first_bare);
+
+        let client_signature = hmac(&stored_key, auth_message.as_bytes());
+        let proof: Vec<u8> = client_key
+            .iter()
+            .zip(&client_signature)
+            .map(|(k, s)| k ^ s)
+            .collect();
+
+        client_key.zeroize();
+
+        let server_key = hmac(&*salted_password, b"Server Key");
+        self.server_signature = Some(hmac(&server_key, auth_message.as_bytes()));
+
+        Ok(format!(
+            "{without_proof},p={}",
+            base64::engine::general_purpose::STANDARD.encode(proof)
+        ))
+    }
+
+    /// Check the server-final message (`v=<signature>`) against our own computation.
+    pub fn verify_server_final(&self, server_final: &str) -> Result<(), Error> {
+        if let Some(err) = server_final.strip_prefix("e=") {
+            return Err(Error::Server(format!("SCRAM error: {err}")));
+        }
+        let expected = self
+            .server_signature
+            .as_ref()
+            .ok_or_else(|| Error::Protocol("SCRAM exchange out of order".to_string()))?;
+        let signature = server_final
+            .strip_prefix("v=")
+            .and_then(|v| base64::engine::general_purpose::STANDARD.decode(v).ok())
+            .ok_or_else(|| {
+                Error::Protocol(format!("Malformed SCRAM server-final message: {server_final}"))
+            })?;
+        if &signature != expected {
+            return Err(Error::Protocol("SCRAM server signature mismatch".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// A fresh random client nonce.
+pub fn generate_nonce() -> Result<String, Error> {
+    let mut bytes = [0u8; 24];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|e| Error::Protocol(format!("Could not generate nonce: {e}")))?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(bytes))
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Escape `=` and `,` in a username as required by the `n=` attribute.
+fn sasl_name(username: &str) -> String {
+    username.replace('=', "=3D").replace(',', "=2C")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test vector from RFC 7677, section 3
+    const NONCE: &str = "rOprNGfwEbeRWgbNEkqO";
+    const SERVER_FIRST: &str =
+        "r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096";
+
+    #[test]
+    fn test_rfc7677_vector() {
+        let mut client = ScramClient::new("user", "pencil", NONCE);
+        assert_eq!(client.client_first(), "n,,n=user,r=rOprNGfwEbeRWgbNEkqO");
+        let client_final = client.client_final(SERVER_FIRST).unwrap();
+        assert_eq!(
+            client_final,
+            "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ="
+        );
+        assert!(client
+            .verify_server_final("v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=")
+            .is_ok());
+        assert!(client.verify_server_final("v=AAAA").is_err());
+    }
+
+    #[test]
+    fn test_rejects_foreign_nonce() {
+        let mut client = ScramClient::new("user", "pencil", "abc");
+        assert!(client.client_final(SERVER_FIRST).is_err());
+    }
+}
@@ -6,10 +6,16 @@ pub struct Script {
 }
 
 /// A top-level command in a SIEVE script.
+// Scripts hold a handful of commands, so the size of `If` doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, PartialEq)]
 pub enum Command {
-    /// `require ["ext1", "ext2"];`
-    Require(Vec<String>),
+    /// `require "ext";` or `require ["ext1", "ext2"];`
+    Require {
+        extensions: Vec<String>,
+        /// Written as a bracketed list, even of a single extension
+        list: bool,
+    },
 
     /// `if <test> { <actions> }` with optional elsif/else chain
     If(IfBlock),
@@ -17,6 +23,14 @@ pub enum Command {
     /// A simple action command like `keep;`, `stop;`, `fileinto "X";`
     Action(ActionCommand),
 
+    /// `set [:lower ...] "name" "value";` (RFC 5229)
+    Set {
+        name: String,
+        value: String,
+        /// Modifier tags such as `:lower` or `:quotewildcard`, in order
+        modifiers: Vec<String>,
+    },
+
     /// A comment line: `# text`
     Comment(String),
 
@@ -30,6 +44,11 @@ pub struct IfBlock {
     pub name: Option<String>,
     /// Whether the filter is enabled (disabled = `# Filter: name [DISABLED]` or wrapped in comment)
     pub enabled: bool,
+    /// Disabled by wrapping the whole block in `/* ... */` rather than by
+    /// the `[DISABLED]` marker
+    pub commented_out: bool,
+    /// Free-text notes from the comment lines right after the `# Filter:` line
+    pub comment: Option<String>,
     pub condition: TestExpr,
     pub actions: Vec<ActionCommand>,
     /// elsif/else chain
@@ -56,23 +75,32 @@ pub enum TestExpr {
     AnyOf(Vec<TestExpr>),
     /// `not <test>`
     Not(Box<TestExpr>),
-    /// `header :match_type "Header" "value"`
+    /// `header [:comparator "c"] :match_type "Header" "value"`
     Header {
         match_type: String,
+        comparator: Option<String>,
+        /// Relational match, replacing `match_type` when present
+        relation: Option<(RelTag, String)>,
         header_names: Vec<String>,
         keys: Vec<String>,
     },
-    /// `address [:address_part] :match_type "Header" "value"`
+    /// `address [:comparator "c"] [:address_part] :match_type "Header" "value"`
     Address {
+        /// `:localpart` or `:domain`; `None` for the default `:all`
         address_part: Option<String>,
         match_type: String,
+        comparator: Option<String>,
+        relation: Option<(RelTag, String)>,
         header_names: Vec<String>,
         keys: Vec<String>,
     },
-    /// `envelope [:address_part] :match_type "Header" "value"`
+    /// `envelope [:comparator "c"] [:address_part] :match_type "Header" "value"`
     Envelope {
+        /// `:localpart` or `:domain`; `None` for the default `:all`
         address_part: Option<String>,
         match_type: String,
+        comparator: Option<String>,
+        relation: Option<(RelTag, String)>,
         header_names: Vec<String>,
         keys: Vec<String>,
     },
@@ -85,17 +113,66 @@ pub enum TestExpr {
     Exists {
         header_names: Vec<String>,
     },
-    /// `body :match_type "value"`
+    /// `body [:comparator "c"] :match_type [transform] "value"`
     Body {
         match_type: String,
+        comparator: Option<String>,
+        relation: Option<(RelTag, String)>,
+        /// `:raw`, `:text` or `:content` (RFC 5173 §5); `None` is `:text`
+        transform: Option<String>,
+        /// Media types after `:content`
+        content_types: Vec<String>,
         keys: Vec<String>,
     },
+    /// `string [:comparator "c"] :match_type "source" "key"` (RFC 5229)
+    StringTest {
+        match_type: String,
+        comparator: Option<String>,
+        relation: Option<(RelTag, String)>,
+        source: Vec<String>,
+        keys: Vec<String>,
+    },
+    /// `hasflag [:comparator "c"] :match_type [<variables>] <flags>` (RFC 5232)
+    Hasflag {
+        match_type: String,
+        comparator: Option<String>,
+        relation: Option<(RelTag, String)>,
+        /// Variables holding the flags; empty for the internal flags
+        variables: Vec<String>,
+        flags: Vec<String>,
+    },
     /// `true`
     True,
     /// `false`
     False,
 }
 
+/// Relational match type (RFC 5231), paired with an operator such as `"gt"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelTag {
+    /// `:count` — compare the number of values
+    Count,
+    /// `:value` — compare the values themselves
+    Value,
+}
+
+impl RelTag {
+    pub fn as_sieve(&self) -> &'static str {
+        match self {
+            Self::Count => ":count",
+            Self::Value => ":value",
+        }
+    }
+
+    pub fn from_sieve(s: &str) -> Option<Self> {
+        match s {
+            ":count" => Some(Self::Count),
+            ":value" => Some(Self::Value),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct ActionCommand {
     pub name: String,
@@ -108,4 +185,6 @@ pub enum Argument {
     Number(String),
     Tag(String),
     StringList(Vec<String>),
+    /// A `text:` multi-line string, stored without dot-stuffing or terminator.
+    MultiLine(String),
 }
@@ -3,11 +3,12 @@
 /// `text_to_script()` — parse text → AST → model
 /// `script_to_text()` — model → AST → emit text
 use crate::model::enums::*;
-use crate::model::rule::{Action, Condition, SieveRule};
-use crate::model::script::SieveScript;
+use crate::model::rule::{Action, Condition, ConditionNode, RuleBranch, SieveRule};
+use crate::model::script::{RequireStatement, SieveScript};
 use crate::sieve::ast::*;
-use crate::sieve::emitter;
+use crate::sieve::emitter::{self, EmitOptions};
 use crate::sieve::parser;
+use std::collections::BTreeSet;
 
 /// Parse SIEVE script text into a SieveScript model.
 pub fn text_to_script(text: &str, script_name: &str) -> SieveScript {
@@ -18,7 +19,9 @@ pub fn text_to_script(text: &str, script_name: &str) -> SieveScript {
         };
     }
 
-    let ast = match parser::parse(text) {
+    // A broken block becomes a raw rule of its own; only text that can't
+    // even be tokenized is one raw rule as a whole
+    let ast = match parser::parse_recovering(text) {
         Ok(ast) => ast,
         Err(_) => {
             return SieveScript {
@@ -34,38 +37,253 @@ pub fn text_to_script(text: &str, script_name: &str) -> SieveScript {
     };
 
     let mut requires = Vec::new();
+    let mut require_statements = Vec::new();
     let mut rules = Vec::new();
+    let mut preamble = Vec::new();
+    let mut comments: Vec<String> = Vec::new();
+    let mut sets: Vec<Command> = Vec::new();
+    let mut seen_code = false;
 
     for cmd in &ast.commands {
+        if let Command::Comment(text) = cmd {
+            if seen_code {
+                comments.push(text.clone());
+            } else {
+                preamble.push(text.clone());
+            }
+            continue;
+        }
+        seen_code = true;
+        if matches!(cmd, Command::Set { .. }) {
+            // Comments between two `set`s end the run before them
+            if !comments.is_empty() {
+                flush_set_rule(&mut rules, &mut sets);
+            }
+            flush_comment_rule(&mut rules, &mut comments);
+            sets.push(cmd.clone());
+            continue;
+        }
+        flush_set_rule(&mut rules, &mut sets);
+        flush_comment_rule(&mut rules, &mut comments);
         match cmd {
-            Command::Require(exts) => {
-                for ext in exts {
-                    if !requires.contains(ext) {
+            Command::Require { extensions, list } => {
+                // Extension names are case-insensitive: keep the first
+                // mention of each, lowercased
+                let mut statement = Vec::new();
+                for ext in extensions.iter().map(|e| extension_name(e)) {
+                    if !requires.contains(&ext) {
                         requires.push(ext.clone());
+                        statement.push(ext);
                     }
                 }
+                if !statement.is_empty() {
+                    require_statements.push(RequireStatement {
+                        extensions: statement,
+                        list: *list,
+                    });
+                }
             }
             Command::If(block) => {
                 let rule = if_block_to_rule(block);
                 rules.push(rule);
             }
-            Command::Action(_) | Command::Comment(_) | Command::Raw(_) => {}
+            Command::Raw(raw) => rules.push(SieveRule {
+                name: broken_block_name(raw),
+                raw_block: Some(raw.clone()),
+                ..Default::default()
+            }),
+            Command::Action(_) | Command::Set { .. } | Command::Comment(_) => {}
         }
     }
+    flush_set_rule(&mut rules, &mut sets);
+    flush_comment_rule(&mut rules, &mut comments);
 
     SieveScript {
         name: script_name.to_string(),
         rules,
         requires,
+        preamble,
+        require_statements,
+        ..Default::default()
+    }
+}
+
+/// Name of the raw rule for a block that failed to parse: its `# Filter:`
+/// name, if it has one.
+fn broken_block_name(raw: &str) -> String {
+    raw.lines()
+        .next()
+        .and_then(|line| line.trim().strip_prefix('#')?.trim().strip_prefix("Filter:"))
+        .map(|name| name.trim().trim_end_matches("[DISABLED]").trim())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("(parse error)")
+        .to_string()
+}
+
+/// Comments between rules (section dividers and the like) become a raw
+/// pseudo-rule so they keep their place in the script.
+fn flush_comment_rule(rules: &mut Vec<SieveRule>, comments: &mut Vec<String>) {
+    if comments.is_empty() {
+        return;
+    }
+    let raw = Script {
+        commands: comments.drain(..).map(Command::Comment).collect(),
+    };
+    let text = emitter::emit(&raw, &EmitOptions::default());
+    let name = text.lines().next().unwrap_or_default().trim_start_matches('#').trim();
+    rules.push(SieveRule {
+        name: name.to_string(),
+        raw_block: Some(text),
+        ..Default::default()
+    });
+}
+
+/// A run of `set` commands becomes a raw "Variables" pseudo-rule, keeping
+/// its place ahead of the rules that read the variables.
+fn flush_set_rule(rules: &mut Vec<SieveRule>, sets: &mut Vec<Command>) {
+    if sets.is_empty() {
+        return;
+    }
+    let raw = Script {
+        commands: std::mem::take(sets),
+    };
+    rules.push(SieveRule {
+        name: "Variables".to_string(),
+        raw_block: Some(emitter::emit(&raw, &EmitOptions::default())),
         ..Default::default()
+    });
+}
+
+/// Whether every action is one the model can edit; others (`set` inside a
+/// block, extensions we don't know, tags the model has no field for) would
+/// be lost.
+fn all_actions_known(actions: &[ActionCommand]) -> bool {
+    actions.iter().all(|a| match ActionType::from_sieve(&a.name) {
+        Some(ActionType::Vacation) => only_tags(a, &[":days", ":subject", ":addresses"]),
+        Some(ActionType::Notify) => only_tags(a, &[":message"]),
+        // The model holds one name and one value: no `:index`, `:last`,
+        // comparator or match type, nor a list of values to delete
+        Some(ActionType::Addheader) => only_tags(a, &[]),
+        Some(ActionType::Deleteheader) => {
+            only_tags(a, &[])
+                && !a
+                    .arguments
+                    .iter()
+                    .any(|arg| matches!(arg, Argument::StringList(items) if items.len() > 1))
+        }
+        Some(_) => true,
+        None => false,
+    })
+}
+
+/// Whether `cmd` carries no tags besides `known`.
+fn only_tags(cmd: &ActionCommand, known: &[&str]) -> bool {
+    cmd.arguments
+        .iter()
+        .all(|a| !matches!(a, Argument::Tag(t) if !known.contains(&t.as_str())))
+}
+
+/// Start of the note lines that hold a rule's disabled conditions.
+///
+/// A disabled condition is left out of the rule's test and kept, written
+/// as the test it would be, among the notes under the `# Filter:` line:
+///
+/// ```text
+/// # Filter: Spam
+/// # Disabled condition 2: header :contains "Subject" "[SPAM]"
+/// if allof (header :is "X-Spam" "yes") {
+/// ```
+///
+/// The number is its place among the rule's conditions, so it comes back
+/// where it was. While some are disabled the rest stay an `allof`/`anyof`
+/// group even when only one is left, which keeps the rule's logic; with
+/// all of them disabled the group holds just `false` and matches nothing.
+/// A rule needs a name for the notes and is written as `(unnamed)` without
+/// one.
+const DISABLED_CONDITION: &str = "Disabled condition ";
+
+/// Whether `cond` can be switched off. One whose test spans lines can't
+/// sit in a comment, so it stays on.
+pub fn can_disable(cond: &Condition) -> bool {
+    !emitter::test_to_string(&condition_to_test_expr(cond)).contains('\n')
+}
+
+/// Whether `cond` is written as a note rather than in the test.
+fn stored_as_note(cond: &Condition) -> bool {
+    !cond.enabled && can_disable(cond)
+}
+
+/// The notes of a rule apart from its disabled conditions, which come with
+/// their place among the conditions.
+fn split_disabled_conditions(comment: Option<&str>) -> (Option<String>, Vec<(usize, Condition)>) {
+    let mut notes = Vec::new();
+    let mut disabled = Vec::new();
+    for line in comment.unwrap_or_default().lines() {
+        match parse_disabled_condition(line) {
+            Some(entry) => disabled.push(entry),
+            None => notes.push(line),
+        }
     }
+    let notes = (!notes.is_empty()).then(|| notes.join("\n"));
+    (notes, disabled)
+}
+
+fn parse_disabled_condition(line: &str) -> Option<(usize, Condition)> {
+    let (number, test) = line.strip_prefix(DISABLED_CONDITION)?.split_once(": ")?;
+    let position = number.parse::<usize>().ok()?.checked_sub(1)?;
+    let ast = parser::parse(&format!("if {test} {{\n}}\n")).ok()?;
+    let Some(Command::If(block)) = ast.commands.first() else {
+        return None;
+    };
+    let mut cond = single_test_to_condition(&block.condition)?;
+    cond.enabled = false;
+    Some((position, cond))
 }
 
 fn if_block_to_rule(block: &IfBlock) -> SieveRule {
-    let (logic, conditions) = extract_conditions(&block.condition);
+    // `not allof (...)` / `not anyof (...)` negates the whole group
+    let (test, negate_group) = match &block.condition {
+        TestExpr::Not(inner) if matches!(**inner, TestExpr::AllOf(_) | TestExpr::AnyOf(_)) => (&**inner, true),
+        test => (test, false),
+    };
+    let main = extract_conditions(test);
+    // Nested groups don't flatten; keep them as a tree instead
+    let condition_tree = match main {
+        Some(_) => None,
+        None => test_to_node(test),
+    };
+    let mut lossy = main.is_none() && condition_tree.is_none();
+    lossy |= !all_actions_known(&block.actions);
+    lossy |= block.alternatives.iter().any(|alt| match alt {
+        Alternative::ElsIf { actions, .. } | Alternative::Else { actions } => !all_actions_known(actions),
+    });
+    let (logic, mut conditions) = main.unwrap_or((LogicOperator::AllOf, Vec::new()));
     let actions = extract_actions(&block.actions);
 
-    if conditions.is_empty() && actions.is_empty() {
+    let mut alternatives = Vec::new();
+    let mut else_actions = None;
+    for alt in &block.alternatives {
+        match alt {
+            Alternative::ElsIf { condition, actions } => {
+                let Some((logic, conditions)) = extract_conditions(condition) else {
+                    lossy = true;
+                    continue;
+                };
+                let actions = extract_actions(actions);
+                lossy |= conditions.is_empty() && actions.is_empty();
+                alternatives.push(RuleBranch {
+                    logic,
+                    conditions,
+                    actions,
+                });
+            }
+            Alternative::Else { actions } => {
+                else_actions = Some(extract_actions(actions));
+            }
+        }
+    }
+
+    if (conditions.is_empty() && condition_tree.is_none() && actions.is_empty()) || lossy {
         // Fall back to raw block
         let raw_ast = Script {
             commands: vec![Command::If(block.clone())],
@@ -73,36 +291,84 @@ fn if_block_to_rule(block: &IfBlock) -> SieveRule {
         return SieveRule {
             name: block.name.clone().unwrap_or_default(),
             enabled: block.enabled,
-            raw_block: Some(emitter::emit(&raw_ast)),
+            commented_out: block.commented_out,
+            comment: block.comment.clone(),
+            raw_block: Some(emitter::emit(&raw_ast, &EmitOptions::default())),
             ..Default::default()
         };
     }
 
+    let (comment, disabled) = match condition_tree {
+        Some(_) => (block.comment.clone(), Vec::new()),
+        None => split_disabled_conditions(block.comment.as_deref()),
+    };
+    // A lone `false` beside disabled conditions stands for none left on, as
+    // does a lone `true` in a negated group
+    let placeholder = if negate_group { ConditionTest::True } else { ConditionTest::False };
+    let only_placeholder =
+        matches!(conditions.as_slice(), [c] if c.test_type == placeholder && !c.negate);
+    if only_placeholder && (negate_group || !disabled.is_empty()) {
+        conditions.clear();
+    }
+    for (position, cond) in disabled {
+        conditions.insert(position.min(conditions.len()), cond);
+    }
+
     SieveRule {
         name: block.name.clone().unwrap_or_default(),
         enabled: block.enabled,
+        commented_out: block.commented_out,
+        comment,
         logic,
         conditions,
+        negate_group,
+        condition_tree,
         actions,
+        alternatives,
+        else_actions,
         raw_block: None,
     }
 }
 
-fn extract_conditions(expr: &TestExpr) -> (LogicOperator, Vec<Condition>) {
+/// Flatten a test into the model's single level of conditions; `None` when
+/// some test can't be represented, so the caller keeps the rule raw.
+fn extract_conditions(expr: &TestExpr) -> Option<(LogicOperator, Vec<Condition>)> {
     match expr {
         TestExpr::AllOf(tests) => {
-            let conditions: Vec<Condition> = tests.iter().filter_map(single_test_to_condition).collect();
-            (LogicOperator::AllOf, conditions)
+            let conditions = tests.iter().map(single_test_to_condition).collect::<Option<Vec<_>>>()?;
+            Some((LogicOperator::AllOf, conditions))
         }
         TestExpr::AnyOf(tests) => {
-            let conditions: Vec<Condition> = tests.iter().filter_map(single_test_to_condition).collect();
-            (LogicOperator::AnyOf, conditions)
+            let conditions = tests.iter().map(single_test_to_condition).collect::<Option<Vec<_>>>()?;
+            Some((LogicOperator::AnyOf, conditions))
         }
-        _ => {
-            if let Some(c) = single_test_to_condition(expr) {
-                (LogicOperator::AllOf, vec![c])
-            } else {
-                (LogicOperator::AllOf, vec![])
+        TestExpr::True => Some((LogicOperator::AllOf, vec![])),
+        _ => single_test_to_condition(expr).map(|c| (LogicOperator::AllOf, vec![c])),
+    }
+}
+
+/// Map a test to a condition tree, groups included; `None` when some test
+/// can't be represented.
+fn test_to_node(expr: &TestExpr) -> Option<ConditionNode> {
+    let group = |logic, tests: &[TestExpr]| {
+        let children = tests.iter().map(test_to_node).collect::<Option<Vec<_>>>()?;
+        Some(ConditionNode::Group { logic, children })
+    };
+    match expr {
+        TestExpr::AllOf(tests) => group(LogicOperator::AllOf, tests),
+        TestExpr::AnyOf(tests) => group(LogicOperator::AnyOf, tests),
+        _ => single_test_to_condition(expr).map(ConditionNode::Leaf),
+    }
+}
+
+fn node_to_test_expr(node: &ConditionNode) -> TestExpr {
+    match node {
+        ConditionNode::Leaf(cond) => condition_to_test_expr(cond),
+        ConditionNode::Group { logic, children } => {
+            let tests = children.iter().map(node_to_test_expr).collect();
+            match logic {
+                LogicOperator::AllOf => TestExpr::AllOf(tests),
+                LogicOperator::AnyOf => TestExpr::AnyOf(tests),
             }
         }
     }
@@ -110,8 +376,12 @@ fn extract_conditions(expr: &TestExpr) -> (LogicOperator, Vec<Condition>) {
 
 fn single_test_to_condition(expr: &TestExpr) -> Option<Condition> {
     match expr {
+        // Relational matches have no place in the model yet; such rules are
+        // kept as raw blocks
         TestExpr::Header {
             match_type,
+            comparator,
+            relation: None,
             header_names,
             keys,
         } => Some(Condition {
@@ -119,11 +389,14 @@ fn single_test_to_condition(expr: &TestExpr) -> Option<Condition> {
             header_names: header_names.clone(),
             keys: keys.clone(),
             match_type: MatchType::from_sieve(match_type).unwrap_or(MatchType::Contains),
+            comparator: comparator.clone(),
             ..Default::default()
         }),
         TestExpr::Address {
             address_part,
             match_type,
+            comparator,
+            relation: None,
             header_names,
             keys,
         } => Some(Condition {
@@ -131,15 +404,15 @@ fn single_test_to_condition(expr: &TestExpr) -> Option<Condition> {
             header_names: header_names.clone(),
             keys: keys.clone(),
             match_type: MatchType::from_sieve(match_type).unwrap_or(MatchType::Contains),
-            address_part: address_part
-                .as_deref()
-                .and_then(AddressPartType::from_sieve)
-                .unwrap_or(AddressPartType::All),
+            comparator: comparator.clone(),
+            address_part: address_part_from_tag(address_part.as_deref()),
             ..Default::default()
         }),
         TestExpr::Envelope {
             address_part,
             match_type,
+            comparator,
+            relation: None,
             header_names,
             keys,
         } => Some(Condition {
@@ -147,10 +420,8 @@ fn single_test_to_condition(expr: &TestExpr) -> Option<Condition> {
             header_names: header_names.clone(),
             keys: keys.clone(),
             match_type: MatchType::from_sieve(match_type).unwrap_or(MatchType::Contains),
-            address_part: address_part
-                .as_deref()
-                .and_then(AddressPartType::from_sieve)
-                .unwrap_or(AddressPartType::All),
+            comparator: comparator.clone(),
+            address_part: address_part_from_tag(address_part.as_deref()),
             ..Default::default()
         }),
         TestExpr::Size { comparator, limit } => Some(Condition {
@@ -164,6 +435,44 @@ fn single_test_to_condition(expr: &TestExpr) -> Option<Condition> {
             header_names: header_names.clone(),
             ..Default::default()
         }),
+        TestExpr::Body {
+            match_type,
+            comparator,
+            relation: None,
+            transform,
+            content_types,
+            keys,
+        } => Some(Condition {
+            test_type: ConditionTest::Body,
+            keys: keys.clone(),
+            match_type: MatchType::from_sieve(match_type).unwrap_or(MatchType::Contains),
+            comparator: comparator.clone(),
+            transform: body_transform(transform.as_deref(), content_types)?,
+            ..Default::default()
+        }),
+        // Flags held in variables have no field in the model
+        TestExpr::Hasflag {
+            match_type,
+            comparator,
+            relation: None,
+            variables,
+            flags,
+        } if variables.is_empty() => Some(Condition {
+            test_type: ConditionTest::Hasflag,
+            header_names: Vec::new(),
+            keys: flags.clone(),
+            match_type: MatchType::from_sieve(match_type).unwrap_or(MatchType::Is),
+            comparator: comparator.clone(),
+            ..Default::default()
+        }),
+        TestExpr::True => Some(Condition {
+            test_type: ConditionTest::True,
+            ..Default::default()
+        }),
+        TestExpr::False => Some(Condition {
+            test_type: ConditionTest::False,
+            ..Default::default()
+        }),
         TestExpr::Not(inner) => {
             single_test_to_condition(inner).map(|mut c| {
                 c.negate = true;
@@ -179,130 +488,477 @@ fn extract_actions(action_cmds: &[ActionCommand]) -> Vec<Action> {
         .iter()
         .filter_map(|cmd| {
             let action_type = ActionType::from_sieve(&cmd.name)?;
+            match action_type {
+                ActionType::Vacation => return Some(extract_vacation(cmd)),
+                ActionType::Notify => return Some(extract_notify(cmd)),
+                ActionType::Addheader | ActionType::Deleteheader => {
+                    return Some(extract_header_edit(action_type, cmd));
+                }
+                _ if action_type.is_flag_action() => return Some(extract_flags(action_type, cmd)),
+                _ => {}
+            }
             let argument = if action_type.takes_argument() {
-                cmd.arguments.first().map(|a| match a {
-                    Argument::QuotedString(s) => s.clone(),
-                    Argument::Number(n) => n.clone(),
-                    Argument::Tag(t) => t.clone(),
-                    Argument::StringList(items) => items.join(", "),
-                }).unwrap_or_default()
+                cmd.arguments
+                    .iter()
+                    .find(|a| !matches!(a, Argument::Tag(_)))
+                    .map(argument_to_string)
+                    .unwrap_or_default()
             } else {
                 String::new()
             };
+            let has_tag = |tag: &str| {
+                cmd.arguments
+                    .iter()
+                    .any(|a| matches!(a, Argument::Tag(t) if t == tag))
+            };
             Some(Action {
                 action_type,
                 argument,
+                copy: has_tag(":copy"),
+                create: has_tag(":create"),
+                ..Default::default()
             })
         })
         .collect()
 }
 
+fn argument_to_string(arg: &Argument) -> String {
+    match arg {
+        Argument::QuotedString(s) => s.clone(),
+        Argument::Number(n) => n.clone(),
+        Argument::Tag(t) => t.clone(),
+        Argument::StringList(items) => items.join(", "),
+        Argument::MultiLine(s) => s.clone(),
+    }
+}
+
+/// Read `setflag [<variablename>] <list-of-flags>` and its `addflag` and
+/// `removeflag` siblings (RFC 5232). A flag string may hold several
+/// space-separated flags, so `"\\Seen \\Flagged"` and `["\\Seen", "\\Flagged"]`
+/// read the same.
+fn extract_flags(action_type: ActionType, cmd: &ActionCommand) -> Action {
+    let positional: Vec<&Argument> = cmd
+        .arguments
+        .iter()
+        .filter(|a| !matches!(a, Argument::Tag(_)))
+        .collect();
+    let (flag_variable, flag_list) = match positional.as_slice() {
+        [variable, list, ..] => (Some(argument_to_string(variable)), Some(*list)),
+        [list] => (None, Some(*list)),
+        [] => (None, None),
+    };
+    let flags = match flag_list {
+        Some(Argument::StringList(items)) => items
+            .iter()
+            .flat_map(|item| item.split_whitespace())
+            .map(str::to_string)
+            .collect(),
+        Some(other) => argument_to_string(other)
+            .split_whitespace()
+            .map(str::to_string)
+            .collect(),
+        None => Vec::new(),
+    };
+    Action {
+        action_type,
+        flags,
+        flag_variable,
+        ..Default::default()
+    }
+}
+
+/// Read `vacation [:days N] [:subject "..."] [:addresses [...]] <reason>`.
+///
+/// A rule whose vacation has other tags (`:from`, `:mime`, ...) stays raw;
+/// they are still skipped here, with their value if they take one.
+fn extract_vacation(cmd: &ActionCommand) -> Action {
+    let mut action = Action {
+        action_type: ActionType::Vacation,
+        ..Default::default()
+    };
+    let mut args = cmd.arguments.iter();
+    while let Some(arg) = args.next() {
+        match arg {
+            Argument::Tag(tag) if tag == ":mime" => {}
+            Argument::Tag(tag) => match (tag.as_str(), args.next()) {
+                (":days", Some(Argument::Number(n))) => action.days = n.parse().ok(),
+                (":subject", Some(value)) => action.subject = Some(argument_to_string(value)),
+                (":addresses", Some(Argument::StringList(items))) => action.addresses = items.clone(),
+                (":addresses", Some(value)) => action.addresses = vec![argument_to_string(value)],
+                _ => {}
+            },
+            other => action.argument = argument_to_string(other),
+        }
+    }
+    action
+}
+
+/// Read `notify [:message "..."] <method>` (RFC 5435). A rule whose notify
+/// also has `:from`, `:importance` or `:options` stays raw.
+fn extract_notify(cmd: &ActionCommand) -> Action {
+    let mut action = Action {
+        action_type: ActionType::Notify,
+        ..Default::default()
+    };
+    let mut args = cmd.arguments.iter();
+    while let Some(arg) = args.next() {
+        match arg {
+            // Every notify tag takes a value
+            Argument::Tag(tag) => {
+                if let (":message", Some(value)) = (tag.as_str(), args.next()) {
+                    action.message = Some(argument_to_string(value));
+                }
+            }
+            other => action.argument = argument_to_string(other),
+        }
+    }
+    action
+}
+
+/// Read `addheader [:last] <name> <value>` or
+/// `deleteheader [:index N] [:last] [:comparator ..] [MATCH] <name> [<values>]`
+/// (RFC 5293) into the field name and the value.
+fn extract_header_edit(action_type: ActionType, cmd: &ActionCommand) -> Action {
+    let mut positional = Vec::new();
+    let mut args = cmd.arguments.iter();
+    while let Some(arg) = args.next() {
+        match arg {
+            Argument::Tag(tag) => {
+                if matches!(tag.as_str(), ":index" | ":comparator") {
+                    args.next();
+                }
+            }
+            other => positional.push(argument_to_string(other)),
+        }
+    }
+    let mut positional = positional.into_iter();
+    Action {
+        action_type,
+        argument: positional.next().unwrap_or_default(),
+        value: positional.next().unwrap_or_default(),
+        ..Default::default()
+    }
+}
+
+/// `text` reformatted the way generated scripts are written. Everything
+/// the parser keeps survives, comments included; an error leaves nothing to
+/// format.
+pub fn format_text(text: &str, options: &EmitOptions) -> Result<String, String> {
+    parser::parse(text).map(|ast| emitter::emit(&ast, options))
+}
+
+/// One rule as a script of its own, requires included, for sharing.
+pub fn rule_to_text(rule: &SieveRule, options: &EmitOptions) -> String {
+    let script = SieveScript {
+        rules: vec![rule.clone()],
+        ..Default::default()
+    };
+    script_to_text(&script, options)
+}
+
+/// The first `if` block of `text` as a rule, e.g. a filter pasted from
+/// elsewhere.
+pub fn rule_from_text(text: &str) -> Result<SieveRule, String> {
+    let ast = parser::parse(text)?;
+    ast.commands
+        .iter()
+        .find_map(|cmd| match cmd {
+            Command::If(block) => Some(if_block_to_rule(block)),
+            _ => None,
+        })
+        .ok_or_else(|| "no rule (if block) found".to_string())
+}
+
 /// Convert a SieveScript model back to SIEVE script text.
-pub fn script_to_text(script: &SieveScript) -> String {
-    let ast = script_to_ast(script);
-    emitter::emit(&ast)
+pub fn script_to_text(script: &SieveScript, options: &EmitOptions) -> String {
+    let ast = script_to_ast(script, options.keep_require_order);
+    emitter::emit(&ast, options)
 }
 
-fn script_to_ast(script: &SieveScript) -> Script {
-    let mut commands = Vec::new();
+/// Canonical form of an extension name, which SIEVE compares
+/// case-insensitively.
+fn extension_name(ext: &str) -> String {
+    ext.to_ascii_lowercase()
+}
 
-    // Compute requires from rules
-    let requires = collect_requires(&script.rules);
-    if !requires.is_empty() {
-        commands.push(Command::Require(requires));
+/// The script's declared extensions followed by the inferred ones, each
+/// once. Sorted unless `keep_order`, in which case the declared ones stay
+/// in the order the script first listed them and new ones follow.
+fn merge_requires(declared: &[String], inferred: Vec<String>, keep_order: bool) -> Vec<String> {
+    let mut requires: Vec<String> = Vec::new();
+    for ext in declared.iter().map(|e| extension_name(e)).chain(inferred) {
+        if !requires.contains(&ext) {
+            requires.push(ext);
+        }
     }
+    if !keep_order {
+        requires.sort();
+    }
+    requires
+}
+
+fn script_to_ast(script: &SieveScript, keep_require_order: bool) -> Script {
+    let mut commands: Vec<Command> = script.preamble.iter().cloned().map(Command::Comment).collect();
 
     for rule in &script.rules {
         if let Some(raw) = &rule.raw_block {
-            // Try to re-parse raw blocks
+            // Try to re-parse raw blocks, keeping everything they hold: a
+            // block edited by hand may now be several rules
             if let Ok(parsed) = parser::parse(raw) {
-                for cmd in parsed.commands {
-                    if matches!(cmd, Command::If(_)) {
-                        commands.push(cmd);
-                        break;
-                    }
-                }
+                commands.extend(parsed.commands);
             } else {
                 commands.push(Command::Raw(raw.clone()));
             }
             continue;
         }
 
-        let condition = build_test_expr(rule);
-        let actions = build_action_commands(rule);
+        let mut notes: Vec<String> = rule
+            .comment
+            .iter()
+            .filter(|c| !c.trim().is_empty())
+            .cloned()
+            .collect();
+        let condition = match &rule.condition_tree {
+            Some(tree) if rule.negate_group => TestExpr::Not(Box::new(node_to_test_expr(tree))),
+            Some(tree) => node_to_test_expr(tree),
+            None => {
+                let mut active = Vec::new();
+                for (i, cond) in rule.conditions.iter().enumerate() {
+                    if stored_as_note(cond) {
+                        let test = emitter::test_to_string(&condition_to_test_expr(cond));
+                        notes.push(format!("{DISABLED_CONDITION}{}: {test}", i + 1));
+                    } else {
+                        active.push(cond.clone());
+                    }
+                }
+                if rule.negate_group {
+                    negated_group(rule.logic, &active)
+                } else if active.len() < rule.conditions.len() {
+                    group_test_expr(rule.logic, &active)
+                } else {
+                    build_test_expr(rule.logic, &active)
+                }
+            }
+        };
+        let disables_conditions =
+            rule.condition_tree.is_none() && rule.conditions.iter().any(stored_as_note);
+        let actions = build_action_commands(&rule.actions);
+
+        let mut alternatives: Vec<Alternative> = rule
+            .alternatives
+            .iter()
+            .map(|branch| Alternative::ElsIf {
+                condition: build_test_expr(branch.logic, &branch.conditions),
+                actions: build_action_commands(&branch.actions),
+            })
+            .collect();
+        if let Some(else_actions) = &rule.else_actions {
+            alternatives.push(Alternative::Else {
+                actions: build_action_commands(else_actions),
+            });
+        }
 
         commands.push(Command::If(IfBlock {
-            name: if rule.name.is_empty() {
-                None
-            } else {
-                Some(rule.name.clone())
+            name: match rule.name.as_str() {
+                "" if disables_conditions => Some("(unnamed)".to_string()),
+                "" => None,
+                name => Some(name.to_string()),
             },
             enabled: rule.enabled,
+            commented_out: rule.commented_out,
+            comment: (!notes.is_empty()).then(|| notes.join("\n")),
             condition,
             actions,
-            alternatives: Vec::new(),
+            alternatives,
         }));
     }
 
-    Script { commands }
+    // Requires inferred from the generated commands (raw blocks included),
+    // plus whatever the loaded script declared (extensions the model can't
+    // infer, e.g. "variables")
+    let mut script_ast = Script { commands };
+    let requires = merge_requires(
+        &script.requires,
+        emitter::compute_requires(&script_ast),
+        keep_require_order,
+    );
+    // The script's own statements while they declare the same set, so an
+    // unchanged script isn't rewritten
+    let declared: BTreeSet<String> = script
+        .require_statements
+        .iter()
+        .flat_map(|s| &s.extensions)
+        .map(|e| extension_name(e))
+        .collect();
+    let statements: Vec<Command> = if declared == requires.iter().cloned().collect() {
+        script
+            .require_statements
+            .iter()
+            .map(|s| Command::Require {
+                extensions: s.extensions.clone(),
+                list: s.list,
+            })
+            .collect()
+    } else {
+        vec![Command::Require {
+            extensions: requires,
+            list: false,
+        }]
+    };
+    // Below the preamble, which heads the file
+    let at = script.preamble.len();
+    script_ast.commands.splice(at..at, statements);
+
+    script_ast
 }
 
-fn build_test_expr(rule: &SieveRule) -> TestExpr {
-    if rule.conditions.is_empty() {
+fn build_test_expr(logic: LogicOperator, conditions: &[Condition]) -> TestExpr {
+    if conditions.is_empty() {
         return TestExpr::True;
     }
 
-    let tests: Vec<TestExpr> = rule.conditions.iter().map(condition_to_test_expr).collect();
+    let tests: Vec<TestExpr> = conditions.iter().map(condition_to_test_expr).collect();
 
     if tests.len() == 1 {
         tests.into_iter().next().unwrap()
     } else {
-        match rule.logic {
+        match logic {
             LogicOperator::AllOf => TestExpr::AllOf(tests),
             LogicOperator::AnyOf => TestExpr::AnyOf(tests),
         }
     }
 }
 
+/// `allof (...)` / `anyof (...)` even for a single condition. Without any
+/// the group holds `false`: `true` would run the actions on every message.
+fn group_test_expr(logic: LogicOperator, conditions: &[Condition]) -> TestExpr {
+    let tests = if conditions.is_empty() {
+        vec![TestExpr::False]
+    } else {
+        conditions.iter().map(condition_to_test_expr).collect()
+    };
+    match logic {
+        LogicOperator::AllOf => TestExpr::AllOf(tests),
+        LogicOperator::AnyOf => TestExpr::AnyOf(tests),
+    }
+}
+
+/// `not allof (...)` / `not anyof (...)`, kept a group even for a single
+/// condition so it reads back as a negated group. Without conditions the
+/// group holds `true`, so like an empty plain group it matches nothing.
+fn negated_group(logic: LogicOperator, conditions: &[Condition]) -> TestExpr {
+    let group = if conditions.is_empty() {
+        match logic {
+            LogicOperator::AllOf => TestExpr::AllOf(vec![TestExpr::True]),
+            LogicOperator::AnyOf => TestExpr::AnyOf(vec![TestExpr::True]),
+        }
+    } else {
+        group_test_expr(logic, conditions)
+    };
+    TestExpr::Not(Box::new(group))
+}
+
+/// The transform of a `body` test; `None` for `:content` with other than
+/// one media type, which the model can't hold.
+fn body_transform(tag: Option<&str>, content_types: &[String]) -> Option<BodyTransform> {
+    match tag {
+        None | Some(":text") => Some(BodyTransform::Text),
+        Some(":raw") => Some(BodyTransform::Raw),
+        Some(":content") => match content_types {
+            [media] => Some(BodyTransform::Content(media.clone())),
+            _ => None,
+        },
+        Some(_) => None,
+    }
+}
+
+/// The address-part tag to write: none for `:all`, the default, which the
+/// parser reads back the same way.
+fn address_part_tag(part: AddressPartType) -> Option<String> {
+    (part != AddressPartType::All).then(|| part.as_sieve().to_string())
+}
+
+fn address_part_from_tag(tag: Option<&str>) -> AddressPartType {
+    tag.and_then(AddressPartType::from_sieve)
+        .unwrap_or(AddressPartType::All)
+}
+
+/// The keys to write for `cond`. Blank entries, left by an added value
+/// never filled in, are dropped unless there is nothing else: a lone `""`
+/// is a test of its own.
+fn condition_keys(cond: &Condition) -> Vec<String> {
+    let keys: Vec<String> = cond.keys.iter().filter(|k| !k.is_empty()).cloned().collect();
+    if keys.is_empty() {
+        vec![String::new()]
+    } else {
+        keys
+    }
+}
+
 fn condition_to_test_expr(cond: &Condition) -> TestExpr {
     let expr = match cond.test_type {
         ConditionTest::Header => TestExpr::Header {
             match_type: cond.match_type.as_sieve().to_string(),
+            comparator: cond.comparator.clone(),
+            relation: None,
             header_names: cond.header_names.clone(),
-            keys: cond.keys.clone(),
+            keys: condition_keys(cond),
         },
         ConditionTest::Address => TestExpr::Address {
-            address_part: if cond.address_part == AddressPartType::All {
-                None
-            } else {
-                Some(cond.address_part.as_sieve().to_string())
-            },
+            address_part: address_part_tag(cond.address_part),
             match_type: cond.match_type.as_sieve().to_string(),
+            comparator: cond.comparator.clone(),
+            relation: None,
             header_names: cond.header_names.clone(),
-            keys: cond.keys.clone(),
+            keys: condition_keys(cond),
         },
         ConditionTest::Envelope => TestExpr::Envelope {
-            address_part: if cond.address_part == AddressPartType::All {
-                None
-            } else {
-                Some(cond.address_part.as_sieve().to_string())
-            },
+            address_part: address_part_tag(cond.address_part),
             match_type: cond.match_type.as_sieve().to_string(),
+            comparator: cond.comparator.clone(),
+            relation: None,
             header_names: cond.header_names.clone(),
-            keys: cond.keys.clone(),
+            keys: condition_keys(cond),
         },
         ConditionTest::Size => TestExpr::Size {
             comparator: cond.size_comparator.as_sieve().to_string(),
             limit: cond.size_value.clone(),
         },
-        ConditionTest::Exists => TestExpr::Exists {
-            header_names: cond.header_names.clone(),
+        // Blank entries are what typing "X-A, " leaves; with none left
+        // `exists ""` stays valid SIEVE and the lint points it out
+        ConditionTest::Exists => {
+            let mut header_names: Vec<String> = cond
+                .header_names
+                .iter()
+                .filter(|h| !h.trim().is_empty())
+                .cloned()
+                .collect();
+            if header_names.is_empty() {
+                header_names.push(String::new());
+            }
+            TestExpr::Exists { header_names }
+        }
+        ConditionTest::Hasflag => TestExpr::Hasflag {
+            match_type: cond.match_type.as_sieve().to_string(),
+            comparator: cond.comparator.clone(),
+            relation: None,
+            variables: Vec::new(),
+            flags: condition_keys(cond),
         },
         ConditionTest::True => TestExpr::True,
         ConditionTest::False => TestExpr::False,
         ConditionTest::Body => TestExpr::Body {
             match_type: cond.match_type.as_sieve().to_string(),
-            keys: cond.keys.clone(),
+            comparator: cond.comparator.clone(),
+            relation: None,
+            // `:text` is the default and left out
+            transform: (cond.transform != BodyTransform::Text)
+                .then(|| cond.transform.as_sieve().to_string()),
+            content_types: match &cond.transform {
+                BodyTransform::Content(media) => vec![media.clone()],
+                _ => Vec::new(),
+            },
+            keys: condition_keys(cond),
         },
         ConditionTest::Not => TestExpr::True, // fallback
     };
@@ -314,15 +970,72 @@ fn condition_to_test_expr(cond: &Condition) -> TestExpr {
     }
 }
 
-fn build_action_commands(rule: &SieveRule) -> Vec<ActionCommand> {
-    rule.actions
+fn build_action_commands(actions: &[Action]) -> Vec<ActionCommand> {
+    actions
         .iter()
         .map(|action| {
-            let arguments = if action.action_type.takes_argument() && !action.argument.is_empty() {
-                vec![Argument::QuotedString(action.argument.clone())]
+            let mut arguments = Vec::new();
+            if action.action_type == ActionType::Notify {
+                if let Some(message) = &action.message {
+                    arguments.push(Argument::Tag(":message".to_string()));
+                    arguments.push(Argument::QuotedString(message.clone()));
+                }
+                // The method URI is mandatory
+                arguments.push(Argument::QuotedString(action.argument.clone()));
+            } else if action.action_type == ActionType::Addheader {
+                arguments.push(Argument::QuotedString(action.argument.clone()));
+                arguments.push(text_argument(&action.value));
+            } else if action.action_type == ActionType::Deleteheader {
+                arguments.push(Argument::QuotedString(action.argument.clone()));
+                if !action.value.is_empty() {
+                    arguments.push(Argument::QuotedString(action.value.clone()));
+                }
+            } else if action.action_type.is_flag_action() {
+                if let Some(variable) = &action.flag_variable {
+                    arguments.push(Argument::QuotedString(variable.clone()));
+                }
+                // The flag list is mandatory; blank entries are left over
+                // from editing
+                let flags: Vec<&str> = action
+                    .flags
+                    .iter()
+                    .map(|f| f.trim())
+                    .filter(|f| !f.is_empty())
+                    .collect();
+                arguments.push(Argument::QuotedString(flags.join(" ")));
+            } else if action.action_type == ActionType::Vacation {
+                if let Some(days) = action.days {
+                    arguments.push(Argument::Tag(":days".to_string()));
+                    arguments.push(Argument::Number(days.to_string()));
+                }
+                if let Some(subject) = &action.subject {
+                    arguments.push(Argument::Tag(":subject".to_string()));
+                    arguments.push(Argument::QuotedString(subject.clone()));
+                }
+                let addresses: Vec<String> = action
+                    .addresses
+                    .iter()
+                    .map(|a| a.trim())
+                    .filter(|a| !a.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                if !addresses.is_empty() {
+                    arguments.push(Argument::Tag(":addresses".to_string()));
+                    arguments.push(Argument::StringList(addresses));
+                }
+                // The reason is mandatory, even if empty
+                arguments.push(text_argument(&action.argument));
             } else {
-                vec![]
-            };
+                if action.copy && action.action_type.supports_copy() {
+                    arguments.push(Argument::Tag(":copy".to_string()));
+                }
+                if action.create && action.action_type == ActionType::Fileinto {
+                    arguments.push(Argument::Tag(":create".to_string()));
+                }
+                if action.action_type.takes_argument() && !action.argument.is_empty() {
+                    arguments.push(text_argument(&action.argument));
+                }
+            }
             ActionCommand {
                 name: action.action_type.as_sieve().to_string(),
                 arguments,
@@ -331,33 +1044,94 @@ fn build_action_commands(rule: &SieveRule) -> Vec<ActionCommand> {
         .collect()
 }
 
+/// Multi-line values read back best as `text:` blocks.
+fn text_argument(value: &str) -> Argument {
+    if value.contains('\n') {
+        Argument::MultiLine(value.to_string())
+    } else {
+        Argument::QuotedString(value.to_string())
+    }
+}
+
+/// Extensions the rules need that are not in `supported` (case-insensitive).
+pub fn missing_extensions(rules: &[SieveRule], supported: &[String]) -> Vec<String> {
+    collect_requires(rules)
+        .into_iter()
+        .filter(|ext| !supported.iter().any(|s| s.eq_ignore_ascii_case(ext)))
+        .collect()
+}
+
+/// Redirect actions across all branches of the enabled rules, for the
+/// server's `MAXREDIRECTS` limit. Disabled rules never run, and raw blocks
+/// aren't looked into.
+pub fn count_redirects(rules: &[SieveRule]) -> usize {
+    rules
+        .iter()
+        .filter(|rule| rule.enabled)
+        .flat_map(|rule| {
+            let branch_actions = rule.alternatives.iter().flat_map(|b| &b.actions);
+            let else_actions = rule.else_actions.iter().flatten();
+            rule.actions.iter().chain(branch_actions).chain(else_actions)
+        })
+        .filter(|action| action.action_type == ActionType::Redirect)
+        .count()
+}
+
 fn collect_requires(rules: &[SieveRule]) -> Vec<String> {
-    let mut requires = std::collections::BTreeSet::new();
-
-    for rule in rules {
-        for action in &rule.actions {
-            match action.action_type {
-                ActionType::Fileinto => { requires.insert("fileinto".to_string()); }
-                ActionType::Reject => { requires.insert("reject".to_string()); }
-                ActionType::Setflag | ActionType::Addflag | ActionType::Removeflag => {
-                    requires.insert("imap4flags".to_string());
-                }
-                _ => {}
-            }
+    let requires: BTreeSet<String> = rules.iter().flat_map(rule_requires).collect();
+    requires.into_iter().collect()
+}
+
+/// Extensions a single rule needs, from its actions and tests.
+pub fn rule_requires(rule: &SieveRule) -> BTreeSet<String> {
+    let mut requires = BTreeSet::new();
+
+    let branch_actions = rule.alternatives.iter().flat_map(|b| &b.actions);
+    let else_actions = rule.else_actions.iter().flatten();
+    for action in rule.actions.iter().chain(branch_actions).chain(else_actions) {
+        if action.copy && action.action_type.supports_copy() {
+            requires.insert("copy".to_string());
         }
-        for cond in &rule.conditions {
-            match cond.test_type {
-                ConditionTest::Body => { requires.insert("body".to_string()); }
-                ConditionTest::Envelope => { requires.insert("envelope".to_string()); }
-                _ => {}
+        if action.create && action.action_type == ActionType::Fileinto {
+            requires.insert("mailbox".to_string());
+        }
+        match action.action_type {
+            ActionType::Fileinto => { requires.insert("fileinto".to_string()); }
+            ActionType::Reject => { requires.insert("reject".to_string()); }
+            ActionType::Setflag | ActionType::Addflag | ActionType::Removeflag => {
+                requires.insert("imap4flags".to_string());
+                if action.flag_variable.is_some() {
+                    requires.insert("variables".to_string());
+                }
             }
-            if cond.match_type == MatchType::Regex {
-                requires.insert("regex".to_string());
+            ActionType::Vacation => { requires.insert("vacation".to_string()); }
+            ActionType::Notify => { requires.insert("enotify".to_string()); }
+            ActionType::Addheader | ActionType::Deleteheader => {
+                requires.insert("editheader".to_string());
             }
+            _ => {}
+        }
+    }
+    let branch_conditions = rule.alternatives.iter().flat_map(|b| &b.conditions);
+    let tree_conditions = rule.condition_tree.iter().flat_map(ConditionNode::leaves);
+    // Disabled conditions sit in comments and need nothing
+    let conditions = rule.conditions.iter().filter(|c| !stored_as_note(c));
+    for cond in conditions.chain(branch_conditions).chain(tree_conditions) {
+        match cond.test_type {
+            ConditionTest::Body => { requires.insert("body".to_string()); }
+            ConditionTest::Hasflag => { requires.insert("imap4flags".to_string()); }
+            ConditionTest::Envelope => { requires.insert("envelope".to_string()); }
+            _ => {}
+        }
+        if cond.match_type == MatchType::Regex {
+            requires.insert("regex".to_string());
+        }
+        if let Some(ext) = cond.comparator.as_deref().and_then(emitter::comparator_require) {
+            requires.insert(ext);
         }
     }
 
-    requires.into_iter().collect()
+    requires
 }
 
 #[cfg(test)]
@@ -409,7 +1183,7 @@ if address :is :domain "From" "hapimag.com" {
         assert_eq!(rule.name, "Move spam");
         assert!(rule.enabled);
         assert_eq!(rule.conditions.len(), 1);
-        assert!(rule.actions.len() >= 1);
+        assert!(!rule.actions.is_empty());
 
         let cond = &rule.conditions[0];
         assert_eq!(cond.test_type, ConditionTest::Header);
@@ -450,7 +1224,7 @@ if address :is :domain "From" "hapimag.com" {
     #[test]
     fn test_roundtrip_simple() {
         let script1 = text_to_script(SIMPLE_FILEINTO, "test");
-        let text = script_to_text(&script1);
+        let text = script_to_text(&script1, &EmitOptions::default());
         let script2 = text_to_script(&text, "test");
 
         assert_eq!(script2.rules.len(), script1.rules.len());
@@ -469,10 +1243,33 @@ if address :is :domain "From" "hapimag.com" {
         assert!(script.rules[0].raw_block.is_some());
     }
 
+    #[test]
+    fn test_broken_block_keeps_the_others() {
+        let input = "require \"fileinto\";\n\n\
+                     # Filter: Lists\nif header :contains \"List-Id\" \"dev\" { fileinto \"Dev\"; }\n\n\
+                     # Filter: Typo\nif hedaer :contains \"Subject\" \"x\" { discard; }\n\n\
+                     # Filter: Spam\nif header :is \"X-Spam\" \"yes\" { discard; }\n";
+        let script = text_to_script(input, "");
+        assert_eq!(script.requires, vec!["fileinto"]);
+        let names: Vec<_> = script.rules.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["Lists", "Typo", "Spam"]);
+        assert!(script.rules[0].raw_block.is_none());
+        assert_eq!(
+            script.rules[1].raw_block.as_deref(),
+            Some("# Filter: Typo\nif hedaer :contains \"Subject\" \"x\" { discard; }")
+        );
+        assert!(script.rules[2].raw_block.is_none());
+
+        // The broken block is written back as it was
+        let text = script_to_text(&script, &EmitOptions::default());
+        assert!(text.contains("# Filter: Typo\nif hedaer :contains \"Subject\" \"x\" { discard; }\n"));
+        assert_eq!(text_to_script(&text, "").rules, script.rules);
+    }
+
     #[test]
     fn test_generate_requires() {
         let script = text_to_script(SIMPLE_FILEINTO, "");
-        let text = script_to_text(&script);
+        let text = script_to_text(&script, &EmitOptions::default());
         let first_line = text.lines().next().unwrap_or("");
         assert!(first_line.contains("fileinto"));
     }
@@ -507,7 +1304,7 @@ if address :is :domain "From" "hapimag.com" {
     #[test]
     fn test_roundtrip_address_domain() {
         let script1 = text_to_script(ADDRESS_DOMAIN_SCRIPT, "");
-        let text = script_to_text(&script1);
+        let text = script_to_text(&script1, &EmitOptions::default());
         let script2 = text_to_script(&text, "");
 
         assert_eq!(script2.rules.len(), 1);
@@ -518,4 +1315,1008 @@ if address :is :domain "From" "hapimag.com" {
         assert_eq!(r.conditions[0].header_names, vec!["From"]);
         assert_eq!(r.conditions[0].keys, vec!["hapimag.com"]);
     }
+
+    #[test]
+    fn test_roundtrip_reject_multiline() {
+        let input = "require \"reject\";\n\n# Filter: Bounce\nif true {\n    reject text:\nNo longer here.\n..Really.\n.\n;\n}\n";
+        let script1 = text_to_script(input, "");
+        assert_eq!(script1.rules[0].actions[0].argument, "No longer here.\n.Really.\n");
+
+        let text = script_to_text(&script1, &EmitOptions::default());
+        assert!(text.contains("reject text:\nNo longer here.\n..Really.\n.\n;"));
+        let script2 = text_to_script(&text, "");
+        assert_eq!(script2.rules[0].actions[0].argument, script1.rules[0].actions[0].argument);
+    }
+
+    #[test]
+    fn test_roundtrip_vacation() {
+        let input = r#"require "vacation";
+
+# Filter: Away
+if header :contains "To" "me@example.com" {
+    vacation :days 7 :subject "Out of office" "Back on Monday.";
+}
+"#;
+        let script1 = text_to_script(input, "");
+        let action = &script1.rules[0].actions[0];
+        assert_eq!(action.action_type, ActionType::Vacation);
+        assert_eq!(action.days, Some(7));
+        assert_eq!(action.subject.as_deref(), Some("Out of office"));
+        assert_eq!(action.argument, "Back on Monday.");
+
+        let text = script_to_text(&script1, &EmitOptions::default());
+        assert!(text.starts_with("require \"vacation\";"));
+        assert!(text.contains(r#"vacation :days 7 :subject "Out of office" "Back on Monday.";"#));
+        let script2 = text_to_script(&text, "");
+        assert_eq!(&script2.rules[0].actions[0], action);
+    }
+
+    #[test]
+    fn test_roundtrip_vacation_tags() {
+        let input = r#"require "vacation";
+
+# Filter: Away
+if true {
+    vacation :addresses ["me@example.com", "me@example.net"] :days 3 "Back on Monday.";
+}
+"#;
+        let script = text_to_script(input, "");
+        let action = &script.rules[0].actions[0];
+        assert!(script.rules[0].raw_block.is_none());
+        assert_eq!(action.addresses, vec!["me@example.com", "me@example.net"]);
+        assert_eq!(action.days, Some(3));
+        assert_eq!(action.argument, "Back on Monday.");
+        let text = script_to_text(&script, &EmitOptions::default());
+        assert!(
+            text.contains(r#"vacation :days 3 :addresses ["me@example.com", "me@example.net"] "Back on Monday.";"#),
+            "{text}"
+        );
+        assert_eq!(text_to_script(&text, "").rules, script.rules);
+
+        // `:mime` takes no value, so the reason isn't mistaken for one;
+        // the model has no field for it and the rule stays as written
+        let input = r#"require "vacation";
+
+# Filter: Away
+if true {
+    vacation :mime :addresses "me@example.com" "Content-Type: text/plain
+
+Back on Monday.";
+}
+"#;
+        let script = text_to_script(input, "");
+        assert!(script.rules[0].raw_block.is_some());
+        let text = script_to_text(&script, &EmitOptions::default());
+        assert!(text.contains("vacation :mime :addresses \"me@example.com\""), "{text}");
+        assert!(text.contains("Back on Monday."), "{text}");
+        let action = extract_vacation(&ActionCommand {
+            name: "vacation".to_string(),
+            arguments: vec![
+                Argument::Tag(":mime".to_string()),
+                Argument::Tag(":addresses".to_string()),
+                Argument::QuotedString("me@example.com".to_string()),
+                Argument::QuotedString("Back on Monday.".to_string()),
+            ],
+        });
+        assert_eq!(action.addresses, vec!["me@example.com"]);
+        assert_eq!(action.argument, "Back on Monday.");
+    }
+
+    #[test]
+    fn test_roundtrip_elsif_else() {
+        let input = r#"require "fileinto";
+
+# Filter: Sort
+if header :contains "Subject" "invoice" {
+    fileinto "Invoices";
+} elsif anyof (header :is "From" "a@example.com", header :is "From" "b@example.com") {
+    fileinto "Friends";
+} else {
+    keep;
+}
+"#;
+        let script1 = text_to_script(input, "");
+        let rule = &script1.rules[0];
+        assert!(rule.raw_block.is_none());
+        assert_eq!(rule.alternatives.len(), 1);
+        assert_eq!(rule.alternatives[0].logic, LogicOperator::AnyOf);
+        assert_eq!(rule.alternatives[0].conditions.len(), 2);
+        assert_eq!(rule.alternatives[0].actions[0].argument, "Friends");
+        let else_actions = rule.else_actions.as_ref().unwrap();
+        assert_eq!(else_actions[0].action_type, ActionType::Keep);
+
+        let text = script_to_text(&script1, &EmitOptions::default());
+        assert!(text.contains("} elsif anyof ("));
+        assert!(text.contains("} else {"));
+        let script2 = text_to_script(&text, "");
+        assert_eq!(script2.rules, script1.rules);
+    }
+
+    #[test]
+    fn test_roundtrip_preserves_declared_requires() {
+        let input = r#"require ["fileinto", "variables"];
+
+# Filter: Spam
+if header :contains "Subject" "SPAM" {
+    discard;
+}
+"#;
+        let script = text_to_script(input, "");
+        let text = script_to_text(&script, &EmitOptions::default());
+        assert!(text.starts_with(r#"require ["fileinto", "variables"];"#));
+
+        let script2 = text_to_script(&text, "");
+        assert_eq!(script2.requires, vec!["fileinto", "variables"]);
+    }
+
+    #[test]
+    fn test_requires_dedupe_case_insensitively() {
+        let input = r#"require ["FileInto", "Reject"];
+require "fileinto";
+
+if header :contains "Subject" "SPAM" {
+    fileinto "Junk";
+}
+"#;
+        let script = text_to_script(input, "");
+        assert_eq!(script.requires, vec!["fileinto", "reject"]);
+        let text = script_to_text(&script, &EmitOptions::default());
+        assert!(text.starts_with("require [\"fileinto\", \"reject\"];\n\n"), "{text}");
+    }
+
+    #[test]
+    fn test_requires_keep_source_order() {
+        let input = r#"require ["vacation", "fileinto"];
+
+if header :contains "Subject" "SPAM" {
+    fileinto "Junk";
+}
+"#;
+        let mut script = text_to_script(input, "");
+        // A new extension makes the requires be rewritten
+        script.rules[0].actions.push(Action {
+            action_type: ActionType::Reject,
+            argument: "No".to_string(),
+            ..Default::default()
+        });
+        let sorted = script_to_text(&script, &EmitOptions::default());
+        assert!(sorted.starts_with(r#"require ["fileinto", "reject", "vacation"];"#), "{sorted}");
+
+        let options = EmitOptions {
+            keep_require_order: true,
+            ..Default::default()
+        };
+        let kept = script_to_text(&script, &options);
+        assert!(kept.starts_with(r#"require ["vacation", "fileinto", "reject"];"#), "{kept}");
+    }
+
+    #[test]
+    fn test_roundtrip_keeps_split_requires() {
+        let input = r#"require "fileinto";
+require ["reject"];
+require ["imap4flags", "vacation"];
+
+# Filter: Spam
+if header :contains "Subject" "SPAM" {
+    fileinto "Junk";
+}
+"#;
+        let mut script = text_to_script(input, "");
+        assert_eq!(script.require_statements.len(), 3);
+        assert_eq!(script_to_text(&script, &EmitOptions::default()), input);
+
+        // A newly needed extension merges them into one statement
+        script.rules[0].actions.push(Action {
+            action_type: ActionType::Notify,
+            argument: "mailto:me@example.org".to_string(),
+            ..Default::default()
+        });
+        let text = script_to_text(&script, &EmitOptions::default());
+        assert!(text.starts_with(
+            "require [\"enotify\", \"fileinto\", \"imap4flags\", \"reject\", \"vacation\"];\n\n"
+        ));
+    }
+
+    #[test]
+    fn test_roundtrip_preserves_comments() {
+        let input = r#"# Mail rules for example.org
+# Licensed under CC0
+
+require "fileinto";
+
+# Filter: Spam
+if header :contains "Subject" "SPAM" {
+    fileinto "Junk";
+}
+
+# ---- Work ----
+# Filter: Boss
+if address :is "from" "boss@example.org" {
+    fileinto "Work";
+}
+"#;
+        let script = text_to_script(input, "");
+        assert_eq!(script.preamble, vec!["Mail rules for example.org", "Licensed under CC0"]);
+        assert_eq!(script.rules.len(), 3);
+        assert_eq!(script.rules[1].name, "---- Work ----");
+        assert_eq!(script_to_text(&script, &EmitOptions::default()), input);
+
+        // A `/* ... */` banner is kept too, as comment lines
+        let input = "/*\n * Mail rules for example.org\n * Licensed under CC0\n */\nrequire \"fileinto\";\n\nfileinto \"Inbox\";\n";
+        let script = text_to_script(input, "");
+        assert_eq!(script.preamble, vec!["Mail rules for example.org", "Licensed under CC0"]);
+        let text = script_to_text(&script, &EmitOptions::default());
+        assert!(text.starts_with(
+            "# Mail rules for example.org\n# Licensed under CC0\n\nrequire \"fileinto\";\n"
+        ));
+        assert_eq!(text_to_script(&text, "").preamble, script.preamble);
+    }
+
+    #[test]
+    fn test_roundtrip_notify() {
+        let input = r#"require "enotify";
+
+# Filter: Alert
+if header :contains "Subject" "urgent" {
+    notify :message "Urgent mail" "mailto:pager@example.org";
+}
+"#;
+        let script = text_to_script(input, "");
+        let action = &script.rules[0].actions[0];
+        assert_eq!(action.action_type, ActionType::Notify);
+        assert_eq!(action.argument, "mailto:pager@example.org");
+        assert_eq!(action.message.as_deref(), Some("Urgent mail"));
+        assert_eq!(script_to_text(&script, &EmitOptions::default()), input);
+    }
+
+    #[test]
+    fn test_roundtrip_notify_tags() {
+        // The model has no fields for these; the rule stays as written
+        let input = r#"require "enotify";
+
+# Filter: Alert
+if header :contains "Subject" "urgent" {
+    notify :from "alerts@example.org" :importance "1" :options ["x", "y"] :message "Urgent" "mailto:pager@example.org";
+}
+"#;
+        let script = text_to_script(input, "");
+        let rule = &script.rules[0];
+        assert_eq!(rule.name, "Alert");
+        assert!(rule.raw_block.is_some());
+        let text = script_to_text(&script, &EmitOptions::default());
+        assert!(
+            text.contains(r#"notify :from "alerts@example.org" :importance "1" :options ["x", "y"] :message "Urgent" "mailto:pager@example.org";"#),
+            "{text}"
+        );
+        assert_eq!(text_to_script(&text, "").rules, script.rules);
+    }
+
+    #[test]
+    fn test_roundtrip_addheader() {
+        let input = r#"require "editheader";
+
+# Filter: Tag lists
+if exists "List-Id" {
+    addheader "X-Filtered" "mailing list";
+}
+"#;
+        let script = text_to_script(input, "");
+        let action = &script.rules[0].actions[0];
+        assert_eq!(action.action_type, ActionType::Addheader);
+        assert_eq!(action.argument, "X-Filtered");
+        assert_eq!(action.value, "mailing list");
+        assert_eq!(script_to_text(&script, &EmitOptions::default()), input);
+    }
+
+    #[test]
+    fn test_roundtrip_deleteheader() {
+        let input = r#"require "editheader";
+
+# Filter: Strip
+if true {
+    deleteheader "X-Spam-Score";
+    deleteheader "X-Filtered" "mailing list";
+}
+"#;
+        let script = text_to_script(input, "");
+        let actions = &script.rules[0].actions;
+        assert_eq!(actions[0].action_type, ActionType::Deleteheader);
+        assert_eq!(actions[0].argument, "X-Spam-Score");
+        assert_eq!(actions[0].value, "");
+        assert_eq!(actions[1].value, "mailing list");
+        assert_eq!(script_to_text(&script, &EmitOptions::default()), input);
+    }
+
+    #[test]
+    fn test_roundtrip_hasflag() {
+        let input = r#"require ["fileinto", "imap4flags"];
+
+# Filter: Flagged
+if hasflag :contains "\\Flagged" {
+    fileinto "Important";
+}
+"#;
+        let script = text_to_script(input, "");
+        let cond = &script.rules[0].conditions[0];
+        assert_eq!(cond.test_type, ConditionTest::Hasflag);
+        assert_eq!(cond.match_type, MatchType::Contains);
+        assert_eq!(cond.keys, vec!["\\Flagged"]);
+        assert_eq!(script_to_text(&script, &EmitOptions::default()), input);
+
+        // Flags held in variables stay as written
+        let input = "require [\"imap4flags\", \"variables\"];\n\nif hasflag :is \"mine\" \"x\" {\n    keep;\n}\n";
+        let script = text_to_script(input, "");
+        assert!(script.rules[0].raw_block.is_some());
+        assert!(script_to_text(&script, &EmitOptions::default()).contains("hasflag :is \"mine\" \"x\""));
+    }
+
+    #[test]
+    fn test_roundtrip_header_edit_tags() {
+        // Tags and value lists the model can't hold keep the rule as written
+        for action in [
+            r#"deleteheader :index 2 "Received";"#,
+            r#"deleteheader :is "X-Filtered" "mailing list";"#,
+            r#"deleteheader "X-Tag" ["a", "b"];"#,
+            r#"addheader :last "X-Filtered" "yes";"#,
+        ] {
+            let input = format!(
+                "require \"editheader\";\n\n# Filter: Strip\nif true {{\n    {action}\n}}\n"
+            );
+            let script = text_to_script(&input, "");
+            assert!(script.rules[0].raw_block.is_some(), "{action}");
+            let text = script_to_text(&script, &EmitOptions::default());
+            assert!(text.contains(action), "{text}");
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_negated_group() {
+        let text = r#"require "fileinto";
+
+# Filter: Not from the team
+if not anyof (header :contains "From" "alice@example.com", header :contains "From" "bob@example.com") {
+    fileinto "Others";
+}
+"#;
+        let script = text_to_script(text, "");
+        let rule = &script.rules[0];
+        assert!(rule.raw_block.is_none());
+        assert!(rule.negate_group);
+        assert_eq!(rule.logic, LogicOperator::AnyOf);
+        assert_eq!(rule.conditions.len(), 2);
+        assert!(rule.conditions.iter().all(|c| !c.negate));
+        assert_eq!(script_to_text(&script, &EmitOptions::default()), text);
+
+        // A single negated condition stays a group
+        let mut single = script.clone();
+        single.rules[0].conditions.truncate(1);
+        let emitted = script_to_text(&single, &EmitOptions::default());
+        assert!(emitted.contains("if not anyof (header :contains \"From\" \"alice@example.com\")"));
+        assert!(text_to_script(&emitted, "").rules[0].negate_group);
+
+        // With every condition disabled it matches nothing and keeps the flag
+        let mut disabled = script.clone();
+        disabled.rules[0].conditions.iter_mut().for_each(|c| c.enabled = false);
+        let emitted = script_to_text(&disabled, &EmitOptions::default());
+        assert!(emitted.contains("if not anyof (true) {"), "{emitted}");
+        assert_eq!(text_to_script(&emitted, "").rules, disabled.rules);
+
+        // Likewise with none at all
+        let mut empty = script.clone();
+        empty.rules[0].conditions.clear();
+        let emitted = script_to_text(&empty, &EmitOptions::default());
+        assert!(emitted.contains("if not anyof (true) {"), "{emitted}");
+        assert_eq!(text_to_script(&emitted, "").rules, empty.rules);
+    }
+
+    #[test]
+    fn test_roundtrip_commented_out_rule() {
+        let text = r#"require "fileinto";
+
+/*
+# Filter: Old lists
+if header :contains "List-Id" "old" {
+    fileinto "Old";
+}
+*/
+
+# Filter: Spam
+if header :contains "X-Spam" "yes" {
+    discard;
+}
+"#;
+        let script = text_to_script(text, "");
+        let rule = &script.rules[0];
+        assert!(rule.raw_block.is_none());
+        assert!(!rule.enabled);
+        assert!(rule.commented_out);
+        assert_eq!(script_to_text(&script, &EmitOptions::default()), text);
+
+        // Re-enabled it is a live rule again; disabled without the comment
+        // style it gets the marker
+        let mut enabled = script.clone();
+        enabled.rules[0].enabled = true;
+        let emitted = script_to_text(&enabled, &EmitOptions::default());
+        assert!(emitted.starts_with("require \"fileinto\";\n\n# Filter: Old lists\nif"), "{emitted}");
+        let mut marked = script.clone();
+        marked.rules[0].commented_out = false;
+        let emitted = script_to_text(&marked, &EmitOptions::default());
+        assert!(emitted.contains("# Filter: Old lists [DISABLED]\nif"), "{emitted}");
+
+        // A commented-out rule needs no extension
+        let mut only = script.clone();
+        only.rules.truncate(1);
+        only.requires.clear();
+        only.require_statements.clear();
+        let emitted = script_to_text(&only, &EmitOptions::default());
+        assert!(emitted.starts_with("/*\n"), "{emitted}");
+    }
+
+    #[test]
+    fn test_address_all_is_omitted() {
+        let explicit = text_to_script("if address :is :all \"From\" \"x\" {\n    keep;\n}\n", "");
+        let implicit = text_to_script("if address :is \"From\" \"x\" {\n    keep;\n}\n", "");
+        assert_eq!(explicit.rules[0].conditions[0].address_part, AddressPartType::All);
+        assert_eq!(explicit.rules, implicit.rules);
+
+        let emitted = script_to_text(&explicit, &EmitOptions::default());
+        assert!(emitted.contains("if address :is \"From\" \"x\""), "{emitted}");
+        assert_eq!(emitted, script_to_text(&implicit, &EmitOptions::default()));
+
+        // Same for envelope
+        let envelope = text_to_script("require \"envelope\";\nif envelope :all :is \"to\" \"x\" {\n    keep;\n}\n", "");
+        let emitted = script_to_text(&envelope, &EmitOptions::default());
+        assert!(!emitted.contains(":all"), "{emitted}");
+    }
+
+    #[test]
+    fn test_roundtrip_flag_list() {
+        let text = r#"require "imap4flags";
+
+# Filter: Mark read
+if header :contains "From" "alice@example.com" {
+    addflag "\\Seen \\Flagged";
+}
+"#;
+        let script = text_to_script(text, "");
+        let action = &script.rules[0].actions[0];
+        assert_eq!(action.action_type, ActionType::Addflag);
+        assert_eq!(action.flags, vec!["\\Seen", "\\Flagged"]);
+        assert_eq!(action.flag_variable, None);
+        assert_eq!(script_to_text(&script, &EmitOptions::default()), text);
+
+        // A string list reads as the same flags
+        let listed = text_to_script(&text.replace(r#""\\Seen \\Flagged""#, r#"["\\Seen", "\\Flagged"]"#), "");
+        assert_eq!(listed.rules[0].actions[0].flags, action.flags);
+
+        // setflag on a variable
+        let variable = text_to_script(
+            "require [\"imap4flags\", \"variables\"];\nif true {\n    setflag \"tmp\" [\"$Work\", \"\\\\Answered\"];\n}\n",
+            "",
+        );
+        let action = &variable.rules[0].actions[0];
+        assert_eq!(action.flag_variable.as_deref(), Some("tmp"));
+        assert_eq!(action.flags, vec!["$Work", "\\Answered"]);
+        let emitted = script_to_text(&variable, &EmitOptions::default());
+        assert!(emitted.contains("require [\"imap4flags\", \"variables\"];"), "{emitted}");
+        assert!(emitted.contains("setflag \"tmp\" \"$Work \\\\Answered\";"), "{emitted}");
+    }
+
+    #[test]
+    fn test_rule_to_text() {
+        let script = text_to_script(ANYOF_SCRIPT, "");
+        let text = rule_to_text(&script.rules[0], &EmitOptions::default());
+        assert_eq!(text, ANYOF_SCRIPT);
+
+        // Only the given rule, with only the requires it needs
+        let script = text_to_script(ADDRESS_DOMAIN_SCRIPT, "");
+        let text = rule_to_text(&script.rules[0], &EmitOptions::default());
+        assert!(text.starts_with("require \"fileinto\";\n"));
+        assert_eq!(rule_from_text(&text), Ok(script.rules[0].clone()));
+    }
+
+    #[test]
+    fn test_rule_from_text() {
+        let rule = rule_from_text("# Filter: Spam\nif header :contains \"Subject\" \"SPAM\" { discard; }\nkeep;\n").unwrap();
+        assert_eq!(rule.name, "Spam");
+        assert!(rule.raw_block.is_none());
+        assert!(rule_from_text("keep;").is_err());
+        assert!(rule_from_text("if header {").is_err());
+    }
+
+    #[test]
+    fn test_roundtrip_variables() {
+        let input = r#"require ["fileinto", "variables"];
+
+set "folder" "Lists";
+set :lower "domain" "Example.ORG";
+
+# Filter: By list
+if string :is "${domain}" "example.org" {
+    set "folder" "Lists/Example";
+    fileinto "${folder}";
+}
+
+# Filter: Rest
+if exists "List-Id" {
+    fileinto "${folder}";
+}
+"#;
+        let script = text_to_script(input, "");
+        assert_eq!(script.rules.len(), 3);
+        assert_eq!(script.rules[0].name, "Variables");
+        assert!(script.rules[0].raw_block.is_some());
+        assert!(script.rules[1].raw_block.is_some());
+        assert!(script.rules[2].raw_block.is_none());
+        assert_eq!(script_to_text(&script, &EmitOptions::default()), input);
+    }
+
+    #[test]
+    fn test_roundtrip_relational_value() {
+        let input = r#"require ["comparator-i;ascii-numeric", "fileinto", "relational"];
+
+# Filter: Low priority
+if header :comparator "i;ascii-numeric" :value "gt" "X-Priority" "3" {
+    fileinto "Later";
+}
+"#;
+        let script = text_to_script(input, "");
+        assert!(script.rules[0].raw_block.is_some());
+        assert_eq!(script_to_text(&script, &EmitOptions::default()), input);
+
+        // The requires are derived from the test even if the script omitted them
+        let script = text_to_script(&input.replacen("\"relational\"", "\"envelope\"", 1), "");
+        assert!(script_to_text(&script, &EmitOptions::default()).contains("\"relational\""));
+    }
+
+    #[test]
+    fn test_roundtrip_comparator() {
+        let input = r#"# Filter: Exact subject
+if header :comparator "i;octet" :is "Subject" "URGENT" {
+    keep;
+}
+"#;
+        let script = text_to_script(input, "");
+        let rule = &script.rules[0];
+        assert!(rule.raw_block.is_none());
+        assert_eq!(rule.conditions[0].comparator.as_deref(), Some("i;octet"));
+
+        let text = script_to_text(&script, &EmitOptions::default());
+        assert_eq!(text, input);
+        assert_eq!(text_to_script(&text, "").rules, script.rules);
+    }
+
+    #[test]
+    fn test_roundtrip_nested_groups() {
+        let input = r#"# Filter: Team mail
+if allof (anyof (header :contains "From" "alice@example.com", header :contains "From" "bob@example.com"), not header :contains "Subject" "[spam]") {
+    keep;
+}
+"#;
+        let script = text_to_script(input, "");
+        let rule = &script.rules[0];
+        assert!(rule.raw_block.is_none());
+        match &rule.condition_tree {
+            Some(ConditionNode::Group { logic, children }) => {
+                assert_eq!(*logic, LogicOperator::AllOf);
+                assert!(matches!(
+                    &children[0],
+                    ConditionNode::Group { logic: LogicOperator::AnyOf, children } if children.len() == 2
+                ));
+                assert!(matches!(&children[1], ConditionNode::Leaf(c) if c.negate));
+            }
+            other => panic!("Expected a nested group, got {other:?}"),
+        }
+
+        let text = script_to_text(&script, &EmitOptions::default());
+        assert_eq!(text, input);
+        assert_eq!(text_to_script(&text, "").rules, script.rules);
+    }
+
+    #[test]
+    fn test_missing_extensions() {
+        let script = text_to_script(SIMPLE_FILEINTO, "");
+        assert!(missing_extensions(&script.rules, &["FileInto".to_string()]).is_empty());
+        assert_eq!(missing_extensions(&script.rules, &[]), vec!["fileinto"]);
+    }
+
+    #[test]
+    fn test_count_redirects() {
+        let redirect = || Action {
+            action_type: ActionType::Redirect,
+            argument: "a@example.org".to_string(),
+            ..Default::default()
+        };
+        let rules = vec![
+            SieveRule {
+                actions: vec![redirect(), Action::default()],
+                alternatives: vec![RuleBranch {
+                    actions: vec![redirect()],
+                    ..Default::default()
+                }],
+                else_actions: Some(vec![redirect()]),
+                ..Default::default()
+            },
+            SieveRule {
+                actions: vec![redirect()],
+                ..Default::default()
+            },
+        ];
+        assert_eq!(count_redirects(&rules), 4);
+        assert_eq!(count_redirects(&[]), 0);
+
+        // Disabled rules, commented out or not, don't count
+        let mut disabled = rules.clone();
+        disabled[0].enabled = false;
+        disabled[1].enabled = false;
+        disabled[1].commented_out = true;
+        assert_eq!(count_redirects(&disabled), 0);
+        assert_eq!(count_redirects(&text_to_script(SIMPLE_FILEINTO, "").rules), 0);
+    }
+
+    #[test]
+    fn test_rule_requires() {
+        let names = |rule: &SieveRule| rule_requires(rule).into_iter().collect::<Vec<_>>();
+
+        let script = text_to_script(SIMPLE_FILEINTO, "");
+        assert_eq!(names(&script.rules[0]), vec!["fileinto"]);
+
+        let plain = SieveRule {
+            conditions: vec![Condition::default()],
+            actions: vec![Action::default()],
+            ..Default::default()
+        };
+        assert!(rule_requires(&plain).is_empty());
+
+        let rule = SieveRule {
+            conditions: vec![
+                Condition {
+                    test_type: ConditionTest::Body,
+                    match_type: MatchType::Regex,
+                    ..Default::default()
+                },
+                Condition {
+                    comparator: Some("i;ascii-numeric".to_string()),
+                    ..Default::default()
+                },
+            ],
+            actions: vec![Action {
+                action_type: ActionType::Fileinto,
+                copy: true,
+                create: true,
+                ..Default::default()
+            }],
+            else_actions: Some(vec![Action {
+                action_type: ActionType::Addflag,
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        assert_eq!(
+            names(&rule),
+            vec!["body", "comparator-i;ascii-numeric", "copy", "fileinto", "imap4flags", "mailbox", "regex"]
+        );
+
+        // Conditions and actions in else-if branches count too
+        let rule = SieveRule {
+            alternatives: vec![RuleBranch {
+                conditions: vec![Condition {
+                    test_type: ConditionTest::Envelope,
+                    ..Default::default()
+                }],
+                actions: vec![Action {
+                    action_type: ActionType::Vacation,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(names(&rule), vec!["envelope", "vacation"]);
+    }
+
+    #[test]
+    fn test_roundtrip_rule_notes() {
+        let script1 = SieveScript {
+            rules: vec![SieveRule {
+                name: "Lists".to_string(),
+                comment: Some("Mailing lists I read weekly\nAsked for by the team".to_string()),
+                conditions: vec![Condition {
+                    header_names: vec!["List-Id".to_string()],
+                    keys: vec!["dev".to_string()],
+                    ..Default::default()
+                }],
+                actions: vec![Action {
+                    action_type: ActionType::Keep,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let text = script_to_text(&script1, &EmitOptions::default());
+        assert!(text.contains("# Filter: Lists\n# Mailing lists I read weekly\n# Asked for by the team\nif "));
+        let script2 = text_to_script(&text, "");
+        assert_eq!(script2.rules, script1.rules);
+    }
+
+    #[test]
+    fn test_roundtrip_negated_header() {
+        let script1 = SieveScript {
+            rules: vec![SieveRule {
+                name: "Not from boss".to_string(),
+                logic: LogicOperator::AnyOf,
+                conditions: vec![
+                    Condition {
+                        header_names: vec!["From".to_string()],
+                        keys: vec!["boss@example.com".to_string()],
+                        negate: true,
+                        ..Default::default()
+                    },
+                    Condition {
+                        header_names: vec!["Subject".to_string()],
+                        keys: vec!["[list]".to_string()],
+                        ..Default::default()
+                    },
+                ],
+                actions: vec![Action {
+                    action_type: ActionType::Discard,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let text = script_to_text(&script1, &EmitOptions::default());
+        assert!(text.contains(r#"anyof (not header :contains "From" "boss@example.com", header"#));
+        let script2 = text_to_script(&text, "");
+        assert!(script2.rules[0].conditions[0].negate);
+        assert!(!script2.rules[0].conditions[1].negate);
+        assert_eq!(script2.rules, script1.rules);
+    }
+
+    #[test]
+    fn test_roundtrip_fileinto_copy() {
+        let input = r#"require ["copy", "fileinto"];
+
+# Filter: Archive
+if header :contains "Subject" "report" {
+    fileinto :copy "Archive";
+}
+"#;
+        let script1 = text_to_script(input, "");
+        let action = &script1.rules[0].actions[0];
+        assert!(action.copy);
+        assert_eq!(action.argument, "Archive");
+
+        let text = script_to_text(&script1, &EmitOptions::default());
+        assert!(text.starts_with(r#"require ["copy", "fileinto"];"#));
+        assert!(text.contains(r#"fileinto :copy "Archive";"#));
+        assert_eq!(text_to_script(&text, "").rules, script1.rules);
+    }
+
+    #[test]
+    fn test_roundtrip_fileinto_create() {
+        let input = r#"# Filter: Lists
+if exists "List-Id" {
+    fileinto :create "Lists";
+}
+"#;
+        let script1 = text_to_script(input, "");
+        let action = &script1.rules[0].actions[0];
+        assert!(action.create);
+        assert!(!action.copy);
+        assert_eq!(action.argument, "Lists");
+
+        let text = script_to_text(&script1, &EmitOptions::default());
+        assert!(text.starts_with(r#"require ["fileinto", "mailbox"];"#));
+        assert!(text.contains(r#"fileinto :create "Lists";"#));
+        assert_eq!(text_to_script(&text, "").rules, script1.rules);
+    }
+
+    #[test]
+    fn test_roundtrip_escaped_wildcards() {
+        // `\\*` in the source is a backslash before the star, which :matches
+        // reads as a literal star (RFC 5228 §2.7.1)
+        let input = r#"# Filter: Urgent
+if header :matches "Subject" "re:\\*urgent\\*" {
+    fileinto "Urgent";
+}
+"#;
+        let script1 = text_to_script(input, "");
+        assert_eq!(script1.rules[0].conditions[0].keys, vec![r"re:\*urgent\*"]);
+        let text = script_to_text(&script1, &EmitOptions::default());
+        assert!(text.contains(r#"header :matches "Subject" "re:\\*urgent\\*""#), "{text}");
+        assert_eq!(text_to_script(&text, "").rules, script1.rules);
+
+        // A lone `\*` is no escape at the string level: it stands for `*`,
+        // a wildcard, and is written back as one
+        let script = text_to_script(r#"if header :matches "Subject" "re:\*" { stop; }"#, "");
+        assert_eq!(script.rules[0].conditions[0].keys, vec!["re:*"]);
+    }
+
+    #[test]
+    fn test_roundtrip_exists() {
+        let input = r#"# Filter: Lists
+if exists "List-Id" {
+    fileinto "Lists";
+}
+
+# Filter: Spam
+if exists ["X-Spam-Flag", "X-Spam-Status"] {
+    fileinto "Junk";
+}
+"#;
+        let script1 = text_to_script(input, "");
+        assert_eq!(script1.rules[0].conditions[0].test_type, ConditionTest::Exists);
+        assert_eq!(script1.rules[0].conditions[0].header_names, vec!["List-Id"]);
+        assert_eq!(
+            script1.rules[1].conditions[0].header_names,
+            vec!["X-Spam-Flag", "X-Spam-Status"]
+        );
+        let text = script_to_text(&script1, &EmitOptions::default());
+        assert!(text.contains(r#"if exists "List-Id" {"#));
+        assert!(text.contains(r#"if exists ["X-Spam-Flag", "X-Spam-Status"] {"#));
+        assert_eq!(text_to_script(&text, "").rules, script1.rules);
+    }
+
+    #[test]
+    fn test_exists_without_header_names() {
+        let mut script = text_to_script("if exists \"List-Id\" { stop; }", "");
+        // As left by typing "List-Id, " into the header field
+        script.rules[0].conditions[0].header_names = vec!["List-Id".to_string(), String::new()];
+        let text = script_to_text(&script, &EmitOptions::default());
+        assert!(text.contains("if exists \"List-Id\" {"), "{text}");
+
+        // Still valid SIEVE, left to the lint
+        script.rules[0].conditions[0].header_names = vec![" ".to_string()];
+        let text = script_to_text(&script, &EmitOptions::default());
+        assert!(text.contains("if exists \"\" {"), "{text}");
+        let parsed = text_to_script(&text, "");
+        assert_eq!(parsed.rules[0].conditions[0].header_names, vec![""]);
+        assert!(parser::parse("if exists [] { stop; }").is_ok());
+    }
+
+    #[test]
+    fn test_disabled_condition() {
+        let text = r#"require ["body", "fileinto"];
+
+# Filter: Lists
+# Weekly digests
+if anyof (header :contains "List-Id" "digest", body :contains "unsubscribe", header :is "Precedence" "list") {
+    fileinto "Lists";
+}
+"#;
+        let mut script = text_to_script(text, "");
+        script.rules[0].conditions[1].enabled = false;
+        let emitted = script_to_text(&script, &EmitOptions::default());
+        assert_eq!(
+            emitted,
+            r#"require ["body", "fileinto"];
+
+# Filter: Lists
+# Weekly digests
+# Disabled condition 2: body :contains "unsubscribe"
+if anyof (header :contains "List-Id" "digest", header :is "Precedence" "list") {
+    fileinto "Lists";
+}
+"#
+        );
+
+        // Back in its place, switched off, with the notes apart
+        let reloaded = text_to_script(&emitted, "");
+        let rule = &reloaded.rules[0];
+        assert!(rule.raw_block.is_none());
+        assert_eq!(rule.comment.as_deref(), Some("Weekly digests"));
+        assert_eq!(rule.logic, LogicOperator::AnyOf);
+        assert_eq!(rule.conditions, script.rules[0].conditions);
+        assert_eq!(script_to_text(&reloaded, &EmitOptions::default()), emitted);
+
+        // A lone remaining condition stays a group so the logic survives;
+        // a disabled one needs no extension
+        let mut one = script.clone();
+        one.rules[0].conditions[2].enabled = false;
+        one.requires.clear();
+        one.require_statements.clear();
+        let emitted = script_to_text(&one, &EmitOptions::default());
+        assert!(emitted.starts_with("require \"fileinto\";\n"), "{emitted}");
+        assert!(emitted.contains("\nif anyof (header :contains \"List-Id\" \"digest\") {"), "{emitted}");
+        let reloaded = text_to_script(&emitted, "");
+        assert_eq!(reloaded.rules[0].logic, LogicOperator::AnyOf);
+        assert_eq!(reloaded.rules[0].conditions, one.rules[0].conditions);
+
+        // Unnamed rules get a name line to carry the note
+        let mut unnamed = script.clone();
+        unnamed.rules[0].name.clear();
+        let emitted = script_to_text(&unnamed, &EmitOptions::default());
+        assert!(emitted.contains("# Filter: (unnamed)\n"), "{emitted}");
+        assert!(!text_to_script(&emitted, "").rules[0].conditions[1].enabled);
+    }
+
+    #[test]
+    fn test_all_conditions_disabled() {
+        for logic in ["allof", "anyof"] {
+            let text = format!(
+                "# Filter: Spam\nif {logic} (header :contains \"Subject\" \"SPAM\", header :is \"X-Spam\" \"yes\") {{\n    discard;\n}}\n"
+            );
+            let mut script = text_to_script(&text, "");
+            for cond in &mut script.rules[0].conditions {
+                cond.enabled = false;
+            }
+            // Matches nothing rather than everything
+            let emitted = script_to_text(&script, &EmitOptions::default());
+            assert!(emitted.contains(&format!("\nif {logic} (false) {{")), "{emitted}");
+            assert!(!emitted.contains("true"), "{emitted}");
+
+            let reloaded = text_to_script(&emitted, "");
+            assert_eq!(reloaded.rules[0].conditions, script.rules[0].conditions);
+            assert_eq!(reloaded.rules[0].logic, script.rules[0].logic);
+            assert_eq!(script_to_text(&reloaded, &EmitOptions::default()), emitted);
+        }
+
+        // A single disabled condition outside a group
+        let mut script = text_to_script("if header :contains \"Subject\" \"SPAM\" {\n    discard;\n}\n", "");
+        script.rules[0].conditions[0].enabled = false;
+        let emitted = script_to_text(&script, &EmitOptions::default());
+        assert!(emitted.contains("\nif allof (false) {"), "{emitted}");
+        assert_eq!(text_to_script(&emitted, "").rules[0].conditions, script.rules[0].conditions);
+    }
+
+    #[test]
+    fn test_multiline_condition_stays_on() {
+        let mut script = text_to_script("if header :contains \"Subject\" \"a\" {\n    discard;\n}\n", "");
+        let cond = &mut script.rules[0].conditions[0];
+        assert!(can_disable(cond));
+        cond.keys = vec!["first\nsecond".to_string()];
+        assert!(!can_disable(cond));
+    }
+
+    #[test]
+    fn test_blank_keys_dropped() {
+        let mut script = text_to_script("if header :contains \"Subject\" \"a\" {\n    discard;\n}\n", "");
+        let cond = &mut script.rules[0].conditions[0];
+        cond.keys = vec!["Hello, world".to_string(), String::new(), "b".to_string(), String::new()];
+        let text = script_to_text(&script, &EmitOptions::default());
+        assert!(text.contains(r#"header :contains "Subject" ["Hello, world", "b"]"#), "{text}");
+
+        // A lone empty key is a test of its own
+        script.rules[0].conditions[0].keys = vec![String::new(), String::new()];
+        let text = script_to_text(&script, &EmitOptions::default());
+        assert!(text.contains(r#"header :contains "Subject" """#), "{text}");
+    }
+
+    #[test]
+    fn test_roundtrip_body_transform() {
+        // `:text` is the default and reads back without the tag
+        let script = text_to_script("require \"body\";\n\nif body :text :contains \"discount\" {\n    discard;\n}\n", "");
+        let rule = &script.rules[0];
+        assert!(rule.raw_block.is_none());
+        assert_eq!(rule.conditions[0].transform, BodyTransform::Text);
+        let emitted = script_to_text(&script, &EmitOptions::default());
+        assert!(emitted.contains("if body :contains \"discount\" {"), "{emitted}");
+        assert_eq!(text_to_script(&emitted, "").rules, script.rules);
+
+        let text = "require \"body\";\n\nif body :contains :content \"text/plain\" \"x\" {\n    keep;\n}\n";
+        let script = text_to_script(text, "");
+        let cond = &script.rules[0].conditions[0];
+        assert_eq!(cond.transform, BodyTransform::Content("text/plain".to_string()));
+        assert_eq!(cond.match_type, MatchType::Contains);
+        assert_eq!(cond.keys, vec!["x"]);
+        assert_eq!(script_to_text(&script, &EmitOptions::default()), text);
+        let first = text_to_script("if body :content \"text/plain\" :contains \"x\" { keep; }", "");
+        assert_eq!(&first.rules[0].conditions[0], cond);
+
+        // Written as RFC 5173 has it, transform last
+        let script = text_to_script("require \"body\";\nif body :raw :contains \"MIME-Version\" { keep; }\n", "");
+        assert_eq!(script.rules[0].conditions[0].transform, BodyTransform::Raw);
+        assert!(script_to_text(&script, &EmitOptions::default()).contains("if body :contains :raw \"MIME-Version\" {"));
+
+        // More than one media type stays raw
+        let text = "require \"body\";\n\nif body :content [\"text/plain\", \"text/html\"] :contains \"x\" {\n    keep;\n}\n";
+        let script = text_to_script(text, "");
+        assert!(script.rules[0].raw_block.is_some());
+        assert!(script_to_text(&script, &EmitOptions::default()).contains(":content [\"text/plain\", \"text/html\"]"));
+    }
 }
@@ -1,69 +1,175 @@
 /// Emit SIEVE script text from AST nodes.
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
 use crate::sieve::ast::*;
 
-pub fn emit(script: &Script) -> String {
+/// Indentation of the actions inside a block.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Indent {
+    TwoSpaces,
+    #[default]
+    FourSpaces,
+    Tab,
+}
+
+impl Indent {
+    pub const ALL: &'static [Indent] = &[Self::TwoSpaces, Self::FourSpaces, Self::Tab];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::TwoSpaces => "  ",
+            Self::FourSpaces => "    ",
+            Self::Tab => "\t",
+        }
+    }
+}
+
+impl fmt::Display for Indent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TwoSpaces => f.write_str("2 spaces"),
+            Self::FourSpaces => f.write_str("4 spaces"),
+            Self::Tab => f.write_str("Tab"),
+        }
+    }
+}
+
+/// Layout of generated text; none of it changes what the script does.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmitOptions {
+    pub indent: Indent,
+    /// Put a blank line ahead of each rule, comment run and `set` run
+    pub blank_between_rules: bool,
+    /// Extensions from which a `require` is written as one list; fewer get a
+    /// `require "ext";` line each. Statements kept from the loaded script
+    /// stay as written.
+    pub require_as_list_threshold: usize,
+    /// When the requires are rewritten, list the loaded script's extensions
+    /// in their original order ahead of new ones instead of sorting them
+    pub keep_require_order: bool,
+}
+
+impl Default for EmitOptions {
+    fn default() -> Self {
+        Self {
+            indent: Indent::FourSpaces,
+            blank_between_rules: true,
+            require_as_list_threshold: 2,
+            keep_require_order: false,
+        }
+    }
+}
+
+pub fn emit(script: &Script, options: &EmitOptions) -> String {
     let mut out = String::new();
     let mut first = true;
 
-    // Collect all requires into a single statement
-    let mut all_requires: Vec<String> = Vec::new();
-    for cmd in &script.commands {
-        if let Command::Require(exts) = cmd {
-            for ext in exts {
-                if !all_requires.contains(ext) {
-                    all_requires.push(ext.clone());
-                }
-            }
+    // Comments ahead of any code (a license banner, say) stay above the requires
+    let preamble_len = script
+        .commands
+        .iter()
+        .take_while(|cmd| matches!(cmd, Command::Comment(_)))
+        .count();
+    let (preamble, commands) = script.commands.split_at(preamble_len);
+    for cmd in preamble {
+        if let Command::Comment(text) = cmd {
+            emit_comment(&mut out, text);
+            first = false;
         }
     }
 
-    if !all_requires.is_empty() {
-        if all_requires.len() == 1 {
-            out.push_str(&format!("require \"{}\";\n", all_requires[0]));
-        } else {
-            let list = all_requires
+    // Require statements head the code, one line each, as given
+    let mut requires = script
+        .commands
+        .iter()
+        .filter_map(|cmd| match cmd {
+            Command::Require { extensions, list } if !extensions.is_empty() => {
+                Some((extensions, *list))
+            }
+            _ => None,
+        })
+        .peekable();
+    if requires.peek().is_some() && !first {
+        out.push('\n');
+    }
+    for (extensions, list) in requires {
+        if list || extensions.len() >= options.require_as_list_threshold {
+            let names = extensions
                 .iter()
                 .map(|e| format!("\"{}\"", e))
                 .collect::<Vec<_>>()
                 .join(", ");
-            out.push_str(&format!("require [{list}];\n"));
+            out.push_str(&format!("require [{names}];\n"));
+        } else {
+            for ext in extensions {
+                out.push_str(&format!("require \"{ext}\";\n"));
+            }
         }
         first = false;
     }
 
-    for cmd in &script.commands {
+    // A run of comments opens with the blank line its following block would
+    // otherwise get, so section dividers stay attached to what they introduce
+    let mut after_comment = false;
+    let mut after_set = false;
+    let separate = |out: &mut String, first: bool, after_comment: bool| {
+        if !first && !after_comment && options.blank_between_rules {
+            out.push('\n');
+        }
+    };
+    for cmd in commands {
         match cmd {
-            Command::Require(_) => {} // Already handled above
+            Command::Require { .. } => {} // Already handled above
             Command::If(block) => {
-                if !first {
-                    out.push('\n');
-                }
-                emit_if_block(&mut out, block);
+                separate(&mut out, first, after_comment);
+                emit_if_block(&mut out, block, options.indent);
                 first = false;
             }
             Command::Action(action) => {
-                emit_action(&mut out, action, 0);
+                emit_action(&mut out, action, "");
+                first = false;
+            }
+            Command::Set {
+                name,
+                value,
+                modifiers,
+            } => {
+                // A run of `set`s reads as one block
+                separate(&mut out, first, after_comment || after_set);
+                emit_set(&mut out, name, value, modifiers);
                 first = false;
             }
             Command::Comment(text) => {
-                out.push_str(&format!("# {text}\n"));
-                // Don't set first=false so we don't get extra blank lines
+                separate(&mut out, first, after_comment);
+                emit_comment(&mut out, text);
+                first = false;
             }
             Command::Raw(text) => {
-                if !first {
-                    out.push('\n');
-                }
+                separate(&mut out, first, after_comment);
                 out.push_str(text);
                 out.push('\n');
                 first = false;
             }
         }
+        after_comment = matches!(cmd, Command::Comment(_));
+        after_set = matches!(cmd, Command::Set { .. });
     }
 
     out
 }
 
-fn emit_if_block(out: &mut String, block: &IfBlock) {
+fn emit_comment(out: &mut String, text: &str) {
+    out.push_str(format!("# {text}").trim_end());
+    out.push('\n');
+}
+
+fn emit_if_block(out: &mut String, block: &IfBlock, indent: Indent) {
+    if let Some(text) = commented_out_text(block, indent) {
+        out.push_str(&text);
+        return;
+    }
     // Emit filter name comment
     if let Some(name) = &block.name {
         if block.enabled {
@@ -71,6 +177,12 @@ fn emit_if_block(out: &mut String, block: &IfBlock) {
         } else {
             out.push_str(&format!("# Filter: {name} [DISABLED]\n"));
         }
+        if let Some(comment) = &block.comment {
+            for line in comment.lines() {
+                out.push_str(format!("# {line}").trim_end());
+                out.push('\n');
+            }
+        }
     }
 
     out.push_str("if ");
@@ -78,7 +190,7 @@ fn emit_if_block(out: &mut String, block: &IfBlock) {
     out.push_str(" {\n");
 
     for action in &block.actions {
-        emit_action(out, action, 1);
+        emit_action(out, action, indent.as_str());
     }
 
     out.push('}');
@@ -90,14 +202,14 @@ fn emit_if_block(out: &mut String, block: &IfBlock) {
                 emit_test_expr(out, condition);
                 out.push_str(" {\n");
                 for action in actions {
-                    emit_action(out, action, 1);
+                    emit_action(out, action, indent.as_str());
                 }
                 out.push('}');
             }
             Alternative::Else { actions } => {
                 out.push_str(" else {\n");
                 for action in actions {
-                    emit_action(out, action, 1);
+                    emit_action(out, action, indent.as_str());
                 }
                 out.push('}');
             }
@@ -107,6 +219,34 @@ fn emit_if_block(out: &mut String, block: &IfBlock) {
     out.push('\n');
 }
 
+/// A disabled block wrapped in `/* ... */`, or `None` when it is marked
+/// `[DISABLED]` instead. Text holding `*/` would end the comment early, so
+/// such a block keeps the marker.
+fn commented_out_text(block: &IfBlock, indent: Indent) -> Option<String> {
+    if block.enabled || !block.commented_out {
+        return None;
+    }
+    let mut inner = String::new();
+    let active = IfBlock {
+        enabled: true,
+        commented_out: false,
+        ..block.clone()
+    };
+    emit_if_block(&mut inner, &active, indent);
+    if inner.contains("*/") {
+        return None;
+    }
+    Some(format!("/*\n{inner}*/\n"))
+}
+
+/// A test as it is written inside an `if`, on one line unless a string in
+/// it spans several.
+pub fn test_to_string(expr: &TestExpr) -> String {
+    let mut out = String::new();
+    emit_test_expr(&mut out, expr);
+    out
+}
+
 fn emit_test_expr(out: &mut String, expr: &TestExpr) {
     match expr {
         TestExpr::AllOf(tests) => {
@@ -135,11 +275,13 @@ fn emit_test_expr(out: &mut String, expr: &TestExpr) {
         }
         TestExpr::Header {
             match_type,
+            comparator,
+            relation,
             header_names,
             keys,
         } => {
             out.push_str("header ");
-            out.push_str(match_type);
+            emit_match_tags(out, match_type, comparator, relation);
             out.push(' ');
             emit_string_or_list(out, header_names);
             out.push(' ');
@@ -148,16 +290,16 @@ fn emit_test_expr(out: &mut String, expr: &TestExpr) {
         TestExpr::Address {
             address_part,
             match_type,
+            comparator,
+            relation,
             header_names,
             keys,
         } => {
             out.push_str("address ");
-            out.push_str(match_type);
+            emit_match_tags(out, match_type, comparator, relation);
             if let Some(ap) = address_part {
-                if ap != ":all" {
-                    out.push(' ');
-                    out.push_str(ap);
-                }
+                out.push(' ');
+                out.push_str(ap);
             }
             out.push(' ');
             emit_string_or_list(out, header_names);
@@ -167,16 +309,16 @@ fn emit_test_expr(out: &mut String, expr: &TestExpr) {
         TestExpr::Envelope {
             address_part,
             match_type,
+            comparator,
+            relation,
             header_names,
             keys,
         } => {
             out.push_str("envelope ");
-            out.push_str(match_type);
+            emit_match_tags(out, match_type, comparator, relation);
             if let Some(ap) = address_part {
-                if ap != ":all" {
-                    out.push(' ');
-                    out.push_str(ap);
-                }
+                out.push(' ');
+                out.push_str(ap);
             }
             out.push(' ');
             emit_string_or_list(out, header_names);
@@ -193,17 +335,79 @@ fn emit_test_expr(out: &mut String, expr: &TestExpr) {
             out.push_str("exists ");
             emit_string_or_list(out, header_names);
         }
-        TestExpr::Body { match_type, keys } => {
+        TestExpr::Body {
+            match_type,
+            comparator,
+            relation,
+            transform,
+            content_types,
+            keys,
+        } => {
             out.push_str("body ");
-            out.push_str(match_type);
+            emit_match_tags(out, match_type, comparator, relation);
+            if let Some(transform) = transform {
+                out.push(' ');
+                out.push_str(transform);
+                if transform == ":content" {
+                    out.push(' ');
+                    emit_string_or_list(out, content_types);
+                }
+            }
             out.push(' ');
             emit_string_or_list(out, keys);
         }
+        TestExpr::StringTest {
+            match_type,
+            comparator,
+            relation,
+            source,
+            keys,
+        } => {
+            out.push_str("string ");
+            emit_match_tags(out, match_type, comparator, relation);
+            out.push(' ');
+            emit_string_or_list(out, source);
+            out.push(' ');
+            emit_string_or_list(out, keys);
+        }
+        TestExpr::Hasflag {
+            match_type,
+            comparator,
+            relation,
+            variables,
+            flags,
+        } => {
+            out.push_str("hasflag ");
+            emit_match_tags(out, match_type, comparator, relation);
+            if !variables.is_empty() {
+                out.push(' ');
+                emit_string_or_list(out, variables);
+            }
+            out.push(' ');
+            emit_string_or_list(out, flags);
+        }
         TestExpr::True => out.push_str("true"),
         TestExpr::False => out.push_str("false"),
     }
 }
 
+/// `[:comparator "c"] :match_type`, or the relational tag and its operator
+/// in place of the match type.
+fn emit_match_tags(
+    out: &mut String,
+    match_type: &str,
+    comparator: &Option<String>,
+    relation: &Option<(RelTag, String)>,
+) {
+    if let Some(comparator) = comparator {
+        out.push_str(&format!(":comparator \"{}\" ", escape_sieve_string(comparator)));
+    }
+    match relation {
+        Some((tag, op)) => out.push_str(&format!("{} \"{}\"", tag.as_sieve(), escape_sieve_string(op))),
+        None => out.push_str(match_type),
+    }
+}
+
 fn emit_string_or_list(out: &mut String, items: &[String]) {
     if items.len() == 1 {
         out.push_str(&format!("\"{}\"", escape_sieve_string(&items[0])));
@@ -223,9 +427,8 @@ fn escape_sieve_string(s: &str) -> String {
     s.replace('\\', "\\\\").replace('"', "\\\"")
 }
 
-fn emit_action(out: &mut String, action: &ActionCommand, indent: usize) {
-    let prefix = "    ".repeat(indent);
-    out.push_str(&prefix);
+fn emit_action(out: &mut String, action: &ActionCommand, indent: &str) {
+    out.push_str(indent);
     out.push_str(&action.name);
     for arg in &action.arguments {
         out.push(' ');
@@ -238,17 +441,52 @@ fn emit_action(out: &mut String, action: &ActionCommand, indent: usize) {
             Argument::StringList(items) => {
                 emit_string_or_list(out, items);
             }
+            Argument::MultiLine(body) => emit_multiline(out, body),
         }
     }
     out.push_str(";\n");
 }
 
+fn emit_set(out: &mut String, name: &str, value: &str, modifiers: &[String]) {
+    let mut arguments: Vec<Argument> = modifiers.iter().cloned().map(Argument::Tag).collect();
+    arguments.push(Argument::QuotedString(name.to_string()));
+    arguments.push(if value.contains('\n') {
+        Argument::MultiLine(value.to_string())
+    } else {
+        Argument::QuotedString(value.to_string())
+    });
+    let action = ActionCommand {
+        name: "set".to_string(),
+        arguments,
+    };
+    emit_action(out, &action, "");
+}
+
+/// Emit a `text:` multi-line string, dot-stuffing lines that begin with `.`.
+/// The terminating `.` line is followed by a newline, so the caller's `;`
+/// ends up on its own line.
+fn emit_multiline(out: &mut String, body: &str) {
+    out.push_str("text:\n");
+    for line in body.split_inclusive('\n') {
+        if line.starts_with('.') {
+            out.push('.');
+        }
+        out.push_str(line);
+    }
+    if !body.is_empty() && !body.ends_with('\n') {
+        out.push('\n');
+    }
+    out.push_str(".\n");
+}
+
 /// Compute what `require` extensions a script's AST needs.
 pub fn compute_requires(script: &Script) -> Vec<String> {
     let mut requires = std::collections::BTreeSet::new();
 
     for cmd in &script.commands {
         match cmd {
+            // Commented out, the server never reads it
+            Command::If(block) if commented_out_text(block, Indent::default()).is_some() => {}
             Command::If(block) => {
                 collect_test_requires(&block.condition, &mut requires);
                 collect_action_requires(&block.actions, &mut requires);
@@ -267,6 +505,9 @@ pub fn compute_requires(script: &Script) -> Vec<String> {
             Command::Action(action) => {
                 collect_single_action_require(action, &mut requires);
             }
+            Command::Set { .. } => {
+                requires.insert("variables".to_string());
+            }
             _ => {}
         }
     }
@@ -282,22 +523,77 @@ fn collect_test_requires(expr: &TestExpr, requires: &mut std::collections::BTree
             }
         }
         TestExpr::Not(inner) => collect_test_requires(inner, requires),
-        TestExpr::Envelope { .. } => {
-            requires.insert("envelope".to_string());
+        TestExpr::Header {
+            match_type,
+            comparator,
+            relation,
+            ..
         }
-        TestExpr::Body { .. } => {
-            requires.insert("body".to_string());
+        | TestExpr::Address {
+            match_type,
+            comparator,
+            relation,
+            ..
+        }
+        | TestExpr::Envelope {
+            match_type,
+            comparator,
+            relation,
+            ..
         }
-        TestExpr::Header { match_type, .. }
-        | TestExpr::Address { match_type, .. } => {
-            if match_type == ":regex" {
+        | TestExpr::Body {
+            match_type,
+            comparator,
+            relation,
+            ..
+        }
+        | TestExpr::StringTest {
+            match_type,
+            comparator,
+            relation,
+            ..
+        }
+        | TestExpr::Hasflag {
+            match_type,
+            comparator,
+            relation,
+            ..
+        } => {
+            match expr {
+                TestExpr::Envelope { .. } => { requires.insert("envelope".to_string()); }
+                TestExpr::Body { .. } => { requires.insert("body".to_string()); }
+                TestExpr::StringTest { .. } => { requires.insert("variables".to_string()); }
+                TestExpr::Hasflag { variables, .. } => {
+                    requires.insert("imap4flags".to_string());
+                    if !variables.is_empty() {
+                        requires.insert("variables".to_string());
+                    }
+                }
+                _ => {}
+            }
+            if relation.is_some() {
+                requires.insert("relational".to_string());
+            } else if match_type == ":regex" {
                 requires.insert("regex".to_string());
             }
+            if let Some(ext) = comparator.as_deref().and_then(comparator_require) {
+                requires.insert(ext);
+            }
         }
         _ => {}
     }
 }
 
+/// Comparators other than the built-in `i;octet` and `i;ascii-casemap`
+/// need a `comparator-<name>` require (RFC 5228 §2.7.3).
+pub fn comparator_require(comparator: &str) -> Option<String> {
+    if comparator.eq_ignore_ascii_case("i;octet") || comparator.eq_ignore_ascii_case("i;ascii-casemap") {
+        None
+    } else {
+        Some(format!("comparator-{comparator}"))
+    }
+}
+
 fn collect_action_requires(actions: &[ActionCommand], requires: &mut std::collections::BTreeSet<String>) {
     for action in actions {
         collect_single_action_require(action, requires);
@@ -305,10 +601,126 @@ fn collect_action_requires(actions: &[ActionCommand], requires: &mut std::collec
 }
 
 fn collect_single_action_require(action: &ActionCommand, requires: &mut std::collections::BTreeSet<String>) {
+    let has_tag = |tag: &str| action.arguments.iter().any(|a| matches!(a, Argument::Tag(t) if t == tag));
+    if has_tag(":copy") {
+        requires.insert("copy".to_string());
+    }
+    if has_tag(":create") {
+        requires.insert("mailbox".to_string());
+    }
     match action.name.to_lowercase().as_str() {
         "fileinto" => { requires.insert("fileinto".to_string()); }
         "reject" => { requires.insert("reject".to_string()); }
         "setflag" | "addflag" | "removeflag" => { requires.insert("imap4flags".to_string()); }
+        "vacation" => { requires.insert("vacation".to_string()); }
+        "notify" => { requires.insert("enotify".to_string()); }
+        "addheader" | "deleteheader" => { requires.insert("editheader".to_string()); }
+        "set" => { requires.insert("variables".to_string()); }
         _ => {}
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_emit_multiline_dot_terminator() {
+        let action = ActionCommand {
+            name: "reject".to_string(),
+            arguments: vec![Argument::MultiLine("Line one\n.hidden\n".to_string())],
+        };
+        let mut out = String::new();
+        emit_action(&mut out, &action, "");
+        assert_eq!(out, "reject text:\nLine one\n..hidden\n.\n;\n");
+    }
+
+    #[test]
+    fn test_emit_filter_comment() {
+        let script = Script {
+            commands: vec![Command::If(IfBlock {
+                name: Some("Newsletters".to_string()),
+                enabled: false,
+                commented_out: false,
+                comment: Some("Keeps the inbox readable".to_string()),
+                condition: TestExpr::True,
+                actions: vec![ActionCommand {
+                    name: "keep".to_string(),
+                    arguments: Vec::new(),
+                }],
+                alternatives: Vec::new(),
+            })],
+        };
+        assert_eq!(
+            emit(&script, &EmitOptions::default()),
+            "# Filter: Newsletters [DISABLED]\n# Keeps the inbox readable\nif true {\n    keep;\n}\n"
+        );
+    }
+
+    /// Two rules, the second with an `else`, after a two-extension require.
+    fn two_rules() -> Script {
+        let action = |name: &str, arg: &str| ActionCommand {
+            name: name.to_string(),
+            arguments: vec![Argument::QuotedString(arg.to_string())],
+        };
+        let rule = |header: &str, actions, alternatives| {
+            Command::If(IfBlock {
+                name: None,
+                enabled: true,
+                commented_out: false,
+                comment: None,
+                condition: TestExpr::Exists {
+                    header_names: vec![header.to_string()],
+                },
+                actions,
+                alternatives,
+            })
+        };
+        Script {
+            commands: vec![
+                Command::Require {
+                    extensions: vec!["fileinto".to_string(), "imap4flags".to_string()],
+                    list: false,
+                },
+                rule("List-Id", vec![action("fileinto", "Lists")], Vec::new()),
+                rule(
+                    "X-Spam",
+                    vec![action("fileinto", "Junk")],
+                    vec![Alternative::Else {
+                        actions: vec![action("addflag", "\\Seen")],
+                    }],
+                ),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_emit_tab_indent() {
+        let options = EmitOptions {
+            indent: Indent::Tab,
+            ..Default::default()
+        };
+        assert_eq!(
+            emit(&two_rules(), &options),
+            "require [\"fileinto\", \"imap4flags\"];\n\n\
+             if exists \"List-Id\" {\n\tfileinto \"Lists\";\n}\n\n\
+             if exists \"X-Spam\" {\n\tfileinto \"Junk\";\n} else {\n\taddflag \"\\\\Seen\";\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_emit_two_space_indent_compact() {
+        let options = EmitOptions {
+            indent: Indent::TwoSpaces,
+            blank_between_rules: false,
+            require_as_list_threshold: 3,
+            ..Default::default()
+        };
+        assert_eq!(
+            emit(&two_rules(), &options),
+            "require \"fileinto\";\nrequire \"imap4flags\";\n\
+             if exists \"List-Id\" {\n  fileinto \"Lists\";\n}\n\
+             if exists \"X-Spam\" {\n  fileinto \"Junk\";\n} else {\n  addflag \"\\\\Seen\";\n}\n"
+        );
+    }
+}
@@ -109,7 +109,7 @@ pub fn tokenize(input: &str) -> Result<Vec<Span>, String> {
                 let comment_start = i;
                 loop {
                     if i + 1 >= bytes.len() {
-                        return Err(format!("Unterminated block comment at offset {start}"));
+                        return Err(format_error(input, start, "Unterminated block comment"));
                     }
                     if bytes[i] == b'*' && bytes[i + 1] == b'/' {
                         break;
@@ -128,23 +128,30 @@ pub fn tokenize(input: &str) -> Result<Vec<Span>, String> {
             // Quoted string
             b'"' => {
                 i += 1;
-                let mut s = String::new();
+                // Collect raw bytes and decode once, so multi-byte UTF-8
+                // characters stay intact
+                let mut raw = Vec::new();
                 loop {
                     if i >= bytes.len() {
-                        return Err(format!("Unterminated string at offset {start}"));
+                        return Err(format_error(input, start, "Unterminated string"));
                     }
                     if bytes[i] == b'\\' && i + 1 < bytes.len() {
-                        // Escape sequence
-                        s.push(bytes[i + 1] as char);
+                        // Only \" and \\ are defined; any other escaped
+                        // character stands for itself (RFC 5228 §2.4.2), so
+                        // "\*" is a plain wildcard and a literal star for
+                        // :matches is written "\\*"
+                        raw.push(bytes[i + 1]);
                         i += 2;
                     } else if bytes[i] == b'"' {
                         i += 1;
                         break;
                     } else {
-                        s.push(bytes[i] as char);
+                        raw.push(bytes[i]);
                         i += 1;
                     }
                 }
+                // Input is a &str and escapes only drop ASCII backslashes
+                let s = String::from_utf8(raw).expect("quoted string is valid UTF-8");
                 tokens.push(Span {
                     token: Token::QuotedString(s),
                     offset: start,
@@ -169,7 +176,7 @@ pub fn tokenize(input: &str) -> Result<Vec<Span>, String> {
                 // Read until a line that is just "."
                 loop {
                     if i >= bytes.len() {
-                        return Err(format!("Unterminated multi-line string at offset {start}"));
+                        return Err(format_error(input, start, "Unterminated multi-line string"));
                     }
                     // Check if current line is ".\r\n" or ".\n"
                     if bytes[i] == b'.' {
@@ -180,7 +187,7 @@ pub fn tokenize(input: &str) -> Result<Vec<Span>, String> {
                                 && next + 1 < bytes.len()
                                 && bytes[next + 1] == b'\n')
                         {
-                            let body = &input[body_start..i];
+                            let body = unstuff_dots(&input[body_start..i]);
                             // Skip past the dot and newline
                             i += 1;
                             if i < bytes.len() && bytes[i] == b'\r' {
@@ -190,7 +197,7 @@ pub fn tokenize(input: &str) -> Result<Vec<Span>, String> {
                                 i += 1;
                             }
                             tokens.push(Span {
-                                token: Token::MultiLineString(body.to_string()),
+                                token: Token::MultiLineString(body),
                                 offset: start,
                                 len: i - start,
                             });
@@ -255,9 +262,10 @@ pub fn tokenize(input: &str) -> Result<Vec<Span>, String> {
             }
 
             _ => {
-                return Err(format!(
-                    "Unexpected character '{}' at offset {start}",
-                    bytes[i] as char
+                return Err(format_error(
+                    input,
+                    start,
+                    &format!("Unexpected character '{}'", bytes[i] as char),
                 ));
             }
         }
@@ -266,6 +274,32 @@ pub fn tokenize(input: &str) -> Result<Vec<Span>, String> {
     Ok(tokens)
 }
 
+/// 1-based line and column (in characters) of a byte offset into `input`.
+pub fn line_col(input: &str, offset: usize) -> (usize, usize) {
+    let mut offset = offset.min(input.len());
+    while !input.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let before = &input[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let col = before[line_start..].chars().count() + 1;
+    (line, col)
+}
+
+/// Format an error message prefixed with its position, e.g. `line 12, col 4: ...`.
+pub fn format_error(input: &str, offset: usize, message: &str) -> String {
+    let (line, col) = line_col(input, offset);
+    format!("line {line}, col {col}: {message}")
+}
+
+/// Undo RFC 5228 dot-stuffing: a body line starting with `..` stands for `.`.
+fn unstuff_dots(body: &str) -> String {
+    body.split_inclusive('\n')
+        .map(|line| line.strip_prefix('.').filter(|l| l.starts_with('.')).unwrap_or(line))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -298,9 +332,48 @@ mod tests {
         assert_eq!(tokens.len(), 5); // [ "a" , "b" ]
     }
 
+    #[test]
+    fn test_quoted_string_utf8_and_escapes() {
+        let tokens = tokenize(r#""café" "a\\b" "say \"hi\"" "\q""#).unwrap();
+        let strings: Vec<&str> = tokens
+            .iter()
+            .map(|t| match &t.token {
+                Token::QuotedString(s) => s.as_str(),
+                other => panic!("Expected string, got {other:?}"),
+            })
+            .collect();
+        assert_eq!(strings, vec!["café", "a\\b", "say \"hi\"", "q"]);
+    }
+
     #[test]
     fn test_number_with_suffix() {
         let tokens = tokenize("100K").unwrap();
         assert!(matches!(&tokens[0].token, Token::Number(s) if s == "100K"));
     }
+
+    #[test]
+    fn test_multiline_string_dot_unstuffing() {
+        let tokens = tokenize("text:\nfirst\n..dotted\n.\n").unwrap();
+        assert!(matches!(&tokens[0].token, Token::MultiLineString(s) if s == "first\n.dotted\n"));
+    }
+
+    #[test]
+    fn test_line_col() {
+        let input = "keep;\n  stop;";
+        assert_eq!(line_col(input, 0), (1, 1));
+        assert_eq!(line_col(input, 8), (2, 3));
+        assert_eq!(line_col(input, 100), (2, 8));
+    }
+
+    #[test]
+    fn test_line_col_counts_chars() {
+        // Columns count characters, as the editor cursor does, not bytes
+        let input = "# Café rules\nif true {\n    fileinto \"Boîte\";\n}";
+        let offset = input.find("fileinto").unwrap();
+        assert_eq!(line_col(input, offset), (3, 5));
+        let offset = input.find(';').unwrap();
+        assert_eq!(line_col(input, offset), (3, 21));
+        // Inside a multi-byte character: snaps back to its start
+        assert_eq!(line_col(input, 6), (1, 6));
+    }
 }
@@ -0,0 +1,510 @@
+/// Advisory checks: SIEVE that is valid but usually a mistake, in a single
+/// rule or across the script. The warnings are shown with the rule or in
+/// the Problems panel and never block saving or uploading.
+use std::fmt;
+
+use crate::model::enums::{ActionType, ConditionTest, LogicOperator, MatchType};
+use crate::model::rule::{Action, Condition, SieveRule};
+use crate::model::script::SieveScript;
+use crate::sieve::ast::Command;
+use crate::sieve::{converter, emitter, parser};
+
+/// The part of a rule a warning is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Branch {
+    /// The actions of the `if`
+    Main,
+    /// The actions of an `elsif`, by index
+    ElsIf(usize),
+    /// The actions of the `else`
+    Else,
+}
+
+impl fmt::Display for Branch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Main => f.write_str("Actions"),
+            Self::ElsIf(i) => write!(f, "Else if {}", i + 1),
+            Self::Else => f.write_str("Otherwise"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleWarning {
+    /// `discard` next to an explicit `keep`, which files the message anyway
+    DiscardAndKeep(Branch),
+    /// Actions listed after `stop` never run
+    AfterStop { branch: Branch, count: usize },
+    /// Not a single action in any branch, so the rule does nothing
+    Empty,
+    /// A flag action with no flags: `setflag` clears them all, `addflag`
+    /// and `removeflag` do nothing
+    NoFlags { branch: Branch, action: ActionType },
+    /// An `exists` condition without a header name, which never matches
+    ExistsWithoutHeader,
+}
+
+impl fmt::Display for RuleWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DiscardAndKeep(branch) => {
+                write!(f, "{branch}: keep still files the message, so discard has no effect")
+            }
+            Self::AfterStop { branch, count: 1 } => {
+                write!(f, "{branch}: the action after stop never runs")
+            }
+            Self::AfterStop { branch, count } => {
+                write!(f, "{branch}: the {count} actions after stop never run")
+            }
+            Self::Empty => f.write_str("This filter has no actions and does nothing"),
+            Self::NoFlags {
+                branch,
+                action: ActionType::Setflag,
+            } => write!(f, "{branch}: setflag without flags clears every flag on the message"),
+            Self::NoFlags { branch, action } => {
+                write!(f, "{branch}: {} without flags does nothing", action.as_sieve())
+            }
+            Self::ExistsWithoutHeader => {
+                f.write_str("An exists condition has no header name and never matches")
+            }
+        }
+    }
+}
+
+/// Warnings for `rule`, in branch order. Raw rules aren't checked.
+pub fn validate_rule(rule: &SieveRule) -> Vec<RuleWarning> {
+    if rule.raw_block.is_some() {
+        return Vec::new();
+    }
+    let branches = std::iter::once((Branch::Main, &rule.actions))
+        .chain(rule.alternatives.iter().enumerate().map(|(i, b)| (Branch::ElsIf(i), &b.actions)))
+        .chain(rule.else_actions.iter().map(|actions| (Branch::Else, actions)));
+
+    let mut warnings = Vec::new();
+    let conditions = rule
+        .conditions
+        .iter()
+        .chain(rule.alternatives.iter().flat_map(|b| &b.conditions));
+    if conditions.filter(|c| c.enabled).any(|c| {
+        c.test_type == ConditionTest::Exists && c.header_names.iter().all(|h| h.trim().is_empty())
+    }) {
+        warnings.push(RuleWarning::ExistsWithoutHeader);
+    }
+    let mut any_action = false;
+    for (branch, actions) in branches {
+        any_action |= !actions.is_empty();
+        warnings.extend(validate_actions(branch, actions));
+    }
+    if !any_action {
+        warnings.push(RuleWarning::Empty);
+    }
+    warnings
+}
+
+fn validate_actions(branch: Branch, actions: &[Action]) -> Vec<RuleWarning> {
+    let mut warnings = Vec::new();
+    let has = |t: ActionType| actions.iter().any(|a| a.action_type == t);
+    if has(ActionType::Discard) && has(ActionType::Keep) {
+        warnings.push(RuleWarning::DiscardAndKeep(branch));
+    }
+    for action in actions {
+        if action.action_type.is_flag_action() && action.flags.iter().all(|f| f.trim().is_empty()) {
+            warnings.push(RuleWarning::NoFlags {
+                branch,
+                action: action.action_type,
+            });
+        }
+    }
+    if let Some(first) = first_dead_action(actions) {
+        let count = actions.len() - first;
+        warnings.push(RuleWarning::AfterStop { branch, count });
+    }
+    warnings
+}
+
+/// Index of the first action that never runs because a `stop` comes
+/// before it.
+pub fn first_dead_action(actions: &[Action]) -> Option<usize> {
+    let stop = actions.iter().position(|a| a.action_type == ActionType::Stop)?;
+    (stop + 1 < actions.len()).then_some(stop + 1)
+}
+
+/// Moves `stop` after the other actions, which keep their order. A second
+/// `stop` adds nothing and is dropped.
+pub fn move_stop_last(actions: &mut Vec<Action>) {
+    let Some(stop) = actions.iter().position(|a| a.action_type == ActionType::Stop) else {
+        return;
+    };
+    let stop = actions.remove(stop);
+    actions.retain(|a| a.action_type != ActionType::Stop);
+    actions.push(stop);
+}
+
+/// A problem with one rule that only shows against the whole script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    /// Index of the rule in the script
+    pub rule: usize,
+    pub kind: LintKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LintKind {
+    /// The rule needs an extension the script doesn't `require`. Generated
+    /// scripts always declare theirs; loaded ones, raw blocks above all,
+    /// may not
+    MissingRequire(String),
+    /// An earlier rule, by index, always matches and stops, so this one
+    /// never runs
+    Shadowed { by: usize },
+    /// The rule redirects to an address its own test matches; if that is
+    /// this mailbox the message comes back and is redirected again
+    RedirectLoop(String),
+}
+
+impl fmt::Display for LintKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingRequire(ext) => write!(f, "uses \"{ext}\" but the script doesn't require it"),
+            Self::Shadowed { by } => {
+                write!(f, "never runs: filter {} always matches and stops", by + 1)
+            }
+            Self::RedirectLoop(address) => {
+                write!(f, "redirects to {address}, which it matches itself; this may loop")
+            }
+        }
+    }
+}
+
+/// Problems across `script`, in rule order.
+pub fn lint_script(script: &SieveScript) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    let mut stopped_by = None;
+    for (idx, rule) in script.rules.iter().enumerate() {
+        // Commented out, the server never reads it
+        if !rule.enabled && rule.commented_out {
+            continue;
+        }
+        let lint = |kind| Lint { rule: idx, kind };
+        for ext in required_extensions(rule) {
+            if !script.requires.iter().any(|r| r.eq_ignore_ascii_case(&ext)) {
+                lints.push(lint(LintKind::MissingRequire(ext)));
+            }
+        }
+        if let Some(by) = stopped_by.filter(|_| is_filter(rule)) {
+            lints.push(lint(LintKind::Shadowed { by }));
+        }
+        if stopped_by.is_none() && always_stops(rule) {
+            stopped_by = Some(idx);
+        }
+        lints.extend(redirect_loops(rule).into_iter().map(|a| lint(LintKind::RedirectLoop(a))));
+    }
+    lints
+}
+
+/// Extensions `rule` uses; a raw block that doesn't parse can't be read.
+fn required_extensions(rule: &SieveRule) -> Vec<String> {
+    match &rule.raw_block {
+        Some(raw) => parser::parse(raw)
+            .map(|ast| emitter::compute_requires(&ast))
+            .unwrap_or_default(),
+        None => converter::rule_requires(rule).into_iter().collect(),
+    }
+}
+
+/// Whether `rule` is an `if` block, not a run of comments or `set`s.
+fn is_filter(rule: &SieveRule) -> bool {
+    match &rule.raw_block {
+        Some(raw) => parser::parse(raw)
+            .map(|ast| ast.commands.iter().any(|c| matches!(c, Command::If(_))))
+            .unwrap_or(true),
+        None => true,
+    }
+}
+
+/// Whether `rule` matches every message and then stops.
+fn always_stops(rule: &SieveRule) -> bool {
+    if rule.raw_block.is_some() || rule.condition_tree.is_some() || rule.negate_group {
+        return false;
+    }
+    let always = |c: &&Condition| match c.test_type {
+        ConditionTest::True => !c.negate,
+        ConditionTest::False => c.negate,
+        _ => false,
+    };
+    let active: Vec<&Condition> = rule.conditions.iter().filter(|c| c.enabled).collect();
+    let matches_all = match rule.logic {
+        LogicOperator::AllOf => active.iter().all(always),
+        LogicOperator::AnyOf => active.is_empty() || active.iter().any(always),
+    };
+    matches_all && rule.actions.iter().any(|a| a.action_type == ActionType::Stop)
+}
+
+/// Redirect targets of `rule` that its own test looks for among the
+/// message's recipients.
+fn redirect_loops(rule: &SieveRule) -> Vec<String> {
+    const RECIPIENTS: &[&str] = &["to", "cc", "bcc", "delivered-to", "x-original-to"];
+    let matched = |address: &str| {
+        rule.conditions.iter().filter(|c| c.enabled && !c.negate).any(|c| {
+            matches!(
+                c.test_type,
+                ConditionTest::Header | ConditionTest::Address | ConditionTest::Envelope
+            ) && c.match_type == MatchType::Is
+                && c.header_names.iter().any(|h| RECIPIENTS.iter().any(|r| h.eq_ignore_ascii_case(r)))
+                && c.keys.iter().any(|k| k.trim().eq_ignore_ascii_case(address))
+        })
+    };
+    if rule.raw_block.is_some() {
+        return Vec::new();
+    }
+    rule.actions
+        .iter()
+        .filter(|a| a.action_type == ActionType::Redirect)
+        .map(|a| a.argument.trim())
+        .filter(|address| !address.is_empty() && matched(address))
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::rule::RuleBranch;
+
+    fn actions(types: &[ActionType]) -> Vec<Action> {
+        types
+            .iter()
+            .map(|&action_type| Action {
+                action_type,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_discard_and_keep() {
+        let rule = SieveRule {
+            actions: actions(&[ActionType::Keep, ActionType::Discard]),
+            ..Default::default()
+        };
+        assert_eq!(validate_rule(&rule), vec![RuleWarning::DiscardAndKeep(Branch::Main)]);
+        assert_eq!(
+            validate_rule(&rule)[0].to_string(),
+            "Actions: keep still files the message, so discard has no effect"
+        );
+
+        // Each branch on its own
+        let rule = SieveRule {
+            actions: actions(&[ActionType::Keep]),
+            else_actions: Some(actions(&[ActionType::Discard])),
+            ..Default::default()
+        };
+        assert!(validate_rule(&rule).is_empty());
+    }
+
+    #[test]
+    fn test_actions_after_stop() {
+        let rule = SieveRule {
+            actions: actions(&[ActionType::Fileinto, ActionType::Stop]),
+            alternatives: vec![RuleBranch {
+                actions: actions(&[ActionType::Stop, ActionType::Fileinto, ActionType::Keep]),
+                ..Default::default()
+            }],
+            else_actions: Some(actions(&[ActionType::Stop, ActionType::Discard])),
+            ..Default::default()
+        };
+        let warnings = validate_rule(&rule);
+        assert_eq!(
+            warnings,
+            vec![
+                RuleWarning::AfterStop {
+                    branch: Branch::ElsIf(0),
+                    count: 2
+                },
+                RuleWarning::AfterStop {
+                    branch: Branch::Else,
+                    count: 1
+                },
+            ]
+        );
+        assert_eq!(warnings[0].to_string(), "Else if 1: the 2 actions after stop never run");
+        assert_eq!(warnings[1].to_string(), "Otherwise: the action after stop never runs");
+    }
+
+    #[test]
+    fn test_dead_actions() {
+        assert_eq!(first_dead_action(&actions(&[ActionType::Keep, ActionType::Stop])), None);
+        assert_eq!(first_dead_action(&actions(&[ActionType::Keep])), None);
+        assert_eq!(first_dead_action(&[]), None);
+        let mut list = actions(&[
+            ActionType::Fileinto,
+            ActionType::Stop,
+            ActionType::Addflag,
+            ActionType::Stop,
+            ActionType::Keep,
+        ]);
+        assert_eq!(first_dead_action(&list), Some(2));
+
+        move_stop_last(&mut list);
+        let types: Vec<_> = list.iter().map(|a| a.action_type).collect();
+        assert_eq!(
+            types,
+            vec![ActionType::Fileinto, ActionType::Addflag, ActionType::Keep, ActionType::Stop]
+        );
+        assert_eq!(first_dead_action(&list), None);
+    }
+
+    #[test]
+    fn test_empty_rule() {
+        assert_eq!(validate_rule(&SieveRule::default()), vec![RuleWarning::Empty]);
+
+        // An action in any branch is enough
+        let rule = SieveRule {
+            else_actions: Some(actions(&[ActionType::Keep])),
+            ..Default::default()
+        };
+        assert!(validate_rule(&rule).is_empty());
+
+        // Raw rules aren't checked
+        let rule = SieveRule {
+            raw_block: Some("if true { }".to_string()),
+            ..Default::default()
+        };
+        assert!(validate_rule(&rule).is_empty());
+    }
+
+    #[test]
+    fn test_exists_without_header() {
+        let mut rule = converter::text_to_script("if exists \"\" { stop; }", "").rules.remove(0);
+        assert_eq!(validate_rule(&rule), vec![RuleWarning::ExistsWithoutHeader]);
+
+        rule.conditions[0].header_names = vec!["List-Id".to_string(), " ".to_string()];
+        assert!(validate_rule(&rule).is_empty());
+
+        // Disabled conditions aren't emitted
+        rule.conditions[0].header_names.clear();
+        rule.conditions[0].enabled = false;
+        assert!(validate_rule(&rule).is_empty());
+    }
+
+    #[test]
+    fn test_flag_action_without_flags() {
+        let mut rule = SieveRule {
+            actions: actions(&[ActionType::Setflag, ActionType::Addflag]),
+            ..Default::default()
+        };
+        rule.actions[1].flags = vec!["\\Seen".to_string()];
+        let warnings = validate_rule(&rule);
+        assert_eq!(
+            warnings,
+            vec![RuleWarning::NoFlags {
+                branch: Branch::Main,
+                action: ActionType::Setflag,
+            }]
+        );
+        assert_eq!(warnings[0].to_string(), "Actions: setflag without flags clears every flag on the message");
+    }
+
+    #[test]
+    fn test_shadowed_rules() {
+        let script = converter::text_to_script(
+            r#"# Filter: Spam
+if header :contains "X-Spam" "yes" { discard; stop; }
+
+# Filter: Catch all
+if true { keep; stop; }
+
+# Trailing notes
+
+# Filter: Never
+if header :contains "Subject" "x" { keep; }
+"#,
+            "",
+        );
+        assert_eq!(
+            lint_script(&script),
+            vec![Lint {
+                rule: 3,
+                kind: LintKind::Shadowed { by: 1 }
+            }]
+        );
+        assert_eq!(
+            LintKind::Shadowed { by: 1 }.to_string(),
+            "never runs: filter 2 always matches and stops"
+        );
+
+        // Without the stop, or with a real test, later rules still run
+        let mut script = script;
+        script.rules[1].actions.truncate(1);
+        assert!(lint_script(&script).is_empty());
+        script.rules[1].actions = script.rules[0].actions.clone();
+        script.rules[1].conditions = script.rules[0].conditions.clone();
+        assert!(lint_script(&script).is_empty());
+    }
+
+    #[test]
+    fn test_missing_require() {
+        let script = converter::text_to_script(
+            r#"require "fileinto";
+
+# Filter: Lists
+if header :contains "List-Id" "x" { fileinto "Lists"; }
+
+# Filter: Bounce
+if header :regex "Subject" "^x+$" { reject "No"; }
+"#,
+            "",
+        );
+        let lints = lint_script(&script);
+        assert_eq!(
+            lints,
+            vec![
+                Lint {
+                    rule: 1,
+                    kind: LintKind::MissingRequire("regex".to_string())
+                },
+                Lint {
+                    rule: 1,
+                    kind: LintKind::MissingRequire("reject".to_string())
+                },
+            ]
+        );
+        assert_eq!(lints[0].kind.to_string(), "uses \"regex\" but the script doesn't require it");
+
+        // Raw blocks are read too
+        let script = converter::text_to_script(
+            "if header :value \"gt\" \"X-Priority\" \"3\" { vacation \"Away\"; }",
+            "",
+        );
+        assert!(script.rules[0].raw_block.is_some());
+        let missing: Vec<LintKind> = lint_script(&script).into_iter().map(|l| l.kind).collect();
+        assert_eq!(
+            missing,
+            vec![
+                LintKind::MissingRequire("relational".to_string()),
+                LintKind::MissingRequire("vacation".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_redirect_loop() {
+        let script = converter::text_to_script(
+            r#"# Filter: Forward
+if address :is "to" "Me@example.com" { redirect "me@example.com"; }
+
+# Filter: Elsewhere
+if address :is "to" "me@example.com" { redirect "other@example.com"; }
+"#,
+            "",
+        );
+        assert_eq!(
+            lint_script(&script),
+            vec![Lint {
+                rule: 0,
+                kind: LintKind::RedirectLoop("me@example.com".to_string())
+            }]
+        );
+    }
+}
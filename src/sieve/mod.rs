@@ -2,4 +2,6 @@ pub mod ast;
 pub mod converter;
 pub mod emitter;
 pub mod lexer;
+pub mod lint;
 pub mod parser;
+pub mod templates;
@@ -1,10 +1,29 @@
 /// Recursive descent SIEVE parser.
 ///
-/// Parses tokenized SIEVE scripts into an AST. Unrecognized constructs
-/// are captured as `Command::Raw` for round-trip preservation.
+/// Parses tokenized SIEVE scripts into an AST. `parse_recovering` captures
+/// top-level commands that fail to parse as `Command::Raw` and carries on,
+/// so one broken block doesn't cost the rest of the script.
+use crate::model::rule::size_to_bytes;
 use crate::sieve::ast::*;
-use crate::sieve::lexer::{Token, tokenize};
+use crate::sieve::lexer::{Span, Token, format_error, tokenize};
 
+/// A parse failure at the token with index `pos` (`tokens.len()` for end of input).
+struct ParseError {
+    pos: usize,
+    message: String,
+}
+
+impl ParseError {
+    fn at(pos: usize, message: impl Into<String>) -> Self {
+        Self {
+            pos,
+            message: message.into(),
+        }
+    }
+}
+
+/// Parse a script. Errors are prefixed with the offending token's position,
+/// e.g. `line 12, col 4: Unknown test 'foo'`.
 pub fn parse(input: &str) -> Result<Script, String> {
     if input.trim().is_empty() {
         return Ok(Script { commands: Vec::new() });
@@ -12,28 +31,102 @@ pub fn parse(input: &str) -> Result<Script, String> {
 
     let spans = tokenize(input)?;
     let tokens: Vec<&Token> = spans.iter().map(|s| &s.token).collect();
+    parse_commands(&tokens, None).map_err(|e| {
+        let offset = spans.get(e.pos).map(|s| s.offset).unwrap_or(input.len());
+        format_error(input, offset, &e.message)
+    })
+}
+
+/// Parse a script, keeping each top-level command that fails to parse as
+/// a `Command::Raw` of its text, from its `# Filter:` line (if any) to the
+/// end of the block. Only errors the tokenizer reports, such as an
+/// unterminated string, fail the whole script.
+pub fn parse_recovering(input: &str) -> Result<Script, String> {
+    if input.trim().is_empty() {
+        return Ok(Script { commands: Vec::new() });
+    }
+
+    let spans = tokenize(input)?;
+    let tokens: Vec<&Token> = spans.iter().map(|s| &s.token).collect();
+    let source = Source { input, spans: &spans };
+    parse_commands(&tokens, Some(&source)).map_err(|e| e.message)
+}
+
+/// The text the tokens came from, to cut failed commands out of.
+struct Source<'a> {
+    input: &'a str,
+    spans: &'a [Span],
+}
+
+impl Source<'_> {
+    /// The text of tokens `from..to`.
+    fn text(&self, from: usize, to: usize) -> String {
+        let end = self.spans[to - 1].offset + self.spans[to - 1].len;
+        self.input[self.spans[from].offset..end].to_string()
+    }
+}
+
+/// Parse top-level commands. With a `source`, a command that fails to parse
+/// becomes `Command::Raw` instead of failing the script.
+fn parse_commands(tokens: &[&Token], source: Option<&Source>) -> Result<Script, ParseError> {
     let mut pos = 0;
     let mut commands = Vec::new();
     let mut pending_comment: Option<String> = None;
+    // Token index of the pending `# Filter:` line
+    let mut pending_pos = 0;
+    let mut pending_notes: Vec<String> = Vec::new();
     let mut saw_valid_command = false;
 
     while pos < tokens.len() {
-        match &tokens[pos] {
+        let start = pos;
+        let parsed = match &tokens[pos] {
             Token::Comment(text) => {
-                pending_comment = Some(text.clone());
+                if extract_filter_name(&Some(text.clone())).is_some() {
+                    // A `# Filter:` line not followed by its `if` is kept as-is
+                    flush_pending_comments(&mut commands, &mut pending_comment, &mut pending_notes);
+                    pending_comment = Some(text.clone());
+                    pending_pos = pos;
+                } else if pending_comment.is_some() {
+                    // Comment lines following a `# Filter:` line are the rule's notes
+                    pending_notes.push(text.clone());
+                } else {
+                    // Standalone comments (banners, section dividers) stay in place
+                    commands.push(Command::Comment(text.clone()));
+                }
                 pos += 1;
+                Ok(())
             }
-            Token::BlockComment(_) => {
+            Token::BlockComment(text) => {
+                // A rule someone disabled by commenting it out
+                if let Some(mut if_block) = commented_out_rule(text) {
+                    if if_block.name.is_none() {
+                        if_block.name = extract_filter_name(&pending_comment);
+                        pending_comment = None;
+                        if !pending_notes.is_empty() {
+                            if_block.comment = Some(pending_notes.join("\n"));
+                            pending_notes.clear();
+                        }
+                    }
+                    flush_pending_comments(&mut commands, &mut pending_comment, &mut pending_notes);
+                    commands.push(Command::If(if_block));
+                } else {
+                    // Any other block comment (a license banner, say) is kept
+                    // as comment lines
+                    flush_pending_comments(&mut commands, &mut pending_comment, &mut pending_notes);
+                    commands.extend(block_comment_lines(text).into_iter().map(Command::Comment));
+                }
                 pos += 1;
+                Ok(())
             }
             Token::Identifier(ident) => {
                 let lower = ident.to_lowercase();
                 match lower.as_str() {
                     "require" => {
                         pos += 1;
-                        let exts = parse_require_args(&tokens, &mut pos)?;
-                        commands.push(Command::Require(exts));
-                        saw_valid_command = true;
+                        flush_pending_comments(&mut commands, &mut pending_comment, &mut pending_notes);
+                        let list = matches!(tokens.get(pos), Some(Token::LBracket));
+                        parse_require_args(tokens, &mut pos)
+                            .map(|extensions| commands.push(Command::Require { extensions, list }))
                     }
                     "if" => {
                         pos += 1;
@@ -42,31 +135,67 @@ pub fn parse(input: &str) -> Result<Script, String> {
                             .as_ref()
                             .map(|c| !c.contains("[DISABLED]"))
                             .unwrap_or(true);
-                        pending_comment = None;
-                        let if_block = parse_if_block(&tokens, &mut pos, filter_name, enabled)?;
-                        commands.push(Command::If(if_block));
-                        saw_valid_command = true;
+                        parse_if_block(tokens, &mut pos, filter_name, enabled).map(|mut if_block| {
+                            // The `# Filter:` line and notes are the block's
+                            // only once it parses
+                            pending_comment = None;
+                            if !pending_notes.is_empty() {
+                                if_block.comment = Some(pending_notes.join("\n"));
+                                pending_notes.clear();
+                            }
+                            commands.push(Command::If(if_block));
+                        })
+                    }
+                    "set" => {
+                        pos += 1;
+                        flush_pending_comments(&mut commands, &mut pending_comment, &mut pending_notes);
+                        parse_set_command(tokens, &mut pos).map(|set| commands.push(set))
                     }
                     // Known top-level action commands
                     "keep" | "stop" | "discard" | "fileinto" | "redirect"
-                    | "reject" | "setflag" | "addflag" | "removeflag" => {
-                        pending_comment = None;
-                        let action = parse_action_command(&tokens, &mut pos)?;
-                        commands.push(Command::Action(action));
-                        saw_valid_command = true;
+                    | "reject" | "setflag" | "addflag" | "removeflag" | "vacation"
+                    | "notify" | "addheader" | "deleteheader" => {
+                        flush_pending_comments(&mut commands, &mut pending_comment, &mut pending_notes);
+                        parse_action_command(tokens, &mut pos)
+                            .map(|action| commands.push(Command::Action(action)))
                     }
                     _ => {
                         // Unknown identifier at top level — not valid SIEVE
-                        return Err(format!("Unknown command '{}' at top level", ident));
+                        Err(ParseError::at(
+                            pos,
+                            format!("Unknown command '{ident}' at top level"),
+                        ))
                     }
                 }
             }
-            _ => {
-                return Err(format!("Unexpected token {:?} at top level", tokens[pos]));
+            _ => Err(ParseError::at(
+                pos,
+                format!("Unexpected token {:?} at top level", tokens[pos]),
+            )),
+        };
+
+        match parsed {
+            Ok(()) => {
+                if matches!(tokens[start], Token::Identifier(_)) {
+                    saw_valid_command = true;
+                }
+            }
+            Err(e) => {
+                let Some(source) = source else {
+                    return Err(e);
+                };
+                // Comments not yet written out belong to the broken command
+                let from = if pending_comment.is_some() { pending_pos } else { start };
+                pending_comment = None;
+                pending_notes.clear();
+                pos = command_end(tokens, start);
+                commands.push(Command::Raw(source.text(from, pos)));
             }
         }
     }
 
+    flush_pending_comments(&mut commands, &mut pending_comment, &mut pending_notes);
+
     if !saw_valid_command && !tokens.is_empty() {
         // Only comments/whitespace — not really a valid script, but ok
         // (empty scripts handled above)
@@ -75,6 +204,103 @@ pub fn parse(input: &str) -> Result<Script, String> {
     Ok(Script { commands })
 }
 
+/// The end (exclusive) of the top-level command at `start`: just past its
+/// `;`, or its closing `}` and any `elsif`/`else` after that. A top-level
+/// `if` or `require` ends it early, for a command missing its `;`. A block
+/// that is never closed ends before the next rule's `# Filter:` line or a
+/// `require`, rather than taking the rest of the script.
+fn command_end(tokens: &[&Token], start: usize) -> usize {
+    let mut depth = 0usize;
+    for (i, token) in tokens.iter().enumerate().skip(start) {
+        match token {
+            Token::LBrace => depth += 1,
+            Token::RBrace => {
+                depth = depth.saturating_sub(1);
+                let continued = matches!(
+                    tokens.get(i + 1),
+                    Some(Token::Identifier(next))
+                        if next.eq_ignore_ascii_case("elsif") || next.eq_ignore_ascii_case("else")
+                );
+                if depth == 0 && !continued {
+                    return i + 1;
+                }
+            }
+            Token::Semicolon if depth == 0 => return i + 1,
+            Token::Identifier(ident)
+                if depth == 0
+                    && i > start
+                    && (ident.eq_ignore_ascii_case("if") || ident.eq_ignore_ascii_case("require")) =>
+            {
+                return i;
+            }
+            _ => {}
+        }
+    }
+    (start + 1..tokens.len())
+        .find(|&i| starts_rule(tokens, i))
+        .unwrap_or(tokens.len())
+}
+
+/// Whether a new top-level rule starts at token `i`: a `# Filter:` line
+/// with its `if` after it (past any notes), or a `require`, which can't be
+/// inside a block.
+fn starts_rule(tokens: &[&Token], i: usize) -> bool {
+    match tokens[i] {
+        Token::Comment(text) if extract_filter_name(&Some(text.clone())).is_some() => {
+            matches!(
+                tokens[i + 1..].iter().find(|t| !matches!(t, Token::Comment(_))),
+                Some(Token::Identifier(ident)) if ident.eq_ignore_ascii_case("if")
+            )
+        }
+        Token::Identifier(ident) => ident.eq_ignore_ascii_case("require"),
+        _ => false,
+    }
+}
+
+/// The `if` block inside a `/* ... */` comment holding one rule (with its
+/// `# Filter:` line and notes) and nothing else, as a disabled rule.
+fn commented_out_rule(text: &str) -> Option<IfBlock> {
+    let spans = tokenize(text).ok()?;
+    let tokens: Vec<&Token> = spans.iter().map(|s| &s.token).collect();
+    let script = parse_commands(&tokens, None).ok()?;
+    match script.commands.as_slice() {
+        [Command::If(block)] => Some(IfBlock {
+            enabled: false,
+            commented_out: true,
+            ..block.clone()
+        }),
+        _ => None,
+    }
+}
+
+/// The lines of a `/* ... */` comment's text, without the ` * ` that
+/// usually runs down its left edge.
+fn block_comment_lines(text: &str) -> Vec<String> {
+    let lines: Vec<String> = text
+        .lines()
+        .map(|line| {
+            let line = line.trim();
+            line.strip_prefix('*').unwrap_or(line).trim().to_string()
+        })
+        .collect();
+    let start = lines.iter().position(|l| !l.is_empty()).unwrap_or(lines.len());
+    let end = lines.iter().rposition(|l| !l.is_empty()).map_or(start, |i| i + 1);
+    lines[start..end].to_vec()
+}
+
+/// Emit a `# Filter:` line and its notes as plain comments when no `if`
+/// follows them.
+fn flush_pending_comments(
+    commands: &mut Vec<Command>,
+    pending_comment: &mut Option<String>,
+    pending_notes: &mut Vec<String>,
+) {
+    if let Some(text) = pending_comment.take() {
+        commands.push(Command::Comment(text));
+    }
+    commands.extend(pending_notes.drain(..).map(Command::Comment));
+}
+
 fn extract_filter_name(comment: &Option<String>) -> Option<String> {
     comment.as_ref().and_then(|c| {
         let trimmed = c.trim();
@@ -93,7 +319,7 @@ fn extract_filter_name(comment: &Option<String>) -> Option<String> {
     })
 }
 
-fn parse_require_args(tokens: &[&Token], pos: &mut usize) -> Result<Vec<String>, String> {
+fn parse_require_args(tokens: &[&Token], pos: &mut usize) -> Result<Vec<String>, ParseError> {
     let mut exts = Vec::new();
 
     match tokens.get(*pos) {
@@ -136,7 +362,7 @@ fn parse_if_block(
     pos: &mut usize,
     name: Option<String>,
     enabled: bool,
-) -> Result<IfBlock, String> {
+) -> Result<IfBlock, ParseError> {
     let condition = parse_test_expr(tokens, pos)?;
     let actions = parse_action_block(tokens, pos)?;
     let mut alternatives = Vec::new();
@@ -166,13 +392,15 @@ fn parse_if_block(
     Ok(IfBlock {
         name,
         enabled,
+        commented_out: false,
+        comment: None,
         condition,
         actions,
         alternatives,
     })
 }
 
-fn parse_test_expr(tokens: &[&Token], pos: &mut usize) -> Result<TestExpr, String> {
+fn parse_test_expr(tokens: &[&Token], pos: &mut usize) -> Result<TestExpr, ParseError> {
     match tokens.get(*pos) {
         Some(Token::Identifier(ident)) => {
             let lower = ident.to_lowercase();
@@ -216,6 +444,14 @@ fn parse_test_expr(tokens: &[&Token], pos: &mut usize) -> Result<TestExpr, Strin
                     *pos += 1;
                     parse_body_test(tokens, pos)
                 }
+                "string" => {
+                    *pos += 1;
+                    parse_string_test(tokens, pos)
+                }
+                "hasflag" => {
+                    *pos += 1;
+                    parse_hasflag_test(tokens, pos)
+                }
                 "true" => {
                     *pos += 1;
                     Ok(TestExpr::True)
@@ -224,18 +460,21 @@ fn parse_test_expr(tokens: &[&Token], pos: &mut usize) -> Result<TestExpr, Strin
                     *pos += 1;
                     Ok(TestExpr::False)
                 }
-                _ => Err(format!("Unknown test '{ident}'")),
+                _ => Err(ParseError::at(*pos, format!("Unknown test '{ident}'"))),
             }
         }
-        Some(other) => Err(format!("Expected test expression, got {other:?}")),
-        None => Err("Expected test expression, got end of input".to_string()),
+        Some(other) => Err(ParseError::at(
+            *pos,
+            format!("Expected test expression, got {other:?}"),
+        )),
+        None => Err(ParseError::at(*pos, "Expected test expression, got end of input")),
     }
 }
 
-fn parse_test_list(tokens: &[&Token], pos: &mut usize) -> Result<Vec<TestExpr>, String> {
+fn parse_test_list(tokens: &[&Token], pos: &mut usize) -> Result<Vec<TestExpr>, ParseError> {
     // Expect '('
     if !matches!(tokens.get(*pos), Some(Token::LParen)) {
-        return Err("Expected '(' in test list".to_string());
+        return Err(ParseError::at(*pos, "Expected '(' in test list"));
     }
     *pos += 1;
 
@@ -258,28 +497,63 @@ fn parse_test_list(tokens: &[&Token], pos: &mut usize) -> Result<Vec<TestExpr>,
     Ok(tests)
 }
 
-fn parse_header_test(tokens: &[&Token], pos: &mut usize) -> Result<TestExpr, String> {
-    let mut match_type = ":is".to_string();
+/// Comparator, match type and relational tags shared by the string tests.
+struct MatchTags {
+    match_type: String,
+    comparator: Option<String>,
+    relation: Option<(RelTag, String)>,
+}
 
-    // Parse optional tags
-    while let Some(Token::Tag(tag)) = tokens.get(*pos) {
+impl Default for MatchTags {
+    fn default() -> Self {
+        Self {
+            match_type: ":is".to_string(),
+            comparator: None,
+            relation: None,
+        }
+    }
+}
+
+impl MatchTags {
+    /// Consume the tag at `*pos`, with its argument where it takes one.
+    fn take(&mut self, tokens: &[&Token], pos: &mut usize, tag: &str) -> Result<(), ParseError> {
+        *pos += 1;
         if tag == ":comparator" {
+            self.comparator = Some(expect_tag_string(tokens, pos, tag)?);
+        } else if let Some(rel) = RelTag::from_sieve(tag) {
+            self.relation = Some((rel, expect_tag_string(tokens, pos, tag)?));
+        } else {
+            self.match_type = tag.to_string();
+        }
+        Ok(())
+    }
+}
+
+fn expect_tag_string(tokens: &[&Token], pos: &mut usize, tag: &str) -> Result<String, ParseError> {
+    match tokens.get(*pos) {
+        Some(Token::QuotedString(s)) => {
             *pos += 1;
-            // Skip comparator argument
-            if matches!(tokens.get(*pos), Some(Token::QuotedString(_))) {
-                *pos += 1;
-            }
-            continue;
+            Ok(s.clone())
         }
-        match_type = tag.clone();
-        *pos += 1;
+        _ => Err(ParseError::at(*pos, format!("Expected string after '{tag}'"))),
+    }
+}
+
+fn parse_header_test(tokens: &[&Token], pos: &mut usize) -> Result<TestExpr, ParseError> {
+    let mut tags = MatchTags::default();
+
+    // Parse optional tags
+    while let Some(Token::Tag(tag)) = tokens.get(*pos) {
+        tags.take(tokens, pos, tag)?;
     }
 
     let header_names = parse_string_or_list(tokens, pos)?;
     let keys = parse_string_or_list(tokens, pos)?;
 
     Ok(TestExpr::Header {
-        match_type,
+        match_type: tags.match_type,
+        comparator: tags.comparator,
+        relation: tags.relation,
         header_names,
         keys,
     })
@@ -289,28 +563,24 @@ fn parse_address_test(
     tokens: &[&Token],
     pos: &mut usize,
     is_envelope: bool,
-) -> Result<TestExpr, String> {
-    let mut match_type = ":is".to_string();
+) -> Result<TestExpr, ParseError> {
+    let mut tags = MatchTags::default();
     let mut address_part: Option<String> = None;
 
     // Parse optional tags (match_type and address_part can appear in any order)
     while let Some(Token::Tag(tag)) = tokens.get(*pos) {
-        if tag == ":comparator" {
-            *pos += 1;
-            if matches!(tokens.get(*pos), Some(Token::QuotedString(_))) {
-                *pos += 1;
-            }
-            continue;
-        }
         match tag.as_str() {
-            ":all" | ":localpart" | ":domain" => {
-                address_part = Some(tag.clone());
+            // `:all` is the default; leaving it out keeps both spellings
+            // the same test
+            ":all" => {
+                address_part = None;
                 *pos += 1;
             }
-            _ => {
-                match_type = tag.clone();
+            ":localpart" | ":domain" => {
+                address_part = Some(tag.clone());
                 *pos += 1;
             }
+            _ => tags.take(tokens, pos, tag)?,
         }
     }
 
@@ -320,71 +590,160 @@ fn parse_address_test(
     if is_envelope {
         Ok(TestExpr::Envelope {
             address_part,
-            match_type,
+            match_type: tags.match_type,
+            comparator: tags.comparator,
+            relation: tags.relation,
             header_names,
             keys,
         })
     } else {
         Ok(TestExpr::Address {
             address_part,
-            match_type,
+            match_type: tags.match_type,
+            comparator: tags.comparator,
+            relation: tags.relation,
             header_names,
             keys,
         })
     }
 }
 
-fn parse_size_test(tokens: &[&Token], pos: &mut usize) -> Result<TestExpr, String> {
+fn parse_size_test(tokens: &[&Token], pos: &mut usize) -> Result<TestExpr, ParseError> {
+    // RFC 5228 makes the comparator mandatory; without one `:over` is the
+    // likelier intent
     let mut comparator = ":over".to_string();
 
     if let Some(Token::Tag(tag)) = tokens.get(*pos) {
+        if tag != ":over" && tag != ":under" {
+            return Err(ParseError::at(*pos, format!("Expected :over or :under, found '{tag}'")));
+        }
         comparator = tag.clone();
         *pos += 1;
     }
 
+    // No made-up limit: `size :over 0` would match every message
     let limit = match tokens.get(*pos) {
-        Some(Token::Number(n)) => {
-            let n = n.clone();
-            *pos += 1;
-            n
-        }
-        Some(Token::QuotedString(s)) => {
-            let s = s.clone();
-            *pos += 1;
-            s
-        }
-        _ => "0".to_string(),
+        Some(Token::Number(n)) => n.clone(),
+        // Not RFC SIEVE, but some scripts quote the limit
+        Some(Token::QuotedString(s)) if size_to_bytes(s).is_some() => s.clone(),
+        _ => return Err(ParseError::at(*pos, "size needs a limit such as 100K")),
     };
+    *pos += 1;
 
     Ok(TestExpr::Size { comparator, limit })
 }
 
-fn parse_exists_test(tokens: &[&Token], pos: &mut usize) -> Result<TestExpr, String> {
+fn parse_exists_test(tokens: &[&Token], pos: &mut usize) -> Result<TestExpr, ParseError> {
     let header_names = parse_string_or_list(tokens, pos)?;
     Ok(TestExpr::Exists { header_names })
 }
 
-fn parse_body_test(tokens: &[&Token], pos: &mut usize) -> Result<TestExpr, String> {
-    let mut match_type = ":is".to_string();
+fn parse_body_test(tokens: &[&Token], pos: &mut usize) -> Result<TestExpr, ParseError> {
+    let mut tags = MatchTags::default();
+    let mut transform = None;
+    let mut content_types = Vec::new();
 
     while let Some(Token::Tag(tag)) = tokens.get(*pos) {
-        if tag == ":comparator" {
-            *pos += 1;
-            if matches!(tokens.get(*pos), Some(Token::QuotedString(_))) {
+        match tag.as_str() {
+            ":raw" | ":text" => {
+                transform = Some(tag.clone());
                 *pos += 1;
             }
-            continue;
+            ":content" => {
+                transform = Some(tag.clone());
+                *pos += 1;
+                content_types = parse_string_or_list(tokens, pos)?;
+            }
+            _ => tags.take(tokens, pos, tag)?,
         }
-        match_type = tag.clone();
-        *pos += 1;
     }
 
     let keys = parse_string_or_list(tokens, pos)?;
 
-    Ok(TestExpr::Body { match_type, keys })
+    Ok(TestExpr::Body {
+        match_type: tags.match_type,
+        comparator: tags.comparator,
+        relation: tags.relation,
+        transform,
+        content_types,
+        keys,
+    })
+}
+
+fn parse_string_test(tokens: &[&Token], pos: &mut usize) -> Result<TestExpr, ParseError> {
+    let mut tags = MatchTags::default();
+
+    while let Some(Token::Tag(tag)) = tokens.get(*pos) {
+        tags.take(tokens, pos, tag)?;
+    }
+
+    let source = parse_string_or_list(tokens, pos)?;
+    let keys = parse_string_or_list(tokens, pos)?;
+
+    Ok(TestExpr::StringTest {
+        match_type: tags.match_type,
+        comparator: tags.comparator,
+        relation: tags.relation,
+        source,
+        keys,
+    })
+}
+
+/// `hasflag [MATCH] [<variables>] <flags>` after the `hasflag`: with two
+/// lists the first names the variables.
+fn parse_hasflag_test(tokens: &[&Token], pos: &mut usize) -> Result<TestExpr, ParseError> {
+    let mut tags = MatchTags::default();
+
+    while let Some(Token::Tag(tag)) = tokens.get(*pos) {
+        tags.take(tokens, pos, tag)?;
+    }
+
+    let first = parse_string_or_list(tokens, pos)?;
+    let (variables, flags) = match tokens.get(*pos) {
+        Some(Token::QuotedString(_) | Token::LBracket) => (first, parse_string_or_list(tokens, pos)?),
+        _ => (Vec::new(), first),
+    };
+
+    Ok(TestExpr::Hasflag {
+        match_type: tags.match_type,
+        comparator: tags.comparator,
+        relation: tags.relation,
+        variables,
+        flags,
+    })
 }
 
-fn parse_string_or_list(tokens: &[&Token], pos: &mut usize) -> Result<Vec<String>, String> {
+/// `set [MODIFIER...] <name: string> <value: string>;` after the `set`.
+fn parse_set_command(tokens: &[&Token], pos: &mut usize) -> Result<Command, ParseError> {
+    let mut modifiers = Vec::new();
+    while let Some(Token::Tag(tag)) = tokens.get(*pos) {
+        modifiers.push(tag.clone());
+        *pos += 1;
+    }
+    let mut strings = Vec::new();
+    for what in ["variable name", "value"] {
+        match tokens.get(*pos) {
+            Some(Token::QuotedString(s)) | Some(Token::MultiLineString(s)) => {
+                strings.push(s.clone());
+                *pos += 1;
+            }
+            _ => return Err(ParseError::at(*pos, format!("Expected {what} in 'set'"))),
+        }
+    }
+    if !matches!(tokens.get(*pos), Some(Token::Semicolon)) {
+        return Err(ParseError::at(*pos, "Expected ';' after 'set'"));
+    }
+    *pos += 1;
+    let value = strings.pop().unwrap_or_default();
+    let name = strings.pop().unwrap_or_default();
+    Ok(Command::Set {
+        name,
+        value,
+        modifiers,
+    })
+}
+
+fn parse_string_or_list(tokens: &[&Token], pos: &mut usize) -> Result<Vec<String>, ParseError> {
     match tokens.get(*pos) {
         Some(Token::QuotedString(s)) => {
             let s = s.clone();
@@ -416,9 +775,9 @@ fn parse_string_or_list(tokens: &[&Token], pos: &mut usize) -> Result<Vec<String
     }
 }
 
-fn parse_action_block(tokens: &[&Token], pos: &mut usize) -> Result<Vec<ActionCommand>, String> {
+fn parse_action_block(tokens: &[&Token], pos: &mut usize) -> Result<Vec<ActionCommand>, ParseError> {
     if !matches!(tokens.get(*pos), Some(Token::LBrace)) {
-        return Err("Expected '{' to start action block".to_string());
+        return Err(ParseError::at(*pos, "Expected '{' to start action block"));
     }
     *pos += 1;
 
@@ -434,7 +793,7 @@ fn parse_action_block(tokens: &[&Token], pos: &mut usize) -> Result<Vec<ActionCo
             break;
         }
         if *pos >= tokens.len() {
-            return Err("Unexpected end of input in action block".to_string());
+            return Err(ParseError::at(*pos, "Unexpected end of input in action block"));
         }
         actions.push(parse_action_command(tokens, pos)?);
     }
@@ -442,15 +801,17 @@ fn parse_action_block(tokens: &[&Token], pos: &mut usize) -> Result<Vec<ActionCo
     Ok(actions)
 }
 
-fn parse_action_command(tokens: &[&Token], pos: &mut usize) -> Result<ActionCommand, String> {
+fn parse_action_command(tokens: &[&Token], pos: &mut usize) -> Result<ActionCommand, ParseError> {
     let name = match tokens.get(*pos) {
         Some(Token::Identifier(s)) => {
             let s = s.clone();
             *pos += 1;
             s
         }
-        Some(other) => return Err(format!("Expected action name, got {other:?}")),
-        None => return Err("Expected action name, got end of input".to_string()),
+        Some(other) => {
+            return Err(ParseError::at(*pos, format!("Expected action name, got {other:?}")))
+        }
+        None => return Err(ParseError::at(*pos, "Expected action name, got end of input")),
     };
 
     let mut arguments = Vec::new();
@@ -466,6 +827,10 @@ fn parse_action_command(tokens: &[&Token], pos: &mut usize) -> Result<ActionComm
                 arguments.push(Argument::QuotedString(s.clone()));
                 *pos += 1;
             }
+            Some(Token::MultiLineString(s)) => {
+                arguments.push(Argument::MultiLine(s.clone()));
+                *pos += 1;
+            }
             Some(Token::Number(n)) => {
                 arguments.push(Argument::Number(n.clone()));
                 *pos += 1;
@@ -517,7 +882,10 @@ mod tests {
         let script = parse("require \"fileinto\";").unwrap();
         assert_eq!(script.commands.len(), 1);
         match &script.commands[0] {
-            Command::Require(exts) => assert_eq!(exts, &["fileinto"]),
+            Command::Require { extensions, list } => {
+                assert_eq!(extensions, &["fileinto"]);
+                assert!(!list);
+            }
             _ => panic!("Expected Require"),
         }
     }
@@ -526,7 +894,10 @@ mod tests {
     fn test_parse_require_list() {
         let script = parse("require [\"fileinto\", \"reject\"];").unwrap();
         match &script.commands[0] {
-            Command::Require(exts) => assert_eq!(exts, &["fileinto", "reject"]),
+            Command::Require { extensions, list } => {
+                assert_eq!(extensions, &["fileinto", "reject"]);
+                assert!(list);
+            }
             _ => panic!("Expected Require"),
         }
     }
@@ -550,6 +921,59 @@ if header :contains "Subject" "SPAM" {
         }
     }
 
+    #[test]
+    fn test_parse_filter_comment() {
+        let input = r#"
+# Filter: Newsletters [DISABLED]
+# Keeps the inbox readable
+if header :contains "List-Id" "news" {
+    fileinto "News";
+}
+"#;
+        let script = parse(input).unwrap();
+        let Command::If(block) = &script.commands[0] else {
+            panic!("Expected If");
+        };
+        assert_eq!(block.name.as_deref(), Some("Newsletters"));
+        assert!(!block.enabled);
+        assert_eq!(block.comment.as_deref(), Some("Keeps the inbox readable"));
+    }
+
+    #[test]
+    fn test_parse_commented_out_rule() {
+        let input = r#"
+/*
+# Filter: Old newsletters
+if header :contains "List-Id" "old" {
+    discard;
+}
+*/
+# Filter: Archive
+/* if header :contains "Subject" "[archive]" { fileinto "Archive"; } */
+/* Just a note, not a rule */
+"#;
+        let script = parse(input).unwrap();
+        assert_eq!(script.commands.len(), 3);
+        let Command::If(block) = &script.commands[0] else {
+            panic!("Expected If");
+        };
+        assert_eq!(block.name.as_deref(), Some("Old newsletters"));
+        assert!(!block.enabled);
+        assert!(block.commented_out);
+        assert_eq!(block.actions[0].name, "discard");
+
+        // The name may sit outside the comment
+        let Command::If(block) = &script.commands[1] else {
+            panic!("Expected If");
+        };
+        assert_eq!(block.name.as_deref(), Some("Archive"));
+        assert!(!block.enabled);
+        assert!(block.commented_out);
+
+        // Anything else is a comment
+        assert_eq!(script.commands[2], Command::Comment("Just a note, not a rule".to_string()));
+    }
+
     #[test]
     fn test_parse_allof() {
         let input = r#"
@@ -581,6 +1005,7 @@ if allof (header :is "From" "boss@example.com", header :contains "Subject" "urge
                     match_type,
                     header_names,
                     keys,
+                    ..
                 } => {
                     assert_eq!(address_part.as_deref(), Some(":domain"));
                     assert_eq!(match_type, ":is");
@@ -591,4 +1016,174 @@ if allof (header :is "From" "boss@example.com", header :contains "Subject" "urge
             }
         }
     }
+
+    #[test]
+    fn test_parse_reject_multiline() {
+        let input = "if true {\n    reject text:\nGoing away.\nPlease resend later.\n.\n;\n}\n";
+        let script = parse(input).unwrap();
+        let Command::If(block) = &script.commands[0] else {
+            panic!("Expected If");
+        };
+        assert_eq!(block.actions[0].name, "reject");
+        assert_eq!(
+            block.actions[0].arguments,
+            vec![Argument::MultiLine("Going away.\nPlease resend later.\n".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_parse_relational_count() {
+        let input = r#"if address :count "ge" :comparator "i;ascii-numeric" ["To", "Cc"] "5" { discard; }"#;
+        let script = parse(input).unwrap();
+        let Command::If(block) = &script.commands[0] else {
+            panic!("Expected If");
+        };
+        match &block.condition {
+            TestExpr::Address {
+                comparator,
+                relation,
+                header_names,
+                keys,
+                ..
+            } => {
+                assert_eq!(comparator.as_deref(), Some("i;ascii-numeric"));
+                assert_eq!(relation, &Some((RelTag::Count, "ge".to_string())));
+                assert_eq!(header_names, &["To", "Cc"]);
+                assert_eq!(keys, &["5"]);
+            }
+            _ => panic!("Expected Address test"),
+        }
+    }
+
+    #[test]
+    fn test_parse_set_and_string_test() {
+        let input = r#"set :lower :upperfirst "who" "${1}"; if string :is "${who}" "Alice" { keep; }"#;
+        let script = parse(input).unwrap();
+        assert_eq!(
+            script.commands[0],
+            Command::Set {
+                name: "who".to_string(),
+                value: "${1}".to_string(),
+                modifiers: vec![":lower".to_string(), ":upperfirst".to_string()],
+            }
+        );
+        let Command::If(block) = &script.commands[1] else {
+            panic!("Expected If");
+        };
+        match &block.condition {
+            TestExpr::StringTest {
+                match_type, source, keys, ..
+            } => {
+                assert_eq!(match_type, ":is");
+                assert_eq!(source, &["${who}"]);
+                assert_eq!(keys, &["Alice"]);
+            }
+            _ => panic!("Expected string test"),
+        }
+    }
+
+    #[test]
+    fn test_parse_hasflag() {
+        let script = parse("if hasflag :contains \"\\\\Seen\" { keep; }\nif hasflag :is \"mine\" [\"a\", \"b\"] { keep; }").unwrap();
+        let flags_of = |cmd: &Command| match cmd {
+            Command::If(IfBlock {
+                condition: TestExpr::Hasflag { variables, flags, .. },
+                ..
+            }) => (variables.clone(), flags.clone()),
+            _ => panic!("Expected hasflag"),
+        };
+        assert_eq!(flags_of(&script.commands[0]), (vec![], vec!["\\Seen".to_string()]));
+        assert_eq!(
+            flags_of(&script.commands[1]),
+            (vec!["mine".to_string()], vec!["a".to_string(), "b".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_error_position_missing_brace() {
+        let input = "require \"fileinto\";\n\nif header :is \"From\" \"boss@example.com\"\n    fileinto \"Boss\";\n}\n";
+        let err = parse(input).unwrap_err();
+        assert_eq!(err, "line 4, col 5: Expected '{' to start action block");
+    }
+
+    #[test]
+    fn test_error_position_unknown_test() {
+        let err = parse("if\n  foo \"x\" { keep; }").unwrap_err();
+        assert_eq!(err, "line 2, col 3: Unknown test 'foo'");
+    }
+
+    #[test]
+    fn test_parse_size() {
+        // The comparator defaults to :over
+        let script = parse("if size 100 { discard; }").unwrap();
+        match &script.commands[0] {
+            Command::If(block) => assert_eq!(
+                block.condition,
+                TestExpr::Size {
+                    comparator: ":over".to_string(),
+                    limit: "100".to_string()
+                }
+            ),
+            _ => panic!("Expected if block"),
+        }
+
+        // A missing or bad limit is an error, not `0`
+        let err = parse("if size :over { discard; }").unwrap_err();
+        assert_eq!(err, "line 1, col 15: size needs a limit such as 100K");
+        assert!(parse("if size :under \"big\" { discard; }").is_err());
+        assert!(parse("if size :under \"10K\" { discard; }").is_ok());
+        let err = parse("if size :is 100 { discard; }").unwrap_err();
+        assert_eq!(err, "line 1, col 9: Expected :over or :under, found ':is'");
+    }
+
+    #[test]
+    fn test_parse_recovering() {
+        let input = "if header :is \"a\" \"b\" { keep; }\n\
+                     # Filter: Broken\n# its notes\n\
+                     if hedaer :is \"a\" \"b\" { fileinto \"x\"; } else { stop; }\n\
+                     filinto \"y\"\n\
+                     if true { discard; }\n\
+                     }\n\
+                     stop;\n";
+        assert!(parse(input).is_err());
+        let script = parse_recovering(input).unwrap();
+        let commands = &script.commands;
+        assert_eq!(commands.len(), 6);
+        assert!(matches!(&commands[0], Command::If(_)));
+        // From the `# Filter:` line through the `else` block
+        assert_eq!(
+            commands[1],
+            Command::Raw(
+                "# Filter: Broken\n# its notes\nif hedaer :is \"a\" \"b\" { fileinto \"x\"; } else { stop; }"
+                    .to_string()
+            )
+        );
+        // Without a `;` it ends at the next `if`
+        assert_eq!(commands[2], Command::Raw("filinto \"y\"".to_string()));
+        assert!(matches!(&commands[3], Command::If(block) if block.condition == TestExpr::True));
+        assert_eq!(commands[4], Command::Raw("}".to_string()));
+        assert!(matches!(&commands[5], Command::Action(a) if a.name == "stop"));
+
+        // A block missing its `}` ends before the next rule
+        let input = "# Filter: A\nif true { keep; }\n\
+                     # Filter: B\nif header :is \"a\" \"b\" {\n    fileinto \"x\";\n\n\
+                     # Filter: C\n# notes\nif true { discard; }\n\
+                     if true { stop; }\n";
+        let script = parse_recovering(input).unwrap();
+        let commands = &script.commands;
+        assert_eq!(commands.len(), 4, "{commands:?}");
+        assert!(matches!(&commands[0], Command::If(block) if block.name.as_deref() == Some("A")));
+        assert_eq!(
+            commands[1],
+            Command::Raw("# Filter: B\nif header :is \"a\" \"b\" {\n    fileinto \"x\";".to_string())
+        );
+        assert!(matches!(&commands[2], Command::If(block)
+            if block.name.as_deref() == Some("C") && block.comment.as_deref() == Some("notes")));
+        assert!(matches!(&commands[3], Command::If(block) if block.name.is_none()));
+
+        // Valid scripts parse the same either way
+        let valid = "require \"fileinto\";\n# Filter: A\nif true { fileinto \"A\"; }\n";
+        assert_eq!(parse_recovering(valid), parse(valid));
+        assert!(parse_recovering("if header :is \"a").is_err());
+    }
 }
@@ -0,0 +1,143 @@
+/// Prebuilt filters for common SIEVE idioms.
+///
+/// Each template builds a complete rule with placeholder values for the user
+/// to replace; they only use tests and actions the visual editor can show.
+use std::fmt;
+
+use crate::model::enums::{ActionType, AddressPartType, ConditionTest, MatchType, SizeComparator};
+use crate::model::rule::{Action, Condition, SieveRule};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Template {
+    FileBySenderDomain,
+    MoveMailingList,
+    FlagLargeAttachments,
+    VacationResponder,
+    RejectSender,
+}
+
+impl Template {
+    pub const ALL: &'static [Template] = &[
+        Self::FileBySenderDomain,
+        Self::MoveMailingList,
+        Self::FlagLargeAttachments,
+        Self::VacationResponder,
+        Self::RejectSender,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::FileBySenderDomain => "File by sender domain",
+            Self::MoveMailingList => "Move mailing list",
+            Self::FlagLargeAttachments => "Flag large attachments",
+            Self::VacationResponder => "Vacation responder",
+            Self::RejectSender => "Reject sender",
+        }
+    }
+
+    pub fn build(&self) -> SieveRule {
+        let (conditions, actions) = match self {
+            Self::FileBySenderDomain => (
+                vec![Condition {
+                    test_type: ConditionTest::Address,
+                    keys: vec!["example.com".to_string()],
+                    match_type: MatchType::Is,
+                    address_part: AddressPartType::Domain,
+                    ..Default::default()
+                }],
+                vec![Action {
+                    action_type: ActionType::Fileinto,
+                    argument: "Example".to_string(),
+                    ..Default::default()
+                }],
+            ),
+            Self::MoveMailingList => (
+                vec![Condition {
+                    header_names: vec!["List-Id".to_string()],
+                    keys: vec!["list.example.com".to_string()],
+                    ..Default::default()
+                }],
+                vec![Action {
+                    action_type: ActionType::Fileinto,
+                    argument: "Lists".to_string(),
+                    ..Default::default()
+                }],
+            ),
+            Self::FlagLargeAttachments => (
+                vec![Condition {
+                    test_type: ConditionTest::Size,
+                    size_comparator: SizeComparator::Over,
+                    size_value: "5M".to_string(),
+                    ..Default::default()
+                }],
+                vec![Action {
+                    action_type: ActionType::Addflag,
+                    flags: vec!["\\Flagged".to_string()],
+                    ..Default::default()
+                }],
+            ),
+            // No conditions: `if true`, answering every message
+            Self::VacationResponder => (
+                Vec::new(),
+                vec![Action {
+                    action_type: ActionType::Vacation,
+                    argument: "I'm away and will reply when I'm back.".to_string(),
+                    days: Some(7),
+                    subject: Some("Out of office".to_string()),
+                    ..Default::default()
+                }],
+            ),
+            Self::RejectSender => (
+                vec![Condition {
+                    test_type: ConditionTest::Address,
+                    keys: vec!["sender@example.com".to_string()],
+                    match_type: MatchType::Is,
+                    ..Default::default()
+                }],
+                vec![Action {
+                    action_type: ActionType::Reject,
+                    argument: "Your mail was not accepted.".to_string(),
+                    ..Default::default()
+                }],
+            ),
+        };
+        SieveRule {
+            name: self.name().to_string(),
+            conditions,
+            actions,
+            ..Default::default()
+        }
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::script::SieveScript;
+    use crate::sieve::emitter::EmitOptions;
+    use crate::sieve::{converter, parser};
+
+    #[test]
+    fn test_templates_emit_valid_sieve() {
+        for template in Template::ALL {
+            let script = SieveScript {
+                rules: vec![template.build()],
+                ..Default::default()
+            };
+            let text = converter::script_to_text(&script, &EmitOptions::default());
+            assert!(parser::parse(&text).is_ok(), "{template}: {text}");
+
+            // And read back into the same editable rule
+            let parsed = converter::text_to_script(&text, "");
+            assert_eq!(parsed.rules.len(), 1, "{template}");
+            assert!(parsed.rules[0].raw_block.is_none(), "{template}: {text}");
+            assert_eq!(parsed.rules[0], template.build(), "{template}");
+        }
+    }
+}
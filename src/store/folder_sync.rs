@@ -0,0 +1,264 @@
+/// Comparing a profile's scripts folder with the scripts on the server.
+///
+/// The folder uses the layout of an export: one `.siv` file per script and
+/// a manifest with their server names. The manifest also records which
+/// scripts the last sync left on both sides, which tells a script new on
+/// one side apart from one deleted on the other.
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+use crate::store::script_io::{self, Manifest, ManifestEntry};
+use crate::util::diff::{self, LineChange};
+
+/// A script file in the folder and the server name it goes under.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalScript {
+    pub name: String,
+    pub file: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncStatus {
+    Same,
+    /// On both sides with different text; the lines the folder's copy
+    /// adds to and removes from the server's
+    Changed { added: usize, removed: usize },
+    /// Only in the folder; `synced` if the last sync saw it on the server
+    /// too, so it has been deleted there since
+    LocalOnly { synced: bool },
+    /// Only on the server; `synced` if the last sync left a file for it,
+    /// so the file has been deleted since
+    ServerOnly { synced: bool },
+}
+
+impl SyncStatus {
+    /// Whether the folder's copy can be uploaded.
+    pub fn can_push(self) -> bool {
+        matches!(self, Self::Changed { .. } | Self::LocalOnly { .. })
+    }
+
+    /// Whether the server's copy can be written to the folder.
+    pub fn can_pull(self) -> bool {
+        matches!(self, Self::Changed { .. } | Self::ServerOnly { .. })
+    }
+}
+
+impl fmt::Display for SyncStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Same => f.write_str("in sync"),
+            Self::Changed { added, removed } => write!(f, "changed (+{added} -{removed})"),
+            Self::LocalOnly { synced: false } => f.write_str("new in folder"),
+            Self::LocalOnly { synced: true } => f.write_str("deleted on server"),
+            Self::ServerOnly { synced: false } => f.write_str("new on server"),
+            Self::ServerOnly { synced: true } => f.write_str("deleted from folder"),
+        }
+    }
+}
+
+/// A script found on either side. `file` is its file in the folder, or the
+/// one a pull would write.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncEntry {
+    pub name: String,
+    pub file: String,
+    pub status: SyncStatus,
+}
+
+/// The scripts in `dir`, named as `script_io::discover_scripts` names them.
+pub fn load_folder(dir: &Path) -> Result<Vec<LocalScript>, std::io::Error> {
+    script_io::discover_scripts(dir)?
+        .into_iter()
+        .map(|entry| {
+            let content = script_io::load_script(&entry.path)?;
+            let file = entry
+                .path
+                .file_name()
+                .map(|f| f.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            Ok(LocalScript {
+                name: entry.name,
+                file,
+                content,
+            })
+        })
+        .collect()
+}
+
+/// Compare the folder's scripts with the server's `(name, content)` pairs,
+/// sorted by name. `recorded` is the folder's manifest.
+pub fn compare(local: &[LocalScript], server: &[(String, String)], recorded: &Manifest) -> Vec<SyncEntry> {
+    let synced = |name: &str| recorded.scripts.iter().any(|e| e.name == name);
+    let mut taken: HashSet<String> = local.iter().map(|l| l.file.to_lowercase()).collect();
+    let mut entries = Vec::new();
+
+    for script in local {
+        let status = match server.iter().find(|(name, _)| *name == script.name) {
+            Some((_, content)) if same_text(content, &script.content) => SyncStatus::Same,
+            Some((_, content)) => changed(content, &script.content),
+            None => SyncStatus::LocalOnly {
+                synced: synced(&script.name),
+            },
+        };
+        entries.push(SyncEntry {
+            name: script.name.clone(),
+            file: script.file.clone(),
+            status,
+        });
+    }
+    for (name, _) in server {
+        if local.iter().any(|l| l.name == *name) {
+            continue;
+        }
+        let stem = script_io::safe_file_stem(name);
+        let mut file = format!("{stem}.siv");
+        let mut n = 2;
+        while !taken.insert(file.to_lowercase()) {
+            file = format!("{stem}-{n}.siv");
+            n += 1;
+        }
+        entries.push(SyncEntry {
+            name: name.clone(),
+            file,
+            status: SyncStatus::ServerOnly {
+                synced: synced(name),
+            },
+        });
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    entries
+}
+
+/// The manifest to write after a sync: the scripts now on both sides.
+/// `active` tells whether a script is the server's active one.
+pub fn synced_manifest(entries: &[SyncEntry], active: impl Fn(&str) -> bool) -> Manifest {
+    let scripts = entries
+        .iter()
+        .filter(|e| matches!(e.status, SyncStatus::Same | SyncStatus::Changed { .. }))
+        .map(|e| ManifestEntry {
+            name: e.name.clone(),
+            file: e.file.clone(),
+            active: active(&e.name),
+        })
+        .collect();
+    Manifest { scripts }
+}
+
+fn changed(server: &str, local: &str) -> SyncStatus {
+    let changes = diff::diff_lines(&server.replace("\r\n", "\n"), &local.replace("\r\n", "\n"));
+    let count = |added: bool| {
+        changes
+            .iter()
+            .filter(|c| match c {
+                LineChange::Added(_) => added,
+                LineChange::Removed(_) => !added,
+                LineChange::Unchanged(_) => false,
+            })
+            .count()
+    };
+    SyncStatus::Changed {
+        added: count(true),
+        removed: count(false),
+    }
+}
+
+/// Servers hand scripts back with CRLF line ends; those and trailing
+/// blank lines don't count as changes.
+fn same_text(a: &str, b: &str) -> bool {
+    a.replace("\r\n", "\n").trim_end() == b.replace("\r\n", "\n").trim_end()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(name: &str, file: &str, content: &str) -> LocalScript {
+        LocalScript {
+            name: name.to_string(),
+            file: file.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    fn server(name: &str, content: &str) -> (String, String) {
+        (name.to_string(), content.to_string())
+    }
+
+    fn status_of(entries: &[SyncEntry], name: &str) -> SyncStatus {
+        entries.iter().find(|e| e.name == name).unwrap().status
+    }
+
+    #[test]
+    fn test_compare() {
+        let local = vec![
+            local("main", "main.siv", "keep;\n"),
+            local("spam", "spam.siv", "discard;\n"),
+            local("draft", "draft.siv", "stop;\n"),
+        ];
+        let server = vec![
+            server("main", "keep;\r\n"),
+            server("spam", "fileinto \"Junk\";\r\n"),
+            server("vacation", "vacation \"away\";\r\n"),
+        ];
+        let entries = compare(&local, &server, &Manifest::default());
+        let names: Vec<_> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["draft", "main", "spam", "vacation"]);
+        // Line ends don't count
+        assert_eq!(status_of(&entries, "main"), SyncStatus::Same);
+        assert_eq!(status_of(&entries, "spam"), SyncStatus::Changed { added: 1, removed: 1 });
+        assert_eq!(status_of(&entries, "draft"), SyncStatus::LocalOnly { synced: false });
+        assert_eq!(status_of(&entries, "vacation"), SyncStatus::ServerOnly { synced: false });
+        assert_eq!(entries[3].file, "vacation.siv");
+
+        // A script the last sync left on both sides was deleted on one
+        let recorded = synced_manifest(
+            &[
+                SyncEntry {
+                    name: "draft".to_string(),
+                    file: "draft.siv".to_string(),
+                    status: SyncStatus::Same,
+                },
+                SyncEntry {
+                    name: "vacation".to_string(),
+                    file: "vacation.siv".to_string(),
+                    status: SyncStatus::Changed { added: 1, removed: 0 },
+                },
+                SyncEntry {
+                    name: "other".to_string(),
+                    file: "other.siv".to_string(),
+                    status: SyncStatus::LocalOnly { synced: false },
+                },
+            ],
+            |name| name == "draft",
+        );
+        assert_eq!(recorded.scripts.len(), 2);
+        assert!(recorded.scripts[0].active);
+        let entries = compare(&local, &server, &recorded);
+        assert_eq!(status_of(&entries, "draft"), SyncStatus::LocalOnly { synced: true });
+        assert_eq!(status_of(&entries, "vacation"), SyncStatus::ServerOnly { synced: true });
+        assert_eq!(SyncStatus::LocalOnly { synced: true }.to_string(), "deleted on server");
+    }
+
+    #[test]
+    fn test_pulled_file_names_are_unique() {
+        let local = vec![local("a/b", "a_b.siv", "keep;")];
+        let server = vec![server("a/b", "keep;"), server("a:b", "stop;"), server("A_B", "stop;")];
+        let entries = compare(&local, &server, &Manifest::default());
+        let files: Vec<_> = entries.iter().map(|e| e.file.as_str()).collect();
+        assert_eq!(files, vec!["A_B-3.siv", "a_b.siv", "a_b-2.siv"]);
+    }
+
+    #[test]
+    fn test_push_and_pull() {
+        let changed = SyncStatus::Changed { added: 2, removed: 0 };
+        assert!(changed.can_push() && changed.can_pull());
+        assert_eq!(changed.to_string(), "changed (+2 -0)");
+        assert!(SyncStatus::LocalOnly { synced: true }.can_push());
+        assert!(!SyncStatus::LocalOnly { synced: false }.can_pull());
+        assert!(SyncStatus::ServerOnly { synced: false }.can_pull());
+        assert!(!SyncStatus::ServerOnly { synced: true }.can_push());
+        assert!(!SyncStatus::Same.can_push() && !SyncStatus::Same.can_pull());
+    }
+}
@@ -1,2 +1,6 @@
+pub mod folder_sync;
+pub mod password_source;
 pub mod profile_store;
 pub mod script_io;
+pub mod secret_store;
+pub mod settings_store;
@@ -0,0 +1,91 @@
+/// Password lookup for headless runs: a password file, the
+/// `SIEVERS_PASSWORD` environment variable, or the OS keyring.
+use std::path::Path;
+
+use zeroize::{Zeroize, Zeroizing};
+
+/// The password to log in with, in order of precedence: the contents of
+/// `password_file` (one trailing newline stripped), a non-empty
+/// `env_password`, then `remembered` (the keyring lookup). An unreadable or
+/// empty password file is an error rather than a fallback.
+pub fn resolve_password(
+    password_file: Option<&Path>,
+    env_password: Option<String>,
+    remembered: impl FnOnce() -> String,
+) -> Result<Zeroizing<String>, String> {
+    if let Some(path) = password_file {
+        let mut bytes = Zeroizing::new(
+            std::fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?,
+        );
+        if bytes.ends_with(b"\n") {
+            bytes.pop();
+            if bytes.ends_with(b"\r") {
+                bytes.pop();
+            }
+        }
+        let password = match String::from_utf8(std::mem::take(&mut *bytes)) {
+            Ok(password) => Zeroizing::new(password),
+            Err(e) => {
+                e.into_bytes().zeroize();
+                return Err(format!("{}: password is not valid UTF-8", path.display()));
+            }
+        };
+        if password.is_empty() {
+            return Err(format!("{}: password file is empty", path.display()));
+        }
+        return Ok(password);
+    }
+    if let Some(password) = env_password.map(Zeroizing::new) {
+        if !password.is_empty() {
+            return Ok(password);
+        }
+    }
+    Ok(Zeroizing::new(remembered()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str, content: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("sievers-{}-{name}", std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_password_precedence() {
+        let file = temp_file("password-precedence", "from-file\n");
+        let env = || Some("from-env".to_string());
+        let keyring = || "from-keyring".to_string();
+
+        let resolved = resolve_password(Some(&file), env(), keyring).unwrap();
+        assert_eq!(resolved.as_str(), "from-file");
+        assert_eq!(resolve_password(None, env(), keyring).unwrap().as_str(), "from-env");
+        assert_eq!(
+            resolve_password(None, Some(String::new()), keyring).unwrap().as_str(),
+            "from-keyring"
+        );
+        assert_eq!(resolve_password(None, None, keyring).unwrap().as_str(), "from-keyring");
+        assert_eq!(resolve_password(None, None, String::new).unwrap().as_str(), "");
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_password_file() {
+        let crlf = temp_file("password-crlf", "p@ss word\r\n");
+        assert_eq!(resolve_password(Some(&crlf), None, String::new).unwrap().as_str(), "p@ss word");
+        std::fs::remove_file(crlf).unwrap();
+
+        // Only the one line ending goes; other whitespace is part of the password
+        let padded = temp_file("password-padded", " secret \n\n");
+        assert_eq!(resolve_password(Some(&padded), None, String::new).unwrap().as_str(), " secret \n");
+        std::fs::remove_file(padded).unwrap();
+
+        let empty = temp_file("password-empty", "\n");
+        let keyring = || "from-keyring".to_string();
+        assert!(resolve_password(Some(&empty), None, keyring).is_err());
+        std::fs::remove_file(&empty).unwrap();
+        assert!(resolve_password(Some(&empty), Some("from-env".to_string()), keyring).is_err());
+    }
+}
@@ -1,4 +1,32 @@
-use std::path::Path;
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::paths;
+
+/// Server copies kept per script before the oldest are pruned.
+const MAX_BACKUPS: usize = 20;
+
+/// Written next to exported scripts to record their server names.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// What an export wrote: each script's server name, file, and whether it was
+/// the active one.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub scripts: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub name: String,
+    pub file: String,
+    #[serde(default)]
+    pub active: bool,
+}
 
 pub fn load_script(path: &Path) -> Result<String, std::io::Error> {
     std::fs::read_to_string(path)
@@ -7,3 +35,307 @@ pub fn load_script(path: &Path) -> Result<String, std::io::Error> {
 pub fn save_script(path: &Path, text: &str) -> Result<(), std::io::Error> {
     std::fs::write(path, text)
 }
+
+/// Save the server's copy of a script under
+/// `config_dir()/backups/<name>/<timestamp>.siv` before it gets overwritten.
+pub fn backup_script(name: &str, content: &str) -> Result<PathBuf, std::io::Error> {
+    let root = paths::config_dir().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::NotFound, "No config directory")
+    })?;
+    write_backup(&root, name, content, SystemTime::now())
+}
+
+fn write_backup(
+    root: &Path,
+    name: &str,
+    content: &str,
+    time: SystemTime,
+) -> Result<PathBuf, std::io::Error> {
+    let mut path = backup_path(root, name, time);
+    let dir = path.parent().expect("backup path has a parent").to_path_buf();
+    std::fs::create_dir_all(&dir)?;
+    // A second backup within the same second gets a counter, which still
+    // sorts after the first: `_` comes after `.`
+    let mut n = 1;
+    let mut file = loop {
+        match std::fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => break file,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                n += 1;
+                path = dir.join(format!("{}_{n:02}.siv", timestamp(time)));
+            }
+            Err(e) => return Err(e),
+        }
+    };
+    file.write_all(content.as_bytes())?;
+    prune_backups(&dir, MAX_BACKUPS)?;
+    Ok(path)
+}
+
+/// Pick a `.siv` file name for every `(name, active)` script, unique even
+/// when sanitizing makes two names collide.
+pub fn plan_export<'a>(scripts: impl IntoIterator<Item = (&'a str, bool)>) -> Manifest {
+    let mut taken = HashSet::new();
+    let scripts = scripts
+        .into_iter()
+        .map(|(name, active)| {
+            let stem = safe_file_stem(name);
+            let mut file = format!("{stem}.siv");
+            let mut n = 2;
+            while !taken.insert(file.to_lowercase()) {
+                file = format!("{stem}-{n}.siv");
+                n += 1;
+            }
+            ManifestEntry {
+                name: name.to_string(),
+                file,
+                active,
+            }
+        })
+        .collect();
+    Manifest { scripts }
+}
+
+pub fn write_manifest(dir: &Path, manifest: &Manifest) -> Result<(), std::io::Error> {
+    let data = serde_json::to_string_pretty(manifest).map_err(std::io::Error::other)?;
+    std::fs::write(dir.join(MANIFEST_FILE), data)
+}
+
+pub fn read_manifest(dir: &Path) -> Option<Manifest> {
+    let data = std::fs::read_to_string(dir.join(MANIFEST_FILE)).ok()?;
+    serde_json::from_str(&data).ok()
+}
+
+/// A script file found for import and the server name it goes under.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportEntry {
+    pub name: String,
+    pub path: PathBuf,
+    /// Marked active in the manifest
+    pub active: bool,
+}
+
+/// The `*.siv`/`*.sieve` files in `dir`, sorted by file name. Each is named
+/// after its file stem, unless the manifest of an earlier export records the
+/// original server name.
+pub fn discover_scripts(dir: &Path) -> Result<Vec<ImportEntry>, std::io::Error> {
+    let manifest = read_manifest(dir).unwrap_or_default();
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_script = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("siv") || e.eq_ignore_ascii_case("sieve"));
+        if !is_script || !path.is_file() {
+            continue;
+        }
+        let Some(file) = path.file_name().and_then(|f| f.to_str()) else {
+            continue;
+        };
+        let recorded = manifest.scripts.iter().find(|e| e.file == file);
+        let Some(name) = recorded
+            .map(|e| e.name.clone())
+            .or_else(|| path.file_stem().and_then(|s| s.to_str()).map(str::to_string))
+        else {
+            continue;
+        };
+        entries.push(ImportEntry {
+            name,
+            active: recorded.is_some_and(|e| e.active),
+            path,
+        });
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+/// Script names are server-side strings; keep them to one path component.
+pub fn safe_file_stem(name: &str) -> String {
+    let stem: String = name
+        .chars()
+        .map(|c| if c.is_control() || "/\\:*?\"<>|".contains(c) { '_' } else { c })
+        .collect();
+    if stem.is_empty() || stem.chars().all(|c| c == '.') {
+        format!("_{stem}")
+    } else {
+        stem
+    }
+}
+
+fn backup_path(root: &Path, name: &str, time: SystemTime) -> PathBuf {
+    root.join("backups")
+        .join(safe_file_stem(name))
+        .join(format!("{}.siv", timestamp(time)))
+}
+
+/// UTC `YYYYMMDD-HHMMSS`, which sorts chronologically by name.
+fn timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}{month:02}{day:02}-{:02}{:02}{:02}",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Delete all but the newest `keep` backups in `dir`.
+fn prune_backups(dir: &Path, keep: usize) -> Result<(), std::io::Error> {
+    let mut backups: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "siv"))
+        .collect();
+    if backups.len() <= keep {
+        return Ok(());
+    }
+    backups.sort();
+    for path in &backups[..backups.len() - keep] {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn temp_root(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sievers-backups-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn test_backup_path() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_792_154_096);
+        assert_eq!(
+            backup_path(Path::new("/cfg"), "roundcube", time),
+            PathBuf::from("/cfg/backups/roundcube/20261016-123456.siv")
+        );
+        assert_eq!(
+            backup_path(Path::new("/cfg"), "../work/rules", UNIX_EPOCH),
+            PathBuf::from("/cfg/backups/.._work_rules/19700101-000000.siv")
+        );
+        assert_eq!(
+            backup_path(Path::new("/cfg"), "..", UNIX_EPOCH),
+            PathBuf::from("/cfg/backups/_../19700101-000000.siv")
+        );
+    }
+
+    #[test]
+    fn test_prune_keeps_newest() {
+        let root = temp_root("prune");
+        for i in 0..(MAX_BACKUPS as u64 + 3) {
+            let time = UNIX_EPOCH + Duration::from_secs(1_000_000 + i * 60);
+            write_backup(&root, "main", &format!("# v{i}\nkeep;\n"), time).unwrap();
+        }
+
+        let dir = root.join("backups").join("main");
+        let mut names: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names.len(), MAX_BACKUPS);
+        // The three oldest are gone
+        let oldest_kept = backup_path(&root, "main", UNIX_EPOCH + Duration::from_secs(1_000_180));
+        assert_eq!(dir.join(&names[0]), oldest_kept);
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_backups_in_the_same_second() {
+        let root = temp_root("same-second");
+        let time = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let first = write_backup(&root, "main", "# v1\n", time).unwrap();
+        let second = write_backup(&root, "main", "# v2\n", time).unwrap();
+        let third = write_backup(&root, "main", "# v3\n", time).unwrap();
+
+        assert_ne!(first, second);
+        assert_eq!(std::fs::read_to_string(&first).unwrap(), "# v1\n");
+        assert_eq!(std::fs::read_to_string(&second).unwrap(), "# v2\n");
+        // Names still sort oldest first, which pruning relies on
+        let mut sorted = vec![third.clone(), first.clone(), second.clone()];
+        sorted.sort();
+        assert_eq!(sorted, vec![first, second, third]);
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_plan_export_file_names() {
+        let manifest = plan_export([
+            ("main", true),
+            ("vacation/2024", false),
+            ("vacation_2024", false),
+            ("Main", false),
+            ("", false),
+        ]);
+        let files: Vec<&str> = manifest.scripts.iter().map(|e| e.file.as_str()).collect();
+        assert_eq!(
+            files,
+            vec!["main.siv", "vacation_2024.siv", "vacation_2024-2.siv", "Main-2.siv", "_.siv"]
+        );
+        assert_eq!(manifest.scripts[1].name, "vacation/2024");
+        assert!(manifest.scripts[0].active);
+        assert!(!manifest.scripts[3].active);
+    }
+
+    #[test]
+    fn test_manifest_json() {
+        let manifest = plan_export([("main", true), ("old", false)]);
+        let json = serde_json::to_value(&manifest).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "scripts": [
+                    { "name": "main", "file": "main.siv", "active": true },
+                    { "name": "old", "file": "old.siv", "active": false },
+                ]
+            })
+        );
+        assert_eq!(serde_json::from_value::<Manifest>(json).unwrap(), manifest);
+    }
+
+    #[test]
+    fn test_discover_scripts() {
+        let dir = temp_root("discover");
+        std::fs::create_dir_all(dir.join("nested.siv")).unwrap();
+        for file in ["work.sieve", "main.siv", "vacation_2024.siv", "Notes.SIV", "readme.txt"] {
+            std::fs::write(dir.join(file), "keep;\n").unwrap();
+        }
+        let manifest = plan_export([("main", true), ("vacation/2024", false)]);
+        write_manifest(&dir, &manifest).unwrap();
+
+        let found: Vec<(String, bool)> = discover_scripts(&dir)
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.name, e.active))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("Notes".to_string(), false),
+                ("main".to_string(), true),
+                ("vacation/2024".to_string(), false),
+                ("work".to_string(), false),
+            ]
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,131 @@
+/// Connection passwords in the OS keyring (Keychain, Credential Manager,
+/// Secret Service). Every operation degrades to a no-op when no keyring
+/// backend is available.
+use crate::model::profile::ConnectionProfile;
+
+const SERVICE: &str = "sievers";
+
+/// Minimal keyring interface, so the wrappers can be exercised without an OS backend.
+pub trait SecretBackend {
+    fn get(&self, key: &str) -> Option<String>;
+    fn set(&self, key: &str, secret: &str) -> bool;
+    fn delete(&self, key: &str);
+}
+
+struct OsKeyring;
+
+impl SecretBackend for OsKeyring {
+    fn get(&self, key: &str) -> Option<String> {
+        keyring::Entry::new(SERVICE, key).ok()?.get_password().ok()
+    }
+
+    fn set(&self, key: &str, secret: &str) -> bool {
+        keyring::Entry::new(SERVICE, key)
+            .and_then(|e| e.set_password(secret))
+            .is_ok()
+    }
+
+    fn delete(&self, key: &str) {
+        if let Ok(entry) = keyring::Entry::new(SERVICE, key) {
+            let _ = entry.delete_credential();
+        }
+    }
+}
+
+/// Keyring entry name for a profile: the same server and user under another
+/// profile name gets its own entry.
+pub fn secret_key(profile: &ConnectionProfile) -> String {
+    format!("{}|{}|{}", profile.name, profile.host, profile.username)
+}
+
+/// The stored password for `profile`, or an empty string if there is none.
+pub fn load_password(profile: &ConnectionProfile) -> String {
+    load_with(&OsKeyring, profile)
+}
+
+/// Store the password; returns false if no keyring backend accepted it.
+pub fn save_password(profile: &ConnectionProfile, password: &str) -> bool {
+    save_with(&OsKeyring, profile, password)
+}
+
+pub fn forget_password(profile: &ConnectionProfile) {
+    OsKeyring.delete(&secret_key(profile));
+}
+
+fn load_with(backend: &dyn SecretBackend, profile: &ConnectionProfile) -> String {
+    if profile.host.is_empty() || profile.username.is_empty() {
+        return String::new();
+    }
+    backend.get(&secret_key(profile)).unwrap_or_default()
+}
+
+fn save_with(backend: &dyn SecretBackend, profile: &ConnectionProfile, password: &str) -> bool {
+    if profile.host.is_empty() || profile.username.is_empty() || password.is_empty() {
+        return false;
+    }
+    backend.set(&secret_key(profile), password)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct MockBackend {
+        secrets: RefCell<HashMap<String, String>>,
+        unavailable: bool,
+    }
+
+    impl SecretBackend for MockBackend {
+        fn get(&self, key: &str) -> Option<String> {
+            self.secrets.borrow().get(key).cloned()
+        }
+
+        fn set(&self, key: &str, secret: &str) -> bool {
+            if self.unavailable {
+                return false;
+            }
+            self.secrets.borrow_mut().insert(key.to_string(), secret.to_string());
+            true
+        }
+
+        fn delete(&self, key: &str) {
+            self.secrets.borrow_mut().remove(key);
+        }
+    }
+
+    fn profile(name: &str) -> ConnectionProfile {
+        ConnectionProfile {
+            name: name.to_string(),
+            host: "mail.example.com".to_string(),
+            username: "alice".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_secret_key() {
+        assert_eq!(secret_key(&profile("Work")), "Work|mail.example.com|alice");
+        assert_ne!(secret_key(&profile("Work")), secret_key(&profile("Home")));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let backend = MockBackend::default();
+        assert!(save_with(&backend, &profile("Work"), "s3cret"));
+        assert_eq!(load_with(&backend, &profile("Work")), "s3cret");
+        assert_eq!(load_with(&backend, &profile("Home")), "");
+    }
+
+    #[test]
+    fn test_unavailable_backend_loads_empty() {
+        let backend = MockBackend {
+            unavailable: true,
+            ..Default::default()
+        };
+        assert!(!save_with(&backend, &profile("Work"), "s3cret"));
+        assert_eq!(load_with(&backend, &profile("Work")), "");
+    }
+}
@@ -0,0 +1,209 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::paths;
+use crate::model::enums::Tab;
+use crate::sieve::emitter::{EmitOptions, Indent};
+
+const SETTINGS_FILE: &str = "settings.json";
+const MAX_RECENT_FILES: usize = 10;
+
+/// What connecting does with the editor: open the server's active (or the
+/// profile's default) script in it, or leave it be.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OpenOnConnect {
+    /// Open it, asking only if the editor has unsaved changes
+    #[default]
+    Always,
+    Ask,
+    Never,
+}
+
+impl OpenOnConnect {
+    pub const ALL: &'static [OpenOnConnect] = &[Self::Always, Self::Ask, Self::Never];
+}
+
+impl fmt::Display for OpenOnConnect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Always => f.write_str("Open the server script"),
+            Self::Ask => f.write_str("Ask first"),
+            Self::Never => f.write_str("Keep the editor as is"),
+        }
+    }
+}
+
+/// Preferences kept between runs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    pub dark_mode: bool,
+    pub window_size: (f32, f32),
+    /// Last window position; `None` opens the window centered
+    pub window_pos: Option<(f32, f32)>,
+    /// Recently opened or saved scripts, most recent first
+    pub recent_files: Vec<PathBuf>,
+    /// Indentation of generated scripts
+    pub indent: Indent,
+    /// Keep the loaded script's `require` order rather than sorting
+    pub keep_require_order: bool,
+    /// Editor tab shown at the last exit
+    pub last_tab: Tab,
+    /// Whether connecting opens a server script in the editor
+    pub open_on_connect: OpenOnConnect,
+    /// Width of the server's script list, as last dragged
+    pub script_list_width: f32,
+    /// Width of the visual editor's rule sidebar, as last dragged
+    pub rule_sidebar_width: f32,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        Self {
+            dark_mode: false,
+            window_size: (1000.0, 650.0),
+            window_pos: None,
+            recent_files: Vec::new(),
+            indent: Indent::default(),
+            keep_require_order: false,
+            last_tab: Tab::default(),
+            open_on_connect: OpenOnConnect::default(),
+            script_list_width: 200.0,
+            rule_sidebar_width: 250.0,
+        }
+    }
+}
+
+impl AppSettings {
+    /// Move `path` to the front of the recent list, dropping the oldest
+    /// entries beyond the limit.
+    pub fn add_recent(&mut self, path: PathBuf) {
+        self.remove_recent(&path);
+        self.recent_files.insert(0, path);
+        self.recent_files.truncate(MAX_RECENT_FILES);
+    }
+
+    pub fn remove_recent(&mut self, path: &Path) {
+        self.recent_files.retain(|p| p != path);
+    }
+
+    /// Formatting for scripts generated from the visual editor.
+    pub fn emit_options(&self) -> EmitOptions {
+        EmitOptions {
+            indent: self.indent,
+            keep_require_order: self.keep_require_order,
+            ..Default::default()
+        }
+    }
+}
+
+fn settings_path() -> Option<PathBuf> {
+    // App-level tests go through `update`, which saves settings; keep them
+    // away from the real file
+    if cfg!(test) {
+        return None;
+    }
+    paths::config_dir().map(|d| d.join(SETTINGS_FILE))
+}
+
+pub fn load_settings() -> AppSettings {
+    settings_path()
+        .map(|path| load_from(&path))
+        .unwrap_or_default()
+}
+
+pub fn save_settings(settings: &AppSettings) {
+    if let Some(path) = settings_path() {
+        save_to(&path, settings);
+    }
+}
+
+fn load_from(path: &Path) -> AppSettings {
+    let Ok(data) = fs::read_to_string(path) else {
+        return AppSettings::default();
+    };
+    serde_json::from_str(&data).unwrap_or_default()
+}
+
+fn save_to(path: &Path, settings: &AppSettings) {
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    if let Ok(data) = serde_json::to_string_pretty(settings) {
+        let _ = fs::write(path, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("sievers-settings-{}-{name}", std::process::id()))
+            .join(SETTINGS_FILE)
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = temp_path("roundtrip");
+        let settings = AppSettings {
+            dark_mode: true,
+            window_size: (1280.0, 800.0),
+            window_pos: Some((1920.0, 40.0)),
+            recent_files: vec![PathBuf::from("/tmp/filters.siv")],
+            indent: Indent::Tab,
+            keep_require_order: true,
+            last_tab: Tab::Visual,
+            open_on_connect: OpenOnConnect::Ask,
+            script_list_width: 240.0,
+            rule_sidebar_width: 320.0,
+        };
+        save_to(&path, &settings);
+        assert_eq!(load_from(&path), settings);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_missing_file_gives_defaults() {
+        assert_eq!(load_from(&temp_path("missing")), AppSettings::default());
+    }
+
+    #[test]
+    fn test_partial_file_fills_defaults() {
+        let settings: AppSettings = serde_json::from_str(r#"{"dark_mode": true}"#).unwrap();
+        assert!(settings.dark_mode);
+        assert_eq!(settings.window_size, (1000.0, 650.0));
+        assert_eq!(settings.window_pos, None);
+    }
+
+    #[test]
+    fn test_add_recent_dedups_and_caps() {
+        let mut settings = AppSettings::default();
+        for i in 0..12 {
+            settings.add_recent(PathBuf::from(format!("/tmp/{i}.siv")));
+        }
+        assert_eq!(settings.recent_files.len(), MAX_RECENT_FILES);
+        assert_eq!(settings.recent_files[0], PathBuf::from("/tmp/11.siv"));
+        assert_eq!(settings.recent_files[9], PathBuf::from("/tmp/2.siv"));
+
+        // Reopening an entry moves it to the front instead of duplicating it
+        settings.add_recent(PathBuf::from("/tmp/5.siv"));
+        assert_eq!(settings.recent_files.len(), MAX_RECENT_FILES);
+        assert_eq!(settings.recent_files[0], PathBuf::from("/tmp/5.siv"));
+        assert_eq!(
+            settings
+                .recent_files
+                .iter()
+                .filter(|p| p.as_path() == Path::new("/tmp/5.siv"))
+                .count(),
+            1
+        );
+
+        settings.remove_recent(Path::new("/tmp/5.siv"));
+        assert_eq!(settings.recent_files[0], PathBuf::from("/tmp/11.siv"));
+    }
+}
@@ -1,17 +1,51 @@
-use iced::widget::{button, column, container, horizontal_rule, pick_list, row, text, text_input};
+use iced::widget::{
+    button, checkbox, column, container, horizontal_rule, pick_list, row, text, text_input, tooltip,
+};
 use iced::{Color, Element, Length, Theme};
 
 use crate::model::enums::ActionType;
 use crate::model::rule::Action;
 use crate::ui::icons;
+use crate::util::email;
 
 #[derive(Debug, Clone)]
 pub enum ActionMessage {
     SetActionType(ActionTypeOption),
     SetArgument(String),
+    SetDays(String),
+    SetSubject(String),
+    /// Comma-separated `vacation :addresses`
+    SetAddresses(String),
+    SetValue(String),
+    SetMessage(String),
+    ToggleCopy(bool),
+    ToggleCreate(bool),
+    /// Check or uncheck one of the `SYSTEM_FLAGS`
+    ToggleFlag(&'static str, bool),
+    /// Space-separated flags other than the system ones
+    SetCustomFlags(String),
+    SetFlagVariable(String),
     Remove,
 }
 
+/// The IMAP system flags offered as checkboxes; anything else is typed in.
+pub const SYSTEM_FLAGS: &[&str] = &["\\Seen", "\\Answered", "\\Flagged", "\\Deleted", "\\Draft"];
+
+pub fn is_system_flag(flag: &str) -> bool {
+    SYSTEM_FLAGS.iter().any(|f| f.eq_ignore_ascii_case(flag))
+}
+
+/// The action's non-system flags as typed, space-separated.
+pub fn custom_flags(action: &Action) -> String {
+    let custom: Vec<&str> = action
+        .flags
+        .iter()
+        .filter(|f| !is_system_flag(f))
+        .map(String::as_str)
+        .collect();
+    custom.join(" ")
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ActionTypeOption(pub ActionType);
 
@@ -31,23 +65,41 @@ pub const ACTION_OPTIONS: &[ActionTypeOption] = &[
     ActionTypeOption(ActionType::Setflag),
     ActionTypeOption(ActionType::Addflag),
     ActionTypeOption(ActionType::Removeflag),
+    ActionTypeOption(ActionType::Vacation),
+    ActionTypeOption(ActionType::Notify),
+    ActionTypeOption(ActionType::Addheader),
+    ActionTypeOption(ActionType::Deleteheader),
 ];
 
 /// View a single action with numbered heading and labeled grid layout.
-pub fn view(action: &Action, number: usize) -> Element<'_, ActionMessage> {
+/// A `dead` action comes after a `stop` and is drawn dimmed.
+pub fn view(action: &Action, number: usize, dead: bool) -> Element<'_, ActionMessage> {
     let action_type = ActionTypeOption(action.action_type);
     let takes_arg = action.action_type.takes_argument();
 
     let mut content = column![].spacing(8);
 
     // Header: "Action N" + trash icon
+    let title = text(format!("Action {number}"))
+        .size(13)
+        .font(iced::Font {
+            weight: iced::font::Weight::Bold,
+            ..iced::Font::DEFAULT
+        });
+    let title: Element<'_, ActionMessage> = if dead {
+        tooltip(
+            row![title, text("never runs").size(11)].spacing(6).align_y(iced::Alignment::Center),
+            container(text("A stop above ends the script before this action").size(12))
+                .padding(6)
+                .style(container::rounded_box),
+            tooltip::Position::Top,
+        )
+        .into()
+    } else {
+        title.into()
+    };
     let heading = row![
-        text(format!("Action {number}"))
-            .size(13)
-            .font(iced::Font {
-                weight: iced::font::Weight::Bold,
-                ..iced::Font::DEFAULT
-            }),
+        title,
         iced::widget::horizontal_space().width(Length::Fill),
         button(
             text(icons::DELETE_BIN.to_string())
@@ -77,26 +129,187 @@ pub fn view(action: &Action, number: usize) -> Element<'_, ActionMessage> {
         .spacing(4),
     );
 
-    if takes_arg {
+    if action.action_type == ActionType::Vacation {
+        let days = action.days.map(|d| d.to_string()).unwrap_or_default();
+        fields = fields.push(
+            column![
+                label_text("Days"),
+                text_input("7", &days)
+                    .on_input(ActionMessage::SetDays)
+                    .width(60),
+            ]
+            .spacing(4),
+        );
         fields = fields.push(
             column![
+                label_text("Subject"),
+                text_input("Out of office", action.subject.as_deref().unwrap_or(""))
+                    .on_input(ActionMessage::SetSubject)
+                    .width(Length::Fill),
+            ]
+            .spacing(4)
+            .width(Length::Fill),
+        );
+        content = content.push(fields);
+        content = content.push(
+            column![
+                label_text("Reason"),
+                text_input("I'm away until...", &action.argument)
+                    .on_input(ActionMessage::SetArgument)
+                    .width(Length::Fill),
+            ]
+            .spacing(4),
+        );
+        let mut addresses = column![
+            label_text("My other addresses (optional)"),
+            text_input("me@example.org, me@example.net", &action.addresses.join(", "))
+                .on_input(ActionMessage::SetAddresses)
+                .width(Length::Fill),
+        ]
+        .spacing(4);
+        // Only a hint, as for redirect; blank entries are still being typed
+        let implausible: Vec<&str> = action
+            .addresses
+            .iter()
+            .map(|a| a.trim())
+            .filter(|a| !a.is_empty() && !email::is_plausible_address(a))
+            .collect();
+        if !implausible.is_empty() {
+            addresses = addresses.push(
+                text(format!("Doesn't look like an address: {}", implausible.join(", ")))
+                    .size(11)
+                    .color(Color::from_rgb(0.85, 0.2, 0.2)),
+            );
+        }
+        content = content.push(addresses);
+    } else if action.action_type == ActionType::Notify {
+        fields = fields.push(labeled_field(
+            "Method",
+            "mailto:me@example.org",
+            &action.argument,
+            ActionMessage::SetArgument,
+        ));
+        content = content.push(fields);
+        content = content.push(labeled_field(
+            "Message",
+            "New mail from ${from}",
+            action.message.as_deref().unwrap_or(""),
+            ActionMessage::SetMessage,
+        ));
+    } else if matches!(action.action_type, ActionType::Addheader | ActionType::Deleteheader) {
+        let value_label = if action.action_type == ActionType::Addheader {
+            "Value"
+        } else {
+            "Value (optional)"
+        };
+        fields = fields.push(labeled_field(
+            "Header",
+            "X-Filtered",
+            &action.argument,
+            ActionMessage::SetArgument,
+        ));
+        fields = fields.push(labeled_field(value_label, "yes", &action.value, ActionMessage::SetValue));
+        content = content.push(fields);
+    } else if action.action_type.is_flag_action() {
+        fields = fields.push(labeled_field(
+            "Variable (optional)",
+            "Internal flags",
+            action.flag_variable.as_deref().unwrap_or(""),
+            ActionMessage::SetFlagVariable,
+        ));
+        content = content.push(fields);
+
+        let mut system = row![].spacing(12);
+        for &flag in SYSTEM_FLAGS {
+            let checked = action.flags.iter().any(|f| f.eq_ignore_ascii_case(flag));
+            system = system.push(
+                checkbox(&flag[1..], checked)
+                    .on_toggle(move |on| ActionMessage::ToggleFlag(flag, on))
+                    .size(14)
+                    .text_size(12),
+            );
+        }
+        content = content.push(column![label_text("Flags"), system].spacing(4));
+        content = content.push(
+            column![
+                label_text("Other flags"),
+                text_input("$Work Junk", &custom_flags(action))
+                    .on_input(ActionMessage::SetCustomFlags)
+                    .width(Length::Fill),
+            ]
+            .spacing(4),
+        );
+    } else {
+        if takes_arg {
+            let mut value_field = column![
                 label_text("Value"),
                 text_input("Folder, address...", &action.argument)
                     .on_input(ActionMessage::SetArgument)
                     .width(Length::Fill),
             ]
             .spacing(4)
-            .width(Length::Fill),
-        );
+            .width(Length::Fill);
+            // Only a hint; the server has the final word on addresses
+            if action.action_type == ActionType::Redirect
+                && !action.argument.is_empty()
+                && !email::is_plausible_address(&action.argument)
+            {
+                value_field = value_field.push(
+                    text("Doesn't look like an address (user@example.org)")
+                        .size(11)
+                        .color(Color::from_rgb(0.85, 0.2, 0.2)),
+                );
+            }
+            fields = fields.push(value_field);
+        }
+        content = content.push(fields);
+        if action.action_type.supports_copy() {
+            content = content.push(
+                checkbox("Keep a copy (:copy)", action.copy)
+                    .on_toggle(ActionMessage::ToggleCopy)
+                    .size(14)
+                    .text_size(12),
+            );
+        }
+        if action.action_type == ActionType::Fileinto {
+            content = content.push(
+                checkbox("Create folder if missing (:create)", action.create)
+                    .on_toggle(ActionMessage::ToggleCreate)
+                    .size(14)
+                    .text_size(12),
+            );
+        }
     }
-
-    content = content.push(fields);
     content = content.push(horizontal_rule(1));
 
-    container(content)
-        .padding([8, 0])
-        .width(Length::Fill)
-        .into()
+    let mut row = container(content).padding([8, 0]).width(Length::Fill);
+    if dead {
+        row = row.style(|theme: &Theme| {
+            let p = theme.palette();
+            container::Style {
+                text_color: Some(Color::from_rgba(p.text.r, p.text.g, p.text.b, 0.45)),
+                ..container::Style::default()
+            }
+        });
+    }
+    row.into()
+}
+
+fn labeled_field<'a>(
+    label: &'a str,
+    placeholder: &'a str,
+    value: &'a str,
+    on_input: fn(String) -> ActionMessage,
+) -> Element<'a, ActionMessage> {
+    column![
+        label_text(label),
+        text_input(placeholder, value)
+            .on_input(on_input)
+            .width(Length::Fill),
+    ]
+    .spacing(4)
+    .width(Length::Fill)
+    .into()
 }
 
 fn label_text(label: &str) -> Element<'_, ActionMessage> {
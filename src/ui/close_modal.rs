@@ -0,0 +1,61 @@
+use iced::widget::{button, column, container, row, text};
+use iced::{Border, Color, Element, Font, Length, Theme};
+
+#[derive(Debug, Clone)]
+pub enum CloseMessage {
+    Discard,
+    Cancel,
+}
+
+/// Confirmation shown when the window is closed with unsaved changes.
+pub fn view<'a>() -> Element<'a, CloseMessage> {
+    let title = text("Unsaved changes").size(18).font(Font {
+        weight: iced::font::Weight::Bold,
+        ..Font::DEFAULT
+    });
+
+    let body = text("The script has changes that were not saved or uploaded. Quit anyway?").size(14);
+
+    let buttons = row![
+        button("Quit without saving")
+            .on_press(CloseMessage::Discard)
+            .style(button::danger),
+        button("Cancel")
+            .on_press(CloseMessage::Cancel)
+            .style(button::secondary),
+    ]
+    .spacing(8);
+
+    let dialog = container(column![title, body, buttons].spacing(12).padding(24).max_width(420))
+        .style(|theme: &Theme| {
+            let palette = theme.palette();
+            container::Style {
+                background: Some(iced::Background::Color(palette.background)),
+                border: Border {
+                    color: Color::from_rgba(
+                        palette.text.r,
+                        palette.text.g,
+                        palette.text.b,
+                        0.3,
+                    ),
+                    width: 1.0,
+                    radius: 8.0.into(),
+                },
+                ..container::Style::default()
+            }
+        });
+
+    // Overlay: dark semi-transparent background + centered dialog
+    container(
+        container(dialog)
+            .center_x(Length::Fill)
+            .center_y(Length::Fill),
+    )
+    .width(Length::Fill)
+    .height(Length::Fill)
+    .style(|_theme: &Theme| container::Style {
+        background: Some(iced::Background::Color(Color::from_rgba(0.0, 0.0, 0.0, 0.5))),
+        ..container::Style::default()
+    })
+    .into()
+}
@@ -1,9 +1,12 @@
-use iced::widget::{button, column, container, horizontal_rule, pick_list, row, text, text_input};
+use iced::widget::{
+    button, checkbox, column, container, horizontal_rule, pick_list, row, text, text_input,
+};
 use iced::{Color, Element, Length, Theme};
 
 use crate::model::enums::*;
 use crate::model::rule::Condition;
 use crate::ui::icons;
+use crate::util::header;
 
 #[derive(Debug, Clone)]
 pub enum ConditionMessage {
@@ -11,8 +14,20 @@ pub enum ConditionMessage {
     SetMatchType(MatchTypeOption),
     SetAddressPart(AddressPartOption),
     SetSizeComparator(SizeComparatorOption),
+    SetComparator(ComparatorOption),
+    SetTransform(TransformOption),
+    /// Media type of a `:content` transform
+    SetContentType(String),
     SetHeaders(String),
+    /// Size limit of a `size` test
     SetValue(String),
+    /// One of the keys the test matches against
+    SetKey(usize, String),
+    AddKey,
+    RemoveKey(usize),
+    ToggleNegate(bool),
+    /// Switch the condition off without removing it
+    ToggleEnabled(bool),
     Remove,
 }
 
@@ -29,6 +44,7 @@ impl std::fmt::Display for ConditionTestOption {
             ConditionTest::Size => write!(f, "Size"),
             ConditionTest::Exists => write!(f, "Exists"),
             ConditionTest::Body => write!(f, "Body"),
+            ConditionTest::Hasflag => write!(f, "Has flag"),
             other => write!(f, "{}", other.as_sieve()),
         }
     }
@@ -40,6 +56,7 @@ pub const TEST_OPTIONS: &[ConditionTestOption] = &[
     ConditionTestOption(ConditionTest::Envelope),
     ConditionTestOption(ConditionTest::Size),
     ConditionTestOption(ConditionTest::Exists),
+    ConditionTestOption(ConditionTest::Hasflag),
 ];
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -99,8 +116,65 @@ pub const SIZE_OPTIONS: &[SizeComparatorOption] = &[
     SizeComparatorOption(SizeComparator::Under),
 ];
 
+/// Kind of body transform; the media type of `:content` is edited apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransformOption {
+    Text,
+    Raw,
+    Content,
+}
+
+impl TransformOption {
+    pub fn of(transform: &BodyTransform) -> Self {
+        match transform {
+            BodyTransform::Text => Self::Text,
+            BodyTransform::Raw => Self::Raw,
+            BodyTransform::Content(_) => Self::Content,
+        }
+    }
+}
+
+impl std::fmt::Display for TransformOption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Text => write!(f, "text"),
+            Self::Raw => write!(f, "raw"),
+            Self::Content => write!(f, "content type"),
+        }
+    }
+}
+
+pub const TRANSFORM_OPTIONS: &[TransformOption] = &[
+    TransformOption::Text,
+    TransformOption::Raw,
+    TransformOption::Content,
+];
+
+/// `:comparator` choice; `None` omits the tag and leaves the server default.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComparatorOption(pub Option<String>);
+
+impl std::fmt::Display for ComparatorOption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0.as_deref() {
+            None => write!(f, "default"),
+            Some(name) => write!(f, "{}", name.strip_prefix("i;").unwrap_or(name)),
+        }
+    }
+}
+
+pub fn comparator_options() -> Vec<ComparatorOption> {
+    [None, Some("i;octet"), Some("i;ascii-casemap"), Some("i;ascii-numeric")]
+        .into_iter()
+        .map(|c| ComparatorOption(c.map(str::to_string)))
+        .collect()
+}
+
 /// View a single condition with numbered heading and labeled grid layout.
-pub fn view(cond: &Condition, number: usize) -> Element<'_, ConditionMessage> {
+/// With `can_disable` the heading has an on/off switch; only a rule's own
+/// conditions can be disabled, not those of its `elsif` branches, and only
+/// those whose test fits on one line.
+pub fn view(cond: &Condition, number: usize, can_disable: bool) -> Element<'_, ConditionMessage> {
     let test_type = ConditionTestOption(cond.test_type);
     let is_size = cond.test_type == ConditionTest::Size;
     let is_exists = cond.test_type == ConditionTest::Exists;
@@ -111,15 +185,28 @@ pub fn view(cond: &Condition, number: usize) -> Element<'_, ConditionMessage> {
 
     let mut content = column![].spacing(8);
 
-    // Header: "Condition N" + trash icon
+    // Header: "Condition N" + on/off switch + trash icon
+    let title = if cond.enabled {
+        format!("Condition {number}")
+    } else {
+        format!("Condition {number} (disabled)")
+    };
     let heading = row![
-        text(format!("Condition {number}"))
+        text(title)
             .size(13)
             .font(iced::Font {
                 weight: iced::font::Weight::Bold,
                 ..iced::Font::DEFAULT
             }),
         iced::widget::horizontal_space().width(Length::Fill),
+    ]
+    .push_maybe(can_disable.then(|| {
+        checkbox("On", cond.enabled)
+            .on_toggle(ConditionMessage::ToggleEnabled)
+            .size(14)
+            .text_size(12)
+    }))
+    .push(
         button(
             text(icons::DELETE_BIN.to_string())
                 .font(icons::ICON_FONT)
@@ -132,7 +219,8 @@ pub fn view(cond: &Condition, number: usize) -> Element<'_, ConditionMessage> {
             ..button::Style::default()
         })
         .padding([2, 6]),
-    ]
+    )
+    .spacing(8)
     .align_y(iced::Alignment::Center);
 
     content = content.push(heading);
@@ -140,6 +228,15 @@ pub fn view(cond: &Condition, number: usize) -> Element<'_, ConditionMessage> {
     // Labeled fields in a row
     let mut fields = row![].spacing(12);
 
+    // Negation
+    fields = fields.push(
+        column![
+            label_text("Not"),
+            checkbox("", cond.negate).on_toggle(ConditionMessage::ToggleNegate),
+        ]
+        .spacing(4),
+    );
+
     // Field (test type)
     fields = fields.push(
         column![
@@ -165,18 +262,56 @@ pub fn view(cond: &Condition, number: usize) -> Element<'_, ConditionMessage> {
         );
     }
 
-    // Header name (not for size)
-    if !is_size {
-        let headers = cond.header_names.join(", ");
+    // Body transform (only for body)
+    if cond.test_type == ConditionTest::Body {
         fields = fields.push(
             column![
-                label_text("Header"),
-                text_input("Header name", &headers)
-                    .on_input(ConditionMessage::SetHeaders)
-                    .width(140),
+                label_text("Part"),
+                pick_list(
+                    TRANSFORM_OPTIONS,
+                    Some(TransformOption::of(&cond.transform)),
+                    ConditionMessage::SetTransform,
+                )
+                .width(120),
             ]
             .spacing(4),
         );
+        if let BodyTransform::Content(media) = &cond.transform {
+            fields = fields.push(
+                column![
+                    label_text("Content Type"),
+                    text_input("e.g. text/html", media)
+                        .on_input(ConditionMessage::SetContentType)
+                        .width(120),
+                ]
+                .spacing(4),
+            );
+        }
+    }
+
+    // Header name (not for size, nor the flags `hasflag` looks at)
+    if !is_size && cond.test_type != ConditionTest::Hasflag {
+        let headers = cond.header_names.join(", ");
+        let mut header_field = column![
+            label_text("Header"),
+            text_input("Header name", &headers)
+                .on_input(ConditionMessage::SetHeaders)
+                .width(140),
+        ]
+        .spacing(4);
+        let names_headers = !matches!(
+            cond.test_type,
+            ConditionTest::Body | ConditionTest::True | ConditionTest::False
+        );
+        if let Some(hint) = header::header_names_hint(&cond.header_names).filter(|_| names_headers) {
+            header_field = header_field.push(
+                text(hint)
+                    .size(11)
+                    .width(140)
+                    .color(Color::from_rgb(0.85, 0.2, 0.2)),
+            );
+        }
+        fields = fields.push(header_field);
     }
 
     // Operator (match type, not for size or exists)
@@ -193,6 +328,18 @@ pub fn view(cond: &Condition, number: usize) -> Element<'_, ConditionMessage> {
             ]
             .spacing(4),
         );
+        fields = fields.push(
+            column![
+                label_text("Comparator"),
+                pick_list(
+                    comparator_options(),
+                    Some(ComparatorOption(cond.comparator.clone())),
+                    ConditionMessage::SetComparator,
+                )
+                .width(120),
+            ]
+            .spacing(4),
+        );
     }
 
     // Size comparator (only for size)
@@ -212,22 +359,72 @@ pub fn view(cond: &Condition, number: usize) -> Element<'_, ConditionMessage> {
     }
 
     // Value field (not for exists)
-    if !is_exists {
-        let value = if is_size {
-            &cond.size_value
-        } else {
-            cond.keys.first().map(String::as_str).unwrap_or("")
+    if is_size {
+        let mut value_field = column![
+            label_text("Value"),
+            text_input("e.g. 500K, 10M", &cond.size_value)
+                .on_input(ConditionMessage::SetValue)
+                .width(Length::Fill),
+        ]
+        .spacing(4)
+        .width(Length::Fill);
+        let size_hint = match cond.size_bytes() {
+            None => Some("Use a number with an optional K, M or G suffix"),
+            // `:over 0` matches every message and `:under 0` none
+            Some(0) => Some("Use a limit above zero"),
+            Some(_) => None,
         };
-        fields = fields.push(
-            column![
-                label_text("Value"),
-                text_input("Value", value)
-                    .on_input(ConditionMessage::SetValue)
-                    .width(Length::Fill),
-            ]
-            .spacing(4)
-            .width(Length::Fill),
+        if let Some(hint) = size_hint {
+            value_field = value_field.push(
+                text(hint)
+                    .size(11)
+                    .color(Color::from_rgb(0.85, 0.2, 0.2)),
+            );
+        }
+        fields = fields.push(value_field);
+    } else if !is_exists {
+        // One input per key, so a key may hold a comma
+        let mut value_field = column![label_text("Value")].spacing(4).width(Length::Fill);
+        let removable = cond.keys.len() > 1;
+        for (i, key) in cond.keys.iter().enumerate() {
+            let placeholder = if i == 0 { "Value" } else { "Another value" };
+            value_field = value_field.push(
+                row![
+                    text_input(placeholder, key)
+                        .on_input(move |s| ConditionMessage::SetKey(i, s))
+                        .width(Length::Fill),
+                ]
+                .push_maybe(removable.then(|| {
+                    button(text(icons::DELETE_BIN.to_string()).font(icons::ICON_FONT).size(12))
+                        .on_press(ConditionMessage::RemoveKey(i))
+                        .style(|_theme: &Theme, _status| button::Style {
+                            background: None,
+                            ..button::Style::default()
+                        })
+                        .padding([2, 6])
+                }))
+                .spacing(4)
+                .align_y(iced::Alignment::Center),
+            );
+        }
+        value_field = value_field.push(
+            button(icons::icon_text(icons::ADD_CIRCLE, "Add Value"))
+                .on_press(ConditionMessage::AddKey)
+                .style(button::secondary)
+                .padding([3, 8]),
         );
+        // An empty key is contained in, and matched by, any value
+        let matches_anything = matches!(cond.match_type, MatchType::Contains | MatchType::Regex)
+            && cond.keys.iter().all(|k| k.is_empty());
+        if matches_anything {
+            let hint = "An empty value matches every message";
+            value_field = value_field.push(
+                text(hint)
+                    .size(11)
+                    .color(Color::from_rgb(0.85, 0.2, 0.2)),
+            );
+        }
+        fields = fields.push(value_field);
     }
 
     content = content.push(fields);
@@ -0,0 +1,70 @@
+use iced::widget::{button, column, container, row, text};
+use iced::{Border, Color, Element, Font, Length, Theme};
+
+#[derive(Debug, Clone)]
+pub enum ConfirmMessage {
+    Confirm,
+    Cancel,
+}
+
+/// A question held open until the user confirms or cancels; `action` is
+/// dispatched only on confirm.
+#[derive(Debug, Clone)]
+pub struct ConfirmState<A> {
+    pub title: String,
+    pub body: String,
+    pub confirm_label: String,
+    pub action: A,
+}
+
+pub fn view<A>(state: &ConfirmState<A>) -> Element<'_, ConfirmMessage> {
+    let title = text(&state.title).size(18).font(Font {
+        weight: iced::font::Weight::Bold,
+        ..Font::DEFAULT
+    });
+
+    let body = text(&state.body).size(14);
+
+    let buttons = row![
+        button(text(&state.confirm_label))
+            .on_press(ConfirmMessage::Confirm)
+            .style(button::danger),
+        button("Cancel")
+            .on_press(ConfirmMessage::Cancel)
+            .style(button::secondary),
+    ]
+    .spacing(8);
+
+    let dialog = container(column![title, body, buttons].spacing(12).padding(24).max_width(420))
+        .style(|theme: &Theme| {
+            let palette = theme.palette();
+            container::Style {
+                background: Some(iced::Background::Color(palette.background)),
+                border: Border {
+                    color: Color::from_rgba(
+                        palette.text.r,
+                        palette.text.g,
+                        palette.text.b,
+                        0.3,
+                    ),
+                    width: 1.0,
+                    radius: 8.0.into(),
+                },
+                ..container::Style::default()
+            }
+        });
+
+    // Overlay: dark semi-transparent background + centered dialog
+    container(
+        container(dialog)
+            .center_x(Length::Fill)
+            .center_y(Length::Fill),
+    )
+    .width(Length::Fill)
+    .height(Length::Fill)
+    .style(|_theme: &Theme| container::Style {
+        background: Some(iced::Background::Color(Color::from_rgba(0.0, 0.0, 0.0, 0.5))),
+        ..container::Style::default()
+    })
+    .into()
+}
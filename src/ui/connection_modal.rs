@@ -1,9 +1,11 @@
-use iced::widget::{
-    button, checkbox, column, container, pick_list, row, text, text_input,
-};
+use iced::widget::{button, checkbox, column, container, pick_list, row, text, text_input};
 use iced::{Border, Color, Element, Length, Theme};
+use std::path::PathBuf;
+use zeroize::Zeroize;
 
-use crate::model::profile::ConnectionProfile;
+use crate::model::profile::{self, ConnectionProfile, TlsMode};
+use crate::store::secret_store;
+use crate::store::settings_store::OpenOnConnect;
 
 #[derive(Debug, Clone)]
 pub enum ConnectionMessage {
@@ -15,8 +17,19 @@ pub enum ConnectionMessage {
     SetPort(String),
     SetUsername(String),
     SetPassword(String),
-    ToggleStartTls(bool),
+    SetTlsMode(TlsMode),
+    ToggleRememberPassword(bool),
+    SetDefaultScript(String),
+    SetLocalDir(String),
+    /// App-wide, unlike the rest of the form
+    SetOpenOnConnect(OpenOnConnect),
+    /// Drop the pinned certificate fingerprint
+    ForgetCertificate,
+    /// Log in and out again without keeping the connection
+    Test,
     Connect,
+    /// Log in as another user, reusing the connection where possible
+    SwitchUser,
     Cancel,
 }
 
@@ -30,7 +43,26 @@ pub struct ConnectionState {
     pub port: String,
     pub username: String,
     pub password: String,
-    pub use_starttls: bool,
+    pub tls_mode: TlsMode,
+    pub remember_password: bool,
+    pub tls_fingerprint: Option<String>,
+    /// Script to open after connecting; empty opens the active one
+    pub default_script: String,
+    /// Folder to sync the scripts with; empty for none
+    pub local_dir: String,
+    /// Opened from "Switch user" while connected
+    pub switching: bool,
+    /// Outcome of the last "Test", shown below the buttons
+    pub test_status: Option<TestStatus>,
+    /// Shown under the name field while the typed name can't be saved
+    pub name_error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TestStatus {
+    Running,
+    Passed(String),
+    Failed(String),
 }
 
 impl Default for ConnectionState {
@@ -44,7 +76,14 @@ impl Default for ConnectionState {
             port: "4190".to_string(),
             username: String::new(),
             password: String::new(),
-            use_starttls: true,
+            tls_mode: TlsMode::StartTls,
+            remember_password: false,
+            tls_fingerprint: None,
+            default_script: String::new(),
+            local_dir: String::new(),
+            switching: false,
+            test_status: None,
+            name_error: None,
         }
     }
 }
@@ -53,39 +92,72 @@ impl ConnectionState {
     pub fn open(&mut self, profiles: Vec<ConnectionProfile>) {
         self.profiles = profiles;
         self.visible = true;
+        self.switching = false;
         if !self.profiles.is_empty() {
             self.select(0);
         }
     }
 
+    /// Hide the modal, wiping the typed password from memory.
     pub fn close(&mut self) {
         self.visible = false;
+        self.password.zeroize();
     }
 
     pub fn select(&mut self, index: usize) {
         if index < self.profiles.len() {
             let p = &self.profiles[index];
             self.selected_index = Some(index);
+            self.test_status = None;
+            self.name_error = None;
             self.name = p.name.clone();
-            self.host = p.host.clone();
+            self.host = p.display_host();
             self.port = p.port.to_string();
             self.username = p.username.clone();
-            self.use_starttls = p.use_starttls;
-            self.password.clear();
+            self.tls_mode = p.tls_mode;
+            self.remember_password = p.remember_password;
+            self.tls_fingerprint = p.tls_fingerprint.clone();
+            self.default_script = p.default_script.clone().unwrap_or_default();
+            self.local_dir = p
+                .local_dir
+                .as_ref()
+                .map(|d| d.display().to_string())
+                .unwrap_or_default();
+            self.password = if p.remember_password {
+                secret_store::load_password(p)
+            } else {
+                String::new()
+            };
         }
     }
 
+    /// Set the profile name, flagging one of only spaces. An empty name is
+    /// fine: the profile is named after its host.
+    pub fn set_name(&mut self, name: String) {
+        self.name_error = (!name.is_empty() && name.trim().is_empty())
+            .then(|| "The profile name can't be blank".to_string());
+        self.name = name;
+    }
+
     pub fn to_profile(&self) -> ConnectionProfile {
+        let host = profile::normalize_host(&self.host);
+        let name = self.name.trim();
         ConnectionProfile {
-            name: if self.name.is_empty() {
-                self.host.clone()
+            name: if name.is_empty() {
+                profile::display_host(&host)
             } else {
-                self.name.clone()
+                name.to_string()
             },
-            host: self.host.clone(),
+            host,
             port: self.port.parse().unwrap_or(4190),
             username: self.username.clone(),
-            use_starttls: self.use_starttls,
+            tls_mode: self.tls_mode,
+            remember_password: self.remember_password,
+            tls_fingerprint: self.tls_fingerprint.clone(),
+            default_script: Some(self.default_script.trim().to_string()).filter(|s| !s.is_empty()),
+            local_dir: Some(self.local_dir.trim())
+                .filter(|d| !d.is_empty())
+                .map(PathBuf::from),
         }
     }
 
@@ -100,7 +172,12 @@ impl ConnectionState {
     }
 }
 
-pub fn view(state: &ConnectionState) -> Element<'_, ConnectionMessage> {
+/// `busy` while a connection attempt runs; connecting again waits for it.
+pub fn view(
+    state: &ConnectionState,
+    open_on_connect: OpenOnConnect,
+    busy: bool,
+) -> Element<'_, ConnectionMessage> {
     let profile_names = state.profile_names();
     let selected = state.selected_name();
 
@@ -113,31 +190,106 @@ pub fn view(state: &ConnectionState) -> Element<'_, ConnectionMessage> {
     ]
     .spacing(4);
 
+    let name_error = state.name_error.as_ref().map(|error| {
+        row![text("").width(120), text(error).size(12).style(text::danger)].spacing(8)
+    });
+
+    let name = column![labeled_input("Profile Name:", &state.name, ConnectionMessage::SetName)]
+        .push_maybe(name_error)
+        .spacing(2);
+
     let form = column![
-        labeled_input("Profile Name:", &state.name, ConnectionMessage::SetName),
+        name,
         labeled_input("Host:", &state.host, ConnectionMessage::SetHost),
         labeled_input("Port:", &state.port, ConnectionMessage::SetPort),
         labeled_input("Username:", &state.username, ConnectionMessage::SetUsername),
         labeled_password("Password:", &state.password, ConnectionMessage::SetPassword),
-        checkbox("Use STARTTLS", state.use_starttls).on_toggle(ConnectionMessage::ToggleStartTls),
+        row![
+            text("").width(120),
+            checkbox("Remember password", state.remember_password)
+                .on_toggle(ConnectionMessage::ToggleRememberPassword)
+                .size(16),
+        ]
+        .spacing(8),
+        row![
+            text("Security:").width(120).size(14),
+            pick_list(TlsMode::ALL, Some(state.tls_mode), ConnectionMessage::SetTlsMode).width(280),
+        ]
+        .spacing(8)
+        .align_y(iced::Alignment::Center),
+        row![
+            text("Open script:").width(120).size(14),
+            text_input("Active script", &state.default_script)
+                .on_input(ConnectionMessage::SetDefaultScript)
+                .width(280),
+        ]
+        .spacing(8)
+        .align_y(iced::Alignment::Center),
+        row![
+            text("Scripts folder:").width(120).size(14),
+            text_input("None (chosen on first sync)", &state.local_dir)
+                .on_input(ConnectionMessage::SetLocalDir)
+                .width(280),
+        ]
+        .spacing(8)
+        .align_y(iced::Alignment::Center),
+        row![
+            text("After connecting:").width(120).size(14),
+            pick_list(
+                OpenOnConnect::ALL,
+                Some(open_on_connect),
+                ConnectionMessage::SetOpenOnConnect,
+            )
+            .width(280),
+        ]
+        .spacing(8)
+        .align_y(iced::Alignment::Center),
     ]
     .spacing(6);
 
+    let form = match &state.tls_fingerprint {
+        Some(fingerprint) => form.push(
+            row![
+                text("Trusted cert:").width(120).size(14),
+                text(fingerprint).size(10).width(Length::Fill),
+                button(text("Forget").size(12))
+                    .on_press(ConnectionMessage::ForgetCertificate)
+                    .style(button::secondary),
+            ]
+            .spacing(8)
+            .align_y(iced::Alignment::Center),
+        ),
+        None => form,
+    };
+
+    let (title, primary) = if state.switching {
+        ("Switch User", button("Switch user").on_press_maybe((!busy).then_some(ConnectionMessage::SwitchUser)))
+    } else {
+        ("Connect to Server", button("Connect").on_press_maybe((!busy).then_some(ConnectionMessage::Connect)))
+    };
+
+    let testing = state.test_status == Some(TestStatus::Running);
     let buttons = row![
-        button("Connect")
-            .on_press(ConnectionMessage::Connect)
-            .style(button::primary),
+        primary.style(button::primary),
+        button("Test")
+            .on_press_maybe((!testing).then_some(ConnectionMessage::Test))
+            .style(button::secondary),
         button("Cancel").on_press(ConnectionMessage::Cancel),
     ]
     .spacing(8);
 
+    let mut body = column![
+        text(title).size(18),
+        profile_row,
+        form,
+        buttons,
+    ];
+    if let Some(status) = &state.test_status {
+        body = body.push(test_status_line(status));
+    }
+
     let dialog = container(
-        column![
-            text("Connect to Server").size(18),
-            profile_row,
-            form,
-            buttons,
-        ]
+        body
         .spacing(12)
         .padding(20)
         .max_width(450),
@@ -175,6 +327,15 @@ pub fn view(state: &ConnectionState) -> Element<'_, ConnectionMessage> {
     .into()
 }
 
+fn test_status_line(status: &TestStatus) -> Element<'_, ConnectionMessage> {
+    let (message, kind): (&str, fn(&Theme) -> text::Style) = match status {
+        TestStatus::Running => ("Testing connection...", text::default),
+        TestStatus::Passed(message) => (message, text::success),
+        TestStatus::Failed(message) => (message, text::danger),
+    };
+    text(message).size(13).style(kind).into()
+}
+
 fn labeled_input<'a>(
     label: &'a str,
     value: &'a str,
@@ -205,3 +366,44 @@ fn labeled_password<'a>(
     .align_y(iced::Alignment::Center)
     .into()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_profile_normalizes_host() {
+        let cases = [
+            ("[::1]", "::1", "[::1]"),
+            ("::1", "::1", "[::1]"),
+            ("192.0.2.1", "192.0.2.1", "192.0.2.1"),
+            (" mail.example.com ", "mail.example.com", "mail.example.com"),
+        ];
+        for (input, host, name) in cases {
+            let state = ConnectionState {
+                host: input.to_string(),
+                ..Default::default()
+            };
+            let profile = state.to_profile();
+            assert_eq!(profile.host, host);
+            assert_eq!(profile.name, name);
+        }
+    }
+
+    #[test]
+    fn test_blank_profile_name() {
+        let mut state = ConnectionState {
+            host: "mail.example.com".to_string(),
+            ..Default::default()
+        };
+        state.set_name("   ".to_string());
+        assert!(state.name_error.is_some());
+        state.set_name(" Work ".to_string());
+        assert_eq!(state.name_error, None);
+        assert_eq!(state.to_profile().name, "Work");
+        // Left empty, the profile is named after its host
+        state.set_name(String::new());
+        assert_eq!(state.name_error, None);
+        assert_eq!(state.to_profile().name, "mail.example.com");
+    }
+}
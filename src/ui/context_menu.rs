@@ -0,0 +1,173 @@
+/// Right-click menus for the sidebar's rule cards and the script list.
+///
+/// The menu opens right below the entry it belongs to and lists the same
+/// operations as the inline buttons; any other message closes it.
+use iced::widget::{button, column, container, text};
+use iced::{Border, Color, Element, Length, Shadow, Theme, Vector};
+
+use crate::app::Message;
+use crate::net::managesieve::ScriptInfo;
+use crate::ui::script_list::ScriptListMessage;
+
+/// The entry a context menu was opened on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MenuTarget {
+    /// A rule card, by index into the rules
+    Rule(usize),
+    /// A script on the server, by name
+    Script(String),
+}
+
+/// One menu entry; without a message it is shown disabled.
+#[derive(Debug, Clone)]
+pub struct MenuItem<M> {
+    pub label: &'static str,
+    pub message: Option<M>,
+    /// Destructive, drawn in red
+    pub danger: bool,
+}
+
+impl<M> MenuItem<M> {
+    fn new(label: &'static str, message: Option<M>) -> Self {
+        Self {
+            label,
+            message,
+            danger: false,
+        }
+    }
+
+    fn danger(label: &'static str, message: Option<M>) -> Self {
+        Self {
+            label,
+            message,
+            danger: true,
+        }
+    }
+}
+
+/// Entries for the rule at `idx` of `count`.
+pub fn rule_items(idx: usize, count: usize) -> Vec<MenuItem<Message>> {
+    vec![
+        MenuItem::new("Duplicate", Some(Message::DuplicateRule(idx))),
+        MenuItem::new("Copy", Some(Message::CopyRule(idx))),
+        MenuItem::new("Move up", (idx > 0).then_some(Message::MoveRuleUp(idx))),
+        MenuItem::new("Move down", (idx + 1 < count).then_some(Message::MoveRuleDown(idx))),
+        MenuItem::danger("Remove...", Some(Message::ConfirmRemoveRule(idx))),
+    ]
+}
+
+/// Entries for a server script. While `busy`, the ones that talk to the
+/// server are off.
+pub fn script_items(script: &ScriptInfo, busy: bool) -> Vec<MenuItem<ScriptListMessage>> {
+    let idle = |msg: ScriptListMessage| (!busy).then_some(msg);
+    let name = script.name.clone();
+    let activation = if script.active {
+        MenuItem::new("Deactivate", idle(ScriptListMessage::DeactivateScripts))
+    } else {
+        MenuItem::new("Activate", idle(ScriptListMessage::ActivateScript(name.clone())))
+    };
+    vec![
+        MenuItem::new("Open", idle(ScriptListMessage::SelectScript(name.clone()))),
+        activation,
+        MenuItem::new("Rename", Some(ScriptListMessage::StartRename(name.clone()))),
+        MenuItem::danger("Delete...", idle(ScriptListMessage::ConfirmDelete(name))),
+    ]
+}
+
+pub fn view<'a, M: Clone + 'a>(items: Vec<MenuItem<M>>) -> Element<'a, M> {
+    let entries = items.into_iter().map(|item| {
+        let style = if item.danger {
+            menu_danger_button
+        } else {
+            button::text
+        };
+        button(text(item.label).size(13))
+            .on_press_maybe(item.message)
+            .style(style)
+            .width(Length::Fill)
+            .padding([3, 10])
+            .into()
+    });
+
+    container(column(entries).spacing(0))
+        .width(160)
+        .padding(4)
+        .style(|theme: &Theme| {
+            let p = theme.palette();
+            container::Style {
+                background: Some(iced::Background::Color(p.background)),
+                border: Border {
+                    color: Color::from_rgba(p.text.r, p.text.g, p.text.b, 0.2),
+                    width: 1.0,
+                    radius: 6.0.into(),
+                },
+                shadow: Shadow {
+                    color: Color::from_rgba(0.0, 0.0, 0.0, 0.2),
+                    offset: Vector::new(0.0, 2.0),
+                    blur_radius: 6.0,
+                },
+                ..container::Style::default()
+            }
+        })
+        .into()
+}
+
+fn menu_danger_button(theme: &Theme, status: button::Status) -> button::Style {
+    let danger = theme.extended_palette().danger.base.color;
+    button::Style {
+        text_color: danger,
+        ..button::text(theme, status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rule_items() {
+        let items = rule_items(0, 2);
+        let labels: Vec<&str> = items.iter().map(|i| i.label).collect();
+        assert_eq!(labels, vec!["Duplicate", "Copy", "Move up", "Move down", "Remove..."]);
+        assert!(matches!(items[0].message, Some(Message::DuplicateRule(0))));
+        assert!(matches!(items[1].message, Some(Message::CopyRule(0))));
+        // Already at the top
+        assert!(items[2].message.is_none());
+        assert!(matches!(items[3].message, Some(Message::MoveRuleDown(0))));
+        assert!(matches!(items[4].message, Some(Message::ConfirmRemoveRule(0))));
+        assert!(items[4].danger);
+
+        let items = rule_items(1, 2);
+        assert!(matches!(items[2].message, Some(Message::MoveRuleUp(1))));
+        assert!(items[3].message.is_none());
+    }
+
+    #[test]
+    fn test_script_items() {
+        let script = ScriptInfo {
+            name: "main".to_string(),
+            active: true,
+        };
+        let items = script_items(&script, false);
+        let labels: Vec<&str> = items.iter().map(|i| i.label).collect();
+        assert_eq!(labels, vec!["Open", "Deactivate", "Rename", "Delete..."]);
+        assert!(matches!(&items[0].message, Some(ScriptListMessage::SelectScript(n)) if n == "main"));
+        assert!(matches!(items[1].message, Some(ScriptListMessage::DeactivateScripts)));
+        assert!(matches!(&items[2].message, Some(ScriptListMessage::StartRename(n)) if n == "main"));
+        assert!(matches!(&items[3].message, Some(ScriptListMessage::ConfirmDelete(n)) if n == "main"));
+
+        let inactive = ScriptInfo {
+            active: false,
+            ..script
+        };
+        let items = script_items(&inactive, true);
+        assert_eq!(items[1].label, "Activate");
+        // Busy: only the local rename stays on
+        let enabled: Vec<&str> = items
+            .iter()
+            .filter(|i| i.message.is_some())
+            .map(|i| i.label)
+            .collect();
+        assert_eq!(enabled, vec!["Rename"]);
+    }
+}
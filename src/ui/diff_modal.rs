@@ -0,0 +1,119 @@
+use iced::widget::{button, column, container, horizontal_rule, row, scrollable, text};
+use iced::{Border, Color, Element, Font, Length, Theme};
+
+use crate::util::diff::LineChange;
+
+#[derive(Debug, Clone)]
+pub enum DiffMessage {
+    Close,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DiffState {
+    pub visible: bool,
+    pub script_name: String,
+    /// Server copy (old) against the editor buffer (new)
+    pub changes: Vec<LineChange>,
+}
+
+pub fn view(state: &DiffState) -> Element<'_, DiffMessage> {
+    let title = text(format!("Changes to \"{}\"", state.script_name))
+        .size(18)
+        .font(Font {
+            weight: iced::font::Weight::Bold,
+            ..Font::DEFAULT
+        });
+
+    let added = state
+        .changes
+        .iter()
+        .filter(|c| matches!(c, LineChange::Added(_)))
+        .count();
+    let removed = state
+        .changes
+        .iter()
+        .filter(|c| matches!(c, LineChange::Removed(_)))
+        .count();
+    let summary = if added == 0 && removed == 0 {
+        text("The editor matches the server copy.").size(13)
+    } else {
+        text(format!("{added} line(s) added, {removed} removed (server → editor)")).size(13)
+    };
+
+    let lines = state
+        .changes
+        .iter()
+        .fold(column![].spacing(0), |col, change| col.push(diff_line(change)));
+
+    let close_btn = button("Close")
+        .on_press(DiffMessage::Close)
+        .style(button::primary);
+
+    let dialog = container(
+        column![
+            title,
+            summary,
+            horizontal_rule(1),
+            scrollable(lines).height(Length::Fill),
+            horizontal_rule(1),
+            row![close_btn],
+        ]
+        .spacing(12)
+        .padding(24)
+        .max_width(760),
+    )
+    .max_height(560)
+    .style(|theme: &Theme| {
+        let palette = theme.palette();
+        container::Style {
+            background: Some(iced::Background::Color(palette.background)),
+            border: Border {
+                color: Color::from_rgba(
+                    palette.text.r,
+                    palette.text.g,
+                    palette.text.b,
+                    0.3,
+                ),
+                width: 1.0,
+                radius: 8.0.into(),
+            },
+            ..container::Style::default()
+        }
+    });
+
+    // Overlay: dark semi-transparent background + centered dialog
+    container(
+        container(dialog)
+            .center_x(Length::Fill)
+            .center_y(Length::Fill),
+    )
+    .width(Length::Fill)
+    .height(Length::Fill)
+    .style(|_theme: &Theme| container::Style {
+        background: Some(iced::Background::Color(Color::from_rgba(0.0, 0.0, 0.0, 0.5))),
+        ..container::Style::default()
+    })
+    .into()
+}
+
+fn diff_line(change: &LineChange) -> Element<'_, DiffMessage> {
+    let (marker, line, tint) = match change {
+        LineChange::Unchanged(l) => (' ', l, None),
+        LineChange::Added(l) => ('+', l, Some(Color::from_rgb(0.2, 0.7, 0.3))),
+        LineChange::Removed(l) => ('-', l, Some(Color::from_rgb(0.85, 0.2, 0.2))),
+    };
+
+    let mut label = text(format!("{marker} {line}")).size(13).font(Font::MONOSPACE);
+    if let Some(color) = tint {
+        label = label.color(color);
+    }
+
+    container(label)
+        .width(Length::Fill)
+        .padding([0, 6])
+        .style(move |_theme: &Theme| container::Style {
+            background: tint.map(|c| iced::Background::Color(Color { a: 0.1, ..c })),
+            ..container::Style::default()
+        })
+        .into()
+}
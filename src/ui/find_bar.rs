@@ -0,0 +1,74 @@
+use iced::widget::{button, checkbox, container, row, text_input};
+use iced::{Color, Element, Length, Theme};
+
+#[derive(Debug, Clone)]
+pub enum FindMessage {
+    /// Ctrl+F: open the bar, or close it when open
+    Toggle,
+    SetQuery(String),
+    SetReplacement(String),
+    ToggleCaseSensitive(bool),
+    /// Select the next match in the raw editor
+    FindNext,
+    ReplaceAll,
+    Close,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FindState {
+    pub visible: bool,
+    pub query: String,
+    pub replacement: String,
+    pub case_sensitive: bool,
+}
+
+/// The search field, focused when the bar opens.
+pub fn query_id() -> text_input::Id {
+    text_input::Id::new("find-query")
+}
+
+pub fn view(state: &FindState) -> Element<'_, FindMessage> {
+    let has_query = !state.query.is_empty();
+
+    let bar = row![
+        text_input("Find", &state.query)
+            .id(query_id())
+            .on_input(FindMessage::SetQuery)
+            .on_submit(FindMessage::FindNext)
+            .size(13)
+            .width(200),
+        text_input("Replace with", &state.replacement)
+            .on_input(FindMessage::SetReplacement)
+            .size(13)
+            .width(200),
+        checkbox("Match case", state.case_sensitive)
+            .on_toggle(FindMessage::ToggleCaseSensitive)
+            .size(14)
+            .text_size(13),
+        button("Find next")
+            .on_press_maybe(has_query.then_some(FindMessage::FindNext))
+            .style(button::secondary),
+        button("Replace all")
+            .on_press_maybe(has_query.then_some(FindMessage::ReplaceAll))
+            .style(button::secondary),
+        button("Close")
+            .on_press(FindMessage::Close)
+            .style(button::text),
+    ]
+    .spacing(6)
+    .align_y(iced::Alignment::Center);
+
+    container(bar)
+        .width(Length::Fill)
+        .padding([4, 8])
+        .style(|theme: &Theme| {
+            let p = theme.palette();
+            container::Style {
+                background: Some(iced::Background::Color(Color::from_rgba(
+                    p.text.r, p.text.g, p.text.b, 0.03,
+                ))),
+                ..container::Style::default()
+            }
+        })
+        .into()
+}
@@ -0,0 +1,293 @@
+/// A short SIEVE reference beside the editor.
+///
+/// The text comes from exhaustive matches on the model's enums, so a new
+/// test, match type or action doesn't build without its entry. What the
+/// selected filter uses is listed first.
+use iced::widget::{button, column, container, horizontal_space, row, scrollable, text};
+use iced::{Color, Element, Font, Length, Theme};
+
+use crate::app::Message;
+use crate::model::enums::{ActionType, ConditionTest, MatchType};
+use crate::model::rule::SieveRule;
+
+/// Tests in the order the reference lists them.
+pub const TESTS: &[ConditionTest] = &[
+    ConditionTest::Header,
+    ConditionTest::Address,
+    ConditionTest::Envelope,
+    ConditionTest::Size,
+    ConditionTest::Exists,
+    ConditionTest::Body,
+    ConditionTest::Hasflag,
+    ConditionTest::Not,
+    ConditionTest::True,
+    ConditionTest::False,
+];
+
+pub const MATCH_TYPES: &[MatchType] = &[
+    MatchType::Is,
+    MatchType::Contains,
+    MatchType::Matches,
+    MatchType::Regex,
+];
+
+pub const ACTIONS: &[ActionType] = &[
+    ActionType::Fileinto,
+    ActionType::Redirect,
+    ActionType::Reject,
+    ActionType::Discard,
+    ActionType::Keep,
+    ActionType::Stop,
+    ActionType::Setflag,
+    ActionType::Addflag,
+    ActionType::Removeflag,
+    ActionType::Vacation,
+    ActionType::Notify,
+    ActionType::Addheader,
+    ActionType::Deleteheader,
+];
+
+pub fn test_help(test: ConditionTest) -> &'static str {
+    match test {
+        ConditionTest::Header => "Compares the value of a header such as Subject or List-Id.",
+        ConditionTest::Address => {
+            "Compares the addresses in From, To, Cc and the like; can look at just the \
+             local part or the domain."
+        }
+        ConditionTest::Envelope => {
+            "Compares the SMTP sender or recipient, which can differ from From and To, \
+             e.g. for Bcc or mailing lists."
+        }
+        ConditionTest::Size => "Matches messages over or under a size, like 500K or 10M.",
+        ConditionTest::Exists => "Matches when all the named headers are present.",
+        ConditionTest::Body => "Searches the message text; the server must support \"body\".",
+        ConditionTest::Hasflag => {
+            "Checks the IMAP flags set so far, e.g. \\Flagged; needs \"imap4flags\"."
+        }
+        ConditionTest::Not => "Inverts a test: matches when it doesn't.",
+        ConditionTest::True => "Always matches.",
+        ConditionTest::False => "Never matches.",
+    }
+}
+
+pub fn match_help(match_type: MatchType) -> &'static str {
+    match match_type {
+        MatchType::Is => "The whole value equals the text, ignoring case.",
+        MatchType::Contains => "The text appears anywhere in the value.",
+        MatchType::Matches => {
+            "The whole value fits a pattern: * stands for any run of characters, \
+             ? for one."
+        }
+        MatchType::Regex => "The value matches a regular expression; needs \"regex\".",
+    }
+}
+
+pub fn action_help(action: ActionType) -> &'static str {
+    match action {
+        ActionType::Fileinto => "Files the message into a folder instead of the inbox.",
+        ActionType::Redirect => "Forwards the message to another address.",
+        ActionType::Reject => "Refuses the message and bounces it with your reason.",
+        ActionType::Discard => "Silently throws the message away.",
+        ActionType::Keep => "Delivers the message to the inbox, even if another action files it.",
+        ActionType::Stop => "Ends the script: later filters don't run for this message.",
+        ActionType::Setflag => "Replaces the message's flags, e.g. \\Seen or \\Flagged.",
+        ActionType::Addflag => "Adds flags to the message, e.g. \\Seen to mark it read.",
+        ActionType::Removeflag => "Takes flags off the message.",
+        ActionType::Vacation => "Sends an automatic reply, at most once every few days per sender.",
+        ActionType::Notify => "Sends a notification, e.g. by mail or to a chat service.",
+        ActionType::Addheader => "Adds a header field to the message.",
+        ActionType::Deleteheader => "Removes header fields, all of them or those matching a value.",
+    }
+}
+
+/// Tests, match types and actions of `rule`, each once, in the order of
+/// the reference.
+fn used_by(rule: &SieveRule) -> (Vec<ConditionTest>, Vec<MatchType>, Vec<ActionType>) {
+    let conditions: Vec<_> = rule
+        .conditions
+        .iter()
+        .chain(rule.alternatives.iter().flat_map(|b| &b.conditions))
+        .chain(rule.condition_tree.iter().flat_map(|t| t.leaves()))
+        .collect();
+    let actions: Vec<_> = rule
+        .actions
+        .iter()
+        .chain(rule.alternatives.iter().flat_map(|b| &b.actions))
+        .chain(rule.else_actions.iter().flatten())
+        .collect();
+
+    let tests = TESTS
+        .iter()
+        .copied()
+        .filter(|t| conditions.iter().any(|c| c.test_type == *t || (c.negate && *t == ConditionTest::Not)))
+        .collect();
+    let matches = MATCH_TYPES
+        .iter()
+        .copied()
+        .filter(|m| {
+            conditions.iter().any(|c| {
+                c.match_type == *m
+                    && !matches!(c.test_type, ConditionTest::Size | ConditionTest::Exists)
+            })
+        })
+        .collect();
+    let used = ACTIONS
+        .iter()
+        .copied()
+        .filter(|a| actions.iter().any(|action| action.action_type == *a))
+        .collect();
+    (tests, matches, used)
+}
+
+/// The reference, headed by what `rule` (the selected filter) uses.
+pub fn view<'a>(rule: Option<&SieveRule>) -> Element<'a, Message> {
+    let mut content = column![row![
+        heading("SIEVE reference"),
+        horizontal_space(),
+        button(text("Close").size(12))
+            .on_press(Message::ToggleHelp)
+            .style(button::text)
+            .padding([2, 6]),
+    ]
+    .align_y(iced::Alignment::Center)]
+    .spacing(6)
+    .padding(10);
+
+    if let Some(rule) = rule.filter(|r| r.raw_block.is_none()) {
+        let (tests, matches, actions) = used_by(rule);
+        if !(tests.is_empty() && matches.is_empty() && actions.is_empty()) {
+            content = content.push(heading("In this filter"));
+            content = content.extend(tests.into_iter().map(|t| entry(t.as_sieve(), test_help(t))));
+            content = content.extend(matches.into_iter().map(|m| entry(m.as_sieve(), match_help(m))));
+            content = content.extend(actions.into_iter().map(|a| entry(a.as_sieve(), action_help(a))));
+        }
+    }
+
+    content = content.push(heading("Tests"));
+    content = content.extend(TESTS.iter().map(|t| entry(t.as_sieve(), test_help(*t))));
+    content = content.push(heading("Match types"));
+    content = content.extend(MATCH_TYPES.iter().map(|m| entry(m.as_sieve(), match_help(*m))));
+    content = content.push(heading("Actions"));
+    content = content.extend(ACTIONS.iter().map(|a| entry(a.as_sieve(), action_help(*a))));
+
+    container(scrollable(content).height(Length::Fill))
+        .width(280)
+        .height(Length::Fill)
+        .style(|theme: &Theme| {
+            let p = theme.palette();
+            container::Style {
+                background: Some(iced::Background::Color(Color::from_rgba(
+                    p.text.r, p.text.g, p.text.b, 0.03,
+                ))),
+                ..container::Style::default()
+            }
+        })
+        .into()
+}
+
+fn heading<'a>(label: &'a str) -> Element<'a, Message> {
+    text(label)
+        .size(13)
+        .font(Font {
+            weight: iced::font::Weight::Bold,
+            ..Font::DEFAULT
+        })
+        .into()
+}
+
+fn entry<'a>(name: &'a str, help: &'a str) -> Element<'a, Message> {
+    column![
+        text(name).size(12).font(Font::MONOSPACE),
+        text(help).size(12),
+    ]
+    .spacing(1)
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::rule::{Action, Condition};
+
+    #[test]
+    fn test_every_variant_has_help() {
+        // No catch-all arms: a new variant doesn't build until it is
+        // listed here, and then must be in the reference too
+        let listed_test = |t: ConditionTest| match t {
+            ConditionTest::Header
+            | ConditionTest::Address
+            | ConditionTest::Envelope
+            | ConditionTest::Size
+            | ConditionTest::Exists
+            | ConditionTest::True
+            | ConditionTest::False
+            | ConditionTest::Not
+            | ConditionTest::Body
+            | ConditionTest::Hasflag => TESTS.contains(&t),
+        };
+        let listed_match = |m: MatchType| match m {
+            MatchType::Is | MatchType::Contains | MatchType::Matches | MatchType::Regex => {
+                MATCH_TYPES.contains(&m)
+            }
+        };
+        let listed_action = |a: ActionType| match a {
+            ActionType::Fileinto
+            | ActionType::Redirect
+            | ActionType::Reject
+            | ActionType::Discard
+            | ActionType::Keep
+            | ActionType::Stop
+            | ActionType::Setflag
+            | ActionType::Addflag
+            | ActionType::Removeflag
+            | ActionType::Vacation
+            | ActionType::Notify
+            | ActionType::Addheader
+            | ActionType::Deleteheader => ACTIONS.contains(&a),
+        };
+
+        assert_eq!(TESTS.len(), 10);
+        assert_eq!(MATCH_TYPES.len(), 4);
+        assert_eq!(ACTIONS.len(), 13);
+        for &t in TESTS {
+            assert!(listed_test(t) && !test_help(t).is_empty(), "{t}");
+            assert_eq!(ConditionTest::from_sieve(t.as_sieve()), Some(t));
+        }
+        for &m in MATCH_TYPES {
+            assert!(listed_match(m) && !match_help(m).is_empty(), "{m}");
+            assert_eq!(MatchType::from_sieve(m.as_sieve()), Some(m));
+        }
+        for &a in ACTIONS {
+            assert!(listed_action(a) && !action_help(a).is_empty(), "{a}");
+            assert_eq!(ActionType::from_sieve(a.as_sieve()), Some(a));
+        }
+    }
+
+    #[test]
+    fn test_used_by() {
+        let rule = SieveRule {
+            conditions: vec![
+                Condition {
+                    test_type: ConditionTest::Size,
+                    ..Default::default()
+                },
+                Condition {
+                    match_type: MatchType::Matches,
+                    negate: true,
+                    ..Default::default()
+                },
+            ],
+            actions: vec![Action {
+                action_type: ActionType::Fileinto,
+                ..Default::default()
+            }],
+            else_actions: Some(vec![Action::default()]),
+            ..Default::default()
+        };
+        let (tests, matches, actions) = used_by(&rule);
+        assert_eq!(tests, vec![ConditionTest::Header, ConditionTest::Size, ConditionTest::Not]);
+        // The size test's unused match type isn't listed
+        assert_eq!(matches, vec![MatchType::Matches]);
+        assert_eq!(actions, vec![ActionType::Fileinto, ActionType::Keep]);
+    }
+}
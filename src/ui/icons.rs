@@ -18,6 +18,18 @@ pub const DELETE_BIN: char = '\u{ec1d}';     // delete-bin-line
 pub const SUN: char = '\u{f1bc}';            // sun-line
 pub const MOON: char = '\u{ef72}';           // moon-line
 pub const INFORMATION: char = '\u{ee58}';    // information-line
+pub const CHECKBOX_CIRCLE: char = '\u{eb81}'; // checkbox-circle-line
+pub const ARROW_DOWN_S: char = '\u{ea4e}';   // arrow-down-s-line
+pub const ARROW_RIGHT_S: char = '\u{ea6e}';  // arrow-right-s-line
+pub const ARROW_UP_S: char = '\u{ea78}';     // arrow-up-s-line
+pub const ARROW_LEFT_RIGHT: char = '\u{ea62}'; // arrow-left-right-line
+pub const USER_SHARED: char = '\u{f272}';    // user-shared-line
+pub const MAGIC: char = '\u{eeea}';          // magic-line
+pub const FILE_COPY: char = '\u{ecd5}';      // file-copy-line
+pub const CLIPBOARD: char = '\u{eb91}';      // clipboard-line
+pub const FILE_ADD: char = '\u{ecc9}';       // file-add-line
+pub const LOCK: char = '\u{eece}';           // lock-line
+pub const LOCK_UNLOCK: char = '\u{eed2}';    // lock-unlock-line
 
 /// Create an icon + label button content.
 pub fn icon_text<'a, M: 'a>(icon: char, label: &'a str) -> Element<'a, M> {
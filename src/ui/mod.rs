@@ -1,11 +1,22 @@
 pub mod about_modal;
 pub mod action_row;
+pub mod close_modal;
 pub mod condition_row;
+pub mod confirm_modal;
 pub mod connection_modal;
+pub mod context_menu;
+pub mod diff_modal;
+pub mod find_bar;
+pub mod help_panel;
 pub mod icons;
+pub mod problems_panel;
 pub mod raw_editor;
 pub mod rule_card;
 pub mod script_list;
+pub mod sieve_highlight;
+pub mod splitter;
 pub mod status_bar;
+pub mod sync_modal;
+pub mod template_modal;
 pub mod toolbar;
 pub mod visual_editor;
@@ -0,0 +1,99 @@
+use iced::widget::{button, column, container, row, scrollable, text};
+use iced::{Border, Color, Element, Font, Length, Theme};
+
+use crate::model::rule::SieveRule;
+use crate::sieve::lint::Lint;
+
+#[derive(Debug, Clone)]
+pub enum ProblemsMessage {
+    /// The status bar's Problems count: open the panel, or close it when open
+    Toggle,
+    /// Select the rule a problem is about
+    Show(usize),
+    Close,
+}
+
+/// Script-wide lints, refreshed whenever the rules and the raw text are
+/// brought in step.
+#[derive(Debug, Clone, Default)]
+pub struct ProblemsState {
+    pub visible: bool,
+    pub lints: Vec<Lint>,
+}
+
+pub fn view<'a>(state: &'a ProblemsState, rules: &'a [SieveRule]) -> Element<'a, ProblemsMessage> {
+    let title = text("Problems").size(18).font(Font {
+        weight: iced::font::Weight::Bold,
+        ..Font::DEFAULT
+    });
+
+    let body: Element<'a, ProblemsMessage> = if state.lints.is_empty() {
+        text("No problems found.").size(13).into()
+    } else {
+        let entries = state.lints.iter().map(|lint| {
+            let name = rules
+                .get(lint.rule)
+                .map(|r| r.name.as_str())
+                .filter(|n| !n.is_empty())
+                .unwrap_or("(unnamed)");
+            row![
+                text(format!("{name}: {}", lint.kind))
+                    .size(13)
+                    .width(Length::Fill),
+                button(text("Show").size(12))
+                    .on_press(ProblemsMessage::Show(lint.rule))
+                    .style(button::secondary)
+                    .padding([2, 8]),
+            ]
+            .spacing(8)
+            .align_y(iced::Alignment::Center)
+            .into()
+        });
+        scrollable(column(entries).spacing(6)).height(Length::Shrink).into()
+    };
+
+    let hint = text("These are hints; the script can be saved and uploaded as it is.").size(12);
+
+    let close_btn = button("Close")
+        .on_press(ProblemsMessage::Close)
+        .style(button::primary);
+
+    let dialog = container(
+        column![title, body, hint, close_btn]
+            .spacing(12)
+            .padding(24)
+            .width(520),
+    )
+    .max_height(480)
+    .style(|theme: &Theme| {
+        let palette = theme.palette();
+        container::Style {
+            background: Some(iced::Background::Color(palette.background)),
+            border: Border {
+                color: Color::from_rgba(
+                    palette.text.r,
+                    palette.text.g,
+                    palette.text.b,
+                    0.3,
+                ),
+                width: 1.0,
+                radius: 8.0.into(),
+            },
+            ..container::Style::default()
+        }
+    });
+
+    // Overlay: dark semi-transparent background + centered dialog
+    container(
+        container(dialog)
+            .center_x(Length::Fill)
+            .center_y(Length::Fill),
+    )
+    .width(Length::Fill)
+    .height(Length::Fill)
+    .style(|_theme: &Theme| container::Style {
+        background: Some(iced::Background::Color(Color::from_rgba(0.0, 0.0, 0.0, 0.5))),
+        ..container::Style::default()
+    })
+    .into()
+}
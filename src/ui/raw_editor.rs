@@ -1,12 +1,65 @@
-use iced::widget::text_editor;
-use iced::{Element, Font};
+use iced::widget::{container, row, scrollable, text, text_editor};
+use iced::{Color, Element, Font, Length, Theme};
 
 use crate::app::Message;
+use crate::ui::sieve_highlight::{self, SieveHighlighter};
 
+const TEXT_SIZE: f32 = 14.0;
+const LINE_HEIGHT: f32 = 1.3;
+/// `text_editor`'s default padding, repeated on the gutter to line them up
+const PADDING: f32 = 5.0;
+
+/// Scrollable holding the gutter and the editor, so both scroll together.
+pub fn scroll_id() -> scrollable::Id {
+    scrollable::Id::new("raw-editor")
+}
+
+/// Height of one editor line in pixels.
+pub fn line_height_px() -> f32 {
+    TEXT_SIZE * LINE_HEIGHT
+}
+
+/// Vertical offset of the top of `line` (0-based) inside the scrollable.
+pub fn line_top(line: usize) -> f32 {
+    PADDING + line as f32 * line_height_px()
+}
+
+/// The editor grows to fit its text and sits next to a line-number gutter in
+/// one scrollable. Long lines still wrap, which pushes the numbers below them
+/// out of step until the next short line.
 pub fn view<'a>(content: &'a text_editor::Content) -> Element<'a, Message> {
-    text_editor(content)
+    let numbers = (1..=content.line_count().max(1))
+        .map(|n| n.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let gutter = container(
+        text(numbers)
+            .size(TEXT_SIZE)
+            .line_height(LINE_HEIGHT)
+            .font(Font::MONOSPACE)
+            .align_x(iced::alignment::Horizontal::Right)
+            .style(|theme: &Theme| {
+                let p = theme.palette();
+                text::Style {
+                    color: Some(Color::from_rgba(p.text.r, p.text.g, p.text.b, 0.4)),
+                }
+            }),
+    )
+    .padding([PADDING, 8.0]);
+
+    let editor = text_editor(content)
         .placeholder("Open a file or connect to a server...")
         .on_action(Message::EditorAction)
         .font(Font::MONOSPACE)
+        .size(TEXT_SIZE)
+        .line_height(LINE_HEIGHT)
+        .padding(PADDING)
+        .highlight_with::<SieveHighlighter>((), sieve_highlight::format);
+
+    scrollable(row![gutter, editor])
+        .id(scroll_id())
+        .on_scroll(Message::RawEditorScrolled)
+        .height(Length::Fill)
         .into()
 }
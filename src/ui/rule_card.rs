@@ -1,11 +1,16 @@
 use iced::widget::{
-    button, column, container, horizontal_rule, pick_list, row, text, text_input, toggler,
+    button, checkbox, column, container, horizontal_rule, pick_list, row, text, text_editor, text_input,
+    toggler,
 };
 use iced::{Border, Color, Element, Font, Length, Theme};
+use std::collections::HashSet;
 
 use crate::app::Message;
 use crate::model::enums::LogicOperator;
-use crate::model::rule::SieveRule;
+use crate::model::enums::{BodyTransform, ConditionTest};
+use crate::model::rule::{Action, Condition, ConditionNode, SieveRule};
+use crate::net::managesieve::Capabilities;
+use crate::sieve::{converter, lint};
 use crate::ui::action_row::{self, ActionMessage};
 use crate::ui::condition_row::{self, ConditionMessage};
 use crate::ui::icons;
@@ -15,11 +20,38 @@ pub enum RuleMessage {
     SetName(String),
     SetEnabled(bool),
     SetLogic(LogicOption),
+    SetNegateGroup(bool),
+    SetCommentedOut(bool),
+    NotesAction(text_editor::Action),
+    /// Edit of a rule kept as raw SIEVE
+    RawBlockAction(text_editor::Action),
     RemoveRule,
     AddCondition,
     AddAction,
     ConditionMsg(usize, ConditionMessage),
     ActionMsg(usize, ActionMessage),
+    AddElsIf,
+    RemoveElsIf(usize),
+    SetBranchLogic(usize, LogicOption),
+    AddBranchCondition(usize),
+    AddBranchAction(usize),
+    BranchConditionMsg(usize, usize, ConditionMessage),
+    BranchActionMsg(usize, usize, ActionMessage),
+    AddElse,
+    RemoveElse,
+    AddElseAction,
+    ElseActionMsg(usize, ActionMessage),
+    /// Put `stop` after the other actions of the rule (`None`) or of one
+    /// of its branches, so they run
+    MoveStopLast(Option<BranchId>),
+    ToggleBranch(BranchId),
+}
+
+/// Identifies an `elsif` or `else` section of the selected rule, for collapsing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BranchId {
+    ElsIf(usize),
+    Else,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -41,11 +73,14 @@ pub const LOGIC_OPTIONS: &[LogicOption] = &[
 
 // ─── Sidebar card (compact) ────────────────────────────────────────
 
-/// Sidebar card as a clickable button that sends `Message::SelectRule`.
+/// Sidebar card as a clickable button that sends `Message::SelectRule`,
+/// with move up/down buttons beside it.
 pub fn sidebar_card_button<'a>(
     rule: &'a SieveRule,
     selected: bool,
+    duplicate: bool,
     idx: usize,
+    count: usize,
 ) -> Element<'a, Message> {
     let name = if rule.name.is_empty() {
         "(unnamed)"
@@ -101,13 +136,30 @@ pub fn sidebar_card_button<'a>(
         );
     }
 
-    let nc = rule.conditions.len();
+    let nc = match &rule.condition_tree {
+        Some(tree) => tree.leaves().len(),
+        None => rule.conditions.len(),
+    };
     let na = rule.actions.len();
     info = info.push(text(format!("{nc} cond, {na} act")).size(11).style(muted_text));
+    if duplicate {
+        info = info.push(
+            container(text("same name").size(10).color(Color::WHITE))
+                .padding([1, 6])
+                .style(|_theme: &Theme| container::Style {
+                    background: Some(iced::Background::Color(Color::from_rgb(0.85, 0.55, 0.1))),
+                    border: Border {
+                        radius: 8.0.into(),
+                        ..Border::default()
+                    },
+                    ..container::Style::default()
+                }),
+        );
+    }
 
     content = content.push(info);
 
-    button(content)
+    let card = button(content)
         .on_press(Message::SelectRule(idx))
         .width(Length::Fill)
         .padding(10)
@@ -133,15 +185,41 @@ pub fn sidebar_card_button<'a>(
                 },
                 ..button::Style::default()
             }
-        })
+        });
+
+    let move_buttons = column![
+        move_button(icons::ARROW_UP_S, (idx > 0).then_some(Message::MoveRuleUp(idx))),
+        move_button(
+            icons::ARROW_DOWN_S,
+            (idx + 1 < count).then_some(Message::MoveRuleDown(idx)),
+        ),
+    ]
+    .spacing(2);
+
+    row![card, move_buttons]
+        .spacing(4)
+        .align_y(iced::Alignment::Center)
+        .into()
+}
+
+/// Small arrow button; disabled when `on_press` is `None`.
+fn move_button<'a>(icon: char, on_press: Option<Message>) -> Element<'a, Message> {
+    button(icons::icon(icon, 14))
+        .on_press_maybe(on_press)
+        .style(button::text)
+        .padding([0, 2])
         .into()
 }
 
 // ─── Detail panel sections ─────────────────────────────────────────
 
-/// Filter Details card: name, enabled toggler, logic operator
-pub fn detail_filter_info(rule: &SieveRule) -> Element<'_, RuleMessage> {
-    let content = column![
+/// Filter Details card: name, enabled toggler, logic operator, required extensions
+pub fn detail_filter_info<'a>(
+    rule: &'a SieveRule,
+    notes: &'a text_editor::Content,
+    capabilities: Option<&Capabilities>,
+) -> Element<'a, RuleMessage> {
+    let mut content = column![
         // Header
         text("Filter Details")
             .size(15)
@@ -158,6 +236,15 @@ pub fn detail_filter_info(rule: &SieveRule) -> Element<'_, RuleMessage> {
                 .width(Length::Fill),
         ]
         .spacing(4),
+        // Notes
+        column![
+            text("Notes").size(11).style(muted_text),
+            text_editor(notes)
+                .placeholder("Why this filter exists")
+                .on_action(RuleMessage::NotesAction)
+                .height(72),
+        ]
+        .spacing(4),
         // Enable toggle
         row![
             column![
@@ -166,6 +253,13 @@ pub fn detail_filter_info(rule: &SieveRule) -> Element<'_, RuleMessage> {
                     .size(11)
                     .style(muted_text),
             ]
+            // Disabled filters are marked [DISABLED] unless commented out
+            .push_maybe((!rule.enabled).then(|| {
+                checkbox("Comment out in the script (/* ... */)", rule.commented_out)
+                    .on_toggle(RuleMessage::SetCommentedOut)
+                    .size(14)
+                    .text_size(12)
+            }))
             .spacing(2)
             .width(Length::Fill),
             toggler(rule.enabled).on_toggle(RuleMessage::SetEnabled),
@@ -184,37 +278,85 @@ pub fn detail_filter_info(rule: &SieveRule) -> Element<'_, RuleMessage> {
             .width(180),
         ]
         .spacing(4),
+        checkbox("Invert whole match", rule.negate_group)
+            .on_toggle(RuleMessage::SetNegateGroup)
+            .size(14)
+            .text_size(13),
     ]
     .spacing(10);
 
+    let requires = converter::rule_requires(rule);
+    if !requires.is_empty() {
+        let badges = requires.into_iter().fold(row![].spacing(4), |badges, ext| {
+            let supported = capabilities.is_none_or(|caps| caps.supports_extension(&ext));
+            badges.push(extension_badge(ext, supported))
+        });
+        content = content.push(
+            column![text("Extensions").size(11).style(muted_text), badges.wrap()].spacing(4),
+        );
+    }
+
     section_card(content)
 }
 
+/// A required extension; red when the connected server does not offer it.
+fn extension_badge<'a>(ext: String, supported: bool) -> Element<'a, RuleMessage> {
+    container(text(ext).size(11))
+        .padding([2, 8])
+        .style(move |theme: &Theme| {
+            let p = theme.palette();
+            let color = if supported { p.text } else { p.danger };
+            container::Style {
+                text_color: (!supported).then_some(p.danger),
+                background: Some(iced::Background::Color(Color::from_rgba(
+                    color.r, color.g, color.b, 0.08,
+                ))),
+                border: Border {
+                    color: Color::from_rgba(color.r, color.g, color.b, 0.3),
+                    width: 1.0,
+                    radius: 10.0.into(),
+                },
+                ..container::Style::default()
+            }
+        })
+        .into()
+}
+
 /// Conditions card with "+ Add Condition" button in header
 pub fn detail_conditions(rule: &SieveRule) -> Element<'_, RuleMessage> {
     let mut content = column![].spacing(6);
 
     // Header row
-    content = content.push(
-        row![
-            text("Conditions")
-                .size(15)
-                .font(Font {
-                    weight: iced::font::Weight::Bold,
-                    ..Font::DEFAULT
-                }),
-            iced::widget::horizontal_space().width(Length::Fill),
+    let mut header = row![
+        text("Conditions")
+            .size(15)
+            .font(Font {
+                weight: iced::font::Weight::Bold,
+                ..Font::DEFAULT
+            }),
+        iced::widget::horizontal_space().width(Length::Fill),
+    ]
+    .align_y(iced::Alignment::Center);
+    if rule.condition_tree.is_none() {
+        header = header.push(
             button(icons::icon_text(icons::ADD_CIRCLE, "Add Condition"))
                 .on_press(RuleMessage::AddCondition)
                 .style(button::secondary)
                 .padding([3, 8]),
-        ]
-        .align_y(iced::Alignment::Center),
-    );
+        );
+    }
+    content = content.push(header);
 
     content = content.push(horizontal_rule(1));
 
-    if rule.conditions.is_empty() {
+    if let Some(tree) = &rule.condition_tree {
+        content = content.push(
+            text("Nested groups are shown read-only; edit them in the Raw tab.")
+                .size(12)
+                .style(muted_text),
+        );
+        content = content.push(condition_tree(tree, 0));
+    } else if rule.conditions.is_empty() {
         content = content.push(
             text("No conditions yet. Add one to start filtering.")
                 .size(12)
@@ -223,7 +365,7 @@ pub fn detail_conditions(rule: &SieveRule) -> Element<'_, RuleMessage> {
     } else {
         for (i, cond) in rule.conditions.iter().enumerate() {
             content = content.push(
-                condition_row::view(cond, i + 1)
+                condition_row::view(cond, i + 1, converter::can_disable(cond))
                     .map(move |msg| RuleMessage::ConditionMsg(i, msg)),
             );
         }
@@ -233,7 +375,11 @@ pub fn detail_conditions(rule: &SieveRule) -> Element<'_, RuleMessage> {
 }
 
 /// Actions card with "+ Add Action" button in header
-pub fn detail_actions(rule: &SieveRule) -> Element<'_, RuleMessage> {
+/// `raw_block` holds the rule's raw SIEVE when the model can't represent it.
+pub fn detail_actions<'a>(
+    rule: &'a SieveRule,
+    raw_block: &'a text_editor::Content,
+) -> Element<'a, RuleMessage> {
     let mut content = column![].spacing(6);
 
     // Header row
@@ -256,24 +402,27 @@ pub fn detail_actions(rule: &SieveRule) -> Element<'_, RuleMessage> {
 
     content = content.push(horizontal_rule(1));
 
-    // Raw block display
-    if let Some(raw) = &rule.raw_block {
+    // Raw block, editable in place; once it is valid SIEVE the next sync
+    // turns it back into conditions and actions
+    if rule.raw_block.is_some() {
         content = content.push(text("Unrecognized construct (raw):").size(12));
         content = content.push(
-            container(text(raw).size(12))
+            text_editor(raw_block)
+                .on_action(RuleMessage::RawBlockAction)
+                .font(Font::MONOSPACE)
+                .size(12)
                 .padding(4)
-                .width(Length::Fill)
-                .style(|theme: &Theme| {
+                .style(|theme: &Theme, status| {
                     let p = theme.palette();
-                    container::Style {
-                        background: Some(iced::Background::Color(Color::from_rgba(
+                    text_editor::Style {
+                        background: iced::Background::Color(Color::from_rgba(
                             p.text.r, p.text.g, p.text.b, 0.05,
-                        ))),
+                        )),
                         border: Border {
                             radius: 4.0.into(),
                             ..Border::default()
                         },
-                        ..container::Style::default()
+                        ..text_editor::default(theme, status)
                     }
                 }),
         );
@@ -286,14 +435,264 @@ pub fn detail_actions(rule: &SieveRule) -> Element<'_, RuleMessage> {
                 .style(muted_text),
         );
     } else {
-        for (i, action) in rule.actions.iter().enumerate() {
-            content = content.push(
-                action_row::view(action, i + 1).map(move |msg| RuleMessage::ActionMsg(i, msg)),
+        content = content.push(action_list(&rule.actions, None, RuleMessage::ActionMsg));
+    }
+
+    section_card(content)
+}
+
+/// "Else if" and "Otherwise" cards, each collapsible, plus buttons to add them.
+pub fn detail_alternatives<'a>(
+    rule: &'a SieveRule,
+    collapsed: &HashSet<BranchId>,
+) -> Element<'a, RuleMessage> {
+    let mut content = column![].spacing(12);
+
+    for (bi, branch) in rule.alternatives.iter().enumerate() {
+        let id = BranchId::ElsIf(bi);
+        let is_collapsed = collapsed.contains(&id);
+        let mut card = column![branch_header(
+            format!("Else if #{}", bi + 1),
+            id,
+            is_collapsed,
+            RuleMessage::RemoveElsIf(bi),
+        )]
+        .spacing(6);
+
+        if !is_collapsed {
+            card = card.push(horizontal_rule(1));
+            card = card.push(
+                column![
+                    text("Match Logic").size(11).style(muted_text),
+                    pick_list(LOGIC_OPTIONS, Some(LogicOption(branch.logic)), move |opt| {
+                        RuleMessage::SetBranchLogic(bi, opt)
+                    })
+                    .width(180),
+                ]
+                .spacing(4),
             );
+            card = card.push(sub_heading(
+                "Conditions",
+                "Add Condition",
+                RuleMessage::AddBranchCondition(bi),
+            ));
+            card = card.push(condition_list(&branch.conditions, move |ci, msg| {
+                RuleMessage::BranchConditionMsg(bi, ci, msg)
+            }));
+            card = card.push(sub_heading(
+                "Actions",
+                "Add Action",
+                RuleMessage::AddBranchAction(bi),
+            ));
+            let branch_id = Some(BranchId::ElsIf(bi));
+            card = card.push(action_list(&branch.actions, branch_id, move |ai, msg| {
+                RuleMessage::BranchActionMsg(bi, ai, msg)
+            }));
         }
+
+        content = content.push(section_card(card));
     }
 
-    section_card(content)
+    if let Some(else_actions) = &rule.else_actions {
+        let is_collapsed = collapsed.contains(&BranchId::Else);
+        let mut card = column![branch_header(
+            "Otherwise".to_string(),
+            BranchId::Else,
+            is_collapsed,
+            RuleMessage::RemoveElse,
+        )]
+        .spacing(6);
+
+        if !is_collapsed {
+            card = card.push(horizontal_rule(1));
+            card = card.push(sub_heading("Actions", "Add Action", RuleMessage::AddElseAction));
+            card = card.push(action_list(else_actions, Some(BranchId::Else), RuleMessage::ElseActionMsg));
+        }
+
+        content = content.push(section_card(card));
+    }
+
+    let mut buttons = row![button(icons::icon_text(icons::ADD_CIRCLE, "Add Else If"))
+        .on_press(RuleMessage::AddElsIf)
+        .style(button::secondary)
+        .padding([3, 8])]
+    .spacing(8);
+    if rule.else_actions.is_none() {
+        buttons = buttons.push(
+            button(icons::icon_text(icons::ADD_CIRCLE, "Add Otherwise"))
+                .on_press(RuleMessage::AddElse)
+                .style(button::secondary)
+                .padding([3, 8]),
+        );
+    }
+    content = content.push(buttons);
+
+    content.into()
+}
+
+fn branch_header<'a>(
+    title: String,
+    id: BranchId,
+    collapsed: bool,
+    on_remove: RuleMessage,
+) -> Element<'a, RuleMessage> {
+    let arrow = if collapsed {
+        icons::ARROW_RIGHT_S
+    } else {
+        icons::ARROW_DOWN_S
+    };
+
+    row![
+        button(
+            row![
+                icons::icon(arrow, 16),
+                text(title).size(15).font(Font {
+                    weight: iced::font::Weight::Bold,
+                    ..Font::DEFAULT
+                }),
+            ]
+            .spacing(4)
+            .align_y(iced::Alignment::Center),
+        )
+        .on_press(RuleMessage::ToggleBranch(id))
+        .style(button::text)
+        .padding(0),
+        iced::widget::horizontal_space().width(Length::Fill),
+        button(
+            text(icons::DELETE_BIN.to_string())
+                .font(icons::ICON_FONT)
+                .size(14)
+                .color(Color::from_rgb(0.85, 0.2, 0.2))
+        )
+        .on_press(on_remove)
+        .style(|_theme: &Theme, _status| button::Style {
+            background: None,
+            ..button::Style::default()
+        })
+        .padding([2, 6]),
+    ]
+    .align_y(iced::Alignment::Center)
+    .into()
+}
+
+fn sub_heading<'a>(
+    title: &'a str,
+    add_label: &'a str,
+    on_add: RuleMessage,
+) -> Element<'a, RuleMessage> {
+    row![
+        text(title).size(13).style(muted_text),
+        iced::widget::horizontal_space().width(Length::Fill),
+        button(icons::icon_text(icons::ADD_CIRCLE, add_label))
+            .on_press(on_add)
+            .style(button::secondary)
+            .padding([3, 8]),
+    ]
+    .align_y(iced::Alignment::Center)
+    .into()
+}
+
+/// Read-only view of a nested condition tree, one indented line per node.
+fn condition_tree(node: &ConditionNode, depth: u16) -> Element<'_, RuleMessage> {
+    let indent = f32::from(depth) * 16.0;
+    match node {
+        ConditionNode::Leaf(cond) => container(text(describe_condition(cond)).size(13))
+            .padding(iced::Padding::ZERO.left(indent))
+            .into(),
+        ConditionNode::Group { logic, children } => {
+            let label = format!("{} of:", logic_label(*logic));
+            let heading = container(text(label).size(13).font(Font {
+                weight: iced::font::Weight::Bold,
+                ..Font::DEFAULT
+            }))
+            .padding(iced::Padding::ZERO.left(indent));
+            children
+                .iter()
+                .fold(column![heading].spacing(4), |col, child| {
+                    col.push(condition_tree(child, depth + 1))
+                })
+                .into()
+        }
+    }
+}
+
+fn logic_label(logic: LogicOperator) -> &'static str {
+    match logic {
+        LogicOperator::AllOf => "All",
+        LogicOperator::AnyOf => "Any",
+    }
+}
+
+/// One-line summary of a condition, e.g. `not From contains "boss"`.
+fn describe_condition(cond: &Condition) -> String {
+    let not = if cond.negate { "not " } else { "" };
+    let quoted = |items: &[String]| {
+        items
+            .iter()
+            .map(|s| format!("\"{s}\""))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let headers = cond.header_names.join(", ");
+    let summary = match cond.test_type {
+        ConditionTest::Size => format!("size {} {}", cond.size_comparator, cond.size_value),
+        ConditionTest::Exists => format!("{headers} exists"),
+        ConditionTest::Body => {
+            let part = match &cond.transform {
+                BodyTransform::Text => String::new(),
+                BodyTransform::Raw => " :raw".to_string(),
+                BodyTransform::Content(media) => format!(" :content \"{media}\""),
+            };
+            format!("body{part} {} {}", cond.match_type, quoted(&cond.keys))
+        }
+        ConditionTest::Hasflag => format!("hasflag {} {}", cond.match_type, quoted(&cond.keys)),
+        ConditionTest::True | ConditionTest::False => cond.test_type.as_sieve().to_string(),
+        _ => format!("{headers} {} {}", cond.match_type, quoted(&cond.keys)),
+    };
+    format!("{not}{summary}")
+}
+
+fn condition_list<'a>(
+    conditions: &'a [Condition],
+    wrap: impl Fn(usize, ConditionMessage) -> RuleMessage + Copy + 'a,
+) -> Element<'a, RuleMessage> {
+    let mut list = column![];
+    for (i, cond) in conditions.iter().enumerate() {
+        list = list.push(condition_row::view(cond, i + 1, false).map(move |msg| wrap(i, msg)));
+    }
+    list.into()
+}
+
+/// The actions of the rule or of `branch`. Those after a `stop` are
+/// dimmed, with a button above them to move the `stop` last.
+fn action_list<'a>(
+    actions: &'a [Action],
+    branch: Option<BranchId>,
+    wrap: impl Fn(usize, ActionMessage) -> RuleMessage + Copy + 'a,
+) -> Element<'a, RuleMessage> {
+    let dead = lint::first_dead_action(actions);
+    let mut list = column![];
+    for (i, action) in actions.iter().enumerate() {
+        if dead == Some(i) {
+            list = list.push(
+                row![
+                    text("The actions below come after stop and never run.")
+                        .size(12)
+                        .style(muted_text)
+                        .width(Length::Fill),
+                    button(text("Auto-fix: move stop last").size(12))
+                        .on_press(RuleMessage::MoveStopLast(branch))
+                        .style(button::secondary)
+                        .padding([3, 8]),
+                ]
+                .spacing(8)
+                .align_y(iced::Alignment::Center),
+            );
+        }
+        let is_dead = dead.is_some_and(|first| i >= first);
+        list = list.push(action_row::view(action, i + 1, is_dead).map(move |msg| wrap(i, msg)));
+    }
+    list.into()
 }
 
 // ─── Shared helpers ────────────────────────────────────────────────
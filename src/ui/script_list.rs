@@ -1,17 +1,47 @@
-use iced::widget::{button, column, container, row, scrollable, text};
+use iced::widget::{button, column, container, mouse_area, row, scrollable, text, text_input};
 use iced::{Border, Color, Element, Font, Length, Theme};
 
 use crate::net::managesieve::ScriptInfo;
+use crate::ui::context_menu;
 
 #[derive(Debug, Clone)]
 pub enum ScriptListMessage {
+    /// Right-click on a script
+    ShowMenu(String),
     SelectScript(String),
     ActivateScript(String),
     DeactivateScripts,
+    /// Ask before deleting; `DeleteScript` does the deletion
+    ConfirmDelete(String),
     DeleteScript(String),
+    StartRename(String),
+    SetRenameText(String),
+    RenameScript { old: String, new: String },
+    CancelRename,
+    ExportAll,
+    ImportAll,
+    /// Compare with the profile's scripts folder
+    SyncFolder,
 }
 
-pub fn view<'a>(scripts: &'a [ScriptInfo], selected: Option<&'a str>) -> Element<'a, ScriptListMessage> {
+/// Inline rename in progress: the script being renamed and the edited name.
+#[derive(Debug, Clone)]
+pub struct RenameState {
+    pub old: String,
+    pub new: String,
+}
+
+/// While `busy`, the buttons that talk to the server are off. `width` is
+/// the list's width as the user last dragged it.
+pub fn view<'a>(
+    scripts: &'a [ScriptInfo],
+    selected: Option<&'a str>,
+    renaming: Option<&'a RenameState>,
+    menu: Option<&'a str>,
+    busy: bool,
+    width: f32,
+) -> Element<'a, ScriptListMessage> {
+    let idle = |msg: ScriptListMessage| (!busy).then_some(msg);
     let mut content = column![text("Scripts").size(14)].spacing(2).padding(4);
 
     if scripts.is_empty() {
@@ -39,70 +69,120 @@ pub fn view<'a>(scripts: &'a [ScriptInfo], selected: Option<&'a str>) -> Element
         let name = script.name.clone();
         let name2 = script.name.clone();
         let name3 = script.name.clone();
+        let name4 = script.name.clone();
 
-        let mut entry = column![
-            button(text(label).font(font).size(13))
-                .on_press(ScriptListMessage::SelectScript(name))
-                .style(if is_selected {
-                    button::primary
-                } else {
-                    button::text
-                })
-                .width(Length::Fill),
-        ];
+        let rename = renaming.filter(|r| r.old == script.name);
+
+        let mut entry = if let Some(rename) = rename {
+            let submit = ScriptListMessage::RenameScript {
+                old: rename.old.clone(),
+                new: rename.new.clone(),
+            };
+            column![
+                text_input("New name", &rename.new)
+                    .on_input(ScriptListMessage::SetRenameText)
+                    .on_submit(submit.clone())
+                    .size(13),
+                row![
+                    button(text("Rename").size(11))
+                        .on_press_maybe(idle(submit))
+                        .style(button::primary),
+                    button(text("Cancel").size(11))
+                        .on_press(ScriptListMessage::CancelRename)
+                        .style(button::secondary),
+                ]
+                .spacing(2),
+            ]
+            .spacing(2)
+        } else {
+            column![
+                button(text(label).font(font).size(13))
+                    .on_press_maybe(idle(ScriptListMessage::SelectScript(name)))
+                    .style(if is_selected {
+                        button::primary
+                    } else {
+                        button::text
+                    })
+                    .width(Length::Fill),
+            ]
+        };
 
         // Context actions (shown for selected script)
-        if is_selected {
+        if is_selected && rename.is_none() {
             let mut actions = row![].spacing(2);
             if script.active {
                 actions = actions.push(
                     button(text("Deactivate").size(11))
-                        .on_press(ScriptListMessage::DeactivateScripts)
+                        .on_press_maybe(idle(ScriptListMessage::DeactivateScripts))
                         .style(button::secondary),
                 );
             } else {
                 actions = actions.push(
                     button(text("Activate").size(11))
-                        .on_press(ScriptListMessage::ActivateScript(name2))
+                        .on_press_maybe(idle(ScriptListMessage::ActivateScript(name2)))
                         .style(button::secondary),
                 );
             }
+            actions = actions.push(
+                button(text("Rename").size(11))
+                    .on_press(ScriptListMessage::StartRename(name4))
+                    .style(button::secondary),
+            );
             actions = actions.push(
                 button(text("Delete").size(11))
-                    .on_press(ScriptListMessage::DeleteScript(name3))
+                    .on_press_maybe(idle(ScriptListMessage::ConfirmDelete(name3)))
                     .style(button::danger),
             );
             entry = entry.push(actions);
         }
 
+        let card = container(entry)
+            .width(Length::Fill)
+            .style(move |theme: &Theme| {
+                let palette = theme.palette();
+                container::Style {
+                    border: Border {
+                        color: Color::from_rgba(
+                            palette.text.r,
+                            palette.text.g,
+                            palette.text.b,
+                            0.1,
+                        ),
+                        width: if is_selected { 1.0 } else { 0.0 },
+                        radius: 4.0.into(),
+                    },
+                    ..container::Style::default()
+                }
+            });
         content = content.push(
-            container(entry)
-                .width(Length::Fill)
-                .style(move |theme: &Theme| {
-                    let palette = theme.palette();
-                    container::Style {
-                        border: Border {
-                            color: Color::from_rgba(
-                                palette.text.r,
-                                palette.text.g,
-                                palette.text.b,
-                                0.1,
-                            ),
-                            width: if is_selected { 1.0 } else { 0.0 },
-                            radius: 4.0.into(),
-                        },
-                        ..container::Style::default()
-                    }
-                }),
+            mouse_area(card).on_right_press(ScriptListMessage::ShowMenu(script.name.clone())),
         );
+        if menu == Some(script.name.as_str()) {
+            content = content.push(context_menu::view(context_menu::script_items(script, busy)));
+        }
     }
 
-    container(
+    let footer = row![
+        button(text("Export all").size(11))
+            .on_press_maybe((!scripts.is_empty()).then(|| idle(ScriptListMessage::ExportAll)).flatten())
+            .style(button::secondary),
+        button(text("Import...").size(11))
+            .on_press_maybe(idle(ScriptListMessage::ImportAll))
+            .style(button::secondary),
+        button(text("Sync folder").size(11))
+            .on_press_maybe(idle(ScriptListMessage::SyncFolder))
+            .style(button::secondary),
+    ]
+    .spacing(4)
+    .padding(4);
+
+    container(column![
         scrollable(content)
             .height(Length::Fill)
             .width(Length::Fill),
-    )
-    .width(200)
+        footer,
+    ])
+    .width(width)
     .height(Length::Fill)
     .into()
 }
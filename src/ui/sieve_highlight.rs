@@ -0,0 +1,266 @@
+/// Syntax highlighting for the raw editor, driven by the SIEVE lexer.
+///
+/// Lines are lexed one at a time. Strings and comments that run past the end
+/// of a line carry a mode over to the next one; anything that doesn't lex
+/// (typically half-typed input) is left in the default color.
+use std::ops::Range;
+
+use iced::advanced::text::highlighter::{self, Format};
+use iced::{Color, Font, Theme};
+
+use crate::sieve::lexer::{Token, tokenize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Highlight {
+    Keyword,
+    Tag,
+    String,
+    Comment,
+    Number,
+}
+
+/// Style class of a token; `None` for punctuation.
+pub fn classify(token: &Token) -> Option<Highlight> {
+    match token {
+        Token::Identifier(_) => Some(Highlight::Keyword),
+        Token::Tag(_) => Some(Highlight::Tag),
+        Token::QuotedString(_) | Token::MultiLineString(_) => Some(Highlight::String),
+        Token::Comment(_) | Token::BlockComment(_) => Some(Highlight::Comment),
+        Token::Number(_) => Some(Highlight::Number),
+        Token::Semicolon
+        | Token::Comma
+        | Token::LParen
+        | Token::RParen
+        | Token::LBrace
+        | Token::RBrace
+        | Token::LBracket
+        | Token::RBracket => None,
+    }
+}
+
+/// What the start of a line is inside of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Code,
+    QuotedString,
+    MultiLineString,
+    BlockComment,
+}
+
+pub struct SieveHighlighter {
+    /// Mode at the start of each line highlighted so far, plus the next one
+    modes: Vec<Mode>,
+}
+
+impl highlighter::Highlighter for SieveHighlighter {
+    type Settings = ();
+    type Highlight = Highlight;
+    type Iterator<'a> = std::vec::IntoIter<(Range<usize>, Highlight)>;
+
+    fn new(_settings: &Self::Settings) -> Self {
+        Self {
+            modes: vec![Mode::Code],
+        }
+    }
+
+    fn update(&mut self, _new_settings: &Self::Settings) {}
+
+    fn change_line(&mut self, line: usize) {
+        self.modes.truncate(line + 1);
+    }
+
+    fn highlight_line(&mut self, line: &str) -> Self::Iterator<'_> {
+        let mode = self.modes.last().copied().unwrap_or(Mode::Code);
+        let (spans, next) = highlight_line(line, mode);
+        self.modes.push(next);
+        spans.into_iter()
+    }
+
+    fn current_line(&self) -> usize {
+        self.modes.len() - 1
+    }
+}
+
+/// Theme-aware colors for each highlight class.
+pub fn format(highlight: &Highlight, theme: &Theme) -> Format<Font> {
+    let palette = theme.extended_palette();
+    let dark = palette.is_dark;
+    let color = match highlight {
+        Highlight::Keyword => palette.primary.base.color,
+        Highlight::Tag => if dark {
+            Color::from_rgb(0.78, 0.58, 0.95)
+        } else {
+            Color::from_rgb(0.55, 0.25, 0.7)
+        },
+        Highlight::String => palette.success.base.color,
+        Highlight::Number => if dark {
+            Color::from_rgb(0.95, 0.65, 0.35)
+        } else {
+            Color::from_rgb(0.75, 0.4, 0.05)
+        },
+        Highlight::Comment => Color {
+            a: 0.5,
+            ..palette.background.base.text
+        },
+    };
+    Format {
+        color: Some(color),
+        font: None,
+    }
+}
+
+fn highlight_line(line: &str, mode: Mode) -> (Vec<(Range<usize>, Highlight)>, Mode) {
+    let mut out = Vec::new();
+    let start = match mode {
+        Mode::Code => 0,
+        Mode::MultiLineString => {
+            out.push((0..line.len(), Highlight::String));
+            let next = if line.trim_end_matches('\r') == "." {
+                Mode::Code
+            } else {
+                Mode::MultiLineString
+            };
+            return (out, next);
+        }
+        Mode::BlockComment => match line.find("*/") {
+            Some(end) => {
+                out.push((0..end + 2, Highlight::Comment));
+                end + 2
+            }
+            None => {
+                out.push((0..line.len(), Highlight::Comment));
+                return (out, Mode::BlockComment);
+            }
+        },
+        Mode::QuotedString => match closing_quote(line) {
+            Some(end) => {
+                out.push((0..end + 1, Highlight::String));
+                end + 1
+            }
+            None => {
+                out.push((0..line.len(), Highlight::String));
+                return (out, Mode::QuotedString);
+            }
+        },
+    };
+    let next = highlight_code(&line[start..], start, &mut out);
+    (out, next)
+}
+
+/// Highlight the longest prefix of `code` that lexes, then work out whether
+/// the rest opens a string or comment that continues on the next line.
+fn highlight_code(code: &str, base: usize, out: &mut Vec<(Range<usize>, Highlight)>) -> Mode {
+    let bytes = code.as_bytes();
+    let cuts = (0..=code.len()).rev().filter(|&i| {
+        i == code.len()
+            || i == 0
+            || (code.is_char_boundary(i)
+                && (bytes[i - 1].is_ascii_whitespace() || matches!(bytes[i], b'"' | b'/')))
+    });
+
+    for cut in cuts {
+        let Ok(spans) = tokenize(&code[..cut]) else {
+            continue;
+        };
+        for span in spans {
+            if let Some(highlight) = classify(&span.token) {
+                out.push((base + span.offset..base + span.offset + span.len, highlight));
+            }
+        }
+
+        let rest = &code[cut..];
+        let rest_range = base + cut..base + code.len();
+        if rest.starts_with('"') {
+            out.push((rest_range, Highlight::String));
+            return Mode::QuotedString;
+        } else if rest.starts_with("/*") {
+            out.push((rest_range, Highlight::Comment));
+            return Mode::BlockComment;
+        } else if rest.get(..5).is_some_and(|s| s.eq_ignore_ascii_case("text:")) {
+            out.push((rest_range, Highlight::String));
+            return Mode::MultiLineString;
+        }
+        return Mode::Code;
+    }
+    Mode::Code
+}
+
+/// Index of the first unescaped `"`.
+fn closing_quote(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return Some(i),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classes(line: &str, mode: Mode) -> (Vec<(&str, Highlight)>, Mode) {
+        let (spans, next) = highlight_line(line, mode);
+        let spans = spans.into_iter().map(|(r, h)| (&line[r], h)).collect();
+        (spans, next)
+    }
+
+    #[test]
+    fn test_classify_tokens() {
+        let spans = tokenize("if header :is \"Subject\" 100K { # note\n} /* x */ ;").unwrap();
+        let classes: Vec<Option<Highlight>> = spans.iter().map(|s| classify(&s.token)).collect();
+        assert_eq!(
+            classes,
+            vec![
+                Some(Highlight::Keyword),
+                Some(Highlight::Keyword),
+                Some(Highlight::Tag),
+                Some(Highlight::String),
+                Some(Highlight::Number),
+                None,
+                Some(Highlight::Comment),
+                None,
+                Some(Highlight::Comment),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_partial_line_colors_what_lexes() {
+        let (spans, next) = classes("if header :contains \"Subj", Mode::Code);
+        assert_eq!(
+            spans,
+            vec![
+                ("if", Highlight::Keyword),
+                ("header", Highlight::Keyword),
+                (":contains", Highlight::Tag),
+                ("\"Subj", Highlight::String),
+            ]
+        );
+        assert_eq!(next, Mode::QuotedString);
+
+        let (spans, next) = classes("ect\" {", Mode::QuotedString);
+        assert_eq!(spans, vec![("ect\"", Highlight::String)]);
+        assert_eq!(next, Mode::Code);
+    }
+
+    #[test]
+    fn test_multiline_string_and_block_comment() {
+        let (spans, next) = classes("reject text:", Mode::Code);
+        assert_eq!(spans, vec![("reject", Highlight::Keyword), ("text:", Highlight::String)]);
+        assert_eq!(next, Mode::MultiLineString);
+        assert_eq!(classes("Go away.", next).1, Mode::MultiLineString);
+        assert_eq!(classes(".", next).1, Mode::Code);
+
+        let (_, next) = classes("keep; /* start", Mode::Code);
+        assert_eq!(next, Mode::BlockComment);
+        let (spans, next) = classes("end */ stop;", next);
+        assert_eq!(spans, vec![("end */", Highlight::Comment), ("stop", Highlight::Keyword)]);
+        assert_eq!(next, Mode::Code);
+    }
+}
@@ -0,0 +1,51 @@
+/// Draggable dividers to the right of the script list and the rule sidebar.
+///
+/// Pressing a divider starts the drag; the app then follows the cursor
+/// across the whole window until the button is released.
+use iced::widget::{container, mouse_area, Space};
+use iced::{mouse, Color, Element, Length, Theme};
+
+use crate::app::Message;
+
+/// Width of the grab strip
+pub const WIDTH: f32 = 5.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Splitter {
+    /// Between the server's script list and the editor
+    ScriptList,
+    /// Between the visual editor's rule sidebar and the rule details
+    RuleSidebar,
+}
+
+impl Splitter {
+    /// The narrowest and widest the panel may get.
+    fn bounds(self) -> (f32, f32) {
+        match self {
+            Self::ScriptList => (140.0, 400.0),
+            Self::RuleSidebar => (180.0, 500.0),
+        }
+    }
+
+    /// `width` brought within the panel's bounds.
+    pub fn clamp(self, width: f32) -> f32 {
+        let (min, max) = self.bounds();
+        width.clamp(min, max)
+    }
+}
+
+pub fn view<'a>(splitter: Splitter) -> Element<'a, Message> {
+    let strip = container(Space::new(WIDTH, Length::Fill)).style(|theme: &Theme| {
+        let p = theme.palette();
+        container::Style {
+            background: Some(iced::Background::Color(Color::from_rgba(
+                p.text.r, p.text.g, p.text.b, 0.05,
+            ))),
+            ..container::Style::default()
+        }
+    });
+    mouse_area(strip)
+        .on_press(Message::StartResize(splitter))
+        .interaction(mouse::Interaction::ResizingHorizontally)
+        .into()
+}
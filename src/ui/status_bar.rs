@@ -1,11 +1,77 @@
-use iced::widget::{container, text};
-use iced::{Element, Length};
+use iced::widget::{button, container, horizontal_space, row, text};
+use iced::{Color, Element, Length, Theme};
 
 use crate::app::Message;
+use crate::ui::problems_panel::ProblemsMessage;
 
-pub fn view(status: &str) -> Element<'_, Message> {
-    container(text(status).size(13))
-        .width(Length::Fill)
-        .padding([2, 8])
-        .into()
+/// `cursor` is the raw editor's 1-based line and column, when it is shown;
+/// `warning` is a standing problem shown beside the latest status.
+/// `problems` is the number of lints; when there are any, their count
+/// opens the Problems panel.
+/// `redirects` is the script's redirect count against the server's limit.
+/// `busy` is the animation step while a server operation runs.
+pub fn view<'a>(
+    status: &str,
+    busy: Option<usize>,
+    dirty: bool,
+    warning: Option<&'a str>,
+    problems: usize,
+    redirects: Option<(usize, u32)>,
+    cursor: Option<(usize, usize)>,
+) -> Element<'a, Message> {
+    let status = if dirty {
+        format!("* {status}")
+    } else {
+        status.to_string()
+    };
+    let spinner = busy.map(busy_indicator);
+    let position = cursor.map(|(line, col)| text(format!("Ln {line}, Col {col}")).size(13));
+    let warning = warning.map(|w| text(w).size(13).style(text::danger));
+    let problems = (problems > 0).then(|| {
+        button(text(format!("Problems: {problems}")).size(13))
+            .on_press(Message::ProblemsMsg(ProblemsMessage::Toggle))
+            .style(button::text)
+            .padding(0)
+    });
+    let redirects = redirects.map(|(count, limit)| {
+        let label = text(format!("Redirects {count}/{limit}")).size(13);
+        if count > limit as usize {
+            label.style(text::danger)
+        } else {
+            label
+        }
+    });
+
+    container(
+        row![]
+            .push_maybe(spinner)
+            .push(text(status).size(13))
+            .push_maybe(warning)
+            .push(horizontal_space())
+            .push_maybe(problems)
+            .push_maybe(redirects)
+            .push_maybe(position)
+            .spacing(8),
+    )
+    .width(Length::Fill)
+    .padding([2, 8])
+    .into()
+}
+
+/// Three dots with a highlight running across them.
+fn busy_indicator<'a>(frame: usize) -> Element<'a, Message> {
+    let lit = frame % 3;
+    let dots = (0..3).map(|i| {
+        let alpha = if i == lit { 1.0 } else { 0.3 };
+        text("●")
+            .size(9)
+            .style(move |theme: &Theme| {
+                let p = theme.palette();
+                text::Style {
+                    color: Some(Color { a: alpha, ..p.primary }),
+                }
+            })
+            .into()
+    });
+    row(dots).spacing(2).align_y(iced::Alignment::Center).into()
 }
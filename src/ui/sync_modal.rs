@@ -0,0 +1,135 @@
+use iced::widget::{button, column, container, row, scrollable, text};
+use iced::{Border, Color, Element, Font, Length, Theme};
+use std::path::PathBuf;
+
+use crate::store::folder_sync::{SyncEntry, SyncStatus};
+
+#[derive(Debug, Clone)]
+pub enum SyncMessage {
+    /// Upload the folder's copy of a script
+    Push(String),
+    /// Write the server's copy of a script to the folder
+    Pull(String),
+    /// Compare again
+    Refresh,
+    /// Pick another folder for the profile
+    ChangeFolder,
+    Close,
+}
+
+/// The comparison of the profile's scripts folder with the server.
+#[derive(Debug, Clone, Default)]
+pub struct FolderSyncState {
+    pub visible: bool,
+    pub dir: PathBuf,
+    pub entries: Vec<SyncEntry>,
+}
+
+/// While `busy`, the buttons that talk to the server are off.
+pub fn view(state: &FolderSyncState, busy: bool) -> Element<'_, SyncMessage> {
+    let idle = |msg: SyncMessage| (!busy).then_some(msg);
+    let title = text("Sync with folder").size(18).font(Font {
+        weight: iced::font::Weight::Bold,
+        ..Font::DEFAULT
+    });
+    let folder = row![
+        text(state.dir.display().to_string()).size(13).width(Length::Fill),
+        button(text("Change...").size(12))
+            .on_press_maybe(idle(SyncMessage::ChangeFolder))
+            .style(button::secondary)
+            .padding([2, 8]),
+    ]
+    .spacing(8)
+    .align_y(iced::Alignment::Center);
+
+    let in_sync = state
+        .entries
+        .iter()
+        .filter(|e| e.status == SyncStatus::Same)
+        .count();
+    let summary = if in_sync == state.entries.len() {
+        text("The folder and the server have the same scripts.").size(13)
+    } else {
+        text(format!("{in_sync} of {} script(s) in sync", state.entries.len())).size(13)
+    };
+
+    let entries = state
+        .entries
+        .iter()
+        .filter(|e| e.status != SyncStatus::Same)
+        .map(|entry| {
+            let push = entry.status.can_push().then(|| {
+                button(text("Push").size(12))
+                    .on_press_maybe(idle(SyncMessage::Push(entry.name.clone())))
+                    .style(button::secondary)
+                    .padding([2, 8])
+            });
+            let pull = entry.status.can_pull().then(|| {
+                button(text("Pull").size(12))
+                    .on_press_maybe(idle(SyncMessage::Pull(entry.name.clone())))
+                    .style(button::secondary)
+                    .padding([2, 8])
+            });
+            row![
+                text(&entry.name).size(13).width(Length::Fill),
+                text(entry.status.to_string()).size(12).width(150),
+            ]
+            .push_maybe(push)
+            .push_maybe(pull)
+            .spacing(8)
+            .align_y(iced::Alignment::Center)
+            .into()
+        });
+    let list = scrollable(column(entries).spacing(6)).height(Length::Shrink);
+
+    let hint = text("Push uploads the folder's copy, Pull overwrites it with the server's. Deletions are not synced.").size(12);
+
+    let buttons = row![
+        button("Compare again")
+            .on_press_maybe(idle(SyncMessage::Refresh))
+            .style(button::secondary),
+        button("Close")
+            .on_press(SyncMessage::Close)
+            .style(button::primary),
+    ]
+    .spacing(8);
+
+    let dialog = container(
+        column![title, folder, summary, list, hint, buttons]
+            .spacing(12)
+            .padding(24)
+            .width(560),
+    )
+    .max_height(520)
+    .style(|theme: &Theme| {
+        let palette = theme.palette();
+        container::Style {
+            background: Some(iced::Background::Color(palette.background)),
+            border: Border {
+                color: Color::from_rgba(
+                    palette.text.r,
+                    palette.text.g,
+                    palette.text.b,
+                    0.3,
+                ),
+                width: 1.0,
+                radius: 8.0.into(),
+            },
+            ..container::Style::default()
+        }
+    });
+
+    // Overlay: dark semi-transparent background + centered dialog
+    container(
+        container(dialog)
+            .center_x(Length::Fill)
+            .center_y(Length::Fill),
+    )
+    .width(Length::Fill)
+    .height(Length::Fill)
+    .style(|_theme: &Theme| container::Style {
+        background: Some(iced::Background::Color(Color::from_rgba(0.0, 0.0, 0.0, 0.5))),
+        ..container::Style::default()
+    })
+    .into()
+}
@@ -0,0 +1,73 @@
+use iced::widget::{button, column, container, pick_list, row, text};
+use iced::{Border, Color, Element, Font, Length, Theme};
+
+use crate::sieve::templates::Template;
+
+#[derive(Debug, Clone)]
+pub enum TemplateMessage {
+    Select(Template),
+    Add,
+    Cancel,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TemplateState {
+    pub visible: bool,
+    pub selected: Option<Template>,
+}
+
+pub fn view(state: &TemplateState) -> Element<'_, TemplateMessage> {
+    let title = text("New filter from template").size(18).font(Font {
+        weight: iced::font::Weight::Bold,
+        ..Font::DEFAULT
+    });
+
+    let picker = pick_list(Template::ALL, state.selected, TemplateMessage::Select)
+        .placeholder("Choose a template")
+        .width(Length::Fill);
+
+    let hint = text("The filter is added with example values to fill in.").size(12);
+
+    let buttons = row![
+        button("Add filter")
+            .on_press_maybe(state.selected.map(|_| TemplateMessage::Add))
+            .style(button::primary),
+        button("Cancel")
+            .on_press(TemplateMessage::Cancel)
+            .style(button::secondary),
+    ]
+    .spacing(8);
+
+    let dialog = container(column![title, picker, hint, buttons].spacing(12).padding(24).width(360))
+        .style(|theme: &Theme| {
+            let palette = theme.palette();
+            container::Style {
+                background: Some(iced::Background::Color(palette.background)),
+                border: Border {
+                    color: Color::from_rgba(
+                        palette.text.r,
+                        palette.text.g,
+                        palette.text.b,
+                        0.3,
+                    ),
+                    width: 1.0,
+                    radius: 8.0.into(),
+                },
+                ..container::Style::default()
+            }
+        });
+
+    // Overlay: dark semi-transparent background + centered dialog
+    container(
+        container(dialog)
+            .center_x(Length::Fill)
+            .center_y(Length::Fill),
+    )
+    .width(Length::Fill)
+    .height(Length::Fill)
+    .style(|_theme: &Theme| container::Style {
+        background: Some(iced::Background::Color(Color::from_rgba(0.0, 0.0, 0.0, 0.5))),
+        ..container::Style::default()
+    })
+    .into()
+}
@@ -1,10 +1,36 @@
-use iced::widget::{button, container, horizontal_space, row, text};
+use iced::widget::{button, checkbox, container, horizontal_space, pick_list, row, text};
 use iced::{Border, Color, Element, Font, Length, Theme};
 
 use crate::app::Message;
+use crate::net::managesieve::Security;
+use std::path::{Path, PathBuf};
+
 use crate::ui::icons;
 
-pub fn view<'a>(connected: bool, dark_mode: bool) -> Element<'a, Message> {
+/// Entry of the recent-files dropdown, shown by file name.
+#[derive(Debug, Clone, PartialEq)]
+struct RecentFile(PathBuf);
+
+impl std::fmt::Display for RecentFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = self.0.file_name().unwrap_or(self.0.as_os_str());
+        write!(f, "{}", Path::new(name).display())
+    }
+}
+
+/// `can_upload` is false while the script has syntax errors; `busy` while
+/// a server operation runs, which turns off the buttons that start one.
+/// `activate_after_upload` is the checkbox beside Upload. `security` is
+/// how the connection is protected, shown beside the connect button.
+pub fn view<'a>(
+    connected: bool,
+    busy: bool,
+    can_upload: bool,
+    activate_after_upload: bool,
+    dark_mode: bool,
+    recent_files: &[PathBuf],
+    security: Option<&Security>,
+) -> Element<'a, Message> {
     let (connect_icon, connect_label) = if connected {
         (icons::SHUT_DOWN, "Disconnect")
     } else {
@@ -24,18 +50,56 @@ pub fn view<'a>(connected: bool, dark_mode: bool) -> Element<'a, Message> {
     .spacing(0)
     .align_y(iced::Alignment::Center);
 
+    let recent: Vec<RecentFile> = recent_files.iter().cloned().map(RecentFile).collect();
+    let recent_list = (!recent.is_empty()).then(|| {
+        pick_list(recent, None::<RecentFile>, |r| Message::OpenRecent(r.0))
+            .placeholder("Recent")
+            .text_size(13)
+            .width(120)
+    });
+
+    let idle = |msg: Message| (!busy).then_some(msg);
+    let switch_user = connected.then(|| {
+        toolbar_button(icons::USER_SHARED, "Switch user", Message::ShowSwitchUser)
+            .on_press_maybe(idle(Message::ShowSwitchUser))
+    });
+
+    let security = security.filter(|_| connected).map(security_badge);
+
     let tb = row![
         branding,
         horizontal_space().width(24),
-        toolbar_button(connect_icon, connect_label, Message::Connect),
-        horizontal_space().width(12),
-        toolbar_button(icons::FOLDER_OPEN, "Open", Message::OpenFile),
-        toolbar_button(icons::SAVE, "Save", Message::SaveFile),
-        toolbar_button(icons::UPLOAD_CLOUD, "Upload", Message::Upload),
-        horizontal_space().width(Length::Fill),
-        toolbar_button(theme_icon, theme_label, Message::ToggleTheme),
-        toolbar_button(icons::INFORMATION, "About", Message::ShowAbout),
+        toolbar_button(connect_icon, connect_label, Message::Connect)
+            .on_press_maybe(idle(Message::Connect)),
     ]
+    .push_maybe(security)
+    .push_maybe(switch_user)
+    .push(horizontal_space().width(12))
+    .push(toolbar_button(icons::FILE_ADD, "New", Message::NewScript))
+    .push(toolbar_button(icons::FOLDER_OPEN, "Open", Message::OpenFile))
+    .push_maybe(recent_list)
+    .push(toolbar_button(icons::SAVE, "Save", Message::SaveFile))
+    .push(
+        toolbar_button(icons::UPLOAD_CLOUD, "Upload", Message::Upload)
+            .on_press_maybe(can_upload.then(|| idle(Message::Upload)).flatten()),
+    )
+    .push(
+        checkbox("Activate", activate_after_upload)
+            .on_toggle(Message::SetActivateAfterUpload)
+            .size(14)
+            .text_size(12),
+    )
+    .push(
+        toolbar_button(icons::CHECKBOX_CIRCLE, "Validate", Message::Validate)
+            .on_press_maybe(idle(Message::Validate)),
+    )
+    .push(
+        toolbar_button(icons::ARROW_LEFT_RIGHT, "Diff", Message::DiffAgainstServer)
+            .on_press_maybe(idle(Message::DiffAgainstServer)),
+    )
+    .push(horizontal_space().width(Length::Fill))
+    .push(toolbar_button(theme_icon, theme_label, Message::ToggleTheme))
+    .push(toolbar_button(icons::INFORMATION, "About", Message::ShowAbout))
     .spacing(4)
     .padding(6)
     .align_y(iced::Alignment::Center);
@@ -67,6 +131,31 @@ pub fn view<'a>(connected: bool, dark_mode: bool) -> Element<'a, Message> {
         .into()
 }
 
+/// Lock and TLS version, or a warning for a connection without TLS.
+fn security_badge<'a>(security: &Security) -> Element<'a, Message> {
+    let (icon, label) = match security {
+        Security::Tls { version, .. } => (icons::LOCK, version.clone()),
+        Security::Plaintext => (icons::LOCK_UNLOCK, "INSECURE".to_string()),
+    };
+    let badge = row![
+        text(icon.to_string()).font(icons::ICON_FONT).size(16),
+        text(label).size(12),
+    ]
+    .spacing(4)
+    .align_y(iced::Alignment::Center);
+    let badge = container(badge).padding([0, 4]);
+    if *security == Security::Plaintext {
+        badge
+            .style(|theme: &Theme| container::Style {
+                text_color: Some(theme.palette().danger),
+                ..container::Style::default()
+            })
+            .into()
+    } else {
+        badge.into()
+    }
+}
+
 fn toolbar_button(icon: char, label: &str, msg: Message) -> iced::widget::Button<'_, Message> {
     button(icons::icon_text(icon, label))
         .on_press(msg)
@@ -87,6 +176,9 @@ fn toolbar_button(icon: char, label: &str, msg: Message) -> iced::widget::Button
                 },
                 ..button::Style::default()
             };
+            if status == button::Status::Disabled {
+                style.text_color = Color::from_rgba(palette.text.r, palette.text.g, palette.text.b, 0.4);
+            }
             if matches!(status, button::Status::Hovered | button::Status::Pressed) {
                 style.background = Some(iced::Background::Color(Color::from_rgba(
                     palette.text.r,
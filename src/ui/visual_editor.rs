@@ -1,22 +1,62 @@
-use iced::widget::{button, column, container, scrollable, text, Space};
+use iced::widget::{
+    button, column, container, mouse_area, row, scrollable, text, text_editor, text_input, Space,
+};
 use iced::{Border, Color, Element, Font, Length, Theme};
+use std::collections::HashSet;
 
 use crate::app::Message;
 use crate::model::rule::SieveRule;
+use crate::net::managesieve::Capabilities;
+use crate::sieve::lint::{self, RuleWarning};
+use crate::ui::context_menu::{self, MenuTarget};
 use crate::ui::icons;
-use crate::ui::rule_card;
+use crate::ui::rule_card::{self, BranchId};
+use crate::ui::splitter::{self, Splitter};
 
-pub fn view<'a>(rules: &'a [SieveRule], selected_rule: Option<usize>) -> Element<'a, Message> {
-    let sidebar = view_sidebar(rules, selected_rule);
-    let detail = view_detail(rules, selected_rule);
+pub fn sidebar_scroll_id() -> scrollable::Id {
+    scrollable::Id::new("rule-sidebar")
+}
+
+/// `rule_menu` is the rule whose context menu is open; `sidebar_width` is
+/// the width of the rule list.
+#[allow(clippy::too_many_arguments)]
+pub fn view<'a>(
+    rules: &'a [SieveRule],
+    selected_rule: Option<usize>,
+    collapsed: &HashSet<BranchId>,
+    notes: &'a text_editor::Content,
+    raw_block: &'a text_editor::Content,
+    filter: &'a str,
+    capabilities: Option<&Capabilities>,
+    rule_menu: Option<usize>,
+    sidebar_width: f32,
+) -> Element<'a, Message> {
+    let duplicates = duplicate_names(rules);
+    let sidebar = view_sidebar(rules, selected_rule, filter, &duplicates, rule_menu, sidebar_width);
+    let detail = view_detail(
+        rules,
+        selected_rule,
+        collapsed,
+        notes,
+        raw_block,
+        capabilities,
+        &duplicates,
+    );
 
-    iced::widget::row![sidebar, detail]
+    iced::widget::row![sidebar, splitter::view(Splitter::RuleSidebar), detail]
         .width(Length::Fill)
         .height(Length::Fill)
         .into()
 }
 
-fn view_sidebar<'a>(rules: &'a [SieveRule], selected_rule: Option<usize>) -> Element<'a, Message> {
+fn view_sidebar<'a>(
+    rules: &'a [SieveRule],
+    selected_rule: Option<usize>,
+    filter: &'a str,
+    duplicates: &HashSet<String>,
+    rule_menu: Option<usize>,
+    width: f32,
+) -> Element<'a, Message> {
     let mut content = column![].spacing(6).padding(8).width(Length::Fill);
 
     // Header
@@ -29,12 +69,26 @@ fn view_sidebar<'a>(rules: &'a [SieveRule], selected_rule: Option<usize>) -> Ele
             }),
     );
 
+    content = content.push(
+        text_input("Search filters", filter)
+            .on_input(Message::SetRuleFilter)
+            .size(13),
+    );
+
     content = content.push(Space::with_height(4));
 
-    // Filter cards
+    // Filter cards; indices stay those of the full list
     for (i, rule) in rules.iter().enumerate() {
+        if !rule_matches(rule, filter) {
+            continue;
+        }
         let is_selected = selected_rule == Some(i);
-        content = content.push(rule_card::sidebar_card_button(rule, is_selected, i));
+        let duplicate = duplicates.contains(&rule.name);
+        let card = rule_card::sidebar_card_button(rule, is_selected, duplicate, i, rules.len());
+        content = content.push(mouse_area(card).on_right_press(Message::ShowContextMenu(MenuTarget::Rule(i))));
+        if rule_menu == Some(i) {
+            content = content.push(context_menu::view(context_menu::rule_items(i, rules.len())));
+        }
     }
 
     content = content.push(Space::with_height(4));
@@ -46,9 +100,27 @@ fn view_sidebar<'a>(rules: &'a [SieveRule], selected_rule: Option<usize>) -> Ele
             .style(button::secondary)
             .width(Length::Fill),
     );
+    content = content.push(
+        button(icons::icon_text(icons::MAGIC, "From template"))
+            .on_press(Message::ShowTemplates)
+            .style(button::secondary)
+            .width(Length::Fill),
+    );
+    content = content.push(
+        button(icons::icon_text(icons::CLIPBOARD, "Paste rule"))
+            .on_press(Message::PasteRule)
+            .style(button::secondary)
+            .width(Length::Fill),
+    );
+    content = content.push(
+        button(icons::icon_text(icons::FILE_COPY, "Copy whole script"))
+            .on_press(Message::CopyScript)
+            .style(button::secondary)
+            .width(Length::Fill),
+    );
 
-    let sidebar = container(scrollable(content).height(Length::Fill))
-        .width(250)
+    let sidebar = container(scrollable(content).id(sidebar_scroll_id()).height(Length::Fill))
+        .width(width)
         .height(Length::Fill)
         .style(|theme: &Theme| {
             let p = theme.palette();
@@ -68,7 +140,102 @@ fn view_sidebar<'a>(rules: &'a [SieveRule], selected_rule: Option<usize>) -> Ele
     sidebar.into()
 }
 
-fn view_detail<'a>(rules: &'a [SieveRule], selected_rule: Option<usize>) -> Element<'a, Message> {
+/// Banner over the details of a rule that shares its name with another.
+fn duplicate_warning<'a>() -> Element<'a, Message> {
+    warning_banner(
+        row![
+            text("Another filter has the same name; the # Filter: markers become ambiguous.")
+                .size(12)
+                .width(Length::Fill),
+            button(text("Auto-rename").size(12))
+                .on_press(Message::RenameDuplicates)
+                .style(button::secondary),
+        ]
+        .spacing(8)
+        .align_y(iced::Alignment::Center),
+    )
+}
+
+/// Likely mistakes in the rule, one per line; advice only.
+fn lint_warnings<'a>(warnings: &[RuleWarning]) -> Element<'a, Message> {
+    let lines = warnings
+        .iter()
+        .map(|w| text(w.to_string()).size(12).width(Length::Fill).into());
+    warning_banner(column(lines).spacing(2))
+}
+
+fn warning_banner<'a>(content: impl Into<Element<'a, Message>>) -> Element<'a, Message> {
+    let warning = Color::from_rgb(0.85, 0.55, 0.1);
+    container(content)
+        .padding([6, 10])
+        .width(Length::Fill)
+        .style(move |_theme: &Theme| container::Style {
+            background: Some(iced::Background::Color(Color { a: 0.12, ..warning })),
+            border: Border {
+                color: warning,
+                width: 1.0,
+                radius: 6.0.into(),
+            },
+            ..container::Style::default()
+        })
+        .into()
+}
+
+/// Names used by more than one rule; unnamed rules don't count.
+pub fn duplicate_names(rules: &[SieveRule]) -> HashSet<String> {
+    let mut seen = HashSet::new();
+    rules
+        .iter()
+        .filter(|r| !r.name.is_empty() && !seen.insert(r.name.as_str()))
+        .map(|r| r.name.clone())
+        .collect()
+}
+
+/// Give every repeat of a name a ` (2)`, ` (3)`, ... suffix, skipping
+/// names already taken. The first rule of each name keeps it.
+pub fn rename_duplicates(rules: &mut [SieveRule]) {
+    let mut taken: HashSet<String> = rules.iter().map(|r| r.name.clone()).collect();
+    let mut seen = HashSet::new();
+    for rule in rules.iter_mut() {
+        if rule.name.is_empty() || seen.insert(rule.name.clone()) {
+            continue;
+        }
+        let renamed = (2..)
+            .map(|n| format!("{} ({n})", rule.name))
+            .find(|name| !taken.contains(name))
+            .expect("a free suffix");
+        taken.insert(renamed.clone());
+        seen.insert(renamed.clone());
+        rule.name = renamed;
+    }
+}
+
+/// Whether `rule` matches the sidebar search: its name or any condition
+/// header or key contains `query`, ignoring case. An empty query matches all.
+pub fn rule_matches(rule: &SieveRule, query: &str) -> bool {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return true;
+    }
+    let contains = |s: &String| s.to_lowercase().contains(&query);
+    contains(&rule.name)
+        || rule
+            .conditions
+            .iter()
+            .chain(rule.alternatives.iter().flat_map(|b| &b.conditions))
+            .chain(rule.condition_tree.iter().flat_map(|t| t.leaves()))
+            .any(|c| c.header_names.iter().any(contains) || c.keys.iter().any(contains))
+}
+
+fn view_detail<'a>(
+    rules: &'a [SieveRule],
+    selected_rule: Option<usize>,
+    collapsed: &HashSet<BranchId>,
+    notes: &'a text_editor::Content,
+    raw_block: &'a text_editor::Content,
+    capabilities: Option<&Capabilities>,
+    duplicates: &HashSet<String>,
+) -> Element<'a, Message> {
     let selected = selected_rule.and_then(|idx| {
         if idx < rules.len() {
             Some((idx, &rules[idx]))
@@ -81,9 +248,18 @@ fn view_detail<'a>(rules: &'a [SieveRule], selected_rule: Option<usize>) -> Elem
         Some((idx, rule)) => {
             let mut detail = column![].spacing(12).padding(16).width(Length::Fill);
 
+            if duplicates.contains(&rule.name) {
+                detail = detail.push(duplicate_warning());
+            }
+            let warnings = lint::validate_rule(rule);
+            if !warnings.is_empty() {
+                detail = detail.push(lint_warnings(&warnings));
+            }
+
             // Filter Details section
             detail = detail.push(
-                rule_card::detail_filter_info(rule).map(move |msg| Message::RuleMsg(idx, msg)),
+                rule_card::detail_filter_info(rule, notes, capabilities)
+                    .map(move |msg| Message::RuleMsg(idx, msg)),
             );
 
             // Conditions section
@@ -93,15 +269,28 @@ fn view_detail<'a>(rules: &'a [SieveRule], selected_rule: Option<usize>) -> Elem
 
             // Actions section
             detail = detail.push(
-                rule_card::detail_actions(rule).map(move |msg| Message::RuleMsg(idx, msg)),
+                rule_card::detail_actions(rule, raw_block).map(move |msg| Message::RuleMsg(idx, msg)),
             );
 
-            // Remove button at the bottom
+            // Else if / Otherwise sections
             detail = detail.push(
-                button(icons::icon_text(icons::DELETE_BIN, "Remove Filter"))
-                    .on_press(Message::RemoveRule(idx))
-                    .style(button::danger)
-                    .padding([6, 12]),
+                rule_card::detail_alternatives(rule, collapsed)
+                    .map(move |msg| Message::RuleMsg(idx, msg)),
+            );
+
+            // Copy and remove buttons at the bottom
+            detail = detail.push(
+                row![
+                    button(icons::icon_text(icons::FILE_COPY, "Copy rule"))
+                        .on_press(Message::CopyRule(idx))
+                        .style(button::secondary)
+                        .padding([6, 12]),
+                    button(icons::icon_text(icons::DELETE_BIN, "Remove Filter"))
+                        .on_press(Message::ConfirmRemoveRule(idx))
+                        .style(button::danger)
+                        .padding([6, 12]),
+                ]
+                .spacing(8),
             );
 
             scrollable(detail).height(Length::Fill).into()
@@ -125,3 +314,54 @@ fn view_detail<'a>(rules: &'a [SieveRule], selected_rule: Option<usize>) -> Elem
         .height(Length::Fill)
         .into()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::rule::Condition;
+
+    #[test]
+    fn test_rule_matches() {
+        let rule = SieveRule {
+            name: "Newsletters".to_string(),
+            conditions: vec![Condition {
+                header_names: vec!["List-Id".to_string()],
+                keys: vec!["weekly@news.example".to_string()],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(rule_matches(&rule, ""));
+        assert!(rule_matches(&rule, "newsLETTER"));
+        assert!(rule_matches(&rule, "list-id"));
+        assert!(rule_matches(&rule, "WEEKLY"));
+        assert!(!rule_matches(&rule, "invoice"));
+    }
+
+    fn named(names: &[&str]) -> Vec<SieveRule> {
+        names
+            .iter()
+            .map(|name| SieveRule {
+                name: name.to_string(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_duplicate_names() {
+        let rules = named(&["Spam", "Lists", "Spam", "", "", "Lists", "Work", "spam"]);
+        let expected: HashSet<String> = ["Spam", "Lists"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(duplicate_names(&rules), expected);
+        assert!(duplicate_names(&named(&["A", "B"])).is_empty());
+    }
+
+    #[test]
+    fn test_rename_duplicates() {
+        let mut rules = named(&["Spam", "Spam (2)", "Spam", "Spam", ""]);
+        rename_duplicates(&mut rules);
+        let names: Vec<&str> = rules.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["Spam", "Spam (2)", "Spam (3)", "Spam (4)", ""]);
+        assert!(duplicate_names(&rules).is_empty());
+    }
+}
@@ -0,0 +1,155 @@
+/// Line diff between two texts (Myers' O(ND) algorithm).
+#[derive(Debug, Clone, PartialEq)]
+pub enum LineChange {
+    Unchanged(String),
+    Added(String),
+    Removed(String),
+}
+
+/// Shortest edit script turning `old` into `new`, line by line. Removals
+/// come before additions within each changed run.
+pub fn diff_lines(old: &str, new: &str) -> Vec<LineChange> {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let trace = shortest_edit(&a, &b);
+
+    let mut changes = Vec::new();
+    let (mut x, mut y) = (a.len() as isize, b.len() as isize);
+    let offset = (a.len() + b.len()) as isize;
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && v[(offset + k - 1) as usize] < v[(offset + k + 1) as usize]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[(offset + prev_k) as usize];
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            changes.push(LineChange::Unchanged(a[(x - 1) as usize].to_string()));
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == prev_x {
+                changes.push(LineChange::Added(b[(y - 1) as usize].to_string()));
+            } else {
+                changes.push(LineChange::Removed(a[(x - 1) as usize].to_string()));
+            }
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+
+    changes.reverse();
+    changes
+}
+
+/// Forward pass: the furthest-reaching x for every diagonal k, recorded
+/// before each edit distance d so the path can be walked back.
+fn shortest_edit(a: &[&str], b: &[&str]) -> Vec<Vec<isize>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    let mut v = vec![0isize; 2 * max as usize + 2];
+    let mut trace = Vec::new();
+
+    for d in 0..=max {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let idx = (max + k) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                return trace;
+            }
+        }
+    }
+    trace
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(changes: &[LineChange]) -> Vec<String> {
+        changes
+            .iter()
+            .map(|c| match c {
+                LineChange::Unchanged(l) => format!("  {l}"),
+                LineChange::Added(l) => format!("+ {l}"),
+                LineChange::Removed(l) => format!("- {l}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_identical() {
+        let text = "require \"fileinto\";\nkeep;\n";
+        assert!(diff_lines(text, text)
+            .iter()
+            .all(|c| matches!(c, LineChange::Unchanged(_))));
+    }
+
+    #[test]
+    fn test_empty_sides() {
+        assert!(diff_lines("", "").is_empty());
+        assert_eq!(render(&diff_lines("", "keep;")), vec!["+ keep;"]);
+        assert_eq!(render(&diff_lines("keep;", "")), vec!["- keep;"]);
+    }
+
+    #[test]
+    fn test_changed_action() {
+        let server = r#"require "fileinto";
+
+# Filter: Spam
+if header :contains "Subject" "SPAM" {
+    fileinto "Junk";
+}
+"#;
+        let local = r#"require "fileinto";
+
+# Filter: Spam
+if header :contains "Subject" "SPAM" {
+    fileinto "Spam";
+    stop;
+}
+"#;
+        assert_eq!(
+            render(&diff_lines(server, local)),
+            vec![
+                "  require \"fileinto\";",
+                "  ",
+                "  # Filter: Spam",
+                "  if header :contains \"Subject\" \"SPAM\" {",
+                "-     fileinto \"Junk\";",
+                "+     fileinto \"Spam\";",
+                "+     stop;",
+                "  }",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_removed_rule() {
+        let server = "# Filter: A\nif true {\n    keep;\n}\n# Filter: B\nif false {\n    discard;\n}\n";
+        let local = "# Filter: A\nif true {\n    keep;\n}\n";
+        let changes = diff_lines(server, local);
+        let removed = changes
+            .iter()
+            .filter(|c| matches!(c, LineChange::Removed(_)))
+            .count();
+        assert_eq!(removed, 4);
+        assert!(!changes.iter().any(|c| matches!(c, LineChange::Added(_))));
+    }
+}
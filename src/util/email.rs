@@ -0,0 +1,90 @@
+/// Loose syntactic check for `local@domain` addresses.
+///
+/// Meant for inline hints, not for rejecting input: it accepts dot-atom and
+/// quoted local parts, domain literals and non-ASCII (IDN) labels, and only
+/// catches what is plainly not an address.
+pub fn is_plausible_address(address: &str) -> bool {
+    let address = address.trim();
+    let Some((local, domain)) = address.rsplit_once('@') else {
+        return false;
+    };
+    is_plausible_local(local) && is_plausible_domain(domain)
+}
+
+fn is_plausible_local(local: &str) -> bool {
+    if let Some(inner) = local.strip_prefix('"').and_then(|l| l.strip_suffix('"')) {
+        // Quoted string: anything but a bare quote, backslash escapes allowed
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if chars.next().is_none() => return false,
+                '"' => return false,
+                _ => {}
+            }
+        }
+        return true;
+    }
+    !local.is_empty()
+        && local.split('.').all(|atom| {
+            !atom.is_empty() && atom.chars().all(|c| !c.is_ascii() || is_atext(c))
+        })
+}
+
+fn is_atext(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-/=?^_`{|}~".contains(c)
+}
+
+fn is_plausible_domain(domain: &str) -> bool {
+    if let Some(literal) = domain.strip_prefix('[').and_then(|d| d.strip_suffix(']')) {
+        return !literal.is_empty() && !literal.contains(['[', ']', '\\']);
+    }
+    let domain = domain.strip_suffix('.').unwrap_or(domain);
+    !domain.is_empty()
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| !c.is_ascii() || c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_addresses() {
+        for address in [
+            "user@example.org",
+            "first.last+tag@mail.example.co.uk",
+            "\"john doe\"@example.org",
+            "\"a\\\"b\"@example.org",
+            "postmaster@localhost",
+            "admin@[192.0.2.1]",
+            "josé@bücher.example",
+            "  padded@example.org ",
+        ] {
+            assert!(is_plausible_address(address), "{address}");
+        }
+    }
+
+    #[test]
+    fn test_invalid_addresses() {
+        for address in [
+            "",
+            "not an email",
+            "@example.org",
+            "user@",
+            "user@@example.org",
+            "user name@example.org",
+            "user..name@example.org",
+            ".user@example.org",
+            "user@example..org",
+            "user@-example.org",
+            "user@exa_mple.org",
+            "\"unterminated@example.org",
+        ] {
+            assert!(!is_plausible_address(address), "{address}");
+        }
+    }
+}
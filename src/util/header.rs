@@ -0,0 +1,50 @@
+/// Whether `name` is a header field name per RFC 5322: one or more printable
+/// US-ASCII characters other than the colon, so no spaces either.
+pub fn is_valid_header_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| (33..=126).contains(&b) && b != b':')
+}
+
+/// Inline hint for a condition's header names, or `None` when they all look
+/// fine. Only a hint: the names are kept as typed.
+pub fn header_names_hint(names: &[String]) -> Option<&'static str> {
+    if names.is_empty() || names.iter().any(|n| n.is_empty()) {
+        Some("Header name can't be empty")
+    } else if names.iter().any(|n| n.contains(':')) {
+        Some("Leave out the colon (From, not From:)")
+    } else if names.iter().all(|n| is_valid_header_name(n)) {
+        None
+    } else {
+        Some("Header names can't contain spaces or special characters")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_names() {
+        assert!(is_valid_header_name("From"));
+        assert!(is_valid_header_name("X-My-Header"));
+        assert!(is_valid_header_name("List-Id"));
+        assert!(!is_valid_header_name("Bad Name"));
+        assert!(!is_valid_header_name("With:Colon"));
+        assert!(!is_valid_header_name("From:"));
+        assert!(!is_valid_header_name(""));
+        assert!(!is_valid_header_name("Tab\tName"));
+        assert!(!is_valid_header_name("Betreff-ä"));
+    }
+
+    #[test]
+    fn test_header_names_hint() {
+        let names = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(header_names_hint(&names(&["From", "Sender"])), None);
+        assert_eq!(header_names_hint(&names(&["From", ""])), Some("Header name can't be empty"));
+        assert_eq!(header_names_hint(&[]), Some("Header name can't be empty"));
+        assert_eq!(
+            header_names_hint(&names(&["From:"])),
+            Some("Leave out the colon (From, not From:)")
+        );
+        assert!(header_names_hint(&names(&["Bad Name"])).is_some());
+    }
+}
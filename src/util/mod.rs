@@ -0,0 +1,4 @@
+pub mod diff;
+pub mod email;
+pub mod header;
+pub mod search;
@@ -0,0 +1,95 @@
+/// Plain-text search for the raw editor's find bar.
+use std::ops::Range;
+
+/// Byte ranges of the non-overlapping occurrences of `query` in `text`,
+/// left to right. Without `case_sensitive`, characters match when their
+/// lowercase forms do.
+pub fn find_all(text: &str, query: &str, case_sensitive: bool) -> Vec<Range<usize>> {
+    let mut matches = Vec::new();
+    if query.is_empty() {
+        return matches;
+    }
+    // One pass over `text`, skipping the starts a match already covers
+    let mut next = 0;
+    for (start, _) in text.char_indices() {
+        if start < next {
+            continue;
+        }
+        if let Some(end) = match_at(text, start, query, case_sensitive) {
+            matches.push(start..end);
+            next = end;
+        }
+    }
+    matches
+}
+
+/// The first occurrence of `query` starting at or after byte `from`.
+pub fn find_from(text: &str, query: &str, from: usize, case_sensitive: bool) -> Option<Range<usize>> {
+    if query.is_empty() {
+        return None;
+    }
+    let from = (from..=text.len()).find(|&i| text.is_char_boundary(i))?;
+    text[from..].char_indices().find_map(|(i, _)| {
+        let start = from + i;
+        match_at(text, start, query, case_sensitive).map(|end| start..end)
+    })
+}
+
+/// Where a match of `query` starting at byte `start` ends, if there is one.
+fn match_at(text: &str, start: usize, query: &str, case_sensitive: bool) -> Option<usize> {
+    let same = |a: char, b: char| a == b || (!case_sensitive && a.to_lowercase().eq(b.to_lowercase()));
+    let mut rest = text[start..].char_indices();
+    for q in query.chars() {
+        match rest.next() {
+            Some((_, c)) if same(c, q) => {}
+            _ => return None,
+        }
+    }
+    Some(rest.next().map_or(text.len(), |(i, _)| start + i))
+}
+
+/// `text` with every occurrence of `find` replaced by `replace`.
+pub fn replace_all(text: &str, find: &str, replace: &str, case_sensitive: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for range in find_all(text, find, case_sensitive) {
+        out.push_str(&text[last..range.start]);
+        out.push_str(replace);
+        last = range.end;
+    }
+    out.push_str(&text[last..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_all() {
+        let text = "if address :domain \"from\" \"Example.org\" { fileinto \"example.org\"; }";
+        assert_eq!(
+            replace_all(text, "example.org", "example.net", true),
+            "if address :domain \"from\" \"Example.org\" { fileinto \"example.net\"; }"
+        );
+        assert_eq!(
+            replace_all(text, "example.org", "example.net", false),
+            "if address :domain \"from\" \"example.net\" { fileinto \"example.net\"; }"
+        );
+        assert_eq!(replace_all(text, "", "x", false), text);
+        assert_eq!(replace_all("aaa", "aa", "b", true), "ba");
+        assert_eq!(replace_all("Ärger ärger", "ä", "a", false), "arger arger");
+    }
+
+    #[test]
+    fn test_find_from() {
+        let text = "Lists\nlists\n";
+        assert_eq!(find_from(text, "lists", 0, false), Some(0..5));
+        assert_eq!(find_from(text, "lists", 0, true), Some(6..11));
+        assert_eq!(find_from(text, "lists", 1, false), Some(6..11));
+        assert_eq!(find_from(text, "lists", 7, false), None);
+        assert_eq!(find_all(text, "LISTS", false), vec![0..5, 6..11]);
+        assert_eq!(find_all("ääää", "ää", false), vec![0..4, 4..8]);
+        assert_eq!(find_from("ä lists", "lists", 1, false), Some(3..8));
+    }
+}
@@ -0,0 +1,169 @@
+//! Drives `sievers --upload` against a mock ManageSieve server.
+//!
+//! The binary finds its profiles through `XDG_CONFIG_HOME`, so these only run
+//! on Linux.
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::thread;
+
+const SCRIPT: &str = "require \"fileinto\";\r\nfileinto \"Lists\";\r\n";
+
+/// Serve one session, answering CHECKSCRIPT with `check_reply` and
+/// everything else with OK. Returns the commands received, literals included.
+fn mock_server(check_reply: &'static str) -> (u16, thread::JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let handle = thread::spawn(move || {
+        let (socket, _) = listener.accept().unwrap();
+        let mut writer = socket.try_clone().unwrap();
+        let mut reader = BufReader::new(socket);
+        writer
+            .write_all(b"\"IMPLEMENTATION\" \"Mock\"\r\n\"SASL\" \"PLAIN\"\r\n\"VERSION\" \"1.0\"\r\nOK\r\n")
+            .unwrap();
+
+        let mut commands = Vec::new();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap() == 0 {
+                break;
+            }
+            let mut command = line.trim_end().to_string();
+            // Non-synchronizing literal: `{N+}` then N bytes and a CRLF
+            if let Some(size) = command
+                .strip_suffix("+}")
+                .and_then(|c| c.rsplit_once('{'))
+                .and_then(|(_, n)| n.parse::<usize>().ok())
+            {
+                let mut literal = vec![0; size + 2];
+                reader.read_exact(&mut literal).unwrap();
+                literal.truncate(size);
+                command.push('\n');
+                command.push_str(&String::from_utf8(literal).unwrap());
+            }
+
+            let reply = if command.starts_with("CHECKSCRIPT") {
+                check_reply
+            } else {
+                "OK\r\n"
+            };
+            writer.write_all(reply.as_bytes()).unwrap();
+            let done = command == "LOGOUT";
+            commands.push(command);
+            if done {
+                break;
+            }
+        }
+        commands
+    });
+    (port, handle)
+}
+
+struct Setup {
+    root: PathBuf,
+}
+
+impl Setup {
+    fn new(name: &str, port: u16) -> Self {
+        let root = std::env::temp_dir().join(format!("sievers-cli-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("config/sievers")).unwrap();
+        fs::write(
+            root.join("config/sievers/profiles.json"),
+            format!(
+                r#"[{{"name": "mock", "host": "127.0.0.1", "port": {port},
+                    "username": "alice", "tls_mode": "None"}}]"#
+            ),
+        )
+        .unwrap();
+        fs::write(root.join("filters.siv"), SCRIPT).unwrap();
+        Self { root }
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_sievers"))
+            .args(args)
+            .env("XDG_CONFIG_HOME", self.root.join("config"))
+            .env("SIEVERS_PASSWORD", "secret")
+            .output()
+            .unwrap()
+    }
+
+    fn script(&self) -> String {
+        self.root.join("filters.siv").to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for Setup {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+#[test]
+fn test_upload_and_activate() {
+    let (port, server) = mock_server("OK\r\n");
+    let setup = Setup::new("ok", port);
+    let output = setup.run(&["--upload", &setup.script(), "--profile", "mock", "--activate"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Uploaded and activated filters"));
+
+    let commands = server.join().unwrap();
+    let names: Vec<&str> = commands
+        .iter()
+        .map(|c| c.split(' ').next().unwrap())
+        .collect();
+    assert_eq!(
+        names,
+        vec!["AUTHENTICATE", "CHECKSCRIPT", "HAVESPACE", "PUTSCRIPT", "SETACTIVE", "LOGOUT"]
+    );
+    assert_eq!(commands[3], format!("PUTSCRIPT \"filters\" {{{}+}}\n{SCRIPT}", SCRIPT.len()));
+    assert_eq!(commands[4], "SETACTIVE \"filters\"");
+}
+
+#[test]
+fn test_invalid_script_is_not_uploaded() {
+    let (port, server) = mock_server("NO \"line 2: unknown command\"\r\n");
+    let setup = Setup::new("invalid", port);
+    let output = setup.run(&["--upload", &setup.script(), "--profile", "mock"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown command"));
+
+    let commands = server.join().unwrap();
+    assert!(commands.iter().all(|c| !c.starts_with("PUTSCRIPT")));
+}
+
+#[test]
+fn test_usage_errors() {
+    let setup = Setup::new("usage", 1);
+    assert_eq!(setup.run(&["--upload", &setup.script()]).status.code(), Some(2));
+    assert_eq!(
+        setup.run(&["--upload", &setup.script(), "--profile", "nope"]).status.code(),
+        Some(1)
+    );
+}
+
+#[test]
+fn test_password_file_overrides_environment() {
+    let (port, server) = mock_server("OK\r\n");
+    let setup = Setup::new("password-file", port);
+    let password_file = setup.root.join("password");
+    fs::write(&password_file, "from-file\n").unwrap();
+    let output = setup.run(&[
+        "--upload",
+        &setup.script(),
+        "--profile",
+        "mock",
+        "--password-file",
+        &password_file.to_string_lossy(),
+    ]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let commands = server.join().unwrap();
+    // base64 of "\0alice\0from-file"
+    assert_eq!(commands[0], "AUTHENTICATE \"PLAIN\" \"AGFsaWNlAGZyb20tZmlsZQ==\"");
+}